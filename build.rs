@@ -1,3 +1,4 @@
 fn main() {
     pkg_config::probe_library("fontconfig").unwrap();
+    pkg_config::probe_library("pangoft2").unwrap();
 }
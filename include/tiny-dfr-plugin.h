@@ -0,0 +1,50 @@
+/*
+ * Plugin interface for tiny-dfr widgets.
+ *
+ * A plugin is a shared library placed in PluginDir (/usr/lib/tiny-dfr/plugins
+ * by default) and used from the config as
+ *
+ *     { Plugin = { Name = "name", Config = "anything" }, RefreshIntervalMs = 1000 }
+ *
+ * which loads <PluginDir>/name.so. It has to export tiny_dfr_plugin_entry,
+ * returning a pointer to a struct tiny_dfr_plugin that stays valid for as
+ * long as the library is loaded. Every callback is optional.
+ *
+ * Callbacks are called from the daemon's main loop, after it has dropped
+ * privileges, and must not block. Plugins that need to wait on the network
+ * should do so on their own thread and hand the result to render_data.
+ */
+#ifndef TINY_DFR_PLUGIN_H
+#define TINY_DFR_PLUGIN_H
+
+#include <stdint.h>
+
+#define TINY_DFR_PLUGIN_ABI_VERSION 1
+#define TINY_DFR_RENDER_BUF_LEN 256
+
+struct tiny_dfr_render_data {
+	/* Label to show, NUL terminated, left empty to keep the current one */
+	char text[TINY_DFR_RENDER_BUF_LEN];
+	/* Path to an svg or png to show, left empty to keep the current one */
+	char icon[TINY_DFR_RENDER_BUF_LEN];
+};
+
+struct tiny_dfr_plugin {
+	/* Must be set to TINY_DFR_PLUGIN_ABI_VERSION */
+	uint32_t abi_version;
+	/* Called once per button using the plugin, with the Config of
+	 * its Plugin table (an empty string if unset). The returned pointer is passed
+	 * to every other callback. */
+	void *(*create)(const char *config);
+	/* Called when the button goes away, e.g. on config reload */
+	void (*destroy)(void *instance);
+	/* Called every RefreshIntervalMs and right after a touch.
+	 * Return non-zero if data was filled in. */
+	int (*render_data)(void *instance, struct tiny_dfr_render_data *data);
+	/* Called when the button is pressed (1) or released (0) */
+	void (*touch)(void *instance, int pressed);
+};
+
+const struct tiny_dfr_plugin *tiny_dfr_plugin_entry(void);
+
+#endif
@@ -0,0 +1,33 @@
+#!/usr/bin/env lua
+-- Example script widget for tiny-dfr, counting how often it was tapped
+-- and showing the system load in between.
+-- Use it from the config with:
+-- { Script = "/usr/share/tiny-dfr/examples/tap-counter.lua", RefreshIntervalMs = 5000, Stretch = 2 }
+
+local taps = 0
+
+local function load()
+    local f = io.open("/proc/loadavg")
+    if not f then return "?" end
+    local avg = f:read("*l"):match("^(%S+)")
+    f:close()
+    return avg
+end
+
+local callbacks = {
+    update = function()
+        return "text load " .. load() .. " / " .. taps .. " taps"
+    end,
+    tap = function()
+        taps = taps + 1
+        return "text tapped " .. taps .. " times"
+    end,
+}
+
+for line in io.lines() do
+    local callback = callbacks[line]
+    if callback then
+        io.write(callback(), "\n")
+        io.flush()
+    end
+end
@@ -0,0 +1,373 @@
+use crate::audit;
+use crate::keycodes;
+use crate::ratelimit;
+use crate::runtime;
+use gio::{prelude::*, BusType, DBusCallFlags, DBusConnection, DBusConnectionFlags, Subprocess, SubprocessFlags};
+use glib::Variant;
+use input_linux::Key;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{
+    ffi::OsStr,
+    time::{Duration, Instant},
+};
+
+// What a button does when it is touched. The `Action` of a button is a key,
+// by name or code as before, or a table for the other kinds:
+//
+// - `{ Command = "playerctl play-pause" }` runs a shell command
+// - `{ DBus = "session", Destination = "...", Path = "...", Interface = "...",
+//   Method = "...", Args = ["..."] }` calls a D-Bus method with string or
+//   boolean arguments, on the system or session bus or the one at an address
+// - `{ Macro = ["LeftCtrl+C", "LeftAlt+Tab", "LeftCtrl+V"] }` presses and
+//   lets go of each chord in turn
+// - `{ PowerMenu = {} }` brings up the power menu
+// - `{ FocusToggle = {} }` turns focus mode on and off
+//
+// Keys are held down for as long as the button is, the others run once, on
+// the phase set with `On`: when it is pressed (the default), held for a
+// moment, or let go. Those acting on the daemon itself, like the power menu,
+// are done by the main loop when the button is let go while touched. New kinds only take another table here and an
+// implementation of `Action`, not more keys on buttons.
+
+/// How long a button has to be held for the Hold phase
+pub const HOLD_DELAY: Duration = Duration::from_millis(600);
+const DBUS_TIMEOUT_MS: i32 = 5000;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+pub enum Phase {
+    #[default]
+    Press,
+    Hold,
+    Release,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct CommandActionConfig {
+    pub command: String,
+    pub on: Option<Phase>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct DbusActionConfig {
+    /// "system", "session" or the address of a bus
+    pub d_bus: String,
+    pub destination: String,
+    pub path: String,
+    pub interface: String,
+    pub method: String,
+    pub args: Option<Vec<DbusArg>>,
+    pub on: Option<Phase>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum DbusArg {
+    Bool(bool),
+    String(String),
+}
+
+impl DbusArg {
+    fn to_variant(&self) -> Variant {
+        match self {
+            DbusArg::Bool(b) => b.to_variant(),
+            DbusArg::String(s) => s.to_variant(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct MacroActionConfig {
+    /// Chords of keys joined by +, e.g. "LeftCtrl+C"
+    pub r#macro: Vec<String>,
+    pub on: Option<Phase>,
+}
+
+/// Kinds of action that change what the daemon does, rather than reach
+/// outside of it
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum BuiltinAction {
+    PowerMenu {},
+    FocusToggle {},
+}
+
+/// The `Action` of a button
+#[derive(Serialize, Clone)]
+#[serde(untagged)]
+pub enum ActionConfig {
+    Key(Key),
+    Command(CommandActionConfig),
+    DBus(DbusActionConfig),
+    Macro(MacroActionConfig),
+    Builtin(BuiltinAction),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawAction {
+    Code(u64),
+    Name(String),
+    Command(CommandActionConfig),
+    DBus(DbusActionConfig),
+    Macro(MacroActionConfig),
+    Builtin(BuiltinAction),
+}
+
+fn parse_chord(chord: &str) -> Option<Vec<Key>> {
+    chord.split('+').map(|name| keycodes::parse_key(name.trim())).collect()
+}
+
+/// Reads `Action`, a key by name or code, or a table for the other kinds
+pub fn deserialize_action<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<ActionConfig>, D::Error> {
+    let Some(action) = Option::<RawAction>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let action = match action {
+        RawAction::Code(code) => ActionConfig::Key(
+            keycodes::from_code(code)
+                .ok_or_else(|| de::Error::custom(format!("key code {code} is above KEY_MAX")))?,
+        ),
+        RawAction::Name(name) => ActionConfig::Key(
+            keycodes::parse_key(&name).ok_or_else(|| de::Error::custom(format!("unknown key {name}")))?,
+        ),
+        RawAction::Command(cfg) => ActionConfig::Command(cfg),
+        RawAction::DBus(cfg) => ActionConfig::DBus(cfg),
+        RawAction::Macro(cfg) => {
+            if let Some(chord) = cfg.r#macro.iter().find(|chord| parse_chord(chord).is_none()) {
+                return Err(de::Error::custom(format!("unknown key in {chord}")));
+            }
+            ActionConfig::Macro(cfg)
+        }
+        RawAction::Builtin(builtin) => ActionConfig::Builtin(builtin),
+    };
+    Ok(Some(action))
+}
+
+pub trait Action {
+    /// The key it sends, which modifiers, colors and focus mode go by
+    fn key(&self) -> Option<Key> {
+        None
+    }
+    /// Every key it may send, for the virtual keyboard to announce
+    fn keys(&self) -> Vec<Key> {
+        self.key().into_iter().collect()
+    }
+    /// What it is called in the button state, for actions without a key
+    fn kind(&self) -> &'static str;
+    /// Whether it does anything when held, so that only those are timed
+    fn wants_hold(&self) -> bool {
+        false
+    }
+    /// What it asks of the daemon itself, for the main loop to do
+    fn builtin(&self) -> Option<BuiltinAction> {
+        None
+    }
+    /// Does what it does in `phase`, sending keys through `send`
+    fn run(&mut self, phase: Phase, send: &mut dyn FnMut(Key, bool));
+}
+
+struct KeyAction(Key);
+
+impl Action for KeyAction {
+    fn key(&self) -> Option<Key> {
+        Some(self.0)
+    }
+    fn kind(&self) -> &'static str {
+        "Key"
+    }
+    fn run(&mut self, phase: Phase, send: &mut dyn FnMut(Key, bool)) {
+        match phase {
+            Phase::Press if ratelimit::allow_key() => send(self.0, true),
+            Phase::Release => send(self.0, false),
+            _ => {}
+        }
+    }
+}
+
+/// Runs `command` with sh on the runtime thread, without waiting for it
+pub fn run_command(command: &str) {
+    audit::record("Ran command", Some(command));
+    let command = command.to_string();
+    runtime::spawn_detached(move || async move {
+        let argv = [OsStr::new("sh"), OsStr::new("-c"), OsStr::new(&command)];
+        match Subprocess::newv(&argv, SubprocessFlags::NONE) {
+            Ok(process) => _ = process.wait_future().await,
+            Err(e) => eprintln!("Failed to run {command}: {e}"),
+        }
+    });
+}
+
+struct CommandAction(CommandActionConfig);
+
+impl Action for CommandAction {
+    fn kind(&self) -> &'static str {
+        "Command"
+    }
+    fn wants_hold(&self) -> bool {
+        self.0.on == Some(Phase::Hold)
+    }
+    fn run(&mut self, phase: Phase, _send: &mut dyn FnMut(Key, bool)) {
+        if phase != self.0.on.unwrap_or_default() || !ratelimit::allow_command() {
+            return;
+        }
+        run_command(&self.0.command);
+    }
+}
+
+struct DbusAction(DbusActionConfig);
+
+async fn connect(bus: &str) -> Result<DBusConnection, glib::Error> {
+    match bus {
+        "system" => gio::bus_get_future(BusType::System).await,
+        "session" => gio::bus_get_future(BusType::Session).await,
+        address => {
+            let flags = DBusConnectionFlags::AUTHENTICATION_CLIENT | DBusConnectionFlags::MESSAGE_BUS_CONNECTION;
+            DBusConnection::for_address_future(address, flags, None).await
+        }
+    }
+}
+
+/// Calls a D-Bus method on `bus`, "system", "session" or the address of one
+pub async fn call_method(
+    bus: &str,
+    destination: &str,
+    path: &str,
+    interface: &str,
+    method: &str,
+    args: &Variant,
+) -> Result<(), glib::Error> {
+    connect(bus)
+        .await?
+        .call_future(
+            Some(destination),
+            path,
+            interface,
+            method,
+            Some(args),
+            None,
+            DBusCallFlags::NONE,
+            DBUS_TIMEOUT_MS,
+        )
+        .await
+        .map(|_| ())
+}
+
+impl Action for DbusAction {
+    fn kind(&self) -> &'static str {
+        "DBus"
+    }
+    fn wants_hold(&self) -> bool {
+        self.0.on == Some(Phase::Hold)
+    }
+    fn run(&mut self, phase: Phase, _send: &mut dyn FnMut(Key, bool)) {
+        if phase != self.0.on.unwrap_or_default() || !ratelimit::allow_command() {
+            return;
+        }
+        let cfg = self.0.clone();
+        audit::record("Called D-Bus method", Some(&format!("{}.{} on {}", cfg.interface, cfg.method, cfg.destination)));
+        runtime::spawn_detached(move || async move {
+            let args = Variant::tuple_from_iter(cfg.args.iter().flatten().map(|arg| arg.to_variant()));
+            let res = call_method(&cfg.d_bus, &cfg.destination, &cfg.path, &cfg.interface, &cfg.method, &args).await;
+            if let Err(e) = res {
+                eprintln!("Failed to call {}.{}: {e}", cfg.interface, cfg.method);
+            }
+        });
+    }
+}
+
+struct MacroAction {
+    chords: Vec<Vec<Key>>,
+    on: Phase,
+}
+
+impl Action for MacroAction {
+    fn keys(&self) -> Vec<Key> {
+        self.chords.iter().flatten().copied().collect()
+    }
+    fn kind(&self) -> &'static str {
+        "Macro"
+    }
+    fn wants_hold(&self) -> bool {
+        self.on == Phase::Hold
+    }
+    fn run(&mut self, phase: Phase, send: &mut dyn FnMut(Key, bool)) {
+        if phase != self.on || !ratelimit::allow_key() {
+            return;
+        }
+        for chord in &self.chords {
+            for key in chord {
+                send(*key, true);
+            }
+            for key in chord.iter().rev() {
+                send(*key, false);
+            }
+        }
+    }
+}
+
+struct Builtin(BuiltinAction);
+
+impl Action for Builtin {
+    fn kind(&self) -> &'static str {
+        match self.0 {
+            BuiltinAction::PowerMenu {} => "PowerMenu",
+            BuiltinAction::FocusToggle {} => "FocusToggle",
+        }
+    }
+    fn builtin(&self) -> Option<BuiltinAction> {
+        Some(self.0)
+    }
+    // done by the main loop, which has what it acts on
+    fn run(&mut self, _phase: Phase, _send: &mut dyn FnMut(Key, bool)) {}
+}
+
+/// Runs the action of a button in the phases of a touch, timing how long
+/// it is held for those that want to know
+pub struct Dispatcher {
+    action: Box<dyn Action>,
+    pressed_at: Option<Instant>,
+}
+
+impl Dispatcher {
+    pub fn new(cfg: ActionConfig) -> Dispatcher {
+        let action: Box<dyn Action> = match cfg {
+            ActionConfig::Key(key) => Box::new(KeyAction(key)),
+            ActionConfig::Command(cfg) => Box::new(CommandAction(cfg)),
+            ActionConfig::DBus(cfg) => Box::new(DbusAction(cfg)),
+            ActionConfig::Macro(cfg) => Box::new(MacroAction {
+                // checked when the config was read
+                chords: cfg.r#macro.iter().filter_map(|chord| parse_chord(chord)).collect(),
+                on: cfg.on.unwrap_or_default(),
+            }),
+            ActionConfig::Builtin(builtin) => Box::new(Builtin(builtin)),
+        };
+        Dispatcher {
+            action,
+            pressed_at: None,
+        }
+    }
+    pub fn action(&self) -> &dyn Action {
+        self.action.as_ref()
+    }
+    pub fn set_pressed(&mut self, pressed: bool, send: &mut dyn FnMut(Key, bool)) {
+        self.pressed_at = pressed.then(Instant::now);
+        let phase = if pressed { Phase::Press } else { Phase::Release };
+        self.action.run(phase, send);
+    }
+    /// Runs the Hold phase once the button was held long enough. Returns
+    /// the number of milliseconds until then.
+    pub fn check_hold(&mut self, send: &mut dyn FnMut(Key, bool)) -> i32 {
+        let Some(pressed_at) = self.pressed_at.filter(|_| self.action.wants_hold()) else {
+            return i32::MAX;
+        };
+        let held = pressed_at.elapsed();
+        if held < HOLD_DELAY {
+            return (HOLD_DELAY - held).as_millis() as i32 + 1;
+        }
+        self.pressed_at = None;
+        self.action.run(Phase::Hold, send);
+        i32::MAX
+    }
+}
@@ -0,0 +1,240 @@
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::json::Value;
+use crate::source::{self, Source, Update};
+use crate::text::truncate;
+use anyhow::{anyhow, Result};
+use freedesktop_icons::lookup;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+// Shows the icon and title of the focused window, followed through the
+// compositor's IPC socket: sway (or i3) window and workspace events, or
+// Hyprland's event socket. Compositor sockets belong to the user's session,
+// so like for AudioOutput, the tiny-dfr user has to be given access to it.
+
+const DEFAULT_MAX_LENGTH: usize = 16;
+const DEFAULT_THEME: &str = "hicolor";
+const FALLBACK_ICON: &str = "application-x-executable";
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+pub const SWAY_GET_TREE: u32 = 4;
+pub const SWAY_SUBSCRIBE: u32 = 2;
+const SWAY_WORKSPACE_EVENT: u32 = 0x80000000;
+const SWAY_WINDOW_EVENT: u32 = 0x80000003;
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum Compositor {
+    /// Also works with i3, which has the same IPC
+    Sway,
+    Hyprland,
+}
+
+/// The `ActiveApp` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ActiveAppConfig {
+    pub compositor: Compositor,
+    pub socket: String,
+    pub theme: Option<String>,
+    pub show_title: Option<bool>,
+    pub max_length: Option<usize>,
+}
+
+struct Window {
+    app: String,
+    title: String,
+}
+
+pub fn sway_send(stream: &mut UnixStream, kind: u32, payload: &str) -> Result<()> {
+    let mut message = b"i3-ipc".to_vec();
+    message.extend((payload.len() as u32).to_ne_bytes());
+    message.extend(kind.to_ne_bytes());
+    message.extend(payload.as_bytes());
+    Ok(stream.write_all(&message)?)
+}
+
+pub fn sway_read(stream: &mut UnixStream) -> Result<(u32, Value)> {
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != b"i3-ipc" {
+        return Err(anyhow!("not an i3/sway IPC socket"));
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+    let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload)?;
+    Ok((kind, Value::parse(&String::from_utf8_lossy(&payload))?))
+}
+
+fn sway_window(node: &Value) -> Option<Window> {
+    // Wayland windows have an app_id, X11 ones only a class
+    let app = node
+        .get("app_id")
+        .and_then(Value::as_str)
+        .or_else(|| node.get("window_properties")?.get("class")?.as_str())?;
+    Some(Window {
+        app: app.to_string(),
+        title: node.get("name").and_then(Value::as_str).unwrap_or_default().to_string(),
+    })
+}
+
+/// Finds the focused node in a sway tree
+pub fn sway_focused_node(node: &Value) -> Option<&Value> {
+    if node.get("focused") == Some(&Value::Bool(true)) {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .into_iter()
+        .filter_map(|key| match node.get(key) {
+            Some(Value::Array(children)) => Some(children),
+            _ => None,
+        })
+        .flatten()
+        .find_map(sway_focused_node)
+}
+
+fn sway_focused(node: &Value) -> Option<Window> {
+    sway_focused_node(node).and_then(sway_window)
+}
+
+fn follow_sway(socket: &str, windows: &Sender<Option<Window>>, waker: &Waker) -> Result<()> {
+    let mut stream = UnixStream::connect(socket)?;
+    sway_send(&mut stream, SWAY_GET_TREE, "")?;
+    let (_, tree) = sway_read(&mut stream)?;
+    windows.send(sway_focused(&tree))?;
+    waker.wake();
+    sway_send(&mut stream, SWAY_SUBSCRIBE, r#"["window","workspace"]"#)?;
+    loop {
+        let (kind, event) = sway_read(&mut stream)?;
+        let change = event.get("change").and_then(Value::as_str);
+        let window = match (kind, change) {
+            (SWAY_WINDOW_EVENT, Some("focus")) => event.get("container").and_then(sway_window),
+            (SWAY_WINDOW_EVENT, Some("title")) => {
+                let container = event.get("container").unwrap_or(&Value::Null);
+                if container.get("focused") != Some(&Value::Bool(true)) {
+                    continue;
+                }
+                sway_window(container)
+            }
+            // switching to an empty workspace does not focus any window
+            (SWAY_WORKSPACE_EVENT, Some("focus")) => event.get("current").and_then(sway_focused),
+            _ => continue,
+        };
+        windows.send(window)?;
+        waker.wake();
+    }
+}
+
+fn follow_hyprland(socket: &str, windows: &Sender<Option<Window>>, waker: &Waker) -> Result<()> {
+    // requests go to the socket next to the event one
+    if let Some(prefix) = socket.strip_suffix(".socket2.sock") {
+        let mut request = UnixStream::connect(format!("{prefix}.socket.sock"))?;
+        request.write_all(b"j/activewindow")?;
+        let mut reply = String::new();
+        request.read_to_string(&mut reply)?;
+        let reply = Value::parse(&reply)?;
+        let field = |key| reply.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+        let window = Window {
+            app: field("class"),
+            title: field("title"),
+        };
+        windows.send(Some(window).filter(|w| !w.app.is_empty()))?;
+        waker.wake();
+    }
+    let events = BufReader::new(UnixStream::connect(socket)?);
+    for line in events.lines() {
+        let line = line?;
+        let Some(data) = line.strip_prefix("activewindow>>") else {
+            continue;
+        };
+        // the class cannot contain a comma, the title can
+        let (app, title) = data.split_once(',').unwrap_or((data, ""));
+        let window = Window {
+            app: app.to_string(),
+            title: title.to_string(),
+        };
+        windows.send(Some(window).filter(|w| !w.app.is_empty()))?;
+        waker.wake();
+    }
+    Err(anyhow!("connection closed"))
+}
+
+fn find_icon(app: &str, theme: &str) -> Option<String> {
+    // X11 classes are often capitalized, icon names rarely are
+    [app, &app.to_lowercase(), FALLBACK_ICON]
+        .into_iter()
+        .find_map(|name| lookup(name).with_theme(theme).with_cache().find())
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+pub struct ActiveApp {
+    cfg: ActiveAppConfig,
+    windows: Option<Receiver<Option<Window>>>,
+}
+
+impl ActiveApp {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(ActiveApp::new(source::parse(value)?)))
+    }
+    pub fn new(cfg: ActiveAppConfig) -> ActiveApp {
+        ActiveApp { cfg, windows: None }
+    }
+    fn start(&mut self, waker: &Waker) {
+        let (tx, rx) = channel();
+        let compositor = self.cfg.compositor;
+        let socket = self.cfg.socket.clone();
+        let waker = waker.clone();
+        thread::spawn(move || loop {
+            let res = match compositor {
+                Compositor::Sway => follow_sway(&socket, &tx, &waker),
+                Compositor::Hyprland => follow_hyprland(&socket, &tx, &waker),
+            };
+            // the button is gone
+            if tx.send(None).is_err() {
+                break;
+            }
+            waker.wake();
+            if let Err(e) = res {
+                eprintln!("Lost compositor socket {socket}: {e:#}");
+            }
+            thread::sleep(RECONNECT_DELAY);
+        });
+        self.windows = Some(rx);
+    }
+}
+
+impl Source for ActiveApp {
+    /// Connects lazily so that the socket is opened after dropping privileges
+    fn poll(&mut self, waker: &Waker) -> (Vec<Update>, i32) {
+        if self.windows.is_none() {
+            self.start(waker);
+        }
+        let Some(window) = self.windows.as_ref().unwrap().try_iter().last() else {
+            return (Vec::new(), i32::MAX);
+        };
+        let Some(window) = window else {
+            return (vec![Update::Text(String::new())], i32::MAX);
+        };
+        let mut updates = Vec::new();
+        let theme = self.cfg.theme.as_deref().unwrap_or(DEFAULT_THEME);
+        if let Some(icon) = find_icon(&window.app, theme) {
+            updates.push(Update::Icon(icon));
+        }
+        let text = if self.cfg.show_title.unwrap_or(true) && !window.title.is_empty() {
+            &window.title
+        } else {
+            &window.app
+        };
+        let max_length = self.cfg.max_length.unwrap_or(DEFAULT_MAX_LENGTH);
+        updates.push(Update::Text(truncate(text, max_length)));
+        (updates, i32::MAX)
+    }
+    fn touch(&mut self, _pressed: bool) {}
+}
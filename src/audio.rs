@@ -0,0 +1,167 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::json::Value;
+use crate::source::{self, Background, Job, Source, Update};
+use crate::text::truncate;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+// Shows the default PipeWire sink and switches to the next one when
+// tapped, using pw-dump to find the sinks and wpctl to change the default,
+// which WirePlumber then remembers like any other change of the default.
+//
+// PipeWire runs inside the user's session, so the daemon can only reach it
+// when pointed at a socket it is allowed to connect to, through Remote.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 2000;
+const DEFAULT_MAX_LENGTH: usize = 16;
+
+/// The `AudioOutput` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AudioOutputConfig {
+    pub remote: Option<String>,
+    pub max_length: Option<usize>,
+}
+
+pub struct Sink {
+    pub id: u64,
+    pub name: String,
+    pub description: String,
+}
+
+fn array(value: &Value) -> &[Value] {
+    match value {
+        Value::Array(items) => items,
+        _ => &[],
+    }
+}
+
+/// Returns the sinks in the graph and the name of the default one
+fn parse_dump(dump: &Value) -> (Vec<Sink>, Option<String>) {
+    let mut sinks = Vec::new();
+    let mut default = None;
+    let mut configured = None;
+    for object in array(dump) {
+        let kind = object.get("type").and_then(Value::as_str);
+        if kind == Some("PipeWire:Interface:Node") {
+            let props = object.get("info").and_then(|info| info.get("props"));
+            let prop = |key| props.and_then(|p| p.get(key)).and_then(Value::as_str);
+            if prop("media.class") != Some("Audio/Sink") {
+                continue;
+            }
+            let (Some(id), Some(name)) = (object.get("id").and_then(Value::as_u64), prop("node.name")) else {
+                continue;
+            };
+            let description = prop("node.description").or(prop("node.nick")).unwrap_or(name);
+            sinks.push(Sink {
+                id,
+                name: name.to_string(),
+                description: description.to_string(),
+            });
+        } else if kind == Some("PipeWire:Interface:Metadata") {
+            let name = object.get("props").and_then(|p| p.get("metadata.name"));
+            if name.and_then(Value::as_str) != Some("default") {
+                continue;
+            }
+            for entry in array(object.get("metadata").unwrap_or(&Value::Null)) {
+                let value = entry
+                    .get("value")
+                    .and_then(|v| v.get("name"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+                match entry.get("key").and_then(Value::as_str) {
+                    Some("default.audio.sink") => default = value,
+                    Some("default.configured.audio.sink") => configured = value,
+                    _ => {}
+                }
+            }
+        }
+    }
+    (sinks, default.or(configured))
+}
+
+fn command(remote: Option<&str>, program: &str) -> Command {
+    let mut cmd = Command::new(program);
+    if let Some(remote) = remote {
+        cmd.env("PIPEWIRE_REMOTE", remote);
+    }
+    cmd.stdin(Stdio::null()).stderr(Stdio::inherit());
+    cmd
+}
+
+/// Returns the sinks of the PipeWire instance at `remote` and the name of
+/// the default one
+pub fn sinks(remote: Option<&str>) -> Result<(Vec<Sink>, Option<String>)> {
+    let output = command(remote, "pw-dump").output()?;
+    if !output.status.success() {
+        return Err(anyhow!("pw-dump exited with {}", output.status));
+    }
+    let dump = Value::parse(&String::from_utf8_lossy(&output.stdout))?;
+    Ok(parse_dump(&dump))
+}
+
+pub fn set_default(remote: Option<&str>, sink: &Sink) -> Result<()> {
+    let status = command(remote, "wpctl")
+        .args(["set-default", &sink.id.to_string()])
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("wpctl exited with {status}"));
+    }
+    Ok(())
+}
+
+pub struct AudioOutput {
+    cfg: AudioOutputConfig,
+}
+
+impl AudioOutput {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let audio = AudioOutput::new(source::parse(value)?);
+        Ok(Box::new(Background::new(audio, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS))))
+    }
+    pub fn new(cfg: AudioOutputConfig) -> AudioOutput {
+        AudioOutput { cfg }
+    }
+    fn switch(&self) -> Result<()> {
+        let (sinks, default) = sinks(self.cfg.remote.as_deref())?;
+        if sinks.is_empty() {
+            return Err(anyhow!("there are no audio outputs"));
+        }
+        let current = sinks.iter().position(|s| Some(&s.name) == default.as_ref());
+        let next = &sinks[current.map_or(0, |i| (i + 1) % sinks.len())];
+        set_default(self.cfg.remote.as_deref(), next)
+    }
+}
+
+impl Job for AudioOutput {
+    const MIN_INTERVAL: Duration = Duration::from_millis(500);
+
+    fn refresh(&mut self) -> Vec<Update> {
+        let text = match sinks(self.cfg.remote.as_deref()) {
+            Ok((sinks, default)) => sinks
+                .into_iter()
+                .find(|s| Some(&s.name) == default.as_ref())
+                .map_or(i18n::tr("No output"), |s| s.description),
+            Err(e) => {
+                eprintln!("Failed to get the audio outputs: {e:#}");
+                i18n::tr("N/A")
+            }
+        };
+        let max_length = self.cfg.max_length.unwrap_or(DEFAULT_MAX_LENGTH);
+        vec![Update::Text(truncate(&text, max_length))]
+    }
+    fn touch(&mut self, pressed: bool) -> bool {
+        if !pressed {
+            return false;
+        }
+        if let Err(e) = self.switch() {
+            eprintln!("Failed to switch the audio output: {e:#}");
+        }
+        true
+    }
+}
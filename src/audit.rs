@@ -0,0 +1,25 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// An optional record of what the bar ran on its own, like commands, web
+// requests and links opened from taps, so that it can be looked up later
+// with `journalctl -u tiny-dfr -g Audit:`. Lines go to the journal, which
+// timestamps them and keeps them out of reach of the daemon itself once
+// written. Whatever came from a button's contents, like the link of a mail
+// or headline, is left out, only what kind of thing was done is kept.
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Records that the bar did `what`, with `detail` when it is not sensitive
+pub fn record(what: &str, detail: Option<&str>) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    match detail {
+        Some(detail) => eprintln!("Audit: {what}: {detail}"),
+        None => eprintln!("Audit: {what}"),
+    }
+}
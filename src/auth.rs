@@ -0,0 +1,143 @@
+use crate::ipc::Access;
+use anyhow::{anyhow, Result};
+use gio::{DBusCallFlags, DBusConnection};
+use glib::{prelude::*, VariantTy};
+use nix::unistd::User;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io,
+    mem,
+    os::{fd::AsRawFd, unix::net::UnixStream},
+};
+
+// Who may change things through the control interfaces. Reading the state
+// is left open, as privacy mode already keeps sensitive contents out of it,
+// but changes are not, so that any process cannot drive the bar:
+//
+// - D-Bus is reachable by every local process, so polkit decides, with one
+//   action for changing what the bar shows, one for changing the config and
+//   one for the battery charge limit, see share/polkit-1/actions. Without
+//   polkit, only root may make changes.
+// - The control socket is only for root, unless SocketUsers lets others in,
+//   which is checked against the credentials of each connection. The charge
+//   limit stays with root, as there is no polkit to ask there.
+//
+// Both are set up once, before privileges are dropped, so changes to the
+// `[Ipc]` section need a restart.
+
+const CONTROL_ACTION: &str = "org.asahilinux.TinyDFR.control";
+const CONFIGURE_ACTION: &str = "org.asahilinux.TinyDFR.configure";
+const CHARGE_LIMIT_ACTION: &str = "org.asahilinux.TinyDFR.chargelimit";
+
+/// The `[Ipc]` section
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct IpcConfig {
+    /// Whether polkit decides on changes over D-Bus, or only root may
+    pub polkit: Option<bool>,
+    /// Users other than root that may use the control socket, by name or uid
+    pub socket_users: Option<Vec<String>>,
+}
+
+impl IpcConfig {
+    /// The uids of SocketUsers, leaving out unknown users
+    pub fn socket_uids(&self) -> Vec<u32> {
+        self.socket_users
+            .iter()
+            .flatten()
+            .filter_map(|user| {
+                let uid = match user.parse() {
+                    Ok(uid) => Some(uid),
+                    Err(_) => User::from_name(user).ok().flatten().map(|u| u.uid.as_raw()),
+                };
+                if uid.is_none() {
+                    eprintln!("Ignoring unknown user {user} in SocketUsers");
+                }
+                uid
+            })
+            .collect()
+    }
+}
+
+/// The uid of the process on the other end of the socket
+pub fn socket_peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: cred and len are valid for the size given
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+fn bus_peer_uid(connection: &DBusConnection, sender: &str) -> Result<u32> {
+    let reply = connection.call_sync(
+        Some("org.freedesktop.DBus"),
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "GetConnectionUnixUser",
+        Some(&(sender,).to_variant()),
+        Some(VariantTy::new("(u)").unwrap()),
+        DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+    )?;
+    let (uid,) = reply.get::<(u32,)>().ok_or(anyhow!("unexpected reply"))?;
+    Ok(uid)
+}
+
+fn polkit_check(connection: &DBusConnection, sender: &str, action: &str) -> Result<bool> {
+    let subject = (
+        "system-bus-name",
+        HashMap::from([("name".to_string(), sender.to_variant())]),
+    );
+    let params = (subject, action, HashMap::<String, String>::new(), 0u32, "").to_variant();
+    let reply = connection.call_sync(
+        Some("org.freedesktop.PolicyKit1"),
+        "/org/freedesktop/PolicyKit1/Authority",
+        "org.freedesktop.PolicyKit1.Authority",
+        "CheckAuthorization",
+        Some(&params),
+        Some(VariantTy::new("((bba{ss}))").unwrap()),
+        DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+    )?;
+    let ((authorized, _, _),) = reply
+        .get::<((bool, bool, HashMap<String, String>),)>()
+        .ok_or(anyhow!("unexpected reply"))?;
+    Ok(authorized)
+}
+
+/// Whether the sender of a D-Bus call may make a request with `access`.
+/// Failures to ask count as not allowed.
+pub fn bus_allowed(connection: &DBusConnection, sender: Option<&str>, access: Access, polkit: bool) -> bool {
+    let action = match access {
+        Access::Read => return true,
+        Access::Control => CONTROL_ACTION,
+        Access::Configure => CONFIGURE_ACTION,
+        Access::ChargeLimit => CHARGE_LIMIT_ACTION,
+    };
+    let Some(sender) = sender else {
+        return false;
+    };
+    let res = if polkit {
+        polkit_check(connection, sender, action)
+    } else {
+        bus_peer_uid(connection, sender).map(|uid| uid == 0)
+    };
+    res.unwrap_or_else(|e| {
+        eprintln!("Cannot tell whether {sender} may use {action}: {e:#}");
+        false
+    })
+}
@@ -1,23 +1,31 @@
 use crate::config::Config;
+use crate::focus::{daily_span, in_daily_span};
 use crate::TIMEOUT_MS;
 use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveTime};
 use input::event::{
     switch::{Switch, SwitchEvent, SwitchState},
     Event,
 };
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::min,
     fs::{self, File, OpenOptions},
     io::Write,
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::fs::FileExt,
+    },
     path::{Path, PathBuf},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 const MAX_DISPLAY_BRIGHTNESS: u32 = 509;
 const MAX_TOUCH_BAR_BRIGHTNESS: u32 = 255;
 const BRIGHTNESS_DIM_TIMEOUT: i32 = TIMEOUT_MS * 3; // should be a multiple of TIMEOUT_MS
 const BRIGHTNESS_OFF_TIMEOUT: i32 = TIMEOUT_MS * 6; // should be a multiple of TIMEOUT_MS
-const DIMMED_BRIGHTNESS: u32 = 1;
+pub const DIMMED_BRIGHTNESS: u32 = 1;
+const FADE_STEP_MS: i32 = 16;
 
 fn read_attr(path: &Path, attr: &str) -> u32 {
     fs::read_to_string(path.join(attr))
@@ -61,6 +69,72 @@ fn find_display_backlight() -> Result<PathBuf> {
     Err(anyhow!("No Built-in Retina Display backlight device found"))
 }
 
+/// The `[BrightnessSync]` section, for the bar to follow the display's
+/// brightness in step instead of along the adaptive curve
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "PascalCase")]
+pub struct BrightnessSyncConfig {
+    pub enable: Option<bool>,
+    /// How bright the bar is relative to the display, 1.0 being the same
+    /// fraction of their maximum brightness
+    pub factor: Option<f64>,
+}
+
+/// The `[NightBrightness]` section, limits used instead of MinBrightness
+/// and MaxBrightness every day between From and To
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct NightBrightnessConfig {
+    /// Start of the night, as HH:MM
+    pub from: Option<String>,
+    /// End of the night, as HH:MM
+    pub to: Option<String>,
+    pub min_brightness: Option<u32>,
+    pub max_brightness: Option<u32>,
+}
+
+impl NightBrightnessConfig {
+    /// Warns about a schedule that will never apply
+    pub fn check(&self) {
+        if (self.from.is_some() || self.to.is_some()) && daily_span(&self.from, &self.to).is_none() {
+            eprintln!("NightBrightness needs both From and To as HH:MM, ignoring it");
+        }
+    }
+}
+
+/// The lowest and highest brightness the bar is set to while in use
+fn brightness_limits(cfg: &Config, now: NaiveTime) -> (u32, u32) {
+    let night = &cfg.night_brightness;
+    let (mut min_bl, mut max_bl) = (cfg.min_brightness, cfg.max_brightness);
+    if daily_span(&night.from, &night.to).is_some_and(|span| in_daily_span(span, now)) {
+        min_bl = night.min_brightness.unwrap_or(min_bl);
+        max_bl = night.max_brightness.unwrap_or(max_bl);
+    }
+    (min_bl.min(max_bl), max_bl)
+}
+
+/// A brightness change in progress
+struct Fade {
+    from: u32,
+    to: u32,
+    start: Instant,
+    duration: Duration,
+}
+
+impl Fade {
+    /// The brightness at this point of the fade, or `None` once it is over
+    fn level(&self, now: Instant) -> Option<u32> {
+        let t = (now - self.start).as_secs_f64() / self.duration.as_secs_f64();
+        if self.duration.is_zero() || t >= 1.0 {
+            return None;
+        }
+        // smoothstep, easing in and out
+        let eased = t * t * (3.0 - 2.0 * t);
+        let (from, to) = (self.from as f64, self.to as f64);
+        Some((from + (to - from) * eased).round() as u32)
+    }
+}
+
 fn set_backlight(mut file: &File, value: u32) {
     file.write_all(format!("{}\n", value).as_bytes()).unwrap();
 }
@@ -68,16 +142,31 @@ fn set_backlight(mut file: &File, value: u32) {
 pub struct BacklightManager {
     last_active: Instant,
     max_bl: u32,
+    /// The brightness the bar is set to, or fading to
     current_bl: u32,
+    /// The brightness the backlight is at right now
+    shown_bl: u32,
+    fade: Option<Fade>,
+    /// Levels below this are shown by dimming the content instead, 0 for never
+    software_below: u32,
+    /// How much the content is dimmed in place of the backlight
+    content_dimming: f64,
+    /// Whether the bar is dimmed or off for not being used
+    idle: bool,
     lid_state: SwitchState,
     bl_file: File,
-    display_bl_path: PathBuf,
+    /// The display's `actual_brightness`, which is notified of changes
+    display_bl_file: File,
+    display_max_bl: u32,
+    brightness_override: Option<u32>,
+    off: bool,
 }
 
 impl BacklightManager {
     pub fn new() -> BacklightManager {
         let bl_path = find_backlight().unwrap();
         let display_bl_path = find_display_backlight().unwrap();
+        let display_bl_file = File::open(display_bl_path.join("actual_brightness")).unwrap();
         let bl_file = OpenOptions::new()
             .write(true)
             .open(bl_path.join("brightness"))
@@ -87,8 +176,16 @@ impl BacklightManager {
             lid_state: SwitchState::Off,
             max_bl: read_attr(&bl_path, "max_brightness"),
             current_bl: read_attr(&bl_path, "brightness"),
+            shown_bl: read_attr(&bl_path, "brightness"),
+            fade: None,
+            software_below: 0,
+            content_dimming: 1.0,
+            idle: false,
             last_active: Instant::now(),
-            display_bl_path,
+            display_bl_file,
+            display_max_bl: read_attr(&display_bl_path, "max_brightness"),
+            brightness_override: None,
+            off: false,
         }
     }
     fn display_to_touchbar(display: u32, active_brightness: u32) -> u32 {
@@ -97,6 +194,26 @@ impl BacklightManager {
         let adjusted = (normalized.powf(0.5) * active_brightness as f64) as u32 + 1;
         adjusted.min(MAX_TOUCH_BAR_BRIGHTNESS) // Clamp the value to the maximum allowed brightness
     }
+    fn display_to_touchbar_synced(&self, display: u32, sync: &BrightnessSyncConfig) -> u32 {
+        let normalized = display as f64 / self.display_max_bl.max(1) as f64;
+        let synced = normalized * sync.factor.unwrap_or(1.0) * MAX_TOUCH_BAR_BRIGHTNESS as f64;
+        // like on the adaptive curve, it does not turn off with the display
+        (synced.round() as u32).clamp(1, MAX_TOUCH_BAR_BRIGHTNESS)
+    }
+    /// Reads the display's brightness, which also lets the fd from
+    /// `display_fd` be notified of the next change
+    fn display_brightness(&self) -> u32 {
+        let mut buf = [0; 16];
+        let len = self.display_bl_file.read_at(&mut buf, 0).unwrap_or(0);
+        std::str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(self.display_max_bl)
+    }
+    /// Becomes readable with `EPOLLPRI` when the display's brightness changed
+    pub fn display_fd(&self) -> BorrowedFd<'_> {
+        self.display_bl_file.as_fd()
+    }
     pub fn process_event(&mut self, event: &Event) {
         match event {
             Event::Keyboard(_) | Event::Pointer(_) | Event::Gesture(_) | Event::Touch(_) => {
@@ -114,31 +231,116 @@ impl BacklightManager {
             _ => {}
         }
     }
-    pub fn update_backlight(&mut self, cfg: &Config) {
+    /// Sets the brightness for the current state, at most `max_brightness`
+    /// while the bar is in use if set
+    pub fn update_backlight(&mut self, cfg: &Config, max_brightness: Option<u32>) {
         let since_last_active = (Instant::now() - self.last_active).as_millis() as u64;
+        let display = self.display_brightness();
+        self.idle = since_last_active >= BRIGHTNESS_DIM_TIMEOUT as u64;
         let new_bl = min(
             self.max_bl,
-            if self.lid_state == SwitchState::On {
+            if self.lid_state == SwitchState::On || self.off {
                 0
             } else if since_last_active < BRIGHTNESS_DIM_TIMEOUT as u64 {
-                if cfg.adaptive_brightness {
-                    BacklightManager::display_to_touchbar(
-                        read_attr(&self.display_bl_path, "brightness"),
-                        cfg.active_brightness,
-                    )
+                let brightness = if let Some(brightness) = self.brightness_override {
+                    brightness
+                } else if cfg.brightness_sync.enable == Some(true) {
+                    self.display_to_touchbar_synced(display, &cfg.brightness_sync)
+                } else if cfg.adaptive_brightness {
+                    BacklightManager::display_to_touchbar(display, cfg.active_brightness)
                 } else {
                     cfg.active_brightness
-                }
+                };
+                let (min_bl, max_bl) = brightness_limits(cfg, Local::now().time());
+                min(brightness.clamp(min_bl, max_bl), max_brightness.unwrap_or(u32::MAX))
             } else if since_last_active < BRIGHTNESS_OFF_TIMEOUT as u64 {
-                DIMMED_BRIGHTNESS
+                min(cfg.dim_brightness, max_brightness.unwrap_or(u32::MAX))
             } else {
                 0
             },
         );
+        let software_below = min(cfg.software_dimming_below, self.max_bl);
+        if self.software_below != software_below {
+            self.software_below = software_below;
+            self.set_level(self.shown_bl);
+        }
         if self.current_bl != new_bl {
             self.current_bl = new_bl;
-            set_backlight(&self.bl_file, self.current_bl);
+            self.fade = Some(Fade {
+                from: self.shown_bl,
+                to: new_bl,
+                start: Instant::now(),
+                duration: Duration::from_millis(cfg.brightness_fade_ms),
+            });
         }
+        self.step_fade();
+    }
+    /// Moves the backlight along the fade in progress
+    fn step_fade(&mut self) {
+        let Some(fade) = &self.fade else {
+            return;
+        };
+        let level = match fade.level(Instant::now()) {
+            Some(level) => level,
+            None => {
+                let to = fade.to;
+                self.fade = None;
+                to
+            }
+        };
+        if level != self.shown_bl {
+            self.set_level(level);
+        }
+    }
+    /// Shows the brightness level, low ones by dimming the content if set to
+    fn set_level(&mut self, level: u32) {
+        self.shown_bl = level;
+        let backlight = if level > 0 && level < self.software_below {
+            self.content_dimming = level as f64 / self.software_below as f64;
+            self.software_below
+        } else {
+            self.content_dimming = 1.0;
+            level
+        };
+        set_backlight(&self.bl_file, backlight);
+    }
+    /// How much the content needs to be dimmed for the brightness level
+    pub fn content_dimming(&self) -> f64 {
+        self.content_dimming
+    }
+    /// The number of milliseconds until the fade in progress needs to be
+    /// stepped by `update_backlight`
+    pub fn ms_until_step(&self) -> i32 {
+        match self.fade {
+            Some(_) => FADE_STEP_MS,
+            None => i32::MAX,
+        }
+    }
+    pub fn set_brightness_override(&mut self, brightness: Option<u32>) {
+        self.brightness_override = brightness;
+        self.last_active = Instant::now();
+    }
+    /// Keeps the bar off regardless of activity, or lets it come back on
+    pub fn toggle_off(&mut self) {
+        self.set_off(!self.off);
+    }
+    pub fn set_off(&mut self, off: bool) {
+        self.off = off;
+        self.last_active = Instant::now();
+    }
+    pub fn is_off(&self) -> bool {
+        self.off
+    }
+    /// Sets the brightness right away, for when nothing else manages it
+    pub fn set_brightness(&mut self, brightness: u32) {
+        self.current_bl = min(brightness, self.max_bl);
+        self.fade = None;
+        self.set_level(self.current_bl);
+    }
+    /// Whether the bar is dimmed or off for not being used, so that a touch
+    /// only wakes it up
+    pub fn is_idle(&self) -> bool {
+        self.idle
     }
     pub fn current_bl(&self) -> u32 {
         self.current_bl
@@ -0,0 +1,144 @@
+use crate::keycodes;
+use input_linux::Key;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+// Turning the whole bar off, for those who want the strip dead except when
+// they ask for it: the backlight goes out and touches do nothing, or with
+// Touches = "FnKeys" they are passed through as keys at fixed places, the
+// bar being split evenly between Keys (F1 to F12 by default), without
+// anything drawn.
+//
+// It is turned off and on by ToggleBar (see [Signals] and [Shortcuts]),
+// SetBarEnabled over IPC, or by holding Fingers fingers on the bar for
+// HoldMs, which works whether it is on or off.
+
+const DEFAULT_HOLD_MS: u64 = 1000;
+const DEFAULT_KEYS: [Key; 12] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+];
+
+/// What touches do while the bar is off
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+pub enum OffTouches {
+    #[default]
+    Ignore,
+    FnKeys,
+}
+
+/// The `[BarOff]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct BarOffConfig {
+    pub touches: Option<OffTouches>,
+    /// Key names for FnKeys, from left to right
+    pub keys: Option<Vec<String>>,
+    /// How many fingers held on the bar turn it off or on, if any
+    pub fingers: Option<usize>,
+    pub hold_ms: Option<u64>,
+}
+
+impl BarOffConfig {
+    /// Warns about keys that cannot be passed through
+    pub fn check(&self) {
+        for name in self.keys.iter().flatten() {
+            if keycodes::parse_key(name).is_none() {
+                eprintln!("BarOff: ignoring the unknown key {name}");
+            }
+        }
+    }
+    /// The keys touches are passed through as while the bar is off, if they are
+    pub fn passthrough_keys(&self) -> Vec<Key> {
+        if self.touches != Some(OffTouches::FnKeys) {
+            return Vec::new();
+        }
+        match &self.keys {
+            Some(names) => names.iter().filter_map(|name| keycodes::parse_key(name)).collect(),
+            None => DEFAULT_KEYS.to_vec(),
+        }
+    }
+}
+
+/// The keys held down by fingers on the bar while it is off
+#[derive(Default)]
+pub struct Passthrough(HashMap<u32, Key>);
+
+impl Passthrough {
+    /// Takes a finger going down at `x`, from 0 at the left end to 1 at the
+    /// right one, returning the key to press
+    pub fn press(&mut self, slot: u32, x: f64, keys: &[Key]) -> Option<Key> {
+        if keys.is_empty() {
+            return None;
+        }
+        let index = ((x * keys.len() as f64) as usize).min(keys.len() - 1);
+        self.0.insert(slot, keys[index]);
+        Some(keys[index])
+    }
+    /// Takes a finger going up, returning the key to let go of
+    pub fn release(&mut self, slot: u32) -> Option<Key> {
+        self.0.remove(&slot)
+    }
+    /// Takes every finger away, returning the keys to let go of
+    pub fn release_all(&mut self) -> Vec<Key> {
+        self.0.drain().map(|(_, key)| key).collect()
+    }
+}
+
+/// Notices Fingers fingers held on the bar for HoldMs
+#[derive(Default)]
+pub struct Gesture {
+    fingers: HashSet<u32>,
+    since: Option<Instant>,
+    /// Done with the fingers still down, which do it again only once lifted
+    done: bool,
+}
+
+impl Gesture {
+    pub fn touch(&mut self, cfg: &BarOffConfig, slot: u32, down: bool) {
+        if down {
+            self.fingers.insert(slot);
+        } else {
+            self.fingers.remove(&slot);
+        }
+        let Some(wanted) = cfg.fingers.filter(|n| *n > 0) else {
+            return;
+        };
+        if self.fingers.len() != wanted {
+            self.since = None;
+        } else if self.since.is_none() && !self.done {
+            self.since = Some(Instant::now());
+        }
+        if self.fingers.is_empty() {
+            self.done = false;
+        }
+    }
+    /// Returns whether the bar is to be turned off or on, and the number of
+    /// milliseconds until then
+    pub fn check(&mut self, cfg: &BarOffConfig) -> (bool, i32) {
+        let Some(since) = self.since else {
+            return (false, i32::MAX);
+        };
+        let hold = Duration::from_millis(cfg.hold_ms.unwrap_or(DEFAULT_HOLD_MS));
+        let held = since.elapsed();
+        if held < hold {
+            return (false, (hold - held).as_millis() as i32 + 1);
+        }
+        self.since = None;
+        self.done = true;
+        (true, i32::MAX)
+    }
+}
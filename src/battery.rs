@@ -0,0 +1,350 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::json::Value;
+use crate::powersource::PowerSource;
+use crate::source;
+use crate::text;
+use crate::widget::{Canvas, Widget};
+use crate::{try_load_image, ButtonImage, ICON_SIZE};
+use anyhow::{anyhow, Result};
+use chrono::Locale;
+use librsvg_rebind::Handle;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+// The Battery widget, with the charge as an icon, in percent or both, and
+// the readings of the machine's own battery from /sys/class/power_supply
+// that it and the power policy go by.
+
+const REFRESH_INTERVAL_MS: u64 = 10 * 1000;
+const MIN_REFRESH_INTERVAL_MS: u64 = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BatteryState {
+    NotCharging,
+    Charging,
+    Low,
+}
+
+/// The machine's own battery, leaving out those of mice, keyboards and the
+/// like, which have a scope of Device
+pub fn find_battery_device() -> Option<String> {
+    let power_supply_path = "/sys/class/power_supply";
+    if let Ok(entries) = fs::read_dir(power_supply_path) {
+        for entry in entries.flatten() {
+            let dev_path = entry.path();
+            let type_path = dev_path.join("type");
+            let scope = fs::read_to_string(dev_path.join("scope")).unwrap_or_default();
+            if let Ok(typ) = fs::read_to_string(&type_path) {
+                if typ.trim() == "Battery" && scope.trim() != "Device" {
+                    if let Some(name) = dev_path.file_name().and_then(|n| n.to_str()) {
+                        return Some(name.to_string());
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+pub fn get_battery_state(battery: &str) -> (u32, BatteryState) {
+    let status_path = format!("/sys/class/power_supply/{}/status", battery);
+    let status = fs::read_to_string(&status_path)
+        .unwrap_or_else(|_| "Unknown".to_string());
+
+    #[cfg(target_arch = "x86_64")]
+    let capacity = {
+        let charge_now_path = format!("/sys/class/power_supply/{}/charge_now", battery);
+        let charge_full_path = format!("/sys/class/power_supply/{}/charge_full", battery);
+        let charge_now = fs::read_to_string(&charge_now_path)
+            .ok()
+            .and_then(|s| s.trim().parse::<f64>().ok());
+        let charge_full = fs::read_to_string(&charge_full_path)
+            .ok()
+            .and_then(|s| s.trim().parse::<f64>().ok());
+        match (charge_now, charge_full) {
+            (Some(now), Some(full)) if full > 0.0 => ((now / full) * 100.0).round() as u32,
+            _ => 100,
+        }
+    };
+
+    #[cfg(target_arch = "aarch64")]
+    let capacity = {
+        let capacity_path = format!("/sys/class/power_supply/{}/capacity", battery);
+        fs::read_to_string(&capacity_path)
+            .ok()
+            .and_then(|s| s.trim().parse::<u32>().ok())
+            .unwrap_or(100)
+    };
+
+    let status = match status.trim() {
+        "Charging" | "Full" => BatteryState::Charging,
+        "Discharging" if capacity < 10 => BatteryState::Low,
+        _ => BatteryState::NotCharging,
+    };
+    (capacity, status)
+}
+
+fn read_battery_value(battery: &str, name: &str) -> Option<f64> {
+    let path = format!("/sys/class/power_supply/{}/{}", battery, name);
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Returns how many watts the battery is charged or discharged with, and
+/// the seconds until it is full or empty. Batteries report either energy
+/// (µWh, µW) or charge (µAh, µA), and some know the time left themselves.
+pub fn get_battery_power(battery: &str) -> (Option<f64>, Option<u64>) {
+    let value = |name| read_battery_value(battery, name);
+    let status = fs::read_to_string(format!("/sys/class/power_supply/{}/status", battery))
+        .unwrap_or_default();
+    let charging = status.trim() == "Charging";
+    let watts = value("power_now")
+        .or_else(|| Some(value("current_now")? * value("voltage_now")? / 1e6))
+        .map(|w| w.abs() / 1e6)
+        .filter(|w| *w > 0.0);
+    let reported_time = if charging {
+        value("time_to_full_now")
+    } else {
+        value("time_to_empty_now")
+    };
+    let (now, full, rate) = match (value("energy_now"), value("energy_full")) {
+        (Some(now), Some(full)) => (now, full, value("power_now")),
+        _ => (
+            value("charge_now").unwrap_or(0.0),
+            value("charge_full").unwrap_or(0.0),
+            value("current_now"),
+        ),
+    };
+    let left = if charging { full - now } else { now };
+    let time = reported_time
+        .or_else(|| {
+            let rate = rate?.abs();
+            (rate > 0.0 && status.trim() != "Full").then(|| left.max(0.0) / rate * 3600.0)
+        })
+        .map(|secs| secs as u64);
+    (watts, time)
+}
+
+struct BatteryImages {
+    plain: Vec<Handle>,
+    charging: Vec<Handle>,
+    bolt: Handle,
+}
+
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum BatteryIconMode {
+    Percentage,
+    Icon,
+    Both
+}
+
+impl BatteryIconMode {
+    fn should_draw_icon(self) -> bool {
+        self != BatteryIconMode::Percentage
+    }
+    fn should_draw_text(self) -> bool {
+        self != BatteryIconMode::Icon
+    }
+}
+
+fn load_battery_image(icon: &str, theme: Option<&str>) -> Result<Handle> {
+    match try_load_image(icon, theme)? {
+        ButtonImage::Svg(svg) => Ok(svg),
+        _ => Err(anyhow!("{icon} is not an SVG")),
+    }
+}
+
+/// What the text of a battery button shows, cycled through by tapping it
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BatteryReadout {
+    Percentage,
+    Time,
+    Watts,
+}
+
+/// The `Battery` key of a button, or just its mode
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BatteryConfig {
+    pub mode: String,
+    pub readouts: Option<Vec<BatteryReadout>>,
+    /// Which battery is shown, the machine's own by default
+    pub source: Option<String>,
+    pub locale: Option<String>,
+}
+
+/// A battery, with the readouts it cycles through when tapped, the one
+/// shown, and when to read the battery again
+pub struct Battery {
+    /// `None` if there is no such battery
+    source: Option<PowerSource>,
+    mode: BatteryIconMode,
+    icons: BatteryImages,
+    readouts: Vec<BatteryReadout>,
+    current: usize,
+    interval: Duration,
+    next_refresh: Instant,
+    /// For the decimal point of watts
+    locale: Locale,
+}
+
+impl Battery {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Widget>> {
+        let battery: BatteryConfig = source::parse_short(value, "Mode")?;
+        let mode = match battery.mode.as_str() {
+            "icon" => BatteryIconMode::Icon,
+            "percentage" => BatteryIconMode::Percentage,
+            "both" => BatteryIconMode::Both,
+            _ => return Err(anyhow!("invalid battery mode, accepted modes: icon, percentage, both")),
+        };
+        let theme = cfg.theme.as_deref();
+        let mut plain = Vec::new();
+        let mut charging = Vec::new();
+        for icon in [
+            "battery_0_bar", "battery_1_bar", "battery_2_bar", "battery_3_bar",
+            "battery_4_bar", "battery_5_bar", "battery_6_bar", "battery_full",
+        ] {
+            plain.push(load_battery_image(icon, theme)?);
+        }
+        for icon in [
+            "battery_charging_20", "battery_charging_30", "battery_charging_50",
+            "battery_charging_60", "battery_charging_80",
+            "battery_charging_90", "battery_charging_full",
+        ] {
+            charging.push(load_battery_image(icon, theme)?);
+        }
+        let bolt = load_battery_image("bolt", theme)?;
+        let interval = cfg
+            .refresh_interval_ms
+            .unwrap_or(REFRESH_INTERVAL_MS)
+            .max(MIN_REFRESH_INTERVAL_MS);
+        let interval = Duration::from_millis(interval);
+        Ok(Box::new(Battery {
+            source: PowerSource::new(battery.source.as_deref(), interval),
+            mode,
+            icons: BatteryImages { plain, charging, bolt },
+            readouts: battery
+                .readouts
+                .filter(|r| !r.is_empty())
+                .unwrap_or(vec![BatteryReadout::Percentage]),
+            current: 0,
+            interval,
+            next_refresh: Instant::now(),
+            locale: i18n::resolve(battery.locale.as_deref()),
+        }))
+    }
+}
+
+impl Widget for Battery {
+    fn name(&self) -> &'static str {
+        "Battery"
+    }
+    fn init(&mut self) {
+        if let Some(source) = &mut self.source {
+            source.start();
+        }
+    }
+    fn update(&mut self, power_supply_changed: bool) -> (bool, i32) {
+        let now = Instant::now();
+        let changed = power_supply_changed || now >= self.next_refresh;
+        if changed {
+            self.next_refresh = source::next_refresh(self.interval);
+        }
+        (changed, (self.next_refresh - now).as_millis() as i32)
+    }
+    fn draw(&self, canvas: &Canvas) {
+        let Some(source) = &self.source else {
+            canvas.centered_text(&i18n::tr("Battery N/A"));
+            return;
+        };
+        let Some((capacity, state)) = source.state() else {
+            // not read yet, or gone
+            canvas.centered_text(&i18n::tr("N/A"));
+            return;
+        };
+        let icons = &self.icons;
+        let icon = if self.mode.should_draw_icon() {
+            Some(match state {
+                BatteryState::Charging => match capacity {
+                    0..=20 => &icons.charging[0],
+                    21..=30 => &icons.charging[1],
+                    31..=50 => &icons.charging[2],
+                    51..=60 => &icons.charging[3],
+                    61..=80 => &icons.charging[4],
+                    81..=99 => &icons.charging[5],
+                    _ => &icons.charging[6],
+                },
+                _ => match capacity {
+                    0 => &icons.plain[0],
+                    1..=20 => &icons.plain[1],
+                    21..=30 => &icons.plain[2],
+                    31..=50 => &icons.plain[3],
+                    51..=60 => &icons.plain[4],
+                    61..=80 => &icons.plain[5],
+                    81..=99 => &icons.plain[6],
+                    _ => &icons.plain[7],
+                },
+            })
+        } else if state == BatteryState::Charging {
+            Some(&icons.bolt)
+        } else {
+            None
+        };
+        let percent_str = match self.readouts[self.current] {
+            BatteryReadout::Percentage => format!("{:.0}%", capacity),
+            BatteryReadout::Time => match source.power().1 {
+                Some(secs) => format!("{}:{:02}", secs / 3600, secs / 60 % 60),
+                None => "-:--".to_string(),
+            },
+            BatteryReadout::Watts => match source.power().0 {
+                Some(watts) => format!("{}W", i18n::decimal(watts, 1, self.locale)),
+                None => "-W".to_string(),
+            },
+        };
+        let c = canvas.c;
+        let layout = text::layout(c, canvas.font, &percent_str, canvas.text_direction);
+        let (text_width, text_height) = text::ink_size(&layout);
+        let mut width = text_width;
+        let mut text_offset = 0;
+        if let Some(svg) = icon {
+            if !self.mode.should_draw_text() {
+                width = ICON_SIZE as f64;
+            } else {
+                width += ICON_SIZE as f64;
+            }
+            text_offset = ICON_SIZE;
+            let x = canvas.left + (canvas.width / 2.0 - width / 2.0).round();
+            let y = canvas.y_shift + ((canvas.height - ICON_SIZE as f64) / 2.0).round();
+            canvas.icon(svg, x, y);
+        }
+        if self.mode.should_draw_text() {
+            canvas.set_text_color();
+            c.move_to(
+                canvas.left + (canvas.width / 2.0 - width / 2.0 + text_offset as f64).round(),
+                canvas.y_shift + (canvas.height / 2.0 + text_height / 2.0).round(),
+            );
+            text::show(c, &layout);
+        }
+    }
+    fn on_touch(&mut self, pressed: bool) {
+        if pressed {
+            self.current = (self.current + 1) % self.readouts.len();
+        }
+    }
+    fn state(&self, fields: &mut Vec<(String, Value)>) -> (&'static str, Value) {
+        let Some((capacity, state)) = self.source.as_ref().and_then(PowerSource::state) else {
+            return ("battery", Value::Null);
+        };
+        let status = match state {
+            BatteryState::Charging => "charging",
+            BatteryState::Low => "low",
+            BatteryState::NotCharging => "discharging",
+        };
+        fields.push(("status".to_string(), status.into()));
+        ("battery", capacity.into())
+    }
+}
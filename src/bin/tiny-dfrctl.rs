@@ -0,0 +1,187 @@
+use anyhow::{anyhow, Result};
+use std::{
+    env,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    process::ExitCode,
+};
+
+#[allow(dead_code)]
+#[path = "../json.rs"]
+mod json;
+
+#[path = "../paths.rs"]
+mod paths;
+
+use json::Value;
+use paths::SOCKET_PATH;
+
+const USAGE: &str = "Usage:
+    tiny-dfrctl state
+    tiny-dfrctl monitor
+    tiny-dfrctl layer [primary|media]
+    tiny-dfrctl brightness [0-255|auto]
+    tiny-dfrctl profile <name|none>
+    tiny-dfrctl toast <text> [ms] [icon]
+    tiny-dfrctl focus <on|off|auto>
+    tiny-dfrctl privacy <on|off|auto>
+    tiny-dfrctl bar <on|off>
+    tiny-dfrctl charge-limit <1-100>
+    tiny-dfrctl edit <on|off>
+    tiny-dfrctl settings
+    tiny-dfrctl set <setting> <value>
+    tiny-dfrctl button set-text <id> <text>
+    tiny-dfrctl button set-icon <id> <path>
+    tiny-dfrctl button flash <id> <color> <ms>
+    tiny-dfrctl button progress <id> <0.0-1.0|clear>
+    tiny-dfrctl button <enable|disable> <id>";
+
+fn param(name: &str, value: impl Into<Value>) -> (String, Value) {
+    (name.to_string(), value.into())
+}
+
+fn parse_args(args: &[String]) -> Option<(&'static str, Vec<(String, Value)>)> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    Some(match args.as_slice() {
+        ["state"] => ("GetState", vec![]),
+        ["monitor"] => ("Subscribe", vec![]),
+        ["layer"] => ("GetLayer", vec![]),
+        ["layer", layer] => ("SetLayer", vec![param("layer", *layer)]),
+        ["brightness"] => ("GetBrightness", vec![]),
+        ["brightness", "auto"] => ("SetBrightness", vec![("brightness".into(), Value::Null)]),
+        ["brightness", value] => (
+            "SetBrightness",
+            vec![param("brightness", value.parse::<u32>().ok()?)],
+        ),
+        ["toast", text] => ("ShowToast", vec![param("text", *text)]),
+        ["toast", text, ms] => (
+            "ShowToast",
+            vec![param("text", *text), param("timeout_ms", ms.parse::<u32>().ok()?)],
+        ),
+        ["toast", text, ms, icon] => {
+            // names are looked up like button icons, anything else is a path
+            let icon = if icon.contains('/') {
+                std::path::absolute(icon).ok()?.to_str()?.to_string()
+            } else {
+                icon.to_string()
+            };
+            (
+                "ShowToast",
+                vec![
+                    param("text", *text),
+                    param("timeout_ms", ms.parse::<u32>().ok()?),
+                    param("icon", icon),
+                ],
+            )
+        }
+        ["profile", "none"] => ("SwitchProfile", vec![("name".into(), Value::Null)]),
+        ["profile", name] => ("SwitchProfile", vec![param("name", *name)]),
+        ["settings"] => ("GetAllSettings", vec![]),
+        ["set", key, value] => ("SetSetting", vec![param("key", *key), param("value", *value)]),
+        ["edit", "on"] => ("SetEditMode", vec![param("enabled", true)]),
+        ["edit", "off"] => ("SetEditMode", vec![param("enabled", false)]),
+        ["focus", "on"] => ("SetFocusMode", vec![param("enabled", true)]),
+        ["focus", "off"] => ("SetFocusMode", vec![param("enabled", false)]),
+        ["focus", "auto"] => ("SetFocusMode", vec![("enabled".into(), Value::Null)]),
+        ["privacy", "on"] => ("SetPrivacyMode", vec![param("enabled", true)]),
+        ["privacy", "off"] => ("SetPrivacyMode", vec![param("enabled", false)]),
+        ["privacy", "auto"] => ("SetPrivacyMode", vec![("enabled".into(), Value::Null)]),
+        ["bar", "on"] => ("SetBarEnabled", vec![param("enabled", true)]),
+        ["bar", "off"] => ("SetBarEnabled", vec![param("enabled", false)]),
+        ["charge-limit", limit] => ("SetChargeLimit", vec![param("limit", limit.parse::<u32>().ok()?)]),
+        ["button", "set-text", id, text] => (
+            "SetButtonText",
+            vec![param("id", *id), param("text", *text)],
+        ),
+        ["button", "set-icon", id, path] => {
+            // the daemon resolves the path in its own working directory
+            let path = std::path::absolute(path).ok()?;
+            (
+                "SetButtonIcon",
+                vec![param("id", *id), param("path", path.to_str()?)],
+            )
+        }
+        ["button", "flash", id, color, ms] => (
+            "FlashButton",
+            vec![
+                param("id", *id),
+                param("color", *color),
+                param("duration_ms", ms.parse::<u32>().ok()?),
+            ],
+        ),
+        ["button", "progress", id, "clear"] => (
+            "SetProgress",
+            vec![param("id", *id), ("fraction".into(), Value::Number(-1.0))],
+        ),
+        ["button", "progress", id, fraction] => (
+            "SetProgress",
+            vec![
+                param("id", *id),
+                ("fraction".into(), Value::Number(fraction.parse().ok()?)),
+            ],
+        ),
+        ["button", "enable", id] => (
+            "SetButtonEnabled",
+            vec![param("id", *id), param("enabled", true)],
+        ),
+        ["button", "disable", id] => (
+            "SetButtonEnabled",
+            vec![param("id", *id), param("enabled", false)],
+        ),
+        _ => return None,
+    })
+}
+
+fn check_error(response: &Value) -> Result<()> {
+    if let Some(error) = response.get("error") {
+        let message = error.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+        return Err(anyhow!("{message}"));
+    }
+    Ok(())
+}
+
+fn call(method: &str, params: Vec<(String, Value)>) -> Result<Value> {
+    let mut stream = UnixStream::connect(SOCKET_PATH)
+        .map_err(|e| anyhow!("failed to connect to {SOCKET_PATH}: {e}"))?;
+    let request = Value::Object(vec![
+        param("jsonrpc", "2.0"),
+        ("id".into(), Value::Number(1.0)),
+        param("method", method),
+        ("params".into(), Value::Object(params)),
+    ]);
+    writeln!(stream, "{request}")?;
+    let mut lines = BufReader::new(stream).lines();
+    let response = Value::parse(&lines.next().ok_or(anyhow!("no response"))??)?;
+    check_error(&response)?;
+    if method == "Subscribe" {
+        // print every event as a line of JSON until the daemon goes away
+        for line in lines {
+            let event = Value::parse(&line?)?;
+            println!("{}", event.get("params").unwrap_or(&Value::Null));
+        }
+    }
+    Ok(response.get("result").cloned().unwrap_or(Value::Null))
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((method, params)) = parse_args(&args) else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    match call(method, params) {
+        Ok(Value::Null) => ExitCode::SUCCESS,
+        Ok(Value::String(s)) => {
+            println!("{s}");
+            ExitCode::SUCCESS
+        }
+        Ok(value) => {
+            println!("{value}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("tiny-dfrctl: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::source::{self, Background, Job, Source, Tint, Update};
+use anyhow::{anyhow, Result};
+use gio::{BusType, Cancellable, DBusCallFlags, DBusConnection};
+use glib::{Variant, VariantTy};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+
+// Battery levels of connected Bluetooth devices, like mice and headphones,
+// as BlueZ reports them with its Battery1 interface. Devices only show up
+// there while connected and if they report a level at all, which most
+// headsets only do with BlueZ's experimental features turned on.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 5000;
+const DBUS_TIMEOUT_MS: i32 = 1000;
+const DEFAULT_LOW: u8 = 20;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+pub enum BluetoothShow {
+    /// One device after the other, going on with each refresh and tap
+    #[default]
+    Cycle,
+    /// The device with the least charge left
+    Lowest,
+}
+
+/// The `BluetoothBattery` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BluetoothBatteryConfig {
+    pub show: Option<BluetoothShow>,
+    /// Only devices whose name contains one of these, all when not set
+    pub devices: Option<Vec<String>>,
+    /// The percentage below which the button turns red
+    pub low: Option<u8>,
+}
+
+struct Device {
+    name: String,
+    percentage: u8,
+}
+
+pub struct BluetoothBattery {
+    cfg: BluetoothBatteryConfig,
+    connection: Option<DBusConnection>,
+    /// The device shown last when cycling, by name, so that the order
+    /// holds up while devices come and go
+    current: Option<String>,
+}
+
+impl BluetoothBattery {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let battery = BluetoothBattery::new(source::parse(value)?);
+        Ok(Box::new(Background::new(battery, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS))))
+    }
+    pub fn new(cfg: BluetoothBatteryConfig) -> BluetoothBattery {
+        BluetoothBattery {
+            cfg,
+            connection: None,
+            current: None,
+        }
+    }
+    fn managed_objects(&mut self) -> Result<Variant> {
+        if self.connection.is_none() {
+            self.connection = Some(gio::bus_get_sync(BusType::System, Cancellable::NONE)?);
+        }
+        let res = self.connection.as_ref().unwrap().call_sync(
+            Some("org.bluez"),
+            "/",
+            "org.freedesktop.DBus.ObjectManager",
+            "GetManagedObjects",
+            None,
+            Some(VariantTy::new("(a{oa{sa{sv}}})").unwrap()),
+            DBusCallFlags::NONE,
+            DBUS_TIMEOUT_MS,
+            Cancellable::NONE,
+        );
+        if res.is_err() {
+            // connect again next time, in case the bus was restarted
+            self.connection = None;
+        }
+        Ok(res?.child_value(0))
+    }
+    /// The connected devices with a battery level, sorted by name
+    fn devices(&mut self) -> Result<Vec<Device>> {
+        let objects = self.managed_objects()?;
+        let mut devices = Vec::new();
+        for object in objects.iter() {
+            let interfaces = object
+                .child_value(1)
+                .get::<HashMap<String, HashMap<String, Variant>>>()
+                .ok_or(anyhow!("unexpected reply from BlueZ"))?;
+            let (Some(device), Some(battery)) =
+                (interfaces.get("org.bluez.Device1"), interfaces.get("org.bluez.Battery1"))
+            else {
+                continue;
+            };
+            let connected = device.get("Connected").and_then(Variant::get::<bool>);
+            let name = device
+                .get("Alias")
+                .or_else(|| device.get("Name"))
+                .and_then(Variant::get::<String>);
+            let percentage = battery.get("Percentage").and_then(Variant::get::<u8>);
+            let (Some(true), Some(name), Some(percentage)) = (connected, name, percentage) else {
+                continue;
+            };
+            let wanted = self.cfg.devices.as_ref().is_none_or(|devices| {
+                devices.iter().any(|wanted| name.contains(wanted.as_str()))
+            });
+            if wanted {
+                devices.push(Device { name, percentage });
+            }
+        }
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices)
+    }
+    /// The device to show, moving on to the next one when cycling
+    fn pick(&mut self, devices: Vec<Device>) -> Option<Device> {
+        match self.cfg.show.unwrap_or_default() {
+            BluetoothShow::Lowest => devices.into_iter().min_by_key(|d| d.percentage),
+            BluetoothShow::Cycle => {
+                let next = match &self.current {
+                    Some(current) => devices
+                        .iter()
+                        .position(|d| d.name > *current)
+                        .unwrap_or(0),
+                    None => 0,
+                };
+                let device = devices.into_iter().nth(next)?;
+                self.current = Some(device.name.clone());
+                Some(device)
+            }
+        }
+    }
+}
+
+impl Job for BluetoothBattery {
+    const MIN_INTERVAL: Duration = Duration::from_millis(1000);
+
+    fn refresh(&mut self) -> Vec<Update> {
+        let device = match self.devices() {
+            Ok(devices) => self.pick(devices),
+            Err(e) => {
+                eprintln!("Failed to get Bluetooth devices: {e:#}");
+                None
+            }
+        };
+        let Some(device) = device else {
+            return vec![Update::Text(i18n::tr("N/A")), Update::Tint(None)];
+        };
+        let low = device.percentage < self.cfg.low.unwrap_or(DEFAULT_LOW);
+        vec![
+            Update::Text(format!("{} {}%", device.name, device.percentage)),
+            Update::Tint(low.then_some(Tint::Red)),
+        ]
+    }
+    fn touch(&mut self, pressed: bool) -> bool {
+        // a tap goes on to the next device right away when cycling
+        pressed && matches!(self.cfg.show.unwrap_or_default(), BluetoothShow::Cycle)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::audit;
+use crate::config::ButtonConfig;
+use crate::source::{self, Background, Job, Source, Update};
+use anyhow::{anyhow, Result};
+use gio::{prelude::*, BusType, Cancellable, DBusCallFlags, UnixFDList};
+use glib::{variant::Handle, VariantTy};
+use serde::{Deserialize, Serialize};
+use std::{
+    os::fd::{FromRawFd, OwnedFd},
+    time::{Duration, Instant},
+};
+
+// Keeps the machine awake while turned on, by holding a logind inhibitor
+// lock, which lasts for as long as the file descriptor logind hands out is
+// open. The cup steams while it is held, and the minutes left are shown in
+// the corner when it lets go by itself after TimeoutMin.
+//
+// logind asks polkit whether the daemon's user may take the lock, which
+// allows it by default only for users in an active session, so a rule
+// for it may be needed.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 10 * 1000;
+const DEFAULT_WHAT: &str = "idle:sleep";
+const DBUS_TIMEOUT_MS: i32 = 5000;
+
+/// The `Caffeine` key of a button
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct CaffeineConfig {
+    /// What is inhibited, like logind takes it, e.g. "idle:sleep:shutdown"
+    pub what: Option<String>,
+    pub timeout_min: Option<u64>,
+}
+
+fn inhibit(what: &str) -> Result<OwnedFd> {
+    let connection = gio::bus_get_sync(BusType::System, Cancellable::NONE)?;
+    let (reply, fds) = connection.call_with_unix_fd_list_sync(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+        "Inhibit",
+        Some(&(what, "tiny-dfr", "Caffeine button", "block").to_variant()),
+        Some(VariantTy::new("(h)").unwrap()),
+        DBusCallFlags::NONE,
+        DBUS_TIMEOUT_MS,
+        None::<&UnixFDList>,
+        Cancellable::NONE,
+    )?;
+    let Handle(index) = reply.child_value(0).get().ok_or(anyhow!("unexpected reply from logind"))?;
+    let fd = fds.ok_or(anyhow!("logind sent no lock"))?.get(index)?;
+    // a duplicate of the one in the list, which closes its own
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+pub struct Caffeine {
+    what: String,
+    timeout: Option<Duration>,
+    /// The lock while it is held, and when it lets go by itself
+    lock: Option<(OwnedFd, Option<Instant>)>,
+    /// Whether the cup steams, so that the icon is only loaded on changes
+    shown: Option<bool>,
+}
+
+impl Caffeine {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let caffeine = Caffeine::new(source::parse(value)?);
+        Ok(Box::new(Background::new(caffeine, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS))))
+    }
+    pub fn new(cfg: CaffeineConfig) -> Caffeine {
+        Caffeine {
+            what: cfg.what.unwrap_or(DEFAULT_WHAT.to_string()),
+            timeout: cfg.timeout_min.map(|min| Duration::from_secs(min * 60)),
+            lock: None,
+            shown: None,
+        }
+    }
+}
+
+impl Job for Caffeine {
+    fn refresh(&mut self) -> Vec<Update> {
+        if self.lock.as_ref().is_some_and(|(_, until)| until.is_some_and(|until| Instant::now() >= until)) {
+            self.lock = None;
+        }
+        let minutes_left = self
+            .lock
+            .as_ref()
+            .and_then(|(_, until)| *until)
+            .map(|until| until.saturating_duration_since(Instant::now()).as_secs().div_ceil(60));
+        let on = self.lock.is_some();
+        let mut updates = vec![Update::Badge(minutes_left.map(|min| min.to_string()))];
+        if self.shown != Some(on) {
+            self.shown = Some(on);
+            updates.push(Update::Icon(if on { "coffee" } else { "coffee_cold" }.to_string()));
+            updates.push(Update::Highlight(on));
+        }
+        updates
+    }
+    fn touch(&mut self, pressed: bool) -> bool {
+        if !pressed {
+            return false;
+        }
+        if self.lock.take().is_some() {
+            // closing the descriptor lets go of it
+            return true;
+        }
+        match inhibit(&self.what) {
+            Ok(fd) => {
+                audit::record("Took an inhibitor lock", Some(&self.what));
+                self.lock = Some((fd, self.timeout.map(|timeout| Instant::now() + timeout)));
+            }
+            Err(e) => eprintln!("Caffeine: cannot keep the machine awake: {e:#}"),
+        }
+        true
+    }
+}
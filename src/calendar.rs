@@ -0,0 +1,333 @@
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+// Dates in other calendars, shown by Time buttons next to the Gregorian one.
+// Days are counted as in Calendrical Calculations (Reingold & Dershowitz),
+// from January 1st of year 1, which is also what chrono's num_days_from_ce
+// gives, and converted from there:
+//
+// - Hijri is the arithmetic (tabular) Islamic calendar, which may be a day
+//   off from the one set by sighting the moon
+// - Hebrew is the fixed calendar of Hillel II, exact
+// - Chinese follows the new moons and solar terms in Beijing, computed with
+//   Meeus' formulas, good to some minutes
+//
+// Days change at midnight, not at sunset as in the Hijri and Hebrew ones.
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum Calendar {
+    Hijri,
+    Hebrew,
+    Chinese,
+}
+
+impl Calendar {
+    /// The date of `date` in this calendar, like "12 Rabiʻ II 1448"
+    pub fn format(self, date: NaiveDate) -> String {
+        let fixed = date.num_days_from_ce() as i64;
+        match self {
+            Calendar::Hijri => {
+                let (year, month, day) = hijri_from_fixed(fixed);
+                format!("{day} {} {year}", HIJRI_MONTHS[month as usize - 1])
+            }
+            Calendar::Hebrew => {
+                let (year, month, day) = hebrew_from_fixed(fixed);
+                let name = match month {
+                    12 if hebrew_leap_year(year) => "Adar I",
+                    month => HEBREW_MONTHS[month as usize - 1],
+                };
+                format!("{day} {name} {year}")
+            }
+            Calendar::Chinese => {
+                let (month, leap, day) = chinese_from_fixed(fixed);
+                format!(
+                    "{}{}月{}",
+                    if leap { "闰" } else { "" },
+                    CHINESE_MONTHS[month as usize - 1],
+                    chinese_day(day)
+                )
+            }
+        }
+    }
+}
+
+const HIJRI_MONTHS: [&str; 12] = [
+    "Muharram", "Safar", "Rabiʻ I", "Rabiʻ II", "Jumada I", "Jumada II",
+    "Rajab", "Shaʻban", "Ramadan", "Shawwal", "Dhuʻl-Qaʻdah", "Dhuʻl-Hijjah",
+];
+/// From Nisan, as months are numbered, though years start with Tishrei
+const HEBREW_MONTHS: [&str; 13] = [
+    "Nisan", "Iyar", "Sivan", "Tammuz", "Av", "Elul", "Tishrei",
+    "Cheshvan", "Kislev", "Tevet", "Shevat", "Adar", "Adar II",
+];
+const CHINESE_MONTHS: [&str; 12] = [
+    "正", "二", "三", "四", "五", "六", "七", "八", "九", "十", "冬", "腊",
+];
+const CHINESE_DIGITS: [&str; 10] = ["", "一", "二", "三", "四", "五", "六", "七", "八", "九"];
+
+/// Day 1 of Muharram of year 1, July 16th 622 in the Julian calendar
+const HIJRI_EPOCH: i64 = 227015;
+
+fn fixed_from_hijri(year: i64, month: i64, day: i64) -> i64 {
+    day + 29 * (month - 1) + (6 * month - 1).div_euclid(11) + (year - 1) * 354
+        + (3 + 11 * year).div_euclid(30)
+        + HIJRI_EPOCH
+        - 1
+}
+
+fn hijri_from_fixed(date: i64) -> (i64, i64, i64) {
+    let year = (30 * (date - HIJRI_EPOCH) + 10646).div_euclid(10631);
+    let prior_days = date - fixed_from_hijri(year, 1, 1);
+    let month = (11 * prior_days + 330).div_euclid(325);
+    let day = date - fixed_from_hijri(year, month, 1) + 1;
+    (year, month, day)
+}
+
+/// Day 1 of Tishrei of year 1, October 7th 3761 BCE in the Julian calendar
+const HEBREW_EPOCH: i64 = -1373427;
+
+fn hebrew_leap_year(year: i64) -> bool {
+    (7 * year + 1).rem_euclid(19) < 7
+}
+
+fn hebrew_last_month(year: i64) -> i64 {
+    if hebrew_leap_year(year) {
+        13
+    } else {
+        12
+    }
+}
+
+/// Days from the epoch to the molad of Tishrei, moved off Sunday,
+/// Wednesday and Friday
+fn hebrew_elapsed_days(year: i64) -> i64 {
+    let months = (235 * year - 234).div_euclid(19);
+    let parts = 12084 + 13753 * months;
+    let days = 29 * months + parts.div_euclid(25920);
+    if (3 * (days + 1)).rem_euclid(7) < 3 {
+        days + 1
+    } else {
+        days
+    }
+}
+
+/// Delays that keep years to their allowed lengths
+fn hebrew_year_delay(year: i64) -> i64 {
+    let (last, this, next) = (
+        hebrew_elapsed_days(year - 1),
+        hebrew_elapsed_days(year),
+        hebrew_elapsed_days(year + 1),
+    );
+    if next - this == 356 {
+        2
+    } else if this - last == 382 {
+        1
+    } else {
+        0
+    }
+}
+
+fn hebrew_new_year(year: i64) -> i64 {
+    HEBREW_EPOCH + hebrew_elapsed_days(year) + hebrew_year_delay(year)
+}
+
+fn hebrew_month_length(year: i64, month: i64) -> i64 {
+    let year_length = hebrew_new_year(year + 1) - hebrew_new_year(year);
+    let short = match month {
+        2 | 4 | 6 | 10 | 13 => true,
+        12 => !hebrew_leap_year(year),
+        // Cheshvan is long and Kislev short in some years
+        8 => year_length % 10 != 5,
+        9 => year_length % 10 == 3,
+        _ => false,
+    };
+    if short {
+        29
+    } else {
+        30
+    }
+}
+
+fn fixed_from_hebrew(year: i64, month: i64, day: i64) -> i64 {
+    let months_before: i64 = if month < 7 {
+        (7..=hebrew_last_month(year))
+            .chain(1..month)
+            .map(|m| hebrew_month_length(year, m))
+            .sum()
+    } else {
+        (7..month).map(|m| hebrew_month_length(year, m)).sum()
+    };
+    hebrew_new_year(year) + months_before + day - 1
+}
+
+fn hebrew_from_fixed(date: i64) -> (i64, i64, i64) {
+    // the average year is 35975351/98496 days
+    let approx = ((date - HEBREW_EPOCH) * 98496).div_euclid(35975351) + 1;
+    let year = if hebrew_new_year(approx) <= date {
+        approx
+    } else {
+        approx - 1
+    };
+    let first = if date < fixed_from_hebrew(year, 1, 1) { 7 } else { 1 };
+    let month = (first..)
+        .find(|&m| date <= fixed_from_hebrew(year, m, hebrew_month_length(year, m)))
+        .unwrap();
+    (year, month, date - fixed_from_hebrew(year, month, 1) + 1)
+}
+
+/// Julian day of the start of day 0, midnight in Greenwich
+const JD_OF_FIXED: f64 = 1721424.5;
+/// Beijing time, which the Chinese calendar is kept in
+const CHINA_OFFSET: f64 = 8.0 / 24.0;
+/// Terrestrial time, which the formulas give, ahead of universal time
+const DELTA_T: f64 = 69.0 / 86400.0;
+const MEAN_SYNODIC_MONTH: f64 = 29.530588861;
+
+fn sin_deg(degrees: f64) -> f64 {
+    (degrees * PI / 180.0).sin()
+}
+
+/// The day in Beijing of the moment `jd`, in universal time
+fn china_day(jd: f64) -> i64 {
+    (jd - JD_OF_FIXED + CHINA_OFFSET).floor() as i64
+}
+
+/// Midnight in Beijing at the start of `day`, in universal time
+fn china_midnight(day: i64) -> f64 {
+    day as f64 + JD_OF_FIXED - CHINA_OFFSET
+}
+
+/// The apparent longitude of the sun in degrees, Meeus chapter 25
+fn solar_longitude(jd: f64) -> f64 {
+    let t = (jd + DELTA_T - 2451545.0) / 36525.0;
+    let l0 = 280.46646 + 36000.76983 * t + 0.0003032 * t * t;
+    let m = 357.52911 + 35999.05029 * t - 0.0001537 * t * t;
+    let c = (1.914602 - 0.004817 * t - 0.000014 * t * t) * sin_deg(m)
+        + (0.019993 - 0.000101 * t) * sin_deg(2.0 * m)
+        + 0.000289 * sin_deg(3.0 * m);
+    let omega = 125.04 - 1934.136 * t;
+    (l0 + c - 0.00569 - 0.00478 * sin_deg(omega)).rem_euclid(360.0)
+}
+
+/// The moment the sun reaches `longitude` within five days of `around`
+fn solar_term(longitude: f64, around: f64) -> f64 {
+    let (mut low, mut high) = (around - 5.0, around + 5.0);
+    while high - low > 1e-5 {
+        let mid = (low + high) / 2.0;
+        if (solar_longitude(mid) - longitude + 540.0).rem_euclid(360.0) - 180.0 < 0.0 {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+/// The moment of new moon number `k`, counted from January 6th 2000,
+/// Meeus chapter 49
+fn new_moon(k: i64) -> f64 {
+    let k = k as f64;
+    let t = k / 1236.85;
+    let jde = 2451550.09766 + MEAN_SYNODIC_MONTH * k + 0.00015437 * t * t
+        - 0.00000015 * t * t * t;
+    let e = 1.0 - 0.002516 * t - 0.0000074 * t * t;
+    let m = 2.5534 + 29.1053567 * k - 0.0000014 * t * t;
+    let mm = 201.5643 + 385.81693528 * k + 0.0107582 * t * t;
+    let f = 160.7108 + 390.67050284 * k - 0.0016118 * t * t;
+    let omega = 124.7746 - 1.56375588 * k + 0.0020672 * t * t;
+    let correction = -0.4072 * sin_deg(mm) + 0.17241 * e * sin_deg(m)
+        + 0.01608 * sin_deg(2.0 * mm)
+        + 0.01039 * sin_deg(2.0 * f)
+        + 0.00739 * e * sin_deg(mm - m)
+        - 0.00514 * e * sin_deg(mm + m)
+        + 0.00208 * e * e * sin_deg(2.0 * m)
+        - 0.00111 * sin_deg(mm - 2.0 * f)
+        - 0.00057 * sin_deg(mm + 2.0 * f)
+        + 0.00056 * e * sin_deg(2.0 * mm + m)
+        - 0.00042 * sin_deg(3.0 * mm)
+        + 0.00042 * e * sin_deg(m + 2.0 * f)
+        + 0.00038 * e * sin_deg(m - 2.0 * f)
+        - 0.00024 * e * sin_deg(2.0 * mm - m)
+        - 0.00017 * sin_deg(omega)
+        - 0.00007 * sin_deg(mm + 2.0 * m)
+        + 0.00004 * sin_deg(2.0 * mm - 2.0 * f)
+        + 0.00004 * sin_deg(3.0 * m)
+        + 0.00003 * sin_deg(mm + m - 2.0 * f)
+        + 0.00003 * sin_deg(2.0 * mm + 2.0 * f)
+        - 0.00003 * sin_deg(mm + m + 2.0 * f)
+        + 0.00003 * sin_deg(mm - m + 2.0 * f)
+        - 0.00002 * sin_deg(mm - m - 2.0 * f)
+        - 0.00002 * sin_deg(3.0 * mm + m)
+        + 0.00002 * sin_deg(4.0 * mm);
+    jde + correction - DELTA_T
+}
+
+/// The number of the last new moon on or before `day` in Beijing
+fn new_moon_on_or_before(day: i64) -> i64 {
+    let mut k = ((china_midnight(day) - 2451550.09766) / MEAN_SYNODIC_MONTH).floor() as i64;
+    while china_day(new_moon(k + 1)) <= day {
+        k += 1;
+    }
+    while china_day(new_moon(k)) > day {
+        k -= 1;
+    }
+    k
+}
+
+/// The day of the winter solstice in December of `year`
+fn winter_solstice(year: i32) -> i64 {
+    let around = NaiveDate::from_ymd_opt(year, 12, 21).unwrap().num_days_from_ce() as i64;
+    china_day(solar_term(270.0, china_midnight(around)))
+}
+
+/// Whether the month from new moon `k` has no major solar term, one at a
+/// multiple of 30°, which makes it the leap month in a year of 13
+fn lacks_major_term(k: i64) -> bool {
+    let start = solar_longitude(china_midnight(china_day(new_moon(k))));
+    let end = solar_longitude(china_midnight(china_day(new_moon(k + 1))));
+    (start / 30.0).floor() == (end / 30.0).floor()
+}
+
+/// The month, whether it is a leap month, and the day
+fn chinese_from_fixed(date: i64) -> (i64, bool, i64) {
+    // the 11th month is the one with the winter solstice, and months are
+    // counted from the one before the date
+    let year = NaiveDate::from_num_days_from_ce_opt(date as i32).map_or(2000, |d| d.year());
+    let mut start = new_moon_on_or_before(winter_solstice(year - 1));
+    let mut end = new_moon_on_or_before(winter_solstice(year));
+    if date >= china_day(new_moon(end)) {
+        start = end;
+        end = new_moon_on_or_before(winter_solstice(year + 1));
+    }
+    let mut leap_due = end - start == 13;
+    let mut month = 11;
+    let mut leap = false;
+    let mut k = start;
+    loop {
+        if china_day(new_moon(k + 1)) > date {
+            return (month, leap, date - china_day(new_moon(k)) + 1);
+        }
+        k += 1;
+        if leap_due && lacks_major_term(k) {
+            leap_due = false;
+            leap = true;
+        } else {
+            leap = false;
+            month = month % 12 + 1;
+        }
+    }
+}
+
+/// Days are written 初一 to 初十, then 十一 to 二十, 廿一 to 廿九 and 三十
+fn chinese_day(day: i64) -> String {
+    let (tens, ones) = (day / 10, (day % 10) as usize);
+    match (tens, ones) {
+        (0, _) => format!("初{}", CHINESE_DIGITS[ones]),
+        (1, 0) => "初十".to_string(),
+        (1, _) => format!("十{}", CHINESE_DIGITS[ones]),
+        (2, 0) => "二十".to_string(),
+        (2, _) => format!("廿{}", CHINESE_DIGITS[ones]),
+        _ => "三十".to_string(),
+    }
+}
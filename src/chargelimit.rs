@@ -0,0 +1,146 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::ipc::Waker;
+use crate::source::{self, Source, Update};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    os::unix::fs::FileExt,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+// Shows and changes the charge limit of the battery, on machines whose
+// driver supports charge_control_end_threshold, like macsmc-power on
+// Apple Silicon. Only root may write it, so it is opened once before
+// privileges are dropped and shared by all charge limit buttons. Like the
+// brightness keys, changing it from the bar needs someone touching it.
+// Over IPC it is changed with SetChargeLimit, which has a polkit action of
+// its own rather than being let through with the other controls, see
+// src/auth.rs.
+
+const DEFAULT_LIMITS: &[u32] = &[80, 100];
+const DEFAULT_FORMAT: &str = "Max {}%";
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 5000;
+const MIN_REFRESH_INTERVAL_MS: u64 = 500;
+
+static THRESHOLD: OnceLock<Option<File>> = OnceLock::new();
+
+/// Opens the charge limit of the first battery that has one. Needs to be
+/// called before dropping privileges.
+pub fn open() {
+    THRESHOLD.get_or_init(|| {
+        let supplies = fs::read_dir("/sys/class/power_supply").ok()?;
+        supplies.flatten().find_map(|supply| {
+            let path = supply.path().join("charge_control_end_threshold");
+            OpenOptions::new().read(true).write(true).open(path).ok()
+        })
+    });
+}
+
+fn threshold() -> Option<&'static File> {
+    THRESHOLD.get().and_then(Option::as_ref)
+}
+
+/// Sets the charge limit to a percentage, for IPC clients
+pub fn set(limit: u32) -> io::Result<()> {
+    if !(1..=100).contains(&limit) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the limit has to be between 1 and 100"));
+    }
+    write_limit(limit)
+}
+
+fn write_limit(limit: u32) -> io::Result<()> {
+    let Some(file) = threshold() else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no battery with a charge limit"));
+    };
+    file.write_at(format!("{limit}\n").as_bytes(), 0)?;
+    Ok(())
+}
+
+fn read_limit() -> Option<u32> {
+    let mut buf = [0u8; 16];
+    let len = threshold()?.read_at(&mut buf, 0).ok()?;
+    std::str::from_utf8(&buf[..len]).ok()?.trim().parse().ok()
+}
+
+/// The `ChargeLimit` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ChargeLimitConfig {
+    pub limits: Option<Vec<u32>>,
+    pub format: Option<String>,
+}
+
+pub struct ChargeLimit {
+    cfg: ChargeLimitConfig,
+    /// The limit on the button, `Some(None)` when it could not be read
+    shown: Option<Option<u32>>,
+    interval: Duration,
+    next_refresh: Instant,
+    /// Whether the last change succeeded, until it was shown
+    outcome: Option<bool>,
+}
+
+impl ChargeLimit {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(ChargeLimit::new(source::parse(value)?, cfg.refresh_interval_ms)))
+    }
+    pub fn new(cfg: ChargeLimitConfig, interval_ms: Option<u64>) -> ChargeLimit {
+        let interval_ms = interval_ms
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL_MS)
+            .max(MIN_REFRESH_INTERVAL_MS);
+        ChargeLimit {
+            cfg,
+            shown: None,
+            interval: Duration::from_millis(interval_ms),
+            next_refresh: Instant::now(),
+            outcome: None,
+        }
+    }
+    /// Moves on to the next of the configured limits
+    fn cycle(&self) -> io::Result<()> {
+        let limits = self.cfg.limits.as_deref().unwrap_or(DEFAULT_LIMITS);
+        let current = read_limit().and_then(|l| limits.iter().position(|c| *c == l));
+        let next = match current {
+            Some(i) => limits[(i + 1) % limits.len()],
+            None => limits[0],
+        };
+        write_limit(next)
+    }
+}
+
+impl Source for ChargeLimit {
+    fn poll(&mut self, _waker: &Waker) -> (Vec<Update>, i32) {
+        let mut updates = Vec::new();
+        if let Some(success) = self.outcome.take() {
+            updates.push(Update::Flash(success));
+        }
+        let now = Instant::now();
+        if now >= self.next_refresh || !updates.is_empty() {
+            self.next_refresh = source::next_refresh(self.interval);
+            let limit = read_limit();
+            if self.shown != Some(limit) {
+                self.shown = Some(limit);
+                let format = self.cfg.format.as_deref().unwrap_or(DEFAULT_FORMAT);
+                updates.push(Update::Text(match limit {
+                    Some(limit) => format.replace("{}", &limit.to_string()),
+                    None => i18n::tr("N/A"),
+                }));
+            }
+        }
+        (updates, (self.next_refresh - now).as_millis() as i32)
+    }
+    fn touch(&mut self, pressed: bool) {
+        if !pressed || self.cfg.limits.as_ref().is_some_and(|l| l.is_empty()) {
+            return;
+        }
+        let res = self.cycle();
+        if let Err(e) = &res {
+            eprintln!("Failed to change the charge limit: {e}");
+        }
+        self.outcome = Some(res.is_ok());
+    }
+}
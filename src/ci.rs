@@ -0,0 +1,201 @@
+use crate::config::ButtonConfig;
+use crate::http;
+use crate::json::Value;
+use crate::runtime;
+use crate::source::{self, Async, AsyncJob, Periodic, Source, Tint, Update};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs::read_to_string, path::Path, time::Duration};
+
+// Shows the state of the latest CI run of a branch, taken from the GitHub
+// Actions or GitLab API, or from a user supplied command for anything else.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 60 * 1000;
+const DEFAULT_GITLAB_URL: &str = "https://gitlab.com";
+
+/// The `Ci` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CiConfig {
+    #[serde(rename = "GitHub")]
+    pub github: Option<String>,
+    #[serde(rename = "GitLab")]
+    pub gitlab: Option<String>,
+    pub command: Option<String>,
+    pub url: Option<String>,
+    pub token: Option<String>,
+    pub branch: Option<String>,
+    pub repo: Option<String>,
+    pub open_command: Option<String>,
+}
+
+fn tint(status: &str) -> Option<Tint> {
+    match status {
+        "success" | "passed" => Some(Tint::Green),
+        "failure" | "failed" | "timed_out" | "action_required" | "startup_failure" => {
+            Some(Tint::Red)
+        }
+        "running" | "pending" | "queued" | "in_progress" | "waiting" | "requested" | "created"
+        | "preparing" | "scheduled" | "waiting_for_resource" => Some(Tint::Yellow),
+        _ => None,
+    }
+}
+
+/// Reads the checked out branch of a git work tree.
+fn current_branch(repo: &str) -> Result<String> {
+    let head = read_to_string(Path::new(repo).join(".git/HEAD"))?;
+    head.trim()
+        .strip_prefix("ref: refs/heads/")
+        .map(str::to_string)
+        .ok_or(anyhow!("{repo} has a detached HEAD"))
+}
+
+struct Run {
+    status: String,
+    url: Option<String>,
+}
+
+pub struct Ci {
+    cfg: CiConfig,
+    url: Option<String>,
+}
+
+impl Ci {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let ci = Ci::new(source::parse(value)?)?;
+        Ok(Box::new(Async::new(Periodic::new(ci, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS)))))
+    }
+    pub fn new(cfg: CiConfig) -> Result<Ci> {
+        if cfg.github.is_none() && cfg.gitlab.is_none() && cfg.command.is_none() {
+            return Err(anyhow!("Ci needs one of GitHub, GitLab or Command"));
+        }
+        Ok(Ci { cfg, url: None })
+    }
+    fn branch(&self) -> Result<Option<String>> {
+        match (&self.cfg.branch, &self.cfg.repo) {
+            (Some(branch), _) => Ok(Some(branch.clone())),
+            (None, Some(repo)) => current_branch(repo).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+    async fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<Value> {
+        let response = http::request_async("GET", url, headers, None).await?;
+        if !response.is_success() {
+            return Err(anyhow!("HTTP {}", response.status));
+        }
+        Value::parse(&response.body)
+    }
+    async fn github(&self, repo: &str, branch: Option<&str>) -> Result<Option<Run>> {
+        let mut url = format!("https://api.github.com/repos/{repo}/actions/runs?per_page=1");
+        if let Some(branch) = branch {
+            url += &format!("&branch={}", http::encode(branch));
+        }
+        let auth = self.cfg.token.as_ref().map(|token| format!("Bearer {token}"));
+        let mut headers = vec![
+            ("Accept", "application/vnd.github+json"),
+            ("User-Agent", "tiny-dfr"),
+        ];
+        if let Some(auth) = &auth {
+            headers.push(("Authorization", auth));
+        }
+        let runs = self.get(&url, &headers).await?;
+        let Some(Value::Array(runs)) = runs.get("workflow_runs") else {
+            return Err(anyhow!("unexpected response from GitHub"));
+        };
+        Ok(runs.first().map(|run| {
+            let field = |name| run.get(name).and_then(Value::as_str);
+            Run {
+                // the conclusion is only set once the run is completed
+                status: field("conclusion")
+                    .or(field("status"))
+                    .unwrap_or("")
+                    .to_string(),
+                url: field("html_url").map(str::to_string),
+            }
+        }))
+    }
+    async fn gitlab(&self, project: &str, branch: Option<&str>) -> Result<Option<Run>> {
+        let server = self.cfg.url.as_deref().unwrap_or(DEFAULT_GITLAB_URL);
+        let mut url = format!(
+            "{}/api/v4/projects/{}/pipelines?per_page=1",
+            server.trim_end_matches('/'),
+            http::encode(project)
+        );
+        if let Some(branch) = branch {
+            url += &format!("&ref={}", http::encode(branch));
+        }
+        let mut headers = Vec::new();
+        if let Some(token) = &self.cfg.token {
+            headers.push(("PRIVATE-TOKEN", token.as_str()));
+        }
+        let Value::Array(pipelines) = self.get(&url, &headers).await? else {
+            return Err(anyhow!("unexpected response from GitLab"));
+        };
+        Ok(pipelines.first().map(|pipeline| {
+            let field = |name| pipeline.get(name).and_then(Value::as_str);
+            Run {
+                status: field("status").unwrap_or("").to_string(),
+                url: field("web_url").map(str::to_string),
+            }
+        }))
+    }
+    /// Runs the configured command with the branch as its argument,
+    /// which prints the status and optionally the URL of the run.
+    async fn command(&self, command: &str, branch: Option<&str>) -> Result<Option<Run>> {
+        let stdout = runtime::shell(command, branch.as_slice()).await?;
+        let mut words = stdout.split_whitespace();
+        Ok(words.next().map(|status| Run {
+            status: status.to_lowercase(),
+            url: words.next().map(str::to_string),
+        }))
+    }
+    async fn fetch(&self) -> Result<(Option<String>, Option<Run>)> {
+        let branch = self.branch()?;
+        let run = if let Some(repo) = &self.cfg.github {
+            self.github(repo, branch.as_deref()).await?
+        } else if let Some(project) = &self.cfg.gitlab {
+            self.gitlab(project, branch.as_deref()).await?
+        } else {
+            self.command(self.cfg.command.as_ref().unwrap(), branch.as_deref())
+                .await?
+        };
+        Ok((branch, run))
+    }
+}
+
+impl AsyncJob for Ci {
+    const MIN_INTERVAL: Duration = Duration::from_millis(10 * 1000);
+
+    async fn refresh(&mut self) -> Vec<Update> {
+        match self.fetch().await {
+            Ok((branch, run)) => {
+                let mut updates = Vec::new();
+                // only follow the branch when it was not given explicitly
+                if let (Some(branch), None) = (branch, &self.cfg.branch) {
+                    updates.push(Update::Text(branch));
+                }
+                self.url = run.as_ref().and_then(|run| run.url.clone());
+                updates.push(Update::Tint(run.and_then(|run| tint(&run.status))));
+                updates
+            }
+            Err(e) => {
+                eprintln!("Failed to get CI status: {e:#}");
+                vec![Update::Tint(None)]
+            }
+        }
+    }
+    async fn touch(&mut self, pressed: bool) -> bool {
+        if let (true, Some(url)) = (pressed, &self.url) {
+            source::open_url(self.cfg.open_command.as_deref(), url);
+        }
+        false
+    }
+    fn label(&self) -> String {
+        self.cfg
+            .branch
+            .clone()
+            .or_else(|| self.cfg.github.clone())
+            .or_else(|| self.cfg.gitlab.clone())
+            .unwrap_or("CI".to_string())
+    }
+}
@@ -0,0 +1,96 @@
+use crate::calendar::Calendar;
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::json::Value;
+use crate::source;
+use crate::widget::{Canvas, Widget};
+use anyhow::{anyhow, Result};
+use chrono::{
+    format::{Item as ChronoItem, StrftimeItems},
+    Local, Locale, Timelike,
+};
+use serde::{Deserialize, Serialize};
+
+// The Time widget, the clock with the date, or whatever strftime format
+// it is given, followed by the date in another calendar if set.
+
+/// The `Time` key of a button, or just its format
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ClockConfig {
+    pub format: Option<String>,
+    pub locale: Option<String>,
+    /// Another calendar whose date is shown too
+    pub calendar: Option<Calendar>,
+}
+
+/// The time now, followed by the date in `calendar` if set
+fn format_time(format: &[ChronoItem<'static>], locale: Locale, calendar: Option<Calendar>) -> String {
+    let now = Local::now();
+    let time = now.format_localized_with_items(format.iter(), locale).to_string();
+    match calendar {
+        Some(calendar) => format!("{time}    {}", calendar.format(now.date_naive())),
+        None => time,
+    }
+}
+
+pub struct Clock {
+    format: Vec<ChronoItem<'static>>,
+    locale: Locale,
+    calendar: Option<Calendar>,
+    /// The minute drawn last
+    minute: Option<u32>,
+}
+
+impl Clock {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Widget>> {
+        let cfg: ClockConfig = source::parse_short(value, "Format")?;
+        // day and month names follow the bar's locale unless set here
+        let locale = i18n::resolve(cfg.locale.as_deref());
+        let format = match cfg.format.as_deref().unwrap_or("auto") {
+            "auto" if i18n::is_12_hour(locale) => "12hr",
+            "auto" => "24hr",
+            format => format,
+        };
+        let format_str = if format == "24hr" {
+            "%H:%M    %a %-e %b"
+        } else if format == "12hr" {
+            "%-l:%M %p    %a %-e %b"
+        } else {
+            format
+        };
+        let format = StrftimeItems::new(format_str).parse_to_owned().map_err(|e| {
+            anyhow!("Invalid time format, consult the configuration file for examples of correct ones: {e:?}")
+        })?;
+        Ok(Box::new(Clock {
+            format,
+            locale,
+            calendar: cfg.calendar,
+            minute: None,
+        }))
+    }
+}
+
+impl Widget for Clock {
+    fn name(&self) -> &'static str {
+        "Time"
+    }
+    fn update(&mut self, _power_supply_changed: bool) -> (bool, i32) {
+        let now = Local::now();
+        let changed = self.minute != Some(now.minute());
+        self.minute = Some(now.minute());
+        let ms_into_minute = now.second() * 1000 + now.timestamp_subsec_millis().min(999);
+        (changed, (60_000 - ms_into_minute) as i32)
+    }
+    fn draw(&self, canvas: &Canvas) {
+        let time = format_time(&self.format, self.locale, self.calendar);
+        canvas.centered_text(&time);
+    }
+    fn preferred_size(&self) -> usize {
+        // the date in another calendar does not fit next to the time otherwise
+        if self.calendar.is_some() { 2 } else { 1 }
+    }
+    fn state(&self, _fields: &mut Vec<(String, Value)>) -> (&'static str, Value) {
+        ("time", format_time(&self.format, self.locale, self.calendar).into())
+    }
+}
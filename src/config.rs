@@ -1,18 +1,63 @@
+use crate::action::{self, ActionConfig, BuiltinAction};
+use crate::audit;
+use crate::auth::IpcConfig;
+use crate::backlight::{BrightnessSyncConfig, NightBrightnessConfig, DIMMED_BRIGHTNESS};
+use crate::baroff::BarOffConfig;
+use crate::docker::{DockerConfig, DockerSection};
+use crate::fnrow::FnRowConfig;
+use crate::focus::FocusSettings;
+use crate::fullscreen::FullscreenConfig;
 use crate::fonts::{FontConfig, Pattern};
+use crate::homeassistant::{HomeAssistantConfig, HomeAssistantServer};
+use crate::hotplug::DisplayHotplugConfig;
+use crate::i18n;
+use crate::json::Value;
+use crate::jack::JackConfig;
+use crate::migrate;
+use crate::mqtt::{MqttConfig, MqttServer};
+use crate::nightlight::{NightLightConfig, NightLightSettings};
+use crate::obs::{ObsConfig, ObsServer};
+use crate::onbattery::{OnBatteryConfig, PowerSupply};
+use crate::plugin::PluginConfig;
+use crate::postprocess::RenderMode;
+use crate::power::{LowBatteryConfig, ThermalConfig};
+use crate::powermenu::PowerMenuConfig;
+use crate::privacy::PrivacyConfig;
+use crate::ratelimit::{self, RateLimitConfig};
+use crate::screenshare::ScreenShareConfig;
+use crate::settings;
+use crate::shortcuts::{self, Shortcut};
+use crate::statickeys::{self, StaticFnKeys};
+use crate::source;
+use crate::sparkline::SparklineConfig;
+use crate::text::TextDirection;
+use crate::usbtoast::UsbToastConfig;
+use crate::writeback::{self, ConfigWriter};
 use crate::FunctionLayer;
-use anyhow::Error;
-use cairo::FontFace;
+use anyhow::{anyhow, Error};
+use cairo::{Antialias, FontFace, FontOptions, HintStyle};
 use freetype::Library as FtLibrary;
 use input_linux::Key;
 use nix::{
     errno::Errno,
     sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor},
 };
-use serde::Deserialize;
-use std::{fs::read_to_string, os::fd::AsFd};
+use pango::FontDescription;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs::read_to_string,
+    os::fd::AsFd,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use std::collections::HashMap;
 
+const SYSTEM_CFG_PATH: &str = "/usr/share/tiny-dfr/config.toml";
 const USER_CFG_PATH: &str = "/etc/tiny-dfr/config.toml";
+const PROFILES_DIR: &str = "/etc/tiny-dfr/profiles";
+pub const MIN_SCALE: f64 = 0.5;
+pub const MAX_SCALE: f64 = 4.0;
+const DEFAULT_MIN_BUTTON_WIDTH: u32 = 48;
 
 #[derive(Debug, Clone)]
 pub struct ColorConfig {
@@ -24,7 +69,7 @@ pub struct ColorConfig {
     pub button_overrides: Option<HashMap<String, ButtonColorOverride>>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ButtonColorOverride {
     pub button_background_inactive: Option<[f64; 3]>,
@@ -47,64 +92,354 @@ impl Default for ColorConfig {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct ButtonColors {
+    pub background_inactive: [f64; 3],
+    pub background_active: [f64; 3],
+    pub icon: [f64; 3],
+    pub icon_active: [f64; 3],
+    pub text: [f64; 3],
+}
+
 impl ColorConfig {
 
-    pub fn get_button_colors(&self, button_text: &str) -> ([f64; 3], [f64; 3], [f64; 3], [f64; 3], [f64; 3]) {
-        let mut bg_inactive = self.button_background_inactive;
-        let mut bg_active = self.button_background_active;
-        let mut icon_color = self.icon_color;
-        let mut icon_color_active = self.icon_color_active;
-        let mut text_color = self.text_color;
+    /// Resolves the colors for a button, looking up overrides by the
+    /// button's `Id` first and falling back to its label for configs
+    /// written before buttons had ids.
+    pub fn get_button_colors(&self, id: Option<&str>, button_text: &str) -> ButtonColors {
+        let mut colors = ButtonColors {
+            background_inactive: self.button_background_inactive,
+            background_active: self.button_background_active,
+            icon: self.icon_color,
+            icon_active: self.icon_color_active,
+            text: self.text_color,
+        };
 
         // Check for button-specific overrides
         if let Some(ref overrides) = self.button_overrides {
-            if let Some(override_config) = overrides.get(button_text) {
+            let override_config = id
+                .and_then(|id| overrides.get(id))
+                .or_else(|| overrides.get(button_text));
+            if let Some(override_config) = override_config {
                 if let Some(inactive) = override_config.button_background_inactive {
-                    bg_inactive = inactive;
+                    colors.background_inactive = inactive;
                 }
                 if let Some(active) = override_config.button_background_active {
-                    bg_active = active;
+                    colors.background_active = active;
                 }
                 if let Some(icon) = override_config.icon_color {
-                    icon_color = icon;
+                    colors.icon = icon;
                 }
                 if let Some(icon_active) = override_config.icon_color_active {
-                    icon_color_active = icon_active;
+                    colors.icon_active = icon_active;
                 }
                 if let Some(text) = override_config.text_color {
-                    text_color = text;
+                    colors.text = text;
                 }
             }
         }
 
-        (bg_inactive, bg_active, icon_color, icon_color_active, text_color)
+        colors
     }
 }
 
 pub struct Config {
-    pub show_button_outlines: bool,
     pub enable_pixel_shift: bool,
+    /// The default font, layers may use another one
     pub font_face: FontFace,
+    /// The same font for labels laid out with pango, see `text::layout`
+    pub font: FontDescription,
     pub adaptive_brightness: bool,
     pub active_brightness: u32,
+    /// How long brightness changes take, 0 for changing it at once
+    pub brightness_fade_ms: u64,
+    /// The brightness after a while without being used, before turning off
+    pub dim_brightness: u32,
+    /// Brightness levels below this are shown by dimming the content
+    pub software_dimming_below: u32,
+    pub render_mode: RenderMode,
+    pub rotate_180: bool,
+    /// Pixels kept clear of buttons at the rounded left and right end
+    pub safe_area_insets: (f64, f64),
+    /// Antialiasing and hinting of text
+    pub font_options: FontOptions,
+    /// How much bigger everything is drawn than on the original Touch Bar,
+    /// multiplying sizes and spacing given in pixels
+    pub scale: f64,
+    /// The band of the panel's height that is used, for panels with more
+    /// rows of pixels than can be seen
+    pub vertical: VerticalLayout,
+    /// Whether touching the bar while it is dimmed only wakes it up
+    pub wake_consumes_touch: bool,
+    /// How long each of the layers, in the order they are loaded in, stays
+    /// up untouched before the default one comes back
+    pub layer_timeouts: [Option<Duration>; 2],
+    pub static_fn_keys: StaticFnKeys,
+    /// Limits of the brightness while in use, whichever way it is set
+    pub min_brightness: u32,
+    pub max_brightness: u32,
+    pub night_brightness: NightBrightnessConfig,
     pub colors: ColorConfig,
+    /// Sockets used by Docker buttons whose group is kept when dropping privileges,
+    /// empty unless the `[Docker]` section asks for it
+    pub docker_sockets: Vec<String>,
+    pub low_battery: LowBatteryConfig,
+    pub thermal: ThermalConfig,
+    pub brightness_sync: BrightnessSyncConfig,
+    pub fn_row: FnRowConfig,
+    pub splash: SplashConfig,
+    pub usb_toast: UsbToastConfig,
+    pub bar_off: BarOffConfig,
+    pub signals: SignalConfig,
+    pub shortcuts: Vec<Shortcut>,
+    pub focus_mode: FocusSettings,
+    /// Shown instead of the default layer while the battery is low
+    pub low_battery_layer: Option<FunctionLayer>,
+    pub fullscreen: FullscreenConfig,
+    /// Shown instead of the default layer while a window is fullscreen
+    pub fullscreen_layer: Option<FunctionLayer>,
+    pub screen_share: ScreenShareConfig,
+    pub privacy: PrivacyConfig,
+    pub ipc: IpcConfig,
+    /// Shown instead of the default layer while the screen is shared
+    pub screen_share_layer: Option<FunctionLayer>,
+    /// Shown instead of the default layer while running on battery
+    pub on_battery_layer: Option<FunctionLayer>,
+    pub jack: JackConfig,
+    /// Shown instead of the default layer for a while after plugging in headphones
+    pub jack_layer: Option<FunctionLayer>,
+    pub display_hotplug: DisplayHotplugConfig,
+    /// Shown instead of the default layer for a while after connecting a display
+    pub display_hotplug_layer: Option<FunctionLayer>,
+    pub power_menu: PowerMenuConfig,
+    /// Shown instead of the default layer for a while after a button opened it
+    pub power_menu_layer: Option<FunctionLayer>,
+    /// Whether any button or layer depends on running on battery
+    pub follows_power_supply: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct ConfigProxy {
+    config_version: Option<i64>,
+    profile: Option<String>,
+    locale: Option<String>,
+    audit_log: Option<bool>,
     media_layer_default: Option<bool>,
+    layer_timeout_sec: Option<u64>,
+    static_fn_keys: Option<StaticFnKeys>,
     show_button_outlines: Option<bool>,
     enable_pixel_shift: Option<bool>,
     font_template: Option<String>,
     adaptive_brightness: Option<bool>,
     active_brightness: Option<u32>,
+    brightness_fade_ms: Option<u64>,
+    dim_brightness: Option<u32>,
+    software_dimming_below: Option<u32>,
+    render_mode: Option<RenderMode>,
+    rotate_180: Option<bool>,
+    safe_area_inset_left: Option<u32>,
+    safe_area_inset_right: Option<u32>,
+    text_antialias: Option<TextAntialias>,
+    subpixel_order: Option<SubpixelOrder>,
+    text_hinting: Option<TextHinting>,
+    scale: Option<f64>,
+    content_height: Option<u32>,
+    vertical_align: Option<VerticalAlign>,
+    min_button_width: Option<u32>,
+    wake_consumes_touch: Option<bool>,
+    min_brightness: Option<u32>,
+    max_brightness: Option<u32>,
+    night_brightness: Option<NightBrightnessConfig>,
+    plugin_dir: Option<String>,
+    home_assistant: Option<HomeAssistantServer>,
+    mqtt: Option<MqttServer>,
+    obs: Option<ObsServer>,
+    docker: Option<DockerSection>,
+    night_light: Option<NightLightSettings>,
+    low_battery: Option<LowBatteryConfig>,
+    fullscreen: Option<FullscreenConfig>,
+    screen_share: Option<ScreenShareConfig>,
+    on_battery: Option<OnBatteryConfig>,
+    jack: Option<JackConfig>,
+    display_hotplug: Option<DisplayHotplugConfig>,
+    power_menu: Option<PowerMenuConfig>,
+    privacy: Option<PrivacyConfig>,
+    ipc: Option<IpcConfig>,
+    rate_limit: Option<RateLimitConfig>,
+    thermal: Option<ThermalConfig>,
+    brightness_sync: Option<BrightnessSyncConfig>,
+    fn_row: Option<FnRowConfig>,
+    splash: Option<SplashConfig>,
+    usb_toast: Option<UsbToastConfig>,
+    bar_off: Option<BarOffConfig>,
+    signals: Option<SignalConfig>,
+    shortcuts: Option<HashMap<String, SignalAction>>,
+    focus_mode: Option<FocusSettings>,
     primary_layer_keys: Option<Vec<ButtonConfig>>,
+    primary_layer_second_row: Option<Vec<ButtonConfig>>,
+    media_layer_second_row: Option<Vec<ButtonConfig>>,
     media_layer_keys: Option<Vec<ButtonConfig>>,
+    primary_layer: Option<LayerSettings>,
+    media_layer: Option<LayerSettings>,
     colors: Option<ColorConfigProxy>,
 }
 
-#[derive(Deserialize, Default)]
+/// The `[PrimaryLayer]` and `[MediaLayer]` sections, overriding the global
+/// settings of the same name for that layer
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct LayerSettings {
+    pub font_template: Option<String>,
+    pub show_button_outlines: Option<bool>,
+    pub layer_timeout_sec: Option<u64>,
+}
+
+/// The `TextAntialias` setting
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TextAntialias {
+    None,
+    Gray,
+    Subpixel,
+}
+
+/// The `SubpixelOrder` setting, for subpixel antialiasing
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SubpixelOrder {
+    Rgb,
+    Bgr,
+    Vrgb,
+    Vbgr,
+}
+
+/// The `TextHinting` setting
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TextHinting {
+    None,
+    Slight,
+    Medium,
+    Full,
+}
+
+/// How text is rendered, cairo's defaults for whatever is not set
+fn font_options(
+    antialias: Option<TextAntialias>,
+    order: Option<SubpixelOrder>,
+    hinting: Option<TextHinting>,
+) -> FontOptions {
+    let mut options = FontOptions::new().unwrap();
+    if let Some(antialias) = antialias {
+        options.set_antialias(match antialias {
+            TextAntialias::None => Antialias::None,
+            TextAntialias::Gray => Antialias::Gray,
+            TextAntialias::Subpixel => Antialias::Subpixel,
+        });
+    }
+    if let Some(order) = order {
+        options.set_subpixel_order(match order {
+            SubpixelOrder::Rgb => cairo::SubpixelOrder::Rgb,
+            SubpixelOrder::Bgr => cairo::SubpixelOrder::Bgr,
+            SubpixelOrder::Vrgb => cairo::SubpixelOrder::Vrgb,
+            SubpixelOrder::Vbgr => cairo::SubpixelOrder::Vbgr,
+        });
+    }
+    if let Some(hinting) = hinting {
+        options.set_hint_style(match hinting {
+            TextHinting::None => HintStyle::None,
+            TextHinting::Slight => HintStyle::Slight,
+            TextHinting::Medium => HintStyle::Medium,
+            TextHinting::Full => HintStyle::Full,
+        });
+    }
+    options
+}
+
+/// The `VerticalAlign` setting, where the content goes on panels that are
+/// higher than ContentHeight
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+pub enum VerticalAlign {
+    Top,
+    #[default]
+    Center,
+    Bottom,
+}
+
+/// How much of the panel's height is used, and where
+#[derive(Clone, Copy, Default)]
+pub struct VerticalLayout {
+    /// In pixels of the panel, all of it if unset
+    pub content_height: Option<u32>,
+    pub align: VerticalAlign,
+}
+
+impl VerticalLayout {
+    /// Where the content starts and how high it is, out of a panel `height`
+    /// high, both in pixels scaled down by `scale`
+    pub fn band(&self, height: f64, scale: f64) -> (f64, f64) {
+        let content = self.content_height.map_or(height, |h| (h as f64 / scale).min(height));
+        let top = match self.align {
+            VerticalAlign::Top => 0.0,
+            VerticalAlign::Center => ((height - content) / 2.0).round(),
+            VerticalAlign::Bottom => height - content,
+        };
+        (top, content)
+    }
+}
+
+/// How a layer is drawn, with the global settings filled in
+pub struct LayerStyle {
+    pub font_face: FontFace,
+    pub font: FontDescription,
+    pub show_button_outlines: bool,
+    /// Pixels left empty at the left and right end, see `Config::safe_area_insets`
+    pub insets: (f64, f64),
+    /// How much bigger everything is drawn, see `Config::scale`
+    pub scale: f64,
+    /// How strongly the buttons are drawn, below 1 for StaticFnKeys
+    pub alpha: f64,
+    /// The band of the panel's height buttons are drawn in, see `Config::vertical`
+    pub vertical: VerticalLayout,
+}
+
+/// The `[Splash]` section, what is shown while starting and after stopping
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct SplashConfig {
+    pub text: Option<String>,
+    pub icon: Option<String>,
+    pub min_duration_ms: Option<u64>,
+    pub goodbye_text: Option<String>,
+}
+
+/// The `[Signals]` section, what SIGUSR1 and SIGUSR2 do
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "PascalCase")]
+pub struct SignalConfig {
+    pub usr1: Option<SignalAction>,
+    pub usr2: Option<SignalAction>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+pub enum SignalAction {
+    /// Switches between the primary and media layer
+    ToggleLayer,
+    /// Reloads the config without waiting for it to change
+    ReloadConfig,
+    /// Turns the bar off until the next signal
+    ToggleBar,
+    /// Turns focus mode on or off
+    ToggleFocus,
+    /// Keeps the layer shown when Fn is pressed or let go of, or lets Fn
+    /// switch layers again
+    LockLayer,
+    /// Turns privacy mode on or off, until set back to automatic over IPC
+    TogglePrivacy,
+}
+
+#[derive(Deserialize, Serialize, Default)]
 #[serde(rename_all = "PascalCase")]
 struct ColorConfigProxy {
     button_background_inactive: Option<[f64; 3]>,
@@ -143,21 +478,47 @@ impl ColorConfigProxy {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IconLayout {
+    #[default]
+    #[serde(rename = "IconLeft")]
+    Left,
+    #[serde(rename = "IconRight")]
+    Right,
+    #[serde(rename = "IconAbove", alias = "TextUnder")]
+    Above,
+}
+
+#[derive(Deserialize, Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ButtonConfig {
-    #[serde(alias = "Svg")]
+    pub id: Option<String>,
     pub icon: Option<String>,
     pub text: Option<String>,
     pub theme: Option<String>,
-    pub time: Option<String>,
-    pub battery: Option<String>,
-    pub locale: Option<String>,
-    pub action: Key,
+    #[serde(default, deserialize_with = "action::deserialize_action")]
+    pub action: Option<ActionConfig>,
+    pub sparkline: Option<SparklineConfig>,
+    pub profiles: Option<Vec<String>>,
+    pub confirm: Option<bool>,
+    pub hold: Option<bool>,
+    pub sensitive: Option<bool>,
+    pub refresh_interval_ms: Option<u64>,
     pub stretch: Option<usize>,
+    pub text_direction: Option<TextDirection>,
+    pub layout: Option<IconLayout>,
+    pub spacing: Option<f64>,
+    /// Pixels around the button where touches are still taken as on it
+    pub touch_padding: Option<f64>,
+    /// Only shows the button on AC or on battery
+    pub show_on: Option<PowerSupply>,
+    /// The keys of widgets, like Time and Battery, and of sources, like
+    /// Script and Mqtt, see `widget::WIDGETS` and `source::SOURCES`
+    #[serde(flatten)]
+    pub widgets: toml::Table,
 }
 
-fn load_font(name: &str) -> FontFace {
+fn load_font(name: &str) -> (FontFace, FontDescription) {
     let fontconfig = FontConfig::new();
     let mut pattern = Pattern::new(name);
     fontconfig.perform_substitutions(&mut pattern);
@@ -169,60 +530,480 @@ fn load_font(name: &str) -> FontFace {
     let file_idx = pat_match.get_font_index();
     let ft_library = FtLibrary::init().unwrap();
     let face = ft_library.new_face(file_name, file_idx).unwrap();
-    FontFace::create_from_ft(&face).unwrap()
-}
-
-fn load_config(width: u16) -> (Config, [FunctionLayer; 2]) {
-    let mut base =
-        toml::from_str::<ConfigProxy>(&read_to_string("/usr/share/tiny-dfr/config.toml").unwrap())
-            .unwrap();
-    let user = read_to_string(USER_CFG_PATH)
-        .map_err::<Error, _>(|e| e.into())
-        .and_then(|r| Ok(toml::from_str::<ConfigProxy>(&r)?));
-    if let Ok(user) = user {
-        base.media_layer_default = user.media_layer_default.or(base.media_layer_default);
-        base.show_button_outlines = user.show_button_outlines.or(base.show_button_outlines);
-        base.enable_pixel_shift = user.enable_pixel_shift.or(base.enable_pixel_shift);
-        base.font_template = user.font_template.or(base.font_template);
-        base.adaptive_brightness = user.adaptive_brightness.or(base.adaptive_brightness);
-        base.media_layer_keys = user.media_layer_keys.or(base.media_layer_keys);
-        base.primary_layer_keys = user.primary_layer_keys.or(base.primary_layer_keys);
-        base.active_brightness = user.active_brightness.or(base.active_brightness);
-        base.colors = user.colors.or(base.colors);
+    (FontFace::create_from_ft(&face).unwrap(), pat_match.font_description())
+}
+
+fn profile_path(name: &str) -> PathBuf {
+    Path::new(PROFILES_DIR).join(format!("{name}.toml"))
+}
+
+fn read_config(path: &Path) -> Result<ConfigProxy, Error> {
+    let mut table = toml::from_str::<toml::Table>(&read_to_string(path)?)?;
+    migrate::migrate(&mut table, path);
+    Ok(table.try_into()?)
+}
+
+impl ConfigProxy {
+    /// Takes every setting that is set in `user` over the ones in `self`
+    fn merge(&mut self, user: ConfigProxy) {
+        self.locale = user.locale.or(self.locale.take());
+        self.audit_log = user.audit_log.or(self.audit_log.take());
+        self.media_layer_default = user.media_layer_default.or(self.media_layer_default.take());
+        self.layer_timeout_sec = user.layer_timeout_sec.or(self.layer_timeout_sec.take());
+        self.static_fn_keys = user.static_fn_keys.or(self.static_fn_keys.take());
+        self.show_button_outlines = user.show_button_outlines.or(self.show_button_outlines.take());
+        self.enable_pixel_shift = user.enable_pixel_shift.or(self.enable_pixel_shift.take());
+        self.font_template = user.font_template.or(self.font_template.take());
+        self.adaptive_brightness = user.adaptive_brightness.or(self.adaptive_brightness.take());
+        self.media_layer_keys = user.media_layer_keys.or(self.media_layer_keys.take());
+        self.primary_layer = user.primary_layer.or(self.primary_layer.take());
+        self.media_layer = user.media_layer.or(self.media_layer.take());
+        self.primary_layer_keys = user.primary_layer_keys.or(self.primary_layer_keys.take());
+        self.primary_layer_second_row = user.primary_layer_second_row.or(self.primary_layer_second_row.take());
+        self.media_layer_second_row = user.media_layer_second_row.or(self.media_layer_second_row.take());
+        self.active_brightness = user.active_brightness.or(self.active_brightness.take());
+        self.brightness_fade_ms = user.brightness_fade_ms.or(self.brightness_fade_ms.take());
+        self.dim_brightness = user.dim_brightness.or(self.dim_brightness.take());
+        self.software_dimming_below = user.software_dimming_below.or(self.software_dimming_below.take());
+        self.render_mode = user.render_mode.or(self.render_mode.take());
+        self.rotate_180 = user.rotate_180.or(self.rotate_180.take());
+        self.safe_area_inset_left = user.safe_area_inset_left.or(self.safe_area_inset_left.take());
+        self.safe_area_inset_right = user.safe_area_inset_right.or(self.safe_area_inset_right.take());
+        self.text_antialias = user.text_antialias.or(self.text_antialias.take());
+        self.subpixel_order = user.subpixel_order.or(self.subpixel_order.take());
+        self.text_hinting = user.text_hinting.or(self.text_hinting.take());
+        self.scale = user.scale.or(self.scale.take());
+        self.content_height = user.content_height.or(self.content_height.take());
+        self.vertical_align = user.vertical_align.or(self.vertical_align.take());
+        self.min_button_width = user.min_button_width.or(self.min_button_width.take());
+        self.wake_consumes_touch = user.wake_consumes_touch.or(self.wake_consumes_touch.take());
+        self.min_brightness = user.min_brightness.or(self.min_brightness.take());
+        self.max_brightness = user.max_brightness.or(self.max_brightness.take());
+        self.night_brightness = user.night_brightness.or(self.night_brightness.take());
+        self.plugin_dir = user.plugin_dir.or(self.plugin_dir.take());
+        self.home_assistant = user.home_assistant.or(self.home_assistant.take());
+        self.mqtt = user.mqtt.or(self.mqtt.take());
+        self.obs = user.obs.or(self.obs.take());
+        self.docker = user.docker.or(self.docker.take());
+        self.night_light = user.night_light.or(self.night_light.take());
+        self.low_battery = user.low_battery.or(self.low_battery.take());
+        self.on_battery = user.on_battery.or(self.on_battery.take());
+        self.jack = user.jack.or(self.jack.take());
+        self.display_hotplug = user.display_hotplug.or(self.display_hotplug.take());
+        self.power_menu = user.power_menu.or(self.power_menu.take());
+        self.fullscreen = user.fullscreen.or(self.fullscreen.take());
+        self.screen_share = user.screen_share.or(self.screen_share.take());
+        self.privacy = user.privacy.or(self.privacy.take());
+        self.ipc = user.ipc.or(self.ipc.take());
+        self.rate_limit = user.rate_limit.or(self.rate_limit.take());
+        self.thermal = user.thermal.or(self.thermal.take());
+        self.brightness_sync = user.brightness_sync.or(self.brightness_sync.take());
+        self.fn_row = user.fn_row.or(self.fn_row.take());
+        self.splash = user.splash.or(self.splash.take());
+        self.usb_toast = user.usb_toast.or(self.usb_toast.take());
+        self.bar_off = user.bar_off.or(self.bar_off.take());
+        self.signals = user.signals.or(self.signals.take());
+        self.shortcuts = user.shortcuts.or(self.shortcuts.take());
+        self.profile = user.profile.or(self.profile.take());
+        self.focus_mode = user.focus_mode.or(self.focus_mode.take());
+        self.colors = user.colors.or(self.colors.take());
+    }
+}
+
+/// Where the config is read from and what overrides it, besides the files
+struct ConfigOptions {
+    system_path: PathBuf,
+    user_path: PathBuf,
+    profile: Option<String>,
+    active_brightness: Option<u32>,
+    theme: Option<String>,
+}
+
+impl ConfigOptions {
+    /// Reads the `TINY_DFR_*` environment variables, paths given on the
+    /// command line take precedence
+    fn new(user_path: Option<PathBuf>, system_path: Option<PathBuf>) -> ConfigOptions {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let active_brightness = var("TINY_DFR_BRIGHTNESS").and_then(|b| match b.parse::<u32>() {
+            Ok(b) if b <= 255 => Some(b),
+            _ => {
+                eprintln!("Ignoring TINY_DFR_BRIGHTNESS={b}, it must be between 0 and 255");
+                None
+            }
+        });
+        ConfigOptions {
+            system_path: system_path.unwrap_or(SYSTEM_CFG_PATH.into()),
+            user_path: user_path
+                .or(var("TINY_DFR_CONFIG").map(PathBuf::from))
+                .unwrap_or(USER_CFG_PATH.into()),
+            profile: None,
+            active_brightness,
+            theme: var("TINY_DFR_THEME"),
+        }
+    }
+}
+
+/// Merges the system config, user config and profile, and fills in what
+/// buttons take from the global sections
+fn resolve_config(options: &ConfigOptions) -> ConfigProxy {
+    let mut base = read_config(&options.system_path).unwrap_or_else(|e| {
+        panic!("Failed to load {}: {e:#}", options.system_path.display())
+    });
+    if let Ok(user) = read_config(&options.user_path) {
+        base.merge(user);
+    }
+    // a profile goes on top of the user config, and only needs what it changes
+    if let Some(profile) = &options.profile {
+        match read_config(&profile_path(profile)) {
+            Ok(profile) => base.merge(profile),
+            Err(e) => eprintln!("Failed to load profile {profile}: {e:#}"),
+        }
+    }
+    let plugin_dir = Path::new(base.plugin_dir.as_deref().unwrap());
+    let home_assistant = base.home_assistant.as_ref().cloned().unwrap_or_default();
+    let mqtt = base.mqtt.as_ref().cloned().unwrap_or_default();
+    let obs = base.obs.as_ref().cloned().unwrap_or_default();
+    let docker_section = base.docker.as_ref().cloned().unwrap_or_default();
+    let docker = &docker_section.server;
+    let night_light = base.night_light.as_ref().cloned().unwrap_or_default();
+    let low_battery_layer_keys = base.low_battery.as_mut().and_then(|l| l.layer_keys.as_mut());
+    let fullscreen_layer_keys = base.fullscreen.as_mut().and_then(|f| f.layer_keys.as_mut());
+    let screen_share_layer_keys = base.screen_share.as_mut().and_then(|s| s.layer_keys.as_mut());
+    let on_battery_layer_keys = base.on_battery.as_mut().and_then(|o| o.layer_keys.as_mut());
+    let jack_layer_keys = base.jack.as_mut().and_then(|j| j.layer_keys.as_mut());
+    for button in base
+        .media_layer_keys
+        .iter_mut()
+        .chain(base.primary_layer_keys.iter_mut())
+        .chain(base.primary_layer_second_row.iter_mut())
+        .chain(base.media_layer_second_row.iter_mut())
+        .chain(low_battery_layer_keys)
+        .chain(fullscreen_layer_keys)
+        .chain(screen_share_layer_keys)
+        .chain(on_battery_layer_keys)
+        .chain(jack_layer_keys)
+        .flatten()
+    {
+        if button.profiles.is_some() && button.text.is_none() && button.icon.is_none() {
+            button.text = Some(options.profile.as_deref().unwrap_or("Default").to_string());
+        }
+        // only buttons using XDG icons have a theme to replace
+        if let (Some(theme), Some(_)) = (&options.theme, &button.theme) {
+            button.theme = Some(theme.clone());
+        }
+        if let Some(value) = button.widgets.get_mut("Plugin") {
+            if let Ok(mut plugin) = source::parse_short::<PluginConfig>(value, "Name") {
+                plugin.name = plugin_dir.join(format!("{}.so", plugin.name)).to_string_lossy().into_owned();
+                if let Ok(filled) = toml::Value::try_from(plugin) {
+                    *value = filled;
+                }
+            }
+        }
+        fill_in(button, "HomeAssistant", |ha: &mut HomeAssistantConfig| {
+            ha.server.url = ha.server.url.take().or(home_assistant.url.clone());
+            ha.server.token = ha.server.token.take().or(home_assistant.token.clone());
+        });
+        fill_in(button, "Mqtt", |button_mqtt: &mut MqttConfig| button_mqtt.server.merge(&mqtt));
+        fill_in(button, "Obs", |button_obs: &mut ObsConfig| {
+            button_obs.server.url = button_obs.server.url.take().or(obs.url.clone());
+            button_obs.server.password = button_obs.server.password.take().or(obs.password.clone());
+        });
+        fill_in(button, "Docker", |button_docker: &mut DockerConfig| {
+            button_docker.server.socket = button_docker.server.socket.take().or(docker.socket.clone());
+        });
+        fill_in(button, "NightLight", |button_night_light: &mut NightLightConfig| {
+            let settings = &mut button_night_light.settings;
+            settings.call = settings.call.take().or(night_light.call.clone());
+            settings.default = settings.default.or(night_light.default);
+            settings.min = settings.min.or(night_light.min);
+            settings.max = settings.max.or(night_light.max);
+        });
+    }
+    base.active_brightness = options.active_brightness.or(base.active_brightness);
+    // whatever the files said, it is in the current shape now
+    base.config_version = Some(migrate::CONFIG_VERSION);
+    base
+}
+
+/// Fills in what the source of `button` under `key` left out from the
+/// sections shared by all buttons. Invalid configs are left alone, to be
+/// reported when the button is made.
+fn fill_in<T: DeserializeOwned + Serialize>(button: &mut ButtonConfig, key: &str, fill: impl FnOnce(&mut T)) {
+    let Some(value) = button.widgets.get_mut(key) else {
+        return;
+    };
+    let Ok(mut cfg) = source::parse::<T>(value) else {
+        return;
     };
-    let mut media_layer_keys = base.media_layer_keys.unwrap();
-    let mut primary_layer_keys = base.primary_layer_keys.unwrap();
-    if width >= 2170 {
-        for layer in [&mut media_layer_keys, &mut primary_layer_keys] {
+    fill(&mut cfg);
+    match toml::Value::try_from(cfg) {
+        Ok(filled) => *value = filled,
+        Err(e) => eprintln!("Failed to fill in the {key} button: {e}"),
+    }
+}
+
+fn load_config(width: u16, options: &ConfigOptions) -> (Config, [FunctionLayer; 2]) {
+    let mut base = resolve_config(options);
+    // before the buttons, which show translated text
+    i18n::load(base.locale.as_deref());
+    audit::set_enabled(base.audit_log.unwrap_or_default());
+    ratelimit::configure(&base.rate_limit.unwrap_or_default());
+    let mut media_layer_keys = base.media_layer_keys.take().unwrap();
+    let mut primary_layer_keys = base.primary_layer_keys.take().unwrap();
+    let mut media_second_row = base.media_layer_second_row.take();
+    let mut primary_second_row = base.primary_layer_second_row.take();
+    let mut low_battery = base.low_battery.take().unwrap_or_default();
+    let mut low_battery_layer_keys = low_battery.layer_keys.take();
+    let mut fullscreen = base.fullscreen.take().unwrap_or_default();
+    let mut fullscreen_layer_keys = fullscreen.layer_keys.take();
+    let mut screen_share = base.screen_share.take().unwrap_or_default();
+    let mut screen_share_layer_keys = screen_share.layer_keys.take();
+    let mut on_battery_layer_keys = base.on_battery.take().unwrap_or_default().layer_keys;
+    let mut jack = base.jack.take().unwrap_or_default();
+    let mut jack_layer_keys = jack.selector_keys();
+    let display_hotplug = base.display_hotplug.take().unwrap_or_default();
+    let mut display_hotplug_layer_keys = display_hotplug.layer_keys();
+    let power_menu = base.power_menu.take().unwrap_or_default();
+    // only made when a button opens it
+    let mut power_menu_layer_keys = media_layer_keys
+        .iter()
+        .chain(&primary_layer_keys)
+        .chain(media_second_row.iter().flatten())
+        .chain(primary_second_row.iter().flatten())
+        .any(|button| matches!(button.action, Some(ActionConfig::Builtin(BuiltinAction::PowerMenu {}))))
+        .then(|| power_menu.layer_keys());
+    let static_fn_keys = base.static_fn_keys.unwrap_or_default();
+    if static_fn_keys.is_on() {
+        media_layer_keys = statickeys::layer_keys();
+        primary_layer_keys = statickeys::layer_keys();
+        media_second_row = None;
+        primary_second_row = None;
+        let stand_ins = [
+            &mut low_battery_layer_keys,
+            &mut fullscreen_layer_keys,
+            &mut screen_share_layer_keys,
+            &mut on_battery_layer_keys,
+            &mut jack_layer_keys,
+            &mut display_hotplug_layer_keys,
+            &mut power_menu_layer_keys,
+        ];
+        for keys in stand_ins {
+            *keys = None;
+        }
+    }
+    let keep_docker_group = base.docker.as_ref().and_then(|d| d.keep_socket_group).unwrap_or(false);
+    let docker_sockets = media_layer_keys
+        .iter()
+        .chain(&primary_layer_keys)
+        .chain(media_second_row.iter().flatten())
+        .chain(primary_second_row.iter().flatten())
+        .chain(low_battery_layer_keys.iter().flatten())
+        .chain(fullscreen_layer_keys.iter().flatten())
+        .chain(screen_share_layer_keys.iter().flatten())
+        .chain(on_battery_layer_keys.iter().flatten())
+        .chain(jack_layer_keys.iter().flatten())
+        .filter(|_| keep_docker_group)
+        .filter_map(|button| source::parse::<DockerConfig>(button.widgets.get("Docker")?).ok())
+        .map(|docker| docker.socket().to_string())
+        .collect();
+    let follows_power_supply = on_battery_layer_keys.is_some()
+        || media_layer_keys
+            .iter()
+            .chain(&primary_layer_keys)
+            .chain(media_second_row.iter().flatten())
+            .chain(primary_second_row.iter().flatten())
+            .chain(low_battery_layer_keys.iter().flatten())
+            .chain(fullscreen_layer_keys.iter().flatten())
+            .chain(screen_share_layer_keys.iter().flatten())
+            .chain(jack_layer_keys.iter().flatten())
+            .any(|button| button.show_on.is_some());
+    // the esc key added on wide bars comes before the configured keys
+    let fixed = (width >= 2170 && !static_fn_keys.is_on()) as usize;
+    if fixed > 0 {
+        let layers = [
+            Some(&mut media_layer_keys),
+            Some(&mut primary_layer_keys),
+            low_battery_layer_keys.as_mut(),
+            fullscreen_layer_keys.as_mut(),
+            screen_share_layer_keys.as_mut(),
+            on_battery_layer_keys.as_mut(),
+            jack_layer_keys.as_mut(),
+            display_hotplug_layer_keys.as_mut(),
+            power_menu_layer_keys.as_mut(),
+        ];
+        for layer in layers.into_iter().flatten() {
             layer.insert(
                 0,
                 ButtonConfig {
+                    id: Some("esc".into()),
                     icon: None,
-                    text: Some("esc".into()),
+                    text: Some(i18n::tr("esc")),
                     theme: None,
-                    action: Key::Esc,
+                    action: Some(ActionConfig::Key(Key::Esc)),
+                    sparkline: None,
+                    profiles: None,
+                    confirm: None,
+                    hold: None,
+                    sensitive: None,
+                    refresh_interval_ms: None,
                     stretch: None,
-                    time: None,
-                    locale: None,
-                    battery: None,
+                    text_direction: None,
+                    layout: None,
+                    spacing: None,
+                    touch_padding: None,
+                    show_on: None,
+                    widgets: toml::Table::new(),
                 },
             );
         }
     }
-    let media_layer = FunctionLayer::with_config(media_layer_keys);
-    let fkey_layer = FunctionLayer::with_config(primary_layer_keys);
-    let layers = if base.media_layer_default.unwrap() {
+    let font_template = base.font_template.unwrap();
+    let (font_face, font) = load_font(&font_template);
+    let show_button_outlines = base.show_button_outlines.unwrap();
+    let scale = match base.scale {
+        Some(scale) if (MIN_SCALE..=MAX_SCALE).contains(&scale) => scale,
+        Some(scale) => {
+            eprintln!("Ignoring Scale = {scale}, it must be between {MIN_SCALE} and {MAX_SCALE}");
+            1.0
+        }
+        None => 1.0,
+    };
+    let safe_area_insets = (
+        base.safe_area_inset_left.unwrap_or_default() as f64,
+        base.safe_area_inset_right.unwrap_or_default() as f64,
+    );
+    let vertical = VerticalLayout {
+        content_height: base.content_height.filter(|h| *h > 0),
+        align: base.vertical_align.unwrap_or_default(),
+    };
+    // 0 squeezes the buttons in however narrow they get
+    let min_button_width = base.min_button_width.unwrap_or(DEFAULT_MIN_BUTTON_WIDTH) as f64;
+    let style = |settings: Option<LayerSettings>| {
+        let settings = settings.unwrap_or_default();
+        let (font_face, font) = match settings.font_template {
+            Some(template) if template != font_template => load_font(&template),
+            _ => (font_face.clone(), font.clone()),
+        };
+        LayerStyle {
+            font_face,
+            font,
+            show_button_outlines: settings.show_button_outlines.unwrap_or(show_button_outlines),
+            insets: safe_area_insets,
+            scale,
+            alpha: static_fn_keys.alpha(),
+            vertical,
+        }
+    };
+    let media_layer_default = base.media_layer_default.unwrap();
+    // 0 keeps the layer up, like leaving it out
+    let timeout = |settings: &Option<LayerSettings>| {
+        let secs = settings.as_ref().and_then(|s| s.layer_timeout_sec).or(base.layer_timeout_sec);
+        secs.filter(|secs| *secs > 0).map(Duration::from_secs)
+    };
+    let layer_timeouts = if media_layer_default {
+        [timeout(&base.media_layer), timeout(&base.primary_layer)]
+    } else {
+        [timeout(&base.primary_layer), timeout(&base.media_layer)]
+    };
+    let media_style = style(base.media_layer);
+    let primary_style = style(base.primary_layer);
+    // they take the place of the default layer, so they are drawn the same way
+    let stand_in = |keys| {
+        let (name, style) = if media_layer_default {
+            ("media", &media_style)
+        } else {
+            ("primary", &primary_style)
+        };
+        let style = LayerStyle {
+            font_face: style.font_face.clone(),
+            font: style.font.clone(),
+            show_button_outlines: style.show_button_outlines,
+            insets: style.insets,
+            scale: style.scale,
+            alpha: style.alpha,
+            vertical: style.vertical,
+        };
+        let mut layer = FunctionLayer::with_config(name, keys, style, fixed);
+        layer.paginate(width, min_button_width);
+        layer
+    };
+    let low_battery_layer = low_battery_layer_keys.map(stand_in);
+    let fullscreen_layer = fullscreen_layer_keys.map(stand_in);
+    let screen_share_layer = screen_share_layer_keys.map(stand_in);
+    let on_battery_layer = on_battery_layer_keys.map(stand_in);
+    let jack_layer = jack_layer_keys.map(stand_in);
+    let display_hotplug_layer = display_hotplug_layer_keys.map(stand_in);
+    let power_menu_layer = power_menu_layer_keys.map(stand_in);
+    let mut media_layer = FunctionLayer::with_config("media", media_layer_keys, media_style, fixed);
+    let mut fkey_layer =
+        FunctionLayer::with_config("primary", primary_layer_keys, primary_style, fixed);
+    if let Some(keys) = media_second_row {
+        media_layer.add_second_row(keys);
+    }
+    if let Some(keys) = primary_second_row {
+        fkey_layer.add_second_row(keys);
+    }
+    media_layer.paginate(width, min_button_width);
+    fkey_layer.paginate(width, min_button_width);
+    let layers = if media_layer_default {
         [media_layer, fkey_layer]
     } else {
         [fkey_layer, media_layer]
     };
+    let focus_mode = base.focus_mode.unwrap_or_default();
+    focus_mode.check();
+    let bar_off = base.bar_off.take().unwrap_or_default();
+    bar_off.check();
+    let night_brightness = base.night_brightness.unwrap_or_default();
+    night_brightness.check();
     let cfg = Config {
-        show_button_outlines: base.show_button_outlines.unwrap(),
         enable_pixel_shift: base.enable_pixel_shift.unwrap(),
         adaptive_brightness: base.adaptive_brightness.unwrap(),
-        font_face: load_font(&base.font_template.unwrap()),
+        font_face,
+        font,
         active_brightness: base.active_brightness.unwrap(),
+        brightness_fade_ms: base.brightness_fade_ms.unwrap_or_default(),
+        dim_brightness: base.dim_brightness.unwrap_or(DIMMED_BRIGHTNESS),
+        software_dimming_below: base.software_dimming_below.unwrap_or_default(),
+        render_mode: base.render_mode.unwrap_or_default(),
+        rotate_180: base.rotate_180.unwrap_or_default(),
+        safe_area_insets,
+        font_options: font_options(base.text_antialias, base.subpixel_order, base.text_hinting),
+        scale,
+        vertical,
+        wake_consumes_touch: base.wake_consumes_touch.unwrap_or(true),
+        layer_timeouts,
+        static_fn_keys,
+        min_brightness: base.min_brightness.unwrap_or(0),
+        max_brightness: base.max_brightness.unwrap_or(255),
+        night_brightness,
         colors: base.colors.unwrap_or_default().to_color_config(),
+        docker_sockets,
+        low_battery,
+        thermal: base.thermal.unwrap_or_default(),
+        brightness_sync: base.brightness_sync.unwrap_or_default(),
+        fn_row: base.fn_row.unwrap_or_default(),
+        splash: base.splash.unwrap_or_default(),
+        usb_toast: base.usb_toast.unwrap_or_default(),
+        bar_off,
+        signals: base.signals.unwrap_or_default(),
+        shortcuts: shortcuts::parse(base.shortcuts.unwrap_or_default()),
+        focus_mode,
+        low_battery_layer,
+        fullscreen,
+        fullscreen_layer,
+        screen_share,
+        screen_share_layer,
+        on_battery_layer,
+        jack,
+        jack_layer,
+        display_hotplug,
+        display_hotplug_layer,
+        power_menu,
+        power_menu_layer,
+        follows_power_supply,
+        privacy: base.privacy.unwrap_or_default(),
+        ipc: base.ipc.unwrap_or_default(),
     };
     (cfg, layers)
 }
@@ -230,11 +1011,26 @@ fn load_config(width: u16) -> (Config, [FunctionLayer; 2]) {
 pub struct ConfigManager {
     inotify_fd: Inotify,
     watch_desc: Option<WatchDescriptor>,
+    options: ConfigOptions,
+    writer: Option<ConfigWriter>,
+    /// The user config as last loaded or written, to tell real changes from
+    /// it just being read or written by the daemon itself
+    contents: Option<String>,
 }
 
-fn arm_inotify(inotify_fd: &Inotify) -> Option<WatchDescriptor> {
+/// The profile saved in the user config, used when starting
+fn saved_profile(user_path: &Path) -> Option<String> {
+    let profile = read_config(user_path).ok()?.profile?;
+    if profile.contains('/') || !profile_path(&profile).is_file() {
+        eprintln!("Ignoring unknown profile {profile}");
+        return None;
+    }
+    Some(profile)
+}
+
+fn arm_inotify(inotify_fd: &Inotify, path: &Path) -> Option<WatchDescriptor> {
     let flags = AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_CLOSE | AddWatchFlags::IN_ONESHOT;
-    match inotify_fd.add_watch(USER_CFG_PATH, flags) {
+    match inotify_fd.add_watch(path, flags) {
         Ok(wd) => Some(wd),
         Err(Errno::ENOENT) => None,
         e => Some(e.unwrap()),
@@ -242,16 +1038,189 @@ fn arm_inotify(inotify_fd: &Inotify) -> Option<WatchDescriptor> {
 }
 
 impl ConfigManager {
-    pub fn new() -> ConfigManager {
+    pub fn new(user_path: Option<PathBuf>, system_path: Option<PathBuf>) -> ConfigManager {
+        let mut options = ConfigOptions::new(user_path, system_path);
+        options.profile = saved_profile(&options.user_path);
         let inotify_fd = Inotify::init(InitFlags::IN_NONBLOCK).unwrap();
-        let watch_desc = arm_inotify(&inotify_fd);
+        let watch_desc = arm_inotify(&inotify_fd, &options.user_path);
         ConfigManager {
             inotify_fd,
             watch_desc,
+            options,
+            writer: None,
+            contents: None,
         }
     }
-    pub fn load_config(&self, width: u16) -> (Config, [FunctionLayer; 2]) {
-        load_config(width)
+    pub fn load_config(&mut self, width: u16) -> (Config, [FunctionLayer; 2]) {
+        self.contents = read_to_string(&self.options.user_path).ok();
+        load_config(width, &self.options)
+    }
+    /// The config as it would be loaded, for `tiny-dfr --dump-config`
+    pub fn dump_config(&self) -> Result<String, Error> {
+        Ok(toml::to_string(&resolve_config(&self.options))?)
+    }
+    /// Starts the helper that saves changes made on the bar, see
+    /// `writeback::start` for when this can be called
+    pub fn start_writer(&mut self) -> Result<(), Error> {
+        self.writer = Some(writeback::start(&self.options.user_path, Path::new(PROFILES_DIR))?);
+        Ok(())
+    }
+    fn writer(&self) -> Result<&ConfigWriter, Error> {
+        self.writer.as_ref().ok_or(anyhow!("changes to the config cannot be saved"))
+    }
+    /// Saves a changed config file, if it still loads. Writing it does not
+    /// count as a change for the watcher, the caller reloads if needed.
+    fn write(&mut self, path: &Path, doc: &toml_edit::DocumentMut) -> Result<(), Error> {
+        let contents = doc.to_string();
+        toml::from_str::<toml::Table>(&contents)?.try_into::<ConfigProxy>()?;
+        self.writer()?.write(path, doc)?;
+        if path == self.options.user_path {
+            self.contents = Some(contents);
+        }
+        Ok(())
+    }
+    /// Changes a setting in the user config, e.g. `ActiveBrightness` or
+    /// `Splash.Text` for one in a section, after checking the value against
+    /// `settings::SETTINGS`. An empty value removes it from the user config.
+    pub fn set_setting(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let setting = settings::find(key).map_err(Error::msg)?;
+        let value = match value {
+            "" => None,
+            value => Some(setting.parse(value).map_err(Error::msg)?),
+        };
+        let user_path = self.options.user_path.clone();
+        let mut doc = self.writer()?.read(&user_path)?;
+        let (sections, name) = match key.rsplit_once('.') {
+            Some((sections, name)) => (sections.split('.').collect(), name),
+            None => (Vec::new(), key),
+        };
+        let mut table = doc.as_table_mut();
+        for section in sections {
+            table = table
+                .entry(section)
+                .or_insert(toml_edit::table())
+                .as_table_mut()
+                .ok_or(anyhow!("{section} is not a section"))?;
+        }
+        match value {
+            Some(value) => {
+                table.insert(name, toml_edit::value(value));
+            }
+            None => {
+                table.remove(name);
+            }
+        }
+        self.write(&user_path, &doc)
+    }
+    /// The value in effect of every setting in `settings::SETTINGS`, null
+    /// for those not set anywhere
+    pub fn settings(&self) -> Result<Value, Error> {
+        let config = toml::Table::try_from(resolve_config(&self.options))?;
+        let values = settings::SETTINGS
+            .iter()
+            .map(|setting| {
+                let mut value = None;
+                let mut table = Some(&config);
+                for part in setting.key.split('.') {
+                    value = table.and_then(|t| t.get(part));
+                    table = value.and_then(toml::Value::as_table);
+                }
+                let value = value.map(settings::to_json).unwrap_or(Value::Null);
+                (setting.key.to_string(), value)
+            })
+            .collect();
+        Ok(Value::Object(values))
+    }
+    /// Saves a new order of a layer's buttons, given as indices into its
+    /// keys as configured, to the file the keys come from
+    pub fn save_layer_order(&mut self, layer: &str, order: &[usize]) -> Result<(), Error> {
+        let writer = self.writer()?;
+        let key = match layer {
+            "primary" => "PrimaryLayerKeys",
+            "media" => "MediaLayerKeys",
+            _ => return Err(anyhow!("the {layer} layer cannot be changed")),
+        };
+        // the keys in effect are the ones of the last file that has them
+        let profile = self.options.profile.as_deref().map(profile_path);
+        let mut target = None;
+        for path in profile.iter().chain([&self.options.user_path]) {
+            let doc = writer.read(path)?;
+            if doc.contains_key(key) {
+                target = Some((path.clone(), doc));
+                break;
+            }
+        }
+        let (path, mut doc) = match target {
+            Some(target) => target,
+            None => {
+                // still the system ones, which are copied to the user config to change them
+                let system = writer.read(&self.options.system_path)?;
+                let keys = system
+                    .get(key)
+                    .ok_or(anyhow!("no {key} in {}", self.options.system_path.display()))?;
+                let mut doc = writer.read(&self.options.user_path)?;
+                doc.insert(key, keys.clone());
+                (self.options.user_path.clone(), doc)
+            }
+        };
+        let changed = || anyhow!("{key} in {} changed since it was loaded", path.display());
+        match doc.get_mut(key) {
+            Some(toml_edit::Item::Value(toml_edit::Value::Array(keys))) => {
+                if keys.len() != order.len() {
+                    return Err(changed());
+                }
+                let buttons: Vec<_> = keys.iter().cloned().collect();
+                keys.clear();
+                for &i in order {
+                    keys.push_formatted(buttons[i].clone());
+                }
+            }
+            Some(toml_edit::Item::ArrayOfTables(keys)) => {
+                if keys.len() != order.len() {
+                    return Err(changed());
+                }
+                let buttons: Vec<_> = keys.iter().cloned().collect();
+                keys.clear();
+                for &i in order {
+                    keys.push(buttons[i].clone());
+                }
+            }
+            _ => return Err(anyhow!("{key} in {} is not a list", path.display())),
+        }
+        self.write(&path, &doc)
+    }
+    pub fn profile(&self) -> Option<&str> {
+        self.options.profile.as_deref()
+    }
+    /// Selects the profile used from the next reload on, `None` for just
+    /// the user config
+    pub fn set_profile(&mut self, name: Option<String>) -> Result<(), String> {
+        if let Some(name) = &name {
+            if name.contains('/') || !profile_path(name).is_file() {
+                return Err(format!("unknown profile {name}"));
+            }
+        }
+        self.options.profile = name;
+        Ok(())
+    }
+    /// Like `set_profile`, and saves it in the user config so that it is
+    /// used again after restarting. Failing to save it still switches.
+    pub fn switch_profile(&mut self, name: Option<String>) -> Result<(), String> {
+        self.set_profile(name.clone())?;
+        if let Err(e) = self.save_profile(name) {
+            eprintln!("Failed to save the profile: {e:#}");
+        }
+        Ok(())
+    }
+    /// Saves the profile in the user config, for `set_profile`
+    pub fn save_profile(&mut self, name: Option<String>) -> Result<(), Error> {
+        let user_path = self.options.user_path.clone();
+        let mut doc = self.writer()?.read(&user_path)?;
+        match name {
+            Some(name) => doc.insert("Profile", toml_edit::value(name)),
+            None => doc.remove("Profile"),
+        };
+        self.write(&user_path, &doc)
     }
     pub fn update_config(
         &mut self,
@@ -260,7 +1229,7 @@ impl ConfigManager {
         width: u16,
     ) -> bool {
         if self.watch_desc.is_none() {
-            self.watch_desc = arm_inotify(&self.inotify_fd);
+            self.watch_desc = arm_inotify(&self.inotify_fd, &self.options.user_path);
             return false;
         }
         match self.inotify_fd.read_events() {
@@ -268,6 +1237,10 @@ impl ConfigManager {
             r => self.handle_events(cfg, layers, width, r),
         }
     }
+    /// Loads the config again even though it did not change
+    pub fn reload(&mut self, cfg: &mut Config, layers: &mut [FunctionLayer; 2], width: u16) {
+        (*cfg, *layers) = self.load_config(width);
+    }
     #[cold]
     fn handle_events(&mut self, cfg: &mut Config, layers: &mut [FunctionLayer; 2], width: u16, evts: Result<Vec<InotifyEvent>, Errno>) -> bool {
         let mut ret = false;
@@ -275,11 +1248,14 @@ impl ConfigManager {
             if Some(evt.wd) != self.watch_desc {
                 continue;
             }
-            let parts = load_config(width);
-            *cfg = parts.0;
-            *layers = parts.1;
-            ret = true;
-            self.watch_desc = arm_inotify(&self.inotify_fd);
+            // reading it, or writing it from here, also wakes up the watcher
+            let contents = read_to_string(&self.options.user_path).ok();
+            if contents != self.contents {
+                self.options.profile = saved_profile(&self.options.user_path);
+                (*cfg, *layers) = self.load_config(width);
+                ret = true;
+            }
+            self.watch_desc = arm_inotify(&self.inotify_fd, &self.options.user_path);
         }
         ret
     }
@@ -0,0 +1,400 @@
+use crate::auth::{self, IpcConfig};
+use crate::ipc::{Access, Event, IpcClient, Request};
+use crate::json::Value;
+use crate::settings::{self, Setting};
+use anyhow::Result;
+use gio::{BusNameOwnerFlags, BusType, DBusConnection, DBusNodeInfo};
+use glib::{prelude::*, MainContext, MainLoop};
+use std::{collections::HashMap, thread};
+
+const BUS_NAME: &str = "org.asahilinux.TinyDFR";
+const OBJECT_PATH: &str = "/org/asahilinux/TinyDFR";
+
+const INTROSPECTION_XML: &str = r#"
+<node>
+  <interface name="org.asahilinux.TinyDFR">
+    <method name="SetButtonText">
+      <arg type="s" name="id" direction="in"/>
+      <arg type="s" name="text" direction="in"/>
+    </method>
+    <method name="SetButtonIcon">
+      <arg type="s" name="id" direction="in"/>
+      <arg type="s" name="path" direction="in"/>
+    </method>
+    <method name="FlashButton">
+      <arg type="s" name="id" direction="in"/>
+      <arg type="s" name="color" direction="in"/>
+      <arg type="u" name="duration_ms" direction="in"/>
+    </method>
+    <method name="SetProgress">
+      <arg type="s" name="id" direction="in"/>
+      <arg type="d" name="fraction" direction="in"/>
+    </method>
+    <method name="SetButtonEnabled">
+      <arg type="s" name="id" direction="in"/>
+      <arg type="b" name="enabled" direction="in"/>
+    </method>
+    <method name="ShowToast">
+      <arg type="s" name="text" direction="in"/>
+      <arg type="u" name="timeout_ms" direction="in"/>
+      <arg type="s" name="icon" direction="in"/>
+    </method>
+    <method name="SetLayer">
+      <arg type="s" name="layer" direction="in"/>
+    </method>
+    <method name="SetBrightness">
+      <arg type="i" name="brightness" direction="in"/>
+    </method>
+    <method name="SwitchProfile">
+      <arg type="s" name="name" direction="in"/>
+    </method>
+    <method name="SetSetting">
+      <arg type="s" name="key" direction="in"/>
+      <arg type="s" name="value" direction="in"/>
+    </method>
+    <method name="SetEditMode">
+      <arg type="b" name="enabled" direction="in"/>
+    </method>
+    <method name="SetFocusMode">
+      <arg type="i" name="enabled" direction="in"/>
+    </method>
+    <method name="SetPrivacyMode">
+      <arg type="i" name="enabled" direction="in"/>
+    </method>
+    <method name="SetBarEnabled">
+      <arg type="b" name="enabled" direction="in"/>
+    </method>
+    <method name="SetChargeLimit">
+      <arg type="u" name="limit" direction="in"/>
+    </method>
+    <method name="GetLayer">
+      <arg type="s" name="layer" direction="out"/>
+    </method>
+    <method name="GetBrightness">
+      <arg type="u" name="brightness" direction="out"/>
+    </method>
+    <method name="GetState">
+      <arg type="s" name="state" direction="out"/>
+    </method>
+    <method name="GetAllSettings">
+      <arg type="s" name="settings" direction="out"/>
+    </method>
+    <signal name="ButtonPressed">
+      <arg type="s" name="layer"/>
+      <arg type="s" name="id"/>
+    </signal>
+    <signal name="ButtonReleased">
+      <arg type="s" name="layer"/>
+      <arg type="s" name="id"/>
+    </signal>
+    <signal name="LayerChanged">
+      <arg type="s" name="layer"/>
+    </signal>
+    <signal name="BrightnessChanged">
+      <arg type="u" name="brightness"/>
+    </signal>
+    <signal name="ConfigReloaded"/>
+  </interface>
+</node>
+"#;
+
+fn parse_request(method: &str, params: &glib::Variant) -> Option<Request> {
+    match method {
+        "SetButtonText" => {
+            let (id, text) = params.get::<(String, String)>()?;
+            Some(Request::SetButtonText { id, text })
+        }
+        "SetButtonIcon" => {
+            let (id, path) = params.get::<(String, String)>()?;
+            Some(Request::SetButtonIcon { id, path })
+        }
+        "FlashButton" => {
+            let (id, color, duration_ms) = params.get::<(String, String, u32)>()?;
+            Some(Request::FlashButton {
+                id,
+                color,
+                duration_ms: duration_ms.into(),
+            })
+        }
+        "SetProgress" => {
+            let (id, fraction) = params.get::<(String, f64)>()?;
+            Some(Request::SetProgress { id, fraction })
+        }
+        "SetButtonEnabled" => {
+            let (id, enabled) = params.get::<(String, bool)>()?;
+            Some(Request::SetButtonEnabled { id, enabled })
+        }
+        "ShowToast" => {
+            // an empty icon shows just the text
+            let (text, timeout_ms, icon) = params.get::<(String, u32, String)>()?;
+            Some(Request::ShowToast {
+                text,
+                icon: Some(icon).filter(|icon| !icon.is_empty()),
+                timeout_ms: timeout_ms.into(),
+            })
+        }
+        "SetLayer" => {
+            let (layer,) = params.get::<(String,)>()?;
+            Some(Request::SetLayer { layer })
+        }
+        "SetBrightness" => {
+            // negative values go back to the configured brightness
+            let (brightness,) = params.get::<(i32,)>()?;
+            Some(Request::SetBrightness {
+                brightness: u32::try_from(brightness).ok().map(|b| b.min(255)),
+            })
+        }
+        "SwitchProfile" => {
+            // an empty name goes back to just the config
+            let (name,) = params.get::<(String,)>()?;
+            Some(Request::SwitchProfile {
+                name: Some(name).filter(|name| !name.is_empty()),
+            })
+        }
+        "SetSetting" => {
+            let (key, value) = params.get::<(String, String)>()?;
+            Some(Request::SetSetting { key, value })
+        }
+        "SetEditMode" => {
+            let (enabled,) = params.get::<(bool,)>()?;
+            Some(Request::SetEditMode { enabled })
+        }
+        "SetFocusMode" => {
+            // negative values go back to following the schedule
+            let (enabled,) = params.get::<(i32,)>()?;
+            Some(Request::SetFocusMode {
+                enabled: (enabled >= 0).then_some(enabled > 0),
+            })
+        }
+        "SetPrivacyMode" => {
+            // negative values go back to following the screen and lock
+            let (enabled,) = params.get::<(i32,)>()?;
+            Some(Request::SetPrivacyMode {
+                enabled: (enabled >= 0).then_some(enabled > 0),
+            })
+        }
+        "SetBarEnabled" => {
+            let (enabled,) = params.get::<(bool,)>()?;
+            Some(Request::SetBarEnabled { enabled })
+        }
+        "SetChargeLimit" => {
+            let (limit,) = params.get::<(u32,)>()?;
+            Some(Request::SetChargeLimit { limit })
+        }
+        "GetLayer" => Some(Request::GetLayer),
+        "GetBrightness" => Some(Request::GetBrightness),
+        "GetState" => Some(Request::GetState),
+        "GetAllSettings" => Some(Request::GetAllSettings),
+        _ => None,
+    }
+}
+
+fn to_variant(value: Value) -> Option<glib::Variant> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some((s,).to_variant()),
+        Value::Number(n) => Some((n as u32,).to_variant()),
+        Value::Bool(b) => Some((b,).to_variant()),
+        v => Some((v.to_string(),).to_variant()),
+    }
+}
+
+/// The interface with a property for every setting, named like the key
+/// without dots, e.g. `SplashText` for `Splash.Text`
+fn introspection_xml() -> String {
+    let properties: String = settings::SETTINGS
+        .iter()
+        .map(|setting| {
+            format!(
+                "    <property type=\"{}\" name=\"{}\" access=\"readwrite\"/>\n",
+                setting.signature(),
+                setting.property()
+            )
+        })
+        .collect();
+    INTROSPECTION_XML.replace("  </interface>", &format!("{properties}  </interface>"))
+}
+
+/// A setting's value as its property, settings that are not set anywhere
+/// read as empty, false or 0
+fn property_variant(setting: &Setting, value: Option<&Value>) -> glib::Variant {
+    match setting.signature() {
+        "b" => matches!(value, Some(Value::Bool(true))).to_variant(),
+        "x" => (value.and_then(Value::as_f64).unwrap_or_default() as i64).to_variant(),
+        "d" => value.and_then(Value::as_f64).unwrap_or_default().to_variant(),
+        _ => value.and_then(Value::as_str).unwrap_or_default().to_variant(),
+    }
+}
+
+/// The value of a property being set, as `SetSetting` takes it
+fn property_value(value: &glib::Variant) -> Option<String> {
+    if let Some(s) = value.get::<String>() {
+        return Some(s);
+    }
+    if let Some(b) = value.get::<bool>() {
+        return Some(b.to_string());
+    }
+    if let Some(n) = value.get::<i64>() {
+        return Some(n.to_string());
+    }
+    value.get::<f64>().map(|n| n.to_string())
+}
+
+fn all_settings(client: &IpcClient) -> Option<Value> {
+    client
+        .call(Request::GetAllSettings)
+        .map_err(|e| eprintln!("Failed to read the settings: {e}"))
+        .ok()
+}
+
+/// Tells settings apps which properties changed with a reload
+fn emit_properties_changed(connection: &DBusConnection, old: &Value, new: &Value) {
+    let changed: HashMap<String, glib::Variant> = settings::SETTINGS
+        .iter()
+        .filter(|setting| old.get(setting.key) != new.get(setting.key))
+        .map(|setting| (setting.property(), property_variant(setting, new.get(setting.key))))
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+    let params = (BUS_NAME, changed, Vec::<String>::new()).to_variant();
+    let res = connection.emit_signal(
+        None,
+        OBJECT_PATH,
+        "org.freedesktop.DBus.Properties",
+        "PropertiesChanged",
+        Some(&params),
+    );
+    if let Err(e) = res {
+        eprintln!("Failed to emit D-Bus signal: {e}");
+    }
+}
+
+fn emit_events(connection: DBusConnection, client: IpcClient) {
+    let mut settings = all_settings(&client);
+    // subscribes again after falling behind, which loses the events between
+    loop {
+        for event in client.subscribe() {
+            emit_event(&connection, &client, &mut settings, event);
+        }
+        eprintln!("D-Bus signals fell behind, some events were not sent");
+    }
+}
+
+fn emit_event(
+    connection: &DBusConnection,
+    client: &IpcClient,
+    settings: &mut Option<Value>,
+    event: Event,
+) {
+    if let Event::ConfigReloaded = event {
+        let new = all_settings(client);
+        if let (Some(old), Some(new)) = (&*settings, &new) {
+            emit_properties_changed(connection, old, new);
+        }
+        *settings = new.or(settings.take());
+    }
+    let params = match &event {
+        Event::ButtonPressed { layer, id } | Event::ButtonReleased { layer, id } => {
+            Some((layer, id).to_variant())
+        }
+        Event::LayerChanged { layer } => Some((layer,).to_variant()),
+        Event::BrightnessChanged { brightness } => Some((brightness,).to_variant()),
+        Event::ConfigReloaded => None,
+    };
+    let res = connection.emit_signal(
+        None,
+        OBJECT_PATH,
+        BUS_NAME,
+        event.name(),
+        params.as_ref(),
+    );
+    if let Err(e) = res {
+        eprintln!("Failed to emit D-Bus signal: {e}");
+    }
+}
+
+fn serve(connection: DBusConnection, client: IpcClient, polkit: bool) {
+    let ctx = MainContext::new();
+    let main_loop = MainLoop::new(Some(&ctx), false);
+    ctx.with_thread_default(|| {
+        let node = DBusNodeInfo::for_xml(&introspection_xml()).unwrap();
+        let interface = node.lookup_interface(BUS_NAME).unwrap();
+        let get_client = client.clone();
+        let set_client = client.clone();
+        let registration = connection
+            .register_object(OBJECT_PATH, &interface)
+            .property(move |_, _, _, _, name| {
+                let setting = settings::find_property(name).unwrap();
+                let values = all_settings(&get_client);
+                property_variant(setting, values.as_ref().and_then(|v| v.get(setting.key)))
+            })
+            .set_property(move |connection, sender, _, _, name, value| {
+                if !auth::bus_allowed(&connection, sender, Access::Configure, polkit) {
+                    return false;
+                }
+                let setting = settings::find_property(name).unwrap();
+                let Some(value) = property_value(&value) else {
+                    return false;
+                };
+                let request = Request::SetSetting {
+                    key: setting.key.to_string(),
+                    value,
+                };
+                match set_client.call(request) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        eprintln!("Failed to set {name}: {e}");
+                        false
+                    }
+                }
+            })
+            .method_call(move |connection, sender, _, _, method, params, invocation| {
+                let Some(request) = parse_request(method, &params) else {
+                    invocation.return_dbus_error(
+                        "org.freedesktop.DBus.Error.InvalidArgs",
+                        &format!("Invalid arguments for {method}"),
+                    );
+                    return;
+                };
+                if !auth::bus_allowed(&connection, sender, request.access(), polkit) {
+                    invocation.return_dbus_error(
+                        "org.freedesktop.DBus.Error.AccessDenied",
+                        &format!("Not allowed to call {method}"),
+                    );
+                    return;
+                }
+                match client.call(request) {
+                    Ok(value) => invocation.return_value(to_variant(value).as_ref()),
+                    Err(e) => invocation.return_dbus_error("org.asahilinux.TinyDFR.Error", &e),
+                }
+            })
+            .build();
+        if let Err(e) = registration {
+            eprintln!("Failed to register D-Bus object: {e}");
+            return;
+        }
+        gio::bus_own_name_on_connection(
+            &connection,
+            BUS_NAME,
+            BusNameOwnerFlags::NONE,
+            |_, _| {},
+            |_, name| eprintln!("Lost D-Bus name {name}"),
+        );
+        main_loop.run();
+    })
+    .unwrap();
+}
+
+/// Connects to the system bus and serves the control interface on a
+/// separate thread. Needs to be called before dropping privileges,
+/// as only root is allowed to own the bus name.
+pub fn start(client: IpcClient, cfg: &IpcConfig) -> Result<()> {
+    let connection = gio::bus_get_sync(BusType::System, gio::Cancellable::NONE)?;
+    let signal_connection = connection.clone();
+    let signal_client = client.clone();
+    let polkit = cfg.polkit.unwrap_or(true);
+    thread::spawn(move || emit_events(signal_connection, signal_client));
+    thread::spawn(move || serve(connection, client, polkit));
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use crate::config::ButtonConfig;
+use crate::json::Value;
+use crate::ratelimit;
+use crate::text;
+use crate::widget::{Canvas, Scrub, Widget};
+use anyhow::{anyhow, Result};
+use input_linux::Key;
+use serde::{Deserialize, Serialize};
+use std::{f64::consts::PI, time::Instant};
+
+// A knob turned by dragging along it, for volume, brightness or zoom: each
+// step sends the key that changes it by one, and dragging faster takes more
+// steps for the same distance, so that a long way is quick to go and a small
+// change still precise. Zoom sends Ctrl+= and Ctrl+-, which zoom like Ctrl
+// and the scroll wheel in most applications.
+//
+// The level shown is counted from the steps sent, starting at Start, as the
+// daemon cannot read it back from the session. Changed elsewhere, it drifts
+// until it hits either end.
+
+const DEFAULT_STEPS: u32 = 16;
+const DEFAULT_START: u32 = 50;
+/// Steps for dragging slowly across the whole dial
+const STEPS_PER_WIDTH: f64 = 4.0;
+/// How much each width per second of speed adds to that, and the most it
+/// is multiplied by
+const ACCELERATION: f64 = 0.8;
+const MAX_ACCELERATION: f64 = 6.0;
+const ZOOM_STEP: f64 = 10.0;
+const ZOOM_RANGE: (f64, f64) = (30.0, 300.0);
+const ARC_LINE_PX: f64 = 4.0;
+const ARC_SPACING_PX: f64 = 12.0;
+const TRACK_ALPHA: f64 = 0.3;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum DialControl {
+    Volume,
+    Brightness,
+    Zoom,
+}
+
+impl DialControl {
+    /// The keys going down and up by a step, with the modifier they take
+    fn keys(self) -> (Option<Key>, Key, Key) {
+        match self {
+            DialControl::Volume => (None, Key::VolumeDown, Key::VolumeUp),
+            DialControl::Brightness => (None, Key::BrightnessDown, Key::BrightnessUp),
+            DialControl::Zoom => (Some(Key::LeftCtrl), Key::Minus, Key::Equal),
+        }
+    }
+}
+
+/// The `Dial` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DialConfig {
+    pub control: DialControl,
+    /// Steps from the lowest level to the highest, for volume and brightness
+    pub steps: Option<u32>,
+    /// The level shown at first, in percent, for volume and brightness
+    pub start: Option<u32>,
+}
+
+pub struct Dial {
+    control: DialControl,
+    /// In percent
+    level: f64,
+    step: f64,
+    range: (f64, f64),
+    /// Where the finger was last, across the dial, and when
+    last: Option<(f64, Instant)>,
+    /// Steps dragged but not yet sent, less than one either way
+    pending: f64,
+    changed: bool,
+}
+
+impl Dial {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Widget>> {
+        let cfg: DialConfig = value.clone().try_into()?;
+        let steps = cfg.steps.unwrap_or(DEFAULT_STEPS);
+        if steps == 0 {
+            return Err(anyhow!("Dial needs at least one step"));
+        }
+        let (level, step, range) = match cfg.control {
+            DialControl::Zoom => (100.0, ZOOM_STEP, ZOOM_RANGE),
+            _ => (cfg.start.unwrap_or(DEFAULT_START).min(100) as f64, 100.0 / steps as f64, (0.0, 100.0)),
+        };
+        Ok(Box::new(Dial {
+            control: cfg.control,
+            level,
+            step,
+            range,
+            last: None,
+            pending: 0.0,
+            changed: true,
+        }))
+    }
+    fn send_step(&mut self, up: bool, send: &mut dyn FnMut(Key, bool)) {
+        if !ratelimit::allow_key() {
+            return;
+        }
+        let (modifier, down_key, up_key) = self.control.keys();
+        let key = if up { up_key } else { down_key };
+        if let Some(modifier) = modifier {
+            send(modifier, true);
+        }
+        send(key, true);
+        send(key, false);
+        if let Some(modifier) = modifier {
+            send(modifier, false);
+        }
+        let step = if up { self.step } else { -self.step };
+        self.level = (self.level + step).clamp(self.range.0, self.range.1);
+        self.changed = true;
+    }
+    /// How far round the arc is filled, from 0 to 1
+    fn fraction(&self) -> f64 {
+        (self.level - self.range.0) / (self.range.1 - self.range.0)
+    }
+}
+
+impl Widget for Dial {
+    fn name(&self) -> &'static str {
+        "Dial"
+    }
+    fn update(&mut self, _power_supply_changed: bool) -> (bool, i32) {
+        (std::mem::take(&mut self.changed), i32::MAX)
+    }
+    fn draw(&self, canvas: &Canvas) {
+        let c = canvas.c;
+        let [r, g, b] = canvas.text_color;
+        let text = format!("{:.0}%", self.level);
+        let layout = text::layout(c, canvas.font, &text, canvas.text_direction);
+        let (text_width, text_height) = text::ink_size(&layout);
+        let radius = canvas.height * 0.28;
+        let content_width = radius * 2.0 + ARC_SPACING_PX + text_width;
+        let left = canvas.left + ((canvas.width - content_width) / 2.0).round();
+        let (x, y) = (left + radius, canvas.y_shift + canvas.height / 2.0);
+        // three quarters of a turn, open at the bottom
+        let start = 0.75 * PI;
+        let sweep = 1.5 * PI;
+        c.set_line_width(ARC_LINE_PX);
+        c.new_sub_path();
+        c.set_source_rgba(r, g, b, TRACK_ALPHA);
+        c.arc(x, y, radius, start, start + sweep);
+        c.stroke().unwrap();
+        c.set_source_rgb(r, g, b);
+        if self.fraction() > 0.0 {
+            c.new_sub_path();
+            c.arc(x, y, radius, start, start + sweep * self.fraction());
+            c.stroke().unwrap();
+        }
+        c.move_to(
+            left + radius * 2.0 + ARC_SPACING_PX,
+            canvas.y_shift + ((canvas.height + text_height) / 2.0).round(),
+        );
+        text::show(c, &layout);
+    }
+    fn preferred_size(&self) -> usize {
+        2
+    }
+    fn scrubs(&self) -> bool {
+        true
+    }
+    fn on_scrub(&mut self, scrub: Scrub, send: &mut dyn FnMut(Key, bool)) {
+        match scrub {
+            Scrub::Down(x) => {
+                self.last = Some((x, Instant::now()));
+                self.pending = 0.0;
+            }
+            Scrub::Move(x) => {
+                let Some((last_x, last_at)) = self.last else {
+                    return;
+                };
+                let now = Instant::now();
+                let dx = x - last_x;
+                let secs = (now - last_at).as_secs_f64().max(0.001);
+                let acceleration = (1.0 + ACCELERATION * dx.abs() / secs).min(MAX_ACCELERATION);
+                self.pending += dx * STEPS_PER_WIDTH * acceleration;
+                self.last = Some((x, now));
+                while self.pending.abs() >= 1.0 {
+                    let up = self.pending > 0.0;
+                    self.pending -= self.pending.signum();
+                    self.send_step(up, send);
+                }
+            }
+            Scrub::Up => self.last = None,
+        }
+    }
+    fn keys(&self) -> Vec<Key> {
+        let (modifier, down, up) = self.control.keys();
+        modifier.into_iter().chain([down, up]).collect()
+    }
+    fn state(&self, _fields: &mut Vec<(String, Value)>) -> (&'static str, Value) {
+        ("dial", (self.level.round() as u32).into())
+    }
+}
@@ -0,0 +1,182 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::source::{self, Background, Job, Source, Update};
+use anyhow::{anyhow, Result};
+use chrono::Locale;
+use nix::sys::statvfs::statvfs;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+// Shows the free space on a filesystem, or how fast disks are being read
+// and written, from the sector counts in /proc/diskstats.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 2000;
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiskMode {
+    #[default]
+    Free,
+    Io,
+}
+
+/// The `Disk` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DiskConfig {
+    pub show: Option<DiskMode>,
+    pub mount: Option<String>,
+    pub device: Option<String>,
+    pub format: Option<String>,
+    pub locale: Option<String>,
+}
+
+/// Formats a byte count with a binary prefix, keeping it short
+fn human(bytes: f64, locale: Locale) -> String {
+    const UNITS: &[&str] = &["B", "K", "M", "G", "T", "P"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 && unit > 0 {
+        format!("{}{}", i18n::decimal(value, 1, locale), UNITS[unit])
+    } else {
+        format!("{}{}", i18n::decimal(value, 0, locale), UNITS[unit])
+    }
+}
+
+/// Partitions, loop devices and device mapper targets would be counted
+/// twice, so by default only whole physical disks are summed up.
+fn is_disk(name: &str) -> bool {
+    Path::new("/sys/block").join(name).exists()
+        && !["loop", "ram", "zram", "dm-", "md"].iter().any(|p| name.starts_with(p))
+}
+
+/// Returns the total number of bytes read and written
+fn read_diskstats(device: Option<&str>) -> Result<(u64, u64)> {
+    let stats = fs::read_to_string("/proc/diskstats")?;
+    let mut found = false;
+    let (mut read, mut written) = (0, 0);
+    for line in stats.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [_, _, name, _, _, sectors_read, _, _, _, sectors_written, ..] = fields[..] else {
+            continue;
+        };
+        let wanted = match device {
+            Some(device) => name == device,
+            None => is_disk(name),
+        };
+        if !wanted {
+            continue;
+        }
+        found = true;
+        read += sectors_read.parse::<u64>().unwrap_or(0) * SECTOR_SIZE;
+        written += sectors_written.parse::<u64>().unwrap_or(0) * SECTOR_SIZE;
+    }
+    if !found {
+        return Err(match device {
+            Some(device) => anyhow!("no disk named {device} in /proc/diskstats"),
+            None => anyhow!("no disks in /proc/diskstats"),
+        });
+    }
+    Ok((read, written))
+}
+
+struct Sample {
+    at: Instant,
+    read: u64,
+    written: u64,
+}
+
+pub struct Disk {
+    cfg: DiskConfig,
+    last: Option<Sample>,
+    locale: Locale,
+}
+
+impl Disk {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let disk = Disk::new(source::parse(value)?);
+        Ok(Box::new(Background::new(disk, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS))))
+    }
+    pub fn new(cfg: DiskConfig) -> Disk {
+        Disk {
+            locale: i18n::resolve(cfg.locale.as_deref()),
+            cfg,
+            last: None,
+        }
+    }
+    /// Returns the text to show and how much of the filesystem is used, in percent
+    fn free(&self) -> Result<(String, Option<f64>)> {
+        let mount = self.cfg.mount.as_deref().unwrap_or("/");
+        let stat = statvfs(mount)?;
+        let fragment = stat.fragment_size() as f64;
+        let size = stat.blocks() as f64 * fragment;
+        let free = stat.blocks_available() as f64 * fragment;
+        let used = size - stat.blocks_free() as f64 * fragment;
+        let percent = if size > 0.0 { used / size * 100.0 } else { 0.0 };
+        let format = self.cfg.format.as_deref().unwrap_or("{free}");
+        let text = format
+            .replace("{free}", &human(free, self.locale))
+            .replace("{used}", &human(used, self.locale))
+            .replace("{size}", &human(size, self.locale))
+            .replace("{percent}", &format!("{percent:.0}"));
+        Ok((text, Some(percent)))
+    }
+    /// Returns the text to show and the bytes per second read and written together
+    fn io(&mut self) -> Result<(String, Option<f64>)> {
+        let (read, written) = read_diskstats(self.cfg.device.as_deref())?;
+        let now = Sample {
+            at: Instant::now(),
+            read,
+            written,
+        };
+        let rates = self.last.as_ref().map(|last| {
+            let secs = now.at.duration_since(last.at).as_secs_f64().max(0.001);
+            (
+                now.read.saturating_sub(last.read) as f64 / secs,
+                now.written.saturating_sub(last.written) as f64 / secs,
+            )
+        });
+        self.last = Some(now);
+        let (read, write) = match rates {
+            Some((read, write)) => (human(read, self.locale), human(write, self.locale)),
+            // the first sample only gives a starting point
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let format = self.cfg.format.as_deref().unwrap_or("R {read} W {write}");
+        let text = format.replace("{read}", &read).replace("{write}", &write);
+        Ok((text, rates.map(|(read, write)| read + write)))
+    }
+}
+
+impl Job for Disk {
+    const MIN_INTERVAL: Duration = Duration::from_millis(500);
+
+    fn refresh(&mut self) -> Vec<Update> {
+        let text = match self.cfg.show.unwrap_or_default() {
+            DiskMode::Free => self.free(),
+            DiskMode::Io => self.io(),
+        };
+        match text {
+            Ok((text, sample)) => {
+                let mut updates = vec![Update::Text(text)];
+                updates.extend(sample.map(Update::Sample));
+                updates
+            }
+            Err(e) => {
+                eprintln!("Failed to read disk usage: {e:#}");
+                vec![Update::Text(i18n::tr("N/A"))]
+            }
+        }
+    }
+    fn touch(&mut self, _pressed: bool) -> bool {
+        false
+    }
+}
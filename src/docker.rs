@@ -0,0 +1,135 @@
+use crate::config::ButtonConfig;
+use crate::http;
+use crate::json::Value;
+use crate::source::{self, Background, Job, Source, Update};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Talks to the Docker Engine API on its Unix socket, which Podman provides
+// as well (systemctl enable --now podman.socket).
+
+pub const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 5000;
+
+/// The server settings shared by all Docker buttons
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct DockerServer {
+    pub socket: Option<String>,
+}
+
+/// The `[Docker]` section
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct DockerSection {
+    #[serde(flatten)]
+    pub server: DockerServer,
+    /// Whether to keep the group owning the socket when dropping privileges,
+    /// which is as good as root on most systems, so it has to be asked for
+    pub keep_socket_group: Option<bool>,
+}
+
+/// The `Docker` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DockerConfig {
+    pub container: Option<String>,
+    pub format: Option<String>,
+    #[serde(flatten)]
+    pub server: DockerServer,
+}
+
+impl DockerConfig {
+    pub fn socket(&self) -> &str {
+        self.server.socket.as_deref().unwrap_or(DEFAULT_SOCKET)
+    }
+}
+
+pub struct Docker {
+    cfg: DockerConfig,
+    running: bool,
+}
+
+impl Docker {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let docker = Docker::new(source::parse(value)?);
+        Ok(Box::new(Background::new(docker, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS))))
+    }
+    pub fn new(cfg: DockerConfig) -> Docker {
+        Docker {
+            cfg,
+            running: false,
+        }
+    }
+    fn call(&self, method: &str, path: &str) -> Result<http::Response> {
+        // the host is ignored when talking to a socket, but curl needs one
+        http::request_via(
+            Some(self.cfg.socket()),
+            method,
+            &format!("http://localhost{path}"),
+            &[],
+            None,
+        )
+    }
+    fn get(&self, path: &str) -> Result<Value> {
+        let response = self.call("GET", path)?;
+        if !response.is_success() {
+            return Err(anyhow!("HTTP {}", response.status));
+        }
+        Value::parse(&response.body)
+    }
+    fn fetch(&mut self) -> Result<Vec<Update>> {
+        let Some(container) = &self.cfg.container else {
+            let Value::Array(containers) = self.get("/containers/json")? else {
+                return Err(anyhow!("unexpected response listing containers"));
+            };
+            let count = containers.len().to_string();
+            let format = self.cfg.format.as_deref().unwrap_or("{}");
+            return Ok(vec![Update::Text(format.replace("{}", &count))]);
+        };
+        let info = self.get(&format!("/containers/{}/json", http::encode(container)))?;
+        let state = info.get("State");
+        self.running = state.and_then(|s| s.get("Running")) == Some(&Value::Bool(true));
+        let mut updates = vec![Update::Highlight(self.running)];
+        if let Some(format) = &self.cfg.format {
+            let status = state
+                .and_then(|s| s.get("Status"))
+                .and_then(Value::as_str)
+                .unwrap_or("unknown");
+            updates.push(Update::Text(format.replace("{}", status)));
+        }
+        Ok(updates)
+    }
+}
+
+impl Job for Docker {
+    const MIN_INTERVAL: Duration = Duration::from_millis(500);
+
+    fn refresh(&mut self) -> Vec<Update> {
+        match self.fetch() {
+            Ok(updates) => updates,
+            Err(e) => {
+                eprintln!("Failed to get container status from {}: {e:#}", self.cfg.socket());
+                vec![Update::Highlight(false)]
+            }
+        }
+    }
+    fn touch(&mut self, pressed: bool) -> bool {
+        let (true, Some(container)) = (pressed, &self.cfg.container) else {
+            return false;
+        };
+        let action = if self.running { "stop" } else { "start" };
+        let path = format!("/containers/{}/{action}", http::encode(container));
+        match self.call("POST", &path) {
+            // 304 means it already was in that state
+            Ok(response) if response.is_success() || response.status == 304 => {}
+            Ok(response) => eprintln!("Failed to {action} {container}: HTTP {}", response.status),
+            Err(e) => eprintln!("Failed to {action} {container}: {e:#}"),
+        }
+        true
+    }
+    fn label(&self) -> String {
+        self.cfg.container.clone().unwrap_or_default()
+    }
+}
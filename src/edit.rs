@@ -0,0 +1,84 @@
+use crate::config::ConfigManager;
+use crate::FunctionLayer;
+use anyhow::Result;
+use std::time::Duration;
+
+// Buttons can be rearranged on the bar itself. In edit mode, entered by
+// holding a finger on the bar next to the buttons or over IPC, a button
+// follows the finger dragging it and the others make room. Tapping next to
+// the buttons again leaves edit mode and saves the new order to the config.
+
+/// How long to hold next to the buttons to enter edit mode
+pub const LONG_PRESS: Duration = Duration::from_millis(800);
+
+pub struct EditMode {
+    /// The index of the layer being edited
+    pub layer: usize,
+    /// The touch slot dragging a button, and where that button is now
+    dragging: Option<(u32, usize)>,
+    moved: bool,
+    /// Whether the layer had outlines, which are shown while editing
+    outlines: bool,
+}
+
+impl EditMode {
+    pub fn start(layer: &mut FunctionLayer, index: usize) -> EditMode {
+        let outlines = layer.style.show_button_outlines;
+        layer.style.show_button_outlines = true;
+        EditMode {
+            layer: index,
+            dragging: None,
+            moved: false,
+            outlines,
+        }
+    }
+    /// Picks up the button, unless it is one that is always first
+    pub fn touch_down(&mut self, layer: &mut FunctionLayer, slot: u32, btn: usize) {
+        if self.dragging.is_some() || btn < layer.fixed {
+            return;
+        }
+        let button = &mut layer.buttons[btn].1;
+        button.active = true;
+        button.changed = true;
+        self.dragging = Some((slot, btn));
+    }
+    /// Moves the dragged button to where the finger is. Returns whether the
+    /// layer needs to be redrawn completely.
+    pub fn touch_motion(&mut self, layer: &mut FunctionLayer, slot: u32, width: u16, x: f64) -> bool {
+        let Some((dragging, from)) = self.dragging else {
+            return false;
+        };
+        let to = layer.position_at(width, x, 0).max(layer.fixed);
+        if dragging != slot || to == from {
+            return false;
+        }
+        layer.move_button(from, to);
+        self.dragging = Some((slot, to));
+        self.moved = true;
+        true
+    }
+    pub fn touch_up(&mut self, layer: &mut FunctionLayer, slot: u32) {
+        if let Some((dragging, btn)) = self.dragging {
+            if dragging == slot {
+                let button = &mut layer.buttons[btn].1;
+                button.active = false;
+                button.changed = true;
+                self.dragging = None;
+            }
+        }
+    }
+    /// Leaves edit mode, saving the order if any button was moved
+    pub fn finish(self, layer: &mut FunctionLayer, cfg_mgr: &mut ConfigManager) -> Result<()> {
+        layer.style.show_button_outlines = self.outlines;
+        if let Some((_, btn)) = self.dragging {
+            layer.buttons[btn].1.active = false;
+        }
+        if !self.moved {
+            return Ok(());
+        }
+        cfg_mgr.save_layer_order(layer.name, &layer.config_order())?;
+        // the config is in this order now, without reloading it
+        layer.order = (0..layer.buttons.len()).collect();
+        Ok(())
+    }
+}
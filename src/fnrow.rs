@@ -0,0 +1,194 @@
+use input_linux::{sys, uinput::UInputHandle, EvdevHandle, EventKind, Key};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+};
+
+// Lets the internal keyboard's Fn key turn the number row into F-keys or
+// media keys, without hid_apple's fnmode parameter. The keyboard is grabbed
+// so that nothing else sees its events, and they are passed on through a
+// virtual copy of it, with the number row changed while Fn is held. Fn
+// itself is passed on too, so it still switches the bar's layers.
+//
+// LEDs set on the copy, like Caps Lock, are passed back to the keyboard.
+// Both devices need root to open, so this starts before privileges are
+// dropped and changes to it need a restart.
+
+/// What Fn and the number row send, in order from 1 to =
+const FUNCTION_KEYS: [Key; 12] = [
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6,
+    Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+];
+/// Like the media layer, the keys printed on the F-row of Macs that have one
+const MEDIA_KEYS: [Key; 12] = [
+    Key::BrightnessDown, Key::BrightnessUp, Key::Scale, Key::Search,
+    Key::IllumDown, Key::IllumUp, Key::PreviousSong, Key::PlayPause,
+    Key::NextSong, Key::Mute, Key::VolumeDown, Key::VolumeUp,
+];
+const NUMBER_ROW: [Key; 12] = [
+    Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6,
+    Key::Num7, Key::Num8, Key::Num9, Key::Num0, Key::Minus, Key::Equal,
+];
+
+/// The `Keys` setting of `[FnRow]`
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FnRowKeys {
+    #[default]
+    Function,
+    Media,
+}
+
+/// The `[FnRow]` section
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct FnRowConfig {
+    pub enable: Option<bool>,
+    pub keys: Option<FnRowKeys>,
+    /// A part of the keyboard's name, the internal keyboard when not set
+    pub device: Option<String>,
+}
+
+fn find_keyboard(name: Option<&str>) -> io::Result<EvdevHandle<File>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir("/dev/input")?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.to_string_lossy().contains("/event"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        // writable for the LEDs
+        let Ok(file) = OpenOptions::new().read(true).write(true).open(&path) else {
+            continue;
+        };
+        let device = EvdevHandle::new(file);
+        if !device.key_bits().is_ok_and(|keys| keys.get(Key::Fn) && keys.get(Key::Num1)) {
+            continue;
+        }
+        let device_name = String::from_utf8_lossy(&device.device_name().unwrap_or_default()).into_owned();
+        if device_name.contains(name.unwrap_or("Internal Keyboard")) {
+            return Ok(device);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "no keyboard with an Fn key found"))
+}
+
+/// Creates the copy of `keyboard`, under the same name and ids so that it
+/// is still taken as the internal keyboard, e.g. to disable the touchpad
+/// while typing
+fn create_copy(keyboard: &EvdevHandle<File>, keys: &[Key]) -> io::Result<UInputHandle<File>> {
+    let uinput = UInputHandle::new(OpenOptions::new().read(true).write(true).open("/dev/uinput")?);
+    uinput.set_evbit(EventKind::Key)?;
+    for key in keyboard.key_bits()?.iter().chain(keys.iter().copied()) {
+        uinput.set_keybit(key)?;
+    }
+    uinput.set_evbit(EventKind::Led)?;
+    for led in keyboard.led_bits()?.iter() {
+        uinput.set_ledbit(led)?;
+    }
+    let name = keyboard.device_name()?;
+    let name = name.strip_suffix(&[0]).unwrap_or(&name);
+    uinput.create(&keyboard.device_id()?, name, 0, &[])?;
+    Ok(uinput)
+}
+
+/// Passes the keyboard's events on, changing the number row while Fn is held
+fn pass_keys(keyboard: &EvdevHandle<File>, copy: &UInputHandle<File>, keys: &[Key; 12]) -> io::Result<()> {
+    let mut fn_held = false;
+    // what was sent for number keys pressed with Fn, so that they are let go
+    // of even when Fn is let go of first
+    let mut changed: HashMap<u16, u16> = HashMap::new();
+    let mut events = [sys::input_event {
+        time: sys::timeval { tv_sec: 0, tv_usec: 0 },
+        type_: 0,
+        code: 0,
+        value: 0,
+    }; 64];
+    loop {
+        let count = keyboard.read(&mut events)?;
+        let events = &mut events[..count];
+        for event in events.iter_mut() {
+            if event.type_ != EventKind::Key as u16 {
+                continue;
+            }
+            if event.code == Key::Fn as u16 {
+                fn_held = event.value != 0;
+            } else if let Some(&code) = changed.get(&event.code) {
+                if event.value == 0 {
+                    changed.remove(&event.code);
+                }
+                event.code = code;
+            } else if let (true, 1, Some(i)) = (
+                fn_held,
+                event.value,
+                NUMBER_ROW.iter().position(|key| *key as u16 == event.code),
+            ) {
+                changed.insert(event.code, keys[i] as u16);
+                event.code = keys[i] as u16;
+            }
+        }
+        copy.write(events)?;
+    }
+}
+
+/// Passes LEDs set on the copy back to the keyboard
+fn pass_leds(copy: &UInputHandle<File>, keyboard: &EvdevHandle<File>) -> io::Result<()> {
+    let mut events = [sys::input_event {
+        time: sys::timeval { tv_sec: 0, tv_usec: 0 },
+        type_: 0,
+        code: 0,
+        value: 0,
+    }; 16];
+    loop {
+        let count = copy.read(&mut events)?;
+        let leds: Vec<_> = events[..count]
+            .iter()
+            .filter(|event| event.type_ == EventKind::Led as u16)
+            .copied()
+            .collect();
+        if !leds.is_empty() {
+            keyboard.write(&leds)?;
+        }
+    }
+}
+
+/// Grabs the keyboard and starts passing its events on, if enabled. Needs
+/// to be called before dropping privileges.
+pub fn start(cfg: &FnRowConfig) {
+    if cfg.enable != Some(true) {
+        return;
+    }
+    let keys = match cfg.keys.unwrap_or_default() {
+        FnRowKeys::Function => FUNCTION_KEYS,
+        FnRowKeys::Media => MEDIA_KEYS,
+    };
+    let res = find_keyboard(cfg.device.as_deref()).and_then(|keyboard| {
+        let copy = create_copy(&keyboard, &keys)?;
+        keyboard.grab(true)?;
+        Ok((Arc::new(keyboard), Arc::new(copy)))
+    });
+    let (keyboard, copy) = match res {
+        Ok(devices) => devices,
+        Err(e) => {
+            eprintln!("FnRow: cannot take over the keyboard: {e}");
+            return;
+        }
+    };
+    let (led_keyboard, led_copy) = (keyboard.clone(), copy.clone());
+    thread::spawn(move || {
+        if let Err(e) = pass_keys(&keyboard, &copy, &keys) {
+            eprintln!("FnRow: stopped passing keys on: {e}");
+        }
+        // the keyboard must not stay grabbed with nothing passing it on
+        _ = keyboard.grab(false);
+    });
+    thread::spawn(move || {
+        if let Err(e) = pass_leds(&led_copy, &led_keyboard) {
+            eprintln!("FnRow: stopped passing LEDs on: {e}");
+        }
+    });
+}
@@ -0,0 +1,95 @@
+use chrono::NaiveTime;
+use input_linux::Key;
+use serde::{Deserialize, Serialize};
+
+// Focus mode hides everything on the bar but escape, brightness and the
+// buttons listed in Keep, to take away distractions. It is an overlay on
+// whatever layer is shown: hidden buttons keep their place and keep
+// updating, they are just not drawn and do not react to touches.
+//
+// It is turned on and off by a button, IPC or a signal, or follows a daily
+// schedule. Toggling it by hand lasts until the schedule next starts or ends.
+
+/// The `[FocusMode]` section
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct FocusSettings {
+    /// Ids of buttons shown besides escape and brightness
+    pub keep: Option<Vec<String>>,
+    /// Start of the daily schedule, as HH:MM
+    pub from: Option<String>,
+    /// End of the daily schedule, as HH:MM
+    pub to: Option<String>,
+}
+
+/// A daily span of time from the HH:MM times `from` and `to`
+pub fn daily_span(from: &Option<String>, to: &Option<String>) -> Option<(NaiveTime, NaiveTime)> {
+    let parse = |time: &Option<String>| NaiveTime::parse_from_str(time.as_deref()?, "%H:%M").ok();
+    Some((parse(from)?, parse(to)?))
+}
+
+/// Whether the time is within the daily span, which may go over midnight
+pub fn in_daily_span((from, to): (NaiveTime, NaiveTime), now: NaiveTime) -> bool {
+    if from <= to {
+        from <= now && now < to
+    } else {
+        // e.g. 22:00 to 07:00, over midnight
+        now >= from || now < to
+    }
+}
+
+impl FocusSettings {
+    fn schedule(&self) -> Option<(NaiveTime, NaiveTime)> {
+        daily_span(&self.from, &self.to)
+    }
+    /// Warns about a schedule that will never apply
+    pub fn check(&self) {
+        if (self.from.is_some() || self.to.is_some()) && self.schedule().is_none() {
+            eprintln!("FocusMode needs both From and To as HH:MM, ignoring the schedule");
+        }
+    }
+    /// Whether the button stays visible in focus mode
+    pub fn keeps(&self, action: Option<Key>, id: Option<&str>) -> bool {
+        if matches!(
+            action,
+            Some(Key::Esc | Key::BrightnessDown | Key::BrightnessUp)
+        ) {
+            return true;
+        }
+        match (id, &self.keep) {
+            (Some(id), Some(keep)) => keep.iter().any(|k| k == id),
+            _ => false,
+        }
+    }
+    /// Whether the schedule has focus mode on at the given time
+    fn scheduled(&self, now: NaiveTime) -> bool {
+        self.schedule().is_some_and(|span| in_daily_span(span, now))
+    }
+}
+
+#[derive(Default)]
+pub struct FocusMode {
+    scheduled: bool,
+    /// Set by toggling it, overriding the schedule
+    manual: Option<bool>,
+}
+
+impl FocusMode {
+    pub fn update(&mut self, settings: &FocusSettings, now: NaiveTime) {
+        let scheduled = settings.scheduled(now);
+        if scheduled != self.scheduled {
+            self.scheduled = scheduled;
+            self.manual = None;
+        }
+    }
+    pub fn is_on(&self) -> bool {
+        self.manual.unwrap_or(self.scheduled)
+    }
+    pub fn toggle(&mut self) {
+        self.manual = Some(!self.is_on());
+    }
+    /// Turns it on or off, `None` goes back to following the schedule
+    pub fn set(&mut self, enabled: Option<bool>) {
+        self.manual = enabled;
+    }
+}
@@ -1,4 +1,5 @@
 #![allow(non_upper_case_globals)]
+use glib::translate::from_glib_full;
 use std::ffi::{c_char, c_int, CStr, CString};
 use std::ptr;
 
@@ -107,6 +108,11 @@ impl Pattern {
             index as isize
         }
     }
+    /// The family, weight and style of the pattern for laying out text with
+    /// pango, without a size, which is set when drawing
+    pub fn font_description(&self) -> pango::FontDescription {
+        unsafe { from_glib_full(pango_fc_font_description_from_pattern(self.pattern, 0)) }
+    }
 }
 
 impl Drop for Pattern {
@@ -136,3 +142,10 @@ extern "C" {
     fn FcConfigSubstitute(_: *const FcConfig, _: *const FcPattern, _: FcMatchKind) -> c_int;
     fn FcDefaultSubstitute(_: *const FcPattern);
 }
+
+extern "C" {
+    fn pango_fc_font_description_from_pattern(
+        _: *const FcPattern,
+        _: c_int,
+    ) -> *mut pango::ffi::PangoFontDescription;
+}
@@ -0,0 +1,217 @@
+use crate::activeapp::{sway_focused_node, sway_read, sway_send, SWAY_GET_TREE, SWAY_SUBSCRIBE};
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::json::Value;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    process::{Command, Stdio},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+// Follows whether the focused window is fullscreen, e.g. a video or a game,
+// for the bar to get out of the way meanwhile: LayerKeys stands in for the
+// default layer and animations stop. Each backend asks the compositor in
+// its own way, on a thread that reconnects when the compositor restarts:
+//
+// - Sway (and i3) and Hyprland through their IPC sockets, like ActiveApp
+// - X11 by asking xprop about the active window now and then
+// - Command runs a program printing a line with 1 or 0 whenever it changes,
+//   which covers KDE and GNOME with a KWin script or shell extension, or
+//   any other compositor
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+const X11_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_DISPLAY: &str = ":0";
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum Backend {
+    Sway,
+    Hyprland,
+    X11,
+    Command,
+}
+
+/// The `[Fullscreen]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FullscreenConfig {
+    pub backend: Option<Backend>,
+    /// The IPC socket for Sway and Hyprland
+    pub socket: Option<String>,
+    /// The X display and its cookie, for X11
+    pub display: Option<String>,
+    pub xauthority: Option<String>,
+    pub command: Option<String>,
+    pub pause_animations: Option<bool>,
+    pub layer_keys: Option<Vec<ButtonConfig>>,
+}
+
+fn follow_sway(socket: &str, states: &Sender<bool>, waker: &Waker) -> Result<()> {
+    let mut events = UnixStream::connect(socket)?;
+    let mut requests = UnixStream::connect(socket)?;
+    sway_send(&mut events, SWAY_SUBSCRIBE, r#"["window","workspace"]"#)?;
+    loop {
+        // the whole tree is looked at again, as fullscreen_mode changes,
+        // focus changes and workspace switches are all different events
+        sway_send(&mut requests, SWAY_GET_TREE, "")?;
+        let (_, tree) = sway_read(&mut requests)?;
+        let mode = sway_focused_node(&tree)
+            .and_then(|node| node.get("fullscreen_mode"))
+            .and_then(Value::as_f64);
+        states.send(mode.unwrap_or_default() > 0.0)?;
+        waker.wake();
+        sway_read(&mut events)?;
+    }
+}
+
+fn hyprland_fullscreen(socket: &str) -> Result<bool> {
+    let mut request = UnixStream::connect(socket)?;
+    request.write_all(b"j/activewindow")?;
+    let mut reply = String::new();
+    request.read_to_string(&mut reply)?;
+    // a bool before Hyprland 0.41, a mode after
+    Ok(match Value::parse(&reply)?.get("fullscreen") {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::Number(mode)) => *mode > 0.0,
+        _ => false,
+    })
+}
+
+fn follow_hyprland(socket: &str, states: &Sender<bool>, waker: &Waker) -> Result<()> {
+    // requests go to the socket next to the event one
+    let requests = socket
+        .strip_suffix(".socket2.sock")
+        .map(|prefix| format!("{prefix}.socket.sock"))
+        .ok_or(anyhow!("expected the .socket2.sock event socket"))?;
+    let events = BufReader::new(UnixStream::connect(socket)?);
+    states.send(hyprland_fullscreen(&requests)?)?;
+    waker.wake();
+    for line in events.lines() {
+        let line = line?;
+        let event = line.split_once(">>").map_or(line.as_str(), |(event, _)| event);
+        if !matches!(event, "fullscreen" | "activewindow" | "workspace") {
+            continue;
+        }
+        states.send(hyprland_fullscreen(&requests)?)?;
+        waker.wake();
+    }
+    Err(anyhow!("connection closed"))
+}
+
+/// The X display and its cookie
+struct X11Display {
+    display: String,
+    xauthority: Option<String>,
+}
+
+fn xprop(x11: &X11Display, args: &[&str]) -> Result<String> {
+    let mut cmd = Command::new("xprop");
+    cmd.args(args).env("DISPLAY", &x11.display);
+    if let Some(xauthority) = &x11.xauthority {
+        cmd.env("XAUTHORITY", xauthority);
+    }
+    let output = cmd.stderr(Stdio::null()).output()?;
+    if !output.status.success() {
+        return Err(anyhow!("xprop failed, is the display right?"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn follow_x11(x11: &X11Display, states: &Sender<bool>, waker: &Waker) -> Result<()> {
+    let mut last = None;
+    loop {
+        // _NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007
+        let active = xprop(x11, &["-root", "_NET_ACTIVE_WINDOW"])?;
+        let fullscreen = match active.rsplit_once("# ") {
+            Some((_, id)) if id.trim() != "0x0" => {
+                xprop(x11, &["-id", id.trim(), "_NET_WM_STATE"])?.contains("_NET_WM_STATE_FULLSCREEN")
+            }
+            _ => false,
+        };
+        if last != Some(fullscreen) {
+            last = Some(fullscreen);
+            states.send(fullscreen)?;
+            waker.wake();
+        }
+        thread::sleep(X11_POLL_INTERVAL);
+    }
+}
+
+/// Runs `command` for as long as it goes, taking each line it prints as
+/// whether the state is on
+pub fn follow_command(command: &str, states: &Sender<bool>, waker: &Waker) -> Result<()> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdout(Stdio::piped())
+        .stdin(Stdio::null())
+        .spawn()?;
+    let res = BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .try_for_each(|line| -> Result<()> {
+            states.send(matches!(line?.trim(), "1" | "true"))?;
+            waker.wake();
+            Ok(())
+        });
+    // also when no longer listened to
+    _ = child.kill();
+    child.wait()?;
+    res.and(Err(anyhow!("command exited")))
+}
+
+/// Whether a fullscreen window is focused, as far as the backend knows
+#[derive(Default)]
+pub struct Fullscreen {
+    states: Option<Receiver<bool>>,
+    on: bool,
+}
+
+impl Fullscreen {
+    /// Starts following the backend, if one is set. The thread of the last
+    /// one stops once it notices it is no longer listened to.
+    pub fn start(cfg: &FullscreenConfig, waker: &Waker) -> Fullscreen {
+        let Some(backend) = cfg.backend else {
+            return Fullscreen::default();
+        };
+        let (tx, rx) = channel();
+        let (socket, command) = (cfg.socket.clone(), cfg.command.clone());
+        let x11 = X11Display {
+            display: cfg.display.clone().unwrap_or(DEFAULT_DISPLAY.to_string()),
+            xauthority: cfg.xauthority.clone(),
+        };
+        let waker = waker.clone();
+        thread::spawn(move || loop {
+            let res = match (backend, &socket, &command) {
+                (Backend::Sway, Some(socket), _) => follow_sway(socket, &tx, &waker),
+                (Backend::Hyprland, Some(socket), _) => follow_hyprland(socket, &tx, &waker),
+                (Backend::X11, _, _) => follow_x11(&x11, &tx, &waker),
+                (Backend::Command, _, Some(command)) => follow_command(command, &tx, &waker),
+                (Backend::Command, _, None) => Err(anyhow!("Command is not set")),
+                _ => Err(anyhow!("Socket is not set")),
+            };
+            // without knowing, nothing is fullscreen
+            if tx.send(false).is_err() {
+                break;
+            }
+            waker.wake();
+            if let Err(e) = res {
+                eprintln!("Fullscreen: lost the compositor: {e:#}");
+            }
+            thread::sleep(RECONNECT_DELAY);
+        });
+        Fullscreen {
+            states: Some(rx),
+            on: false,
+        }
+    }
+    pub fn update(&mut self) -> bool {
+        if let Some(on) = self.states.as_ref().and_then(|rx| rx.try_iter().last()) {
+            self.on = on;
+        }
+        self.on
+    }
+}
@@ -0,0 +1,173 @@
+use crate::audit;
+use crate::config::ButtonConfig;
+use crate::http;
+use crate::i18n;
+use crate::json::Value;
+use crate::ratelimit;
+use crate::source::{self, Async, AsyncJob, Periodic, Source, Update};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Talks to the Home Assistant REST API, see
+// https://developers.home-assistant.io/docs/api/rest/
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 5000;
+
+/// The `[HomeAssistant]` section, shared by all Home Assistant buttons
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct HomeAssistantServer {
+    pub url: Option<String>,
+    pub token: Option<String>,
+}
+
+/// The `HomeAssistant` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct HomeAssistantConfig {
+    pub entity: String,
+    pub attribute: Option<String>,
+    pub format: Option<String>,
+    pub service: Option<String>,
+    #[serde(flatten)]
+    pub server: HomeAssistantServer,
+}
+
+/// The service called on tap when none is configured, based on the
+/// entity's domain.
+fn default_service(entity: &str) -> Option<&'static str> {
+    match entity.split_once('.')?.0 {
+        "switch" | "light" | "fan" | "input_boolean" | "automation" | "cover" => {
+            Some("homeassistant.toggle")
+        }
+        "scene" => Some("scene.turn_on"),
+        "script" => Some("script.turn_on"),
+        "button" | "input_button" => Some("button.press"),
+        _ => None,
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => i18n::tr("N/A"),
+        v => v.to_string(),
+    }
+}
+
+pub struct HomeAssistant {
+    url: String,
+    token: String,
+    entity: String,
+    attribute: Option<String>,
+    format: Option<String>,
+    service: Option<String>,
+}
+
+impl HomeAssistant {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let ha = HomeAssistant::new(source::parse(value)?)?;
+        Ok(Box::new(Async::new(Periodic::new(ha, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS)))))
+    }
+    pub fn new(cfg: HomeAssistantConfig) -> Result<HomeAssistant> {
+        let url = cfg
+            .server
+            .url
+            .ok_or(anyhow!("no Url set for Home Assistant"))?;
+        let token = cfg
+            .server
+            .token
+            .ok_or(anyhow!("no Token set for Home Assistant"))?;
+        let service = cfg
+            .service
+            .or_else(|| default_service(&cfg.entity).map(str::to_string));
+        Ok(HomeAssistant {
+            url: url.trim_end_matches('/').to_string(),
+            token,
+            entity: cfg.entity,
+            attribute: cfg.attribute,
+            format: cfg.format,
+            service,
+        })
+    }
+    async fn call(&self, method: &str, path: &str, body: Option<&str>) -> Result<Value> {
+        let auth = format!("Bearer {}", self.token);
+        let response = http::request_async(
+            method,
+            &format!("{}{path}", self.url),
+            &[("Authorization", &auth), ("Content-Type", "application/json")],
+            body,
+        )
+        .await?;
+        if !response.is_success() {
+            return Err(anyhow!("HTTP {}", response.status));
+        }
+        Value::parse(&response.body)
+    }
+    async fn fetch_state(&self) -> Result<String> {
+        let state = self
+            .call("GET", &format!("/api/states/{}", self.entity), None)
+            .await?;
+        let attributes = state.get("attributes");
+        let value = match &self.attribute {
+            Some(attribute) => attributes.and_then(|a| a.get(attribute)),
+            None => state.get("state"),
+        }
+        .map(value_to_string)
+        .unwrap_or(i18n::tr("N/A"));
+        Ok(match &self.format {
+            Some(format) => format.replace("{}", &value),
+            None => match attributes
+                .and_then(|a| a.get("unit_of_measurement"))
+                .and_then(Value::as_str)
+            {
+                Some(unit) if self.attribute.is_none() => format!("{value} {unit}"),
+                _ => value,
+            },
+        })
+    }
+    async fn call_service(&self, service: &str) -> Result<()> {
+        let (domain, service) = service
+            .split_once('.')
+            .ok_or(anyhow!("invalid service {service}, expected domain.service"))?;
+        let body = Value::Object(vec![("entity_id".to_string(), self.entity.as_str().into())]);
+        self.call(
+            "POST",
+            &format!("/api/services/{domain}/{service}"),
+            Some(&body.to_string()),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+impl AsyncJob for HomeAssistant {
+    const MIN_INTERVAL: Duration = Duration::from_millis(1000);
+
+    async fn refresh(&mut self) -> Vec<Update> {
+        match self.fetch_state().await {
+            Ok(text) => vec![Update::Text(text)],
+            Err(e) => {
+                eprintln!("Failed to get state of {}: {e:#}", self.entity);
+                vec![Update::Text(i18n::tr("N/A"))]
+            }
+        }
+    }
+    async fn touch(&mut self, pressed: bool) -> bool {
+        let Some(service) = &self.service else {
+            return false;
+        };
+        if !pressed {
+            return false;
+        }
+        if !ratelimit::allow_command() {
+            return false;
+        }
+        audit::record("Called Home Assistant service", Some(&format!("{service} on {}", self.entity)));
+        if let Err(e) = self.call_service(service).await {
+            eprintln!("Failed to call {service} on {}: {e:#}", self.entity);
+        }
+        true
+    }
+}
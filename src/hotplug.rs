@@ -0,0 +1,127 @@
+use crate::action;
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::ratelimit;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+// Offers to arrange the screens when an external display is connected over
+// HDMI or USB-C: a layer with "mirror", "extend" and "external only"
+// buttons stands in for the default one until one of them is tapped or it
+// times out. What they do is up to the commands they are given, e.g.
+// wlr-randr or xrandr. Disconnect optionally undoes it once the last
+// external display is gone, like turning the built-in one back on.
+//
+// Connectors are counted in /sys/class/drm whenever udev reports a change
+// there. Only HDMI, DisplayPort, DVI and VGA ones count as external, so the
+// built-in panel (eDP, LVDS, DSI) and the touchbar itself are left out.
+
+const DEFAULT_TIMEOUT_MS: u64 = 15 * 1000;
+const EXTERNAL: [&str; 4] = ["HDMI", "DP", "DVI", "VGA"];
+
+/// The `[DisplayHotplug]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct DisplayHotplugConfig {
+    pub mirror: Option<String>,
+    pub extend: Option<String>,
+    pub external_only: Option<String>,
+    pub disconnect: Option<String>,
+    pub timeout_ms: Option<u64>,
+}
+
+impl DisplayHotplugConfig {
+    /// A button for each command that is set, if any is
+    pub fn layer_keys(&self) -> Option<Vec<ButtonConfig>> {
+        let choices = [
+            ("mirror", &self.mirror),
+            ("extend", &self.extend),
+            ("external only", &self.external_only),
+        ];
+        let keys: Vec<ButtonConfig> = choices
+            .into_iter()
+            .filter_map(|(text, command)| {
+                let mut action = toml::Table::new();
+                action.insert("Command".into(), command.clone()?.into());
+                let mut button = toml::Table::new();
+                button.insert("Text".into(), i18n::tr(text).into());
+                button.insert("Action".into(), action.into());
+                button
+                    .try_into()
+                    .map_err(|e| eprintln!("DisplayHotplug: cannot make the {text} button: {e}"))
+                    .ok()
+            })
+            .collect();
+        (!keys.is_empty()).then_some(keys)
+    }
+}
+
+/// The number of external displays that are connected
+fn count_external() -> usize {
+    let Ok(entries) = fs::read_dir("/sys/class/drm") else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            // named like card1-HDMI-A-1
+            let Some((_, connector)) = name.to_str().and_then(|n| n.split_once('-')) else {
+                return false;
+            };
+            EXTERNAL.iter().any(|kind| connector.starts_with(kind))
+                && fs::read_to_string(entry.path().join("status")).is_ok_and(|s| s.trim() == "connected")
+        })
+        .count()
+}
+
+/// Whether the layer is shown, after a display was connected
+pub struct DisplayHotplug {
+    connected: usize,
+    timeout: Duration,
+    disconnect: Option<String>,
+    /// Until when the layer is shown
+    until: Option<Instant>,
+}
+
+impl DisplayHotplug {
+    pub fn new(cfg: &DisplayHotplugConfig) -> DisplayHotplug {
+        DisplayHotplug {
+            connected: count_external(),
+            timeout: Duration::from_millis(cfg.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS)),
+            disconnect: cfg.disconnect.clone(),
+            until: None,
+        }
+    }
+    /// Counts the displays again if `changed`. Returns whether the layer is
+    /// shown, and the number of milliseconds until that changes.
+    pub fn update(&mut self, changed: bool) -> (bool, i32) {
+        let now = Instant::now();
+        if changed {
+            let connected = count_external();
+            if connected > self.connected {
+                self.until = Some(now + self.timeout);
+            } else if connected == 0 && self.connected > 0 {
+                self.until = None;
+                if let (Some(command), true) = (&self.disconnect, ratelimit::allow_command()) {
+                    action::run_command(command);
+                }
+            }
+            self.connected = connected;
+        }
+        match self.until {
+            Some(until) if until > now => (true, (until - now).as_millis() as i32 + 1),
+            _ => {
+                self.until = None;
+                (false, i32::MAX)
+            }
+        }
+    }
+    /// Takes the layer down, once a choice was made
+    pub fn dismiss(&mut self) {
+        self.until = None;
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+use gio::{Subprocess, SubprocessFlags};
+use std::{
+    ffi::OsStr,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+// HTTP requests are made by running curl, which takes care of TLS, proxies
+// and redirects for us. Options are passed through a curl config file on
+// stdin rather than on the command line, so that tokens and other secrets
+// do not show up in the process list. Sources on the runtime use the
+// _async variants, which wait for curl without holding up a thread.
+
+const TIMEOUT_SECS: u32 = 10;
+
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+fn quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Percent-encodes a string for use as a URL path segment or query value.
+pub fn encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            b => out += &format!("%{b:02X}"),
+        }
+    }
+    out
+}
+
+/// Makes a blocking request, only meant to be called from background threads.
+pub fn request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+) -> Result<Response> {
+    request_via(None, method, url, headers, body)
+}
+
+/// Like `request`, but connects to the given Unix socket instead of the
+/// host in the URL, as needed for local APIs such as Docker's.
+pub fn request_via(
+    unix_socket: Option<&str>,
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+) -> Result<Response> {
+    parse_response(&run(request_config(unix_socket, method, url, headers, body))?)
+}
+
+/// Like `request`, but waits for curl on the runtime instead of blocking.
+pub async fn request_async(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+) -> Result<Response> {
+    parse_response(&run_async(request_config(None, method, url, headers, body)).await?)
+}
+
+fn request_config(
+    unix_socket: Option<&str>,
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+) -> String {
+    let header_lines: Vec<String> = headers
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}"))
+        .collect();
+    let mut options = vec![
+        ("url", url),
+        ("request", method),
+        ("location", ""),
+        ("write-out", "\n%{http_code}"),
+    ];
+    options.extend(header_lines.iter().map(|line| ("header", line.as_str())));
+    if let Some(body) = body {
+        options.push(("data-raw", body));
+    }
+    if let Some(unix_socket) = unix_socket {
+        options.push(("unix-socket", unix_socket));
+    }
+    config(&options)
+}
+
+fn parse_response(stdout: &str) -> Result<Response> {
+    let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", stdout));
+    Ok(Response {
+        status: status.trim().parse()?,
+        body: body.to_string(),
+    })
+}
+
+/// Runs curl with the given long options, without the leading dashes,
+/// returning what it printed. Options without a value are passed as flags.
+/// This also covers the other protocols curl speaks, such as IMAP.
+pub async fn curl_async(options: &[(&str, &str)]) -> Result<String> {
+    run_async(config(options)).await
+}
+
+fn config(options: &[(&str, &str)]) -> String {
+    let mut config = format!("silent\nshow-error\nmax-time = {TIMEOUT_SECS}\n");
+    for (name, value) in options {
+        match value {
+            &"" => config += &format!("{name}\n"),
+            value => config += &format!("{name} = {}\n", quote(value)),
+        }
+    }
+    config
+}
+
+fn run(config: String) -> Result<String> {
+    let mut child = Command::new("curl")
+        .args(["--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("failed to run curl: {e}"))?;
+    child.stdin.take().unwrap().write_all(config.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn run_async(config: String) -> Result<String> {
+    let flags = SubprocessFlags::STDIN_PIPE | SubprocessFlags::STDOUT_PIPE | SubprocessFlags::STDERR_PIPE;
+    let process = Subprocess::newv(&[OsStr::new("curl"), OsStr::new("--config"), OsStr::new("-")], flags)
+        .map_err(|e| anyhow!("failed to run curl: {e}"))?;
+    let config = glib::Bytes::from_owned(config.into_bytes());
+    let (stdout, stderr) = process.communicate_future(Some(&config)).await?;
+    let text = |bytes: Option<glib::Bytes>| {
+        String::from_utf8_lossy(bytes.as_deref().unwrap_or_default()).into_owned()
+    };
+    if !process.is_successful() {
+        return Err(anyhow!("{}", text(stderr).trim_end()));
+    }
+    Ok(text(stdout))
+}
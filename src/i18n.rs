@@ -0,0 +1,228 @@
+use chrono::Locale;
+use pure_rust_locales::locale_match;
+use std::{
+    collections::HashMap,
+    env,
+    fs::read_to_string,
+    sync::RwLock,
+};
+
+// Translations of the few strings the bar shows by itself, like the esc key
+// added on wide bars, "N/A" when a reading is missing or the confirmation
+// prompt. They come from gettext catalogs, read without gettext itself:
+// only plain msgid and msgstr pairs are taken, which is all these need.
+//
+// The catalog for the Locale set in the config, or else the system's, is
+// looked up in /etc/tiny-dfr/locale and then in /usr/share/tiny-dfr/locale,
+// first for the whole name, like de_AT.po, then for the language, de.po.
+// Strings without a translation stay in English.
+//
+// Dates and numbers follow the locale's own conventions instead, from the
+// glibc locale data chrono already carries: day names, whether the clock
+// runs to 12 or 24 hours, and the decimal and thousands separators. Widgets
+// with a Locale of their own use it for these, and the bar's otherwise.
+
+const CATALOG_DIRS: [&str; 2] = ["/etc/tiny-dfr/locale", "/usr/share/tiny-dfr/locale"];
+
+struct Catalog {
+    locale: Option<String>,
+    messages: HashMap<String, String>,
+}
+
+static CATALOG: RwLock<Option<Catalog>> = RwLock::new(None);
+
+/// Drops the encoding and modifier, e.g. from de_DE.UTF-8@euro, and leaves
+/// out the locales that mean untranslated
+fn normalize(locale: &str) -> Option<String> {
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    match locale {
+        "" | "C" | "POSIX" => None,
+        locale => Some(locale.to_string()),
+    }
+}
+
+/// The locale messages are shown in, from the environment like gettext
+/// does, or else from /etc/locale.conf, as the daemon usually starts
+/// without one
+fn system_locale() -> Option<String> {
+    for var in ["LC_ALL", "LC_MESSAGES", "LANG"] {
+        match env::var(var) {
+            Ok(locale) if !locale.is_empty() => return normalize(&locale),
+            _ => {}
+        }
+    }
+    let conf = read_to_string("/etc/locale.conf").ok()?;
+    let mut lang = None;
+    for line in conf.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        match key.trim() {
+            "LC_MESSAGES" => return normalize(value),
+            "LANG" => lang = Some(value),
+            _ => {}
+        }
+    }
+    normalize(lang?)
+}
+
+fn unquote(s: &str) -> Option<String> {
+    let s = s.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+/// Reads the msgid and msgstr pairs of a .po file, skipping the header,
+/// plural forms and untranslated strings
+fn parse_po(contents: &str) -> HashMap<String, String> {
+    #[derive(PartialEq)]
+    enum Field {
+        Id,
+        Str,
+        Other,
+    }
+    let mut messages = HashMap::new();
+    let (mut id, mut text) = (String::new(), String::new());
+    let mut field = Field::Other;
+    let mut add = |id: &mut String, text: &mut String| {
+        if !id.is_empty() && !text.is_empty() {
+            messages.insert(std::mem::take(id), std::mem::take(text));
+        }
+        id.clear();
+        text.clear();
+    };
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let part = match keyword {
+            "msgid" => {
+                add(&mut id, &mut text);
+                field = Field::Id;
+                rest
+            }
+            "msgstr" => {
+                field = Field::Str;
+                rest
+            }
+            _ if line.starts_with('"') => line,
+            // msgctxt, msgid_plural, msgstr[n]
+            _ => {
+                field = Field::Other;
+                id.clear();
+                continue;
+            }
+        };
+        let Some(part) = unquote(part) else {
+            continue;
+        };
+        match field {
+            Field::Id => id.push_str(&part),
+            Field::Str => text.push_str(&part),
+            Field::Other => {}
+        }
+    }
+    add(&mut id, &mut text);
+    messages
+}
+
+fn read_catalog(locale: &str) -> Option<HashMap<String, String>> {
+    let language = locale.split('_').next().unwrap_or(locale);
+    [locale, language].iter().find_map(|name| {
+        CATALOG_DIRS
+            .iter()
+            .find_map(|dir| read_to_string(format!("{dir}/{name}.po")).ok())
+            .map(|contents| parse_po(&contents))
+    })
+}
+
+/// Loads the catalog for `locale`, or the system locale when not set, to
+/// be used by `tr` from then on
+pub fn load(locale: Option<&str>) {
+    let locale = match locale {
+        Some(locale) => normalize(locale),
+        None => system_locale(),
+    };
+    let messages = match locale.as_deref() {
+        Some(name) if !name.starts_with("en") => read_catalog(name).unwrap_or_else(|| {
+            eprintln!("No translations for {name} were found, using English");
+            HashMap::new()
+        }),
+        _ => HashMap::new(),
+    };
+    *CATALOG.write().unwrap() = Some(Catalog { locale, messages });
+}
+
+/// The locale in effect, e.g. de_DE, `None` for POSIX
+pub fn locale() -> Option<String> {
+    CATALOG.read().unwrap().as_ref()?.locale.clone()
+}
+
+/// The translation of a built-in string, or the string itself
+pub fn tr(msgid: &str) -> String {
+    CATALOG
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|catalog| catalog.messages.get(msgid))
+        .map_or(msgid, String::as_str)
+        .to_string()
+}
+
+/// The locale data for `locale`, or the bar's locale when not set, POSIX
+/// for unknown ones
+pub fn resolve(locale: Option<&str>) -> Locale {
+    let name = match locale {
+        Some(locale) => normalize(locale),
+        None => self::locale(),
+    };
+    name.and_then(|name| Locale::try_from(name.as_str()).ok())
+        .unwrap_or(Locale::POSIX)
+}
+
+/// Whether times are written with AM and PM in `locale`
+pub fn is_12_hour(locale: Locale) -> bool {
+    let format = locale_match!(locale => LC_TIME::T_FMT);
+    ["%I", "%l", "%p", "%r"].iter().any(|item| format.contains(item))
+}
+
+/// Writes `value` with `decimals` digits after the locale's decimal point,
+/// grouping the thousands
+pub fn decimal(value: f64, decimals: usize, locale: Locale) -> String {
+    let point = locale_match!(locale => LC_NUMERIC::DECIMAL_POINT);
+    let separator = locale_match!(locale => LC_NUMERIC::THOUSANDS_SEP);
+    let group = locale_match!(locale => LC_NUMERIC::GROUPING)
+        .first()
+        .map_or(0, |&size| size.max(0) as usize);
+    let digits = format!("{:.*}", decimals, value.abs());
+    let (int, frac) = digits.split_once('.').unwrap_or((&digits, ""));
+    let mut out = String::new();
+    if value < 0.0 && digits.bytes().any(|b| b.is_ascii_digit() && b != b'0') {
+        out.push('-');
+    }
+    for (i, digit) in int.chars().enumerate() {
+        if i > 0 && group > 0 && !separator.is_empty() && (int.len() - i) % group == 0 {
+            out.push_str(separator);
+        }
+        out.push(digit);
+    }
+    if !frac.is_empty() {
+        out.push_str(point);
+        out.push_str(frac);
+    }
+    out
+}
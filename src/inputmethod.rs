@@ -0,0 +1,170 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::source::{self, Background, Job, Source, Update};
+use anyhow::{anyhow, Result};
+use gio::{BusType, Cancellable, DBusCallFlags, DBusConnection, DBusConnectionFlags};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+// Shows the active input method, e.g. "拼" or "EN", and switches it when
+// tapped. fcitx5 is asked over D-Bus and toggled like its hotkey does,
+// ibus is driven through the ibus tool and cycles through Engines.
+//
+// Both run inside the user's session, so the daemon needs the address of
+// its bus (the session bus for fcitx5, the ibus one for ibus) and access to it.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 1000;
+const DBUS_TIMEOUT_MS: i32 = 1000;
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum Framework {
+    Fcitx5,
+    IBus,
+}
+
+/// The `InputMethod` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InputMethodConfig {
+    pub framework: Framework,
+    pub address: Option<String>,
+    pub engines: Option<Vec<String>>,
+    pub labels: Option<HashMap<String, String>>,
+}
+
+/// Makes up a short label for input methods without one in Labels
+fn default_label(name: &str) -> String {
+    // keyboard layouts are named keyboard-us in fcitx5 and xkb:us::eng in ibus
+    let layout = name
+        .strip_prefix("keyboard-")
+        .or_else(|| name.strip_prefix("xkb:")?.split(':').next());
+    match layout {
+        Some(layout) => layout.split('-').next().unwrap_or(layout).to_uppercase(),
+        None => name.to_string(),
+    }
+}
+
+pub struct InputMethod {
+    cfg: InputMethodConfig,
+    connection: Option<DBusConnection>,
+}
+
+impl InputMethod {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let input_method = InputMethod::new(source::parse(value)?);
+        Ok(Box::new(Background::new(input_method, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS))))
+    }
+    pub fn new(cfg: InputMethodConfig) -> InputMethod {
+        InputMethod {
+            cfg,
+            connection: None,
+        }
+    }
+    fn fcitx5(&mut self, method: &str) -> Result<glib::Variant> {
+        if self.connection.is_none() {
+            let connection = match &self.cfg.address {
+                Some(address) => DBusConnection::for_address_sync(
+                    address,
+                    DBusConnectionFlags::AUTHENTICATION_CLIENT
+                        | DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+                    None,
+                    Cancellable::NONE,
+                )?,
+                None => gio::bus_get_sync(BusType::Session, Cancellable::NONE)?,
+            };
+            self.connection = Some(connection);
+        }
+        let res = self.connection.as_ref().unwrap().call_sync(
+            Some("org.fcitx.Fcitx5"),
+            "/controller",
+            "org.fcitx.Fcitx.Controller1",
+            method,
+            None,
+            None,
+            DBusCallFlags::NONE,
+            DBUS_TIMEOUT_MS,
+            Cancellable::NONE,
+        );
+        if res.is_err() {
+            // connect again next time, in case the session was restarted
+            self.connection = None;
+        }
+        Ok(res?)
+    }
+    fn ibus(&self, args: &[&str]) -> Result<String> {
+        let mut cmd = Command::new("ibus");
+        if let Some(address) = &self.cfg.address {
+            cmd.env("IBUS_ADDRESS", address);
+        }
+        let output = cmd.args(args).stdin(Stdio::null()).output()?;
+        if !output.status.success() {
+            return Err(anyhow!("ibus exited with {}", output.status));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+    fn current(&mut self) -> Result<String> {
+        match self.cfg.framework {
+            Framework::Fcitx5 => {
+                let (name,) = self
+                    .fcitx5("CurrentInputMethod")?
+                    .get::<(String,)>()
+                    .ok_or(anyhow!("unexpected reply from fcitx5"))?;
+                Ok(name)
+            }
+            Framework::IBus => self.ibus(&["engine"]),
+        }
+    }
+    fn switch(&mut self) -> Result<()> {
+        match self.cfg.framework {
+            Framework::Fcitx5 => {
+                self.fcitx5("Toggle")?;
+            }
+            Framework::IBus => {
+                let engines = self.cfg.engines.as_deref().unwrap_or_default();
+                if engines.is_empty() {
+                    return Err(anyhow!("Engines has to be set to switch ibus engines"));
+                }
+                let current = self.ibus(&["engine"])?;
+                let next = engines
+                    .iter()
+                    .position(|e| *e == current)
+                    .map_or(0, |i| (i + 1) % engines.len());
+                self.ibus(&["engine", engines[next].as_str()])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Job for InputMethod {
+    const MIN_INTERVAL: Duration = Duration::from_millis(250);
+
+    fn refresh(&mut self) -> Vec<Update> {
+        let text = match self.current() {
+            Ok(name) => self
+                .cfg
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get(&name).cloned())
+                .unwrap_or_else(|| default_label(&name)),
+            Err(e) => {
+                eprintln!("Failed to get the input method: {e:#}");
+                i18n::tr("N/A")
+            }
+        };
+        vec![Update::Text(text)]
+    }
+    fn touch(&mut self, pressed: bool) -> bool {
+        if !pressed {
+            return false;
+        }
+        if let Err(e) = self.switch() {
+            eprintln!("Failed to switch the input method: {e:#}");
+        }
+        true
+    }
+}
@@ -0,0 +1,228 @@
+use crate::json::Value;
+use nix::sys::eventfd::{EfdFlags, EventFd};
+use std::{
+    os::fd::AsFd,
+    sync::{
+        mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+        Arc, Mutex,
+    },
+};
+
+// Control requests arrive on their own threads (D-Bus, control socket),
+// but the layers are owned by the main loop, so requests are queued on a
+// channel and the main loop is woken up through an eventfd to apply them.
+
+pub enum Request {
+    SetButtonText { id: String, text: String },
+    SetButtonIcon { id: String, path: String },
+    /// Pulses the button's background in the given color, a duration of 0
+    /// stops it
+    FlashButton { id: String, color: String, duration_ms: u64 },
+    /// Fills the button up to the fraction of a task that is done, 1.0
+    /// finishes it and a negative value clears it
+    SetProgress { id: String, fraction: f64 },
+    /// Draws the button dimmed and ignores touches on it while disabled,
+    /// e.g. for actions that do nothing at the moment
+    SetButtonEnabled { id: String, enabled: bool },
+    /// Shows a message over the whole bar, after the ones already queued.
+    /// A timeout of 0 uses the default
+    ShowToast { text: String, icon: Option<String>, timeout_ms: u64 },
+    SetLayer { layer: String },
+    /// Overrides the active brightness, `None` goes back to the configured one
+    SetBrightness { brightness: Option<u32> },
+    /// Loads the named profile on top of the config, `None` goes back to
+    /// just the config
+    SwitchProfile { name: Option<String> },
+    /// Changes a setting in the user config and reloads it, an empty value
+    /// removes it. Only the settings in `settings::SETTINGS` can be changed
+    SetSetting { key: String, value: String },
+    /// Enters or leaves edit mode, where buttons are moved by dragging them.
+    /// Leaving it saves the new order
+    SetEditMode { enabled: bool },
+    /// Turns focus mode on or off, `None` goes back to following its schedule
+    SetFocusMode { enabled: Option<bool> },
+    /// Turns privacy mode on or off, `None` goes back to following the
+    /// screen sharing and lock
+    SetPrivacyMode { enabled: Option<bool> },
+    /// Turns the whole bar off or back on, like the ToggleBar action
+    SetBarEnabled { enabled: bool },
+    /// Sets the battery charge limit to a percentage
+    SetChargeLimit { limit: u32 },
+    GetLayer,
+    GetBrightness,
+    /// Returns the value in effect of every setting that can be changed
+    GetAllSettings,
+    /// Returns the active layer, brightness and the state of every button
+    GetState,
+}
+
+/// What a request may do, for deciding who is allowed to make it
+#[derive(Clone, Copy, PartialEq)]
+pub enum Access {
+    /// Only reads the state
+    Read,
+    /// Changes what the bar shows, until the daemon restarts
+    Control,
+    /// Changes the config, or shows what privacy mode hides
+    Configure,
+    /// Changes the battery charge limit, which outlives the daemon
+    ChargeLimit,
+}
+
+impl Request {
+    pub fn access(&self) -> Access {
+        match self {
+            Request::GetLayer
+            | Request::GetBrightness
+            | Request::GetAllSettings
+            | Request::GetState => Access::Read,
+            Request::SetButtonText { .. }
+            | Request::SetButtonIcon { .. }
+            | Request::FlashButton { .. }
+            | Request::SetProgress { .. }
+            | Request::SetButtonEnabled { .. }
+            | Request::ShowToast { .. }
+            | Request::SetLayer { .. }
+            | Request::SetBrightness { .. }
+            | Request::SetFocusMode { .. }
+            | Request::SetBarEnabled { .. } => Access::Control,
+            Request::SwitchProfile { .. }
+            | Request::SetSetting { .. }
+            | Request::SetEditMode { .. }
+            | Request::SetPrivacyMode { .. } => Access::Configure,
+            Request::SetChargeLimit { .. } => Access::ChargeLimit,
+        }
+    }
+}
+
+pub type Reply = Result<Value, String>;
+
+#[derive(Clone)]
+pub enum Event {
+    ButtonPressed { layer: String, id: String },
+    ButtonReleased { layer: String, id: String },
+    LayerChanged { layer: String },
+    BrightnessChanged { brightness: u32 },
+    ConfigReloaded,
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ButtonPressed { .. } => "ButtonPressed",
+            Event::ButtonReleased { .. } => "ButtonReleased",
+            Event::LayerChanged { .. } => "LayerChanged",
+            Event::BrightnessChanged { .. } => "BrightnessChanged",
+            Event::ConfigReloaded => "ConfigReloaded",
+        }
+    }
+    pub fn to_json(&self) -> Value {
+        let mut fields = vec![("type".to_string(), self.name().into())];
+        match self {
+            Event::ButtonPressed { layer, id } | Event::ButtonReleased { layer, id } => {
+                fields.push(("layer".to_string(), layer.clone().into()));
+                fields.push(("id".to_string(), id.clone().into()));
+            }
+            Event::LayerChanged { layer } => fields.push(("layer".to_string(), layer.clone().into())),
+            Event::BrightnessChanged { brightness } => {
+                fields.push(("brightness".to_string(), (*brightness).into()))
+            }
+            Event::ConfigReloaded => {}
+        }
+        Value::Object(fields)
+    }
+}
+
+/// How many events a subscriber may fall behind before it is dropped
+const SUBSCRIBER_QUEUE: usize = 256;
+
+type Subscribers = Arc<Mutex<Vec<SyncSender<Event>>>>;
+
+/// Wakes up the main loop from another thread, e.g. when a background
+/// widget has new data to show.
+#[derive(Clone)]
+pub struct Waker(Arc<EventFd>);
+
+impl Waker {
+    pub fn wake(&self) {
+        _ = self.0.write(1);
+    }
+}
+
+struct Message {
+    request: Request,
+    reply: Sender<Reply>,
+}
+
+#[derive(Clone)]
+pub struct IpcClient {
+    tx: Sender<Message>,
+    waker: Waker,
+    subscribers: Subscribers,
+}
+
+impl IpcClient {
+    /// Queues a request for the main loop and blocks until it was handled.
+    pub fn call(&self, request: Request) -> Reply {
+        let (reply, rx) = channel();
+        self.tx
+            .send(Message { request, reply })
+            .map_err(|_| "tiny-dfr is shutting down".to_string())?;
+        self.waker.wake();
+        rx.recv()
+            .map_err(|_| "tiny-dfr is shutting down".to_string())?
+    }
+    /// Returns a channel that receives every event published from now on.
+    /// Dropping the receiver unsubscribes. A subscriber that falls too far
+    /// behind is dropped, so the channel ends once the queued events were
+    /// received.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (tx, rx) = sync_channel(SUBSCRIBER_QUEUE);
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+pub struct IpcServer {
+    rx: Receiver<Message>,
+    wakeup: Arc<EventFd>,
+    subscribers: Subscribers,
+}
+
+impl IpcServer {
+    pub fn new() -> (IpcServer, IpcClient) {
+        let (tx, rx) = channel();
+        let wakeup = Arc::new(EventFd::from_flags(EfdFlags::EFD_NONBLOCK).unwrap());
+        let subscribers = Subscribers::default();
+        (
+            IpcServer {
+                rx,
+                wakeup: wakeup.clone(),
+                subscribers: subscribers.clone(),
+            },
+            IpcClient {
+                tx,
+                waker: Waker(wakeup),
+                subscribers,
+            },
+        )
+    }
+    pub fn dispatch(&self, mut handler: impl FnMut(Request) -> Reply) {
+        _ = self.wakeup.read();
+        for msg in self.rx.try_iter() {
+            _ = msg.reply.send(handler(msg.request));
+        }
+    }
+    pub fn waker(&self) -> Waker {
+        Waker(self.wakeup.clone())
+    }
+    pub fn publish(&self, event: Event) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.try_send(event.clone()).is_ok());
+    }
+    pub fn fd(&self) -> &impl AsFd {
+        self.wakeup.as_ref()
+    }
+}
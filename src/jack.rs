@@ -0,0 +1,251 @@
+use crate::audio;
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use anyhow::{anyhow, Result};
+use input_linux::{EventKind, EvdevHandle, SwitchKind};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Reacts to headphones being plugged into the jack, which the sound driver
+// reports as a switch on an input device of its own, read from its evdev
+// node like the keyboard LEDs. What happens is up to OnPlug:
+//
+// - "Selector" shows LayerKeys over the default layer for SelectorMs, by
+//   default a single AudioOutput button to pick where sound goes
+// - "SwapOutput" makes the PipeWire sink whose name or description contains
+//   Output (default "Headphones") the default one, which volume keys then
+//   control, and goes back to the one before when they are unplugged. This
+//   is for machines where the jack is a sink of its own, like Apple Silicon
+//   Macs; elsewhere PipeWire switches ports by itself.
+
+const DEFAULT_SELECTOR_MS: u64 = 5000;
+const DEFAULT_OUTPUT: &str = "Headphones";
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum JackReaction {
+    Selector,
+    SwapOutput,
+}
+
+/// The `[Jack]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct JackConfig {
+    pub on_plug: Option<Vec<JackReaction>>,
+    /// The PipeWire socket, like for AudioOutput
+    pub remote: Option<String>,
+    pub output: Option<String>,
+    pub selector_ms: Option<u64>,
+    pub layer_keys: Option<Vec<ButtonConfig>>,
+}
+
+impl JackConfig {
+    fn reacts(&self, reaction: JackReaction) -> bool {
+        self.on_plug.iter().flatten().any(|r| *r == reaction)
+    }
+    /// The buttons of the selector, if it is shown at all
+    pub fn selector_keys(&mut self) -> Option<Vec<ButtonConfig>> {
+        if !self.reacts(JackReaction::Selector) {
+            return None;
+        }
+        if let Some(keys) = self.layer_keys.take() {
+            return Some(keys);
+        }
+        let mut output = toml::Table::new();
+        if let Some(remote) = &self.remote {
+            output.insert("Remote".into(), remote.clone().into());
+        }
+        let mut button = toml::Table::new();
+        button.insert("AudioOutput".into(), output.into());
+        match button.try_into() {
+            Ok(button) => Some(vec![button]),
+            Err(e) => {
+                eprintln!("Jack: cannot make the output selector: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// The devices with a headphone jack
+fn find_jacks() -> Result<Vec<EvdevHandle<File>>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir("/dev/input")?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.to_string_lossy().contains("/event"))
+        .collect();
+    paths.sort();
+    let jacks: Vec<_> = paths
+        .into_iter()
+        .filter_map(|path| File::open(path).ok())
+        .map(EvdevHandle::new)
+        .filter(|device| {
+            device
+                .switch_bits()
+                .is_ok_and(|switches| switches.get(SwitchKind::HeadphoneInsert))
+        })
+        .collect();
+    if jacks.is_empty() {
+        return Err(anyhow!("no headphone jack found"));
+    }
+    Ok(jacks)
+}
+
+fn is_plugged(device: &EvdevHandle<File>) -> bool {
+    let mut switches = [0u8; 4];
+    let index = SwitchKind::HeadphoneInsert as usize;
+    device.switch_state(&mut switches).is_ok() && switches[index / 8] & (1 << (index % 8)) != 0
+}
+
+/// Switches the default sink to the one matching `output`, returning the
+/// name of the one before
+fn swap_to(remote: Option<&str>, output: &str) -> Result<Option<String>> {
+    let (sinks, default) = audio::sinks(remote)?;
+    let sink = sinks
+        .iter()
+        .find(|s| s.name.contains(output) || s.description.contains(output))
+        .ok_or(anyhow!("no output matching {output}"))?;
+    if Some(&sink.name) != default.as_ref() {
+        audio::set_default(remote, sink)?;
+    }
+    Ok(default)
+}
+
+fn swap_back(remote: Option<&str>, name: &str) -> Result<()> {
+    let (sinks, _) = audio::sinks(remote)?;
+    // gone while the headphones were in, the new default stays
+    match sinks.iter().find(|s| s.name == name) {
+        Some(sink) => audio::set_default(remote, sink),
+        None => Ok(()),
+    }
+}
+
+/// Follows every jack, sending whether headphones are in any of them,
+/// until nobody listens anymore
+fn follow(jacks: Vec<EvdevHandle<File>>, states: &Sender<bool>) -> Result<()> {
+    let (tx, rx) = channel();
+    for (index, jack) in jacks.into_iter().enumerate() {
+        let tx = tx.clone();
+        tx.send((index, is_plugged(&jack)))?;
+        thread::spawn(move || {
+            while let Ok(event) = jack.read_input_event() {
+                if event.kind != EventKind::Switch || event.code != SwitchKind::HeadphoneInsert as u16 {
+                    continue;
+                }
+                if tx.send((index, event.value != 0)).is_err() {
+                    return;
+                }
+            }
+            // unplugged from the machine, e.g. a USB sound card
+            _ = tx.send((index, false));
+        });
+    }
+    drop(tx);
+    let mut plugged = Vec::new();
+    let mut last = None;
+    for (index, on) in rx {
+        if plugged.len() <= index {
+            plugged.resize(index + 1, false);
+        }
+        plugged[index] = on;
+        let any = plugged.contains(&true);
+        if last != Some(any) {
+            last = Some(any);
+            if states.send(any).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    Err(anyhow!("the jacks are gone"))
+}
+
+/// Whether headphones are plugged in, and the selector shown after they were
+#[derive(Default)]
+pub struct Jack {
+    states: Option<Receiver<bool>>,
+    plugged: Option<bool>,
+    /// How long the selector is shown, if at all
+    selector_timeout: Option<Duration>,
+    /// Until when the selector is shown
+    selector_until: Option<Instant>,
+}
+
+impl Jack {
+    /// Starts following the jacks if OnPlug is set. The thread of the last
+    /// one stops once it notices it is no longer listened to.
+    pub fn start(cfg: &JackConfig, waker: &Waker) -> Jack {
+        if cfg.on_plug.as_ref().is_none_or(Vec::is_empty) {
+            return Jack::default();
+        }
+        let (tx, rx) = channel();
+        let (main_tx, main_rx) = channel();
+        thread::spawn(move || loop {
+            match find_jacks().and_then(|jacks| follow(jacks, &tx)) {
+                Ok(()) => break,
+                Err(e) => eprintln!("Jack: cannot follow the headphone jack: {e:#}"),
+            }
+            // without knowing, the headphones are taken as unplugged
+            if tx.send(false).is_err() {
+                break;
+            }
+            thread::sleep(RETRY_DELAY);
+        });
+        let swap = cfg.reacts(JackReaction::SwapOutput);
+        let waker = waker.clone();
+        let remote = cfg.remote.clone();
+        let output = cfg.output.clone().unwrap_or(DEFAULT_OUTPUT.to_string());
+        // sinks are switched here, as pw-dump and wpctl take a while
+        thread::spawn(move || {
+            let mut before = None;
+            for plugged in rx {
+                if swap && plugged {
+                    match swap_to(remote.as_deref(), &output) {
+                        Ok(default) => before = default,
+                        Err(e) => eprintln!("Jack: cannot switch to the headphones: {e:#}"),
+                    }
+                } else if let (true, Some(name)) = (swap, before.take()) {
+                    if let Err(e) = swap_back(remote.as_deref(), &name) {
+                        eprintln!("Jack: cannot switch back to {name}: {e:#}");
+                    }
+                }
+                if main_tx.send(plugged).is_err() {
+                    return;
+                }
+                waker.wake();
+            }
+        });
+        Jack {
+            states: Some(main_rx),
+            plugged: None,
+            selector_timeout: cfg
+                .reacts(JackReaction::Selector)
+                .then(|| Duration::from_millis(cfg.selector_ms.unwrap_or(DEFAULT_SELECTOR_MS))),
+            selector_until: None,
+        }
+    }
+    /// Returns whether the selector is shown, and the number of
+    /// milliseconds until that changes
+    pub fn update(&mut self) -> (bool, i32) {
+        let now = Instant::now();
+        for plugged in self.states.iter().flat_map(Receiver::try_iter) {
+            // the state the jack was in at the start is not a plug
+            if self.plugged.is_some() {
+                self.selector_until = self.selector_timeout.filter(|_| plugged).map(|timeout| now + timeout);
+            }
+            self.plugged = Some(plugged);
+        }
+        match self.selector_until {
+            Some(until) if until > now => (true, (until - now).as_millis() as i32 + 1),
+            _ => {
+                self.selector_until = None;
+                (false, i32::MAX)
+            }
+        }
+    }
+}
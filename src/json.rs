@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Result};
+use std::fmt::{self, Display, Formatter, Write};
+
+// Just enough JSON for the control socket: requests are small objects and
+// replies are built by the daemon, so this favours simplicity over speed.
+// It also reads what other users, D-Bus peers and remote servers send,
+// so nesting is limited to keep the recursion off the end of the stack.
+
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn parse(input: &str) -> Result<Value> {
+        let mut parser = Parser {
+            chars: input.char_indices().peekable(),
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        match parser.chars.next() {
+            None => Ok(value),
+            Some((i, _)) => Err(anyhow!("trailing characters at offset {i}")),
+        }
+    }
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| n as u64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Value {
+        Value::Number(n as f64)
+    }
+}
+
+fn write_str(f: &mut Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) if n.is_finite() => write!(f, "{n}"),
+            Value::Number(_) => f.write_str("null"),
+            Value::String(s) => write_str(f, s),
+            Value::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    /// How many arrays and objects the parser is in
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_ascii_whitespace()).is_some() {}
+    }
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((i, c)) => Err(anyhow!("expected '{expected}' at offset {i}, found '{c}'")),
+            None => Err(anyhow!("expected '{expected}', found end of input")),
+        }
+    }
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value> {
+        for c in word.chars() {
+            self.expect(c)?;
+        }
+        Ok(value)
+    }
+    fn value(&mut self) -> Result<Value> {
+        self.whitespace();
+        match self.chars.peek() {
+            Some((i, '{' | '[')) if self.depth >= MAX_DEPTH => {
+                Err(anyhow!("nested more than {MAX_DEPTH} deep at offset {i}"))
+            }
+            Some((_, '{')) => self.nested(Self::object),
+            Some((_, '[')) => self.nested(Self::array),
+            Some((_, '"')) => Ok(Value::String(self.string()?)),
+            Some((_, 't')) => self.literal("true", Value::Bool(true)),
+            Some((_, 'f')) => self.literal("false", Value::Bool(false)),
+            Some((_, 'n')) => self.literal("null", Value::Null),
+            Some((_, c)) if *c == '-' || c.is_ascii_digit() => self.number(),
+            Some((i, c)) => Err(anyhow!("unexpected '{c}' at offset {i}")),
+            None => Err(anyhow!("unexpected end of input")),
+        }
+    }
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value>) -> Result<Value> {
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+    fn object(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.whitespace();
+        if self.chars.next_if(|(_, c)| *c == '}').is_some() {
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.whitespace();
+            if self.chars.next_if(|(_, c)| *c == ',').is_none() {
+                self.expect('}')?;
+                return Ok(Value::Object(fields));
+            }
+        }
+    }
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.whitespace();
+        if self.chars.next_if(|(_, c)| *c == ']').is_some() {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.whitespace();
+            if self.chars.next_if(|(_, c)| *c == ',').is_none() {
+                self.expect(']')?;
+                return Ok(Value::Array(items));
+            }
+        }
+    }
+    fn hex4(&mut self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let (i, c) = self.chars.next().ok_or(anyhow!("unexpected end of input"))?;
+            code = code * 16 + c.to_digit(16).ok_or(anyhow!("invalid escape at offset {i}"))?;
+        }
+        Ok(code)
+    }
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(out),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, 'n')) => out.push('\n'),
+                    Some((_, 'r')) => out.push('\r'),
+                    Some((_, 't')) => out.push('\t'),
+                    Some((_, 'b')) => out.push('\u{8}'),
+                    Some((_, 'f')) => out.push('\u{c}'),
+                    Some((_, 'u')) => {
+                        let mut code = self.hex4()?;
+                        if (0xD800..0xDC00).contains(&code) {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex4()?;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                        }
+                        out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    Some((_, c)) => out.push(c),
+                    None => return Err(anyhow!("unterminated string")),
+                },
+                Some((_, c)) => out.push(c),
+                None => return Err(anyhow!("unterminated string")),
+            }
+        }
+    }
+    fn number(&mut self) -> Result<Value> {
+        let mut text = String::new();
+        while let Some((_, c)) = self
+            .chars
+            .next_if(|(_, c)| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            text.push(c);
+        }
+        text.parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| anyhow!("invalid number {text}"))
+    }
+}
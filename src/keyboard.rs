@@ -0,0 +1,238 @@
+use crate::config::ButtonConfig;
+use crate::json::Value;
+use crate::keycodes;
+use crate::ratelimit;
+use crate::text;
+use crate::widget::{Canvas, Scrub, Widget};
+use anyhow::{anyhow, Result};
+use input_linux::Key;
+use serde::{Deserialize, Serialize};
+
+// A tiny keyboard for typing short strings where there is no other, like a
+// Wi-Fi password in a recovery environment: a strip of characters that the
+// finger drags along, with the one in the middle picked out. Tapping a
+// character types it and brings it to the middle, tapping ⇧ at the left end
+// switches between lower and upper case.
+//
+// The keys are sent as they are on a US layout, so with another layout set
+// on the desktop the symbols may come out as other ones.
+
+const DEFAULT_CHARS: &str = "abcdefghijklmnopqrstuvwxyz0123456789 .-_@!?";
+/// Cells across the widget, the first one being the shift toggle
+const CELLS: usize = 8;
+const MIDDLE: usize = 4;
+/// How far, in cells, the finger may move for a touch to still be a tap
+const TAP_SLOP: f64 = 0.3;
+const FADED_ALPHA: f64 = 0.5;
+/// The symbols on the keys of the number row and the punctuation keys, and
+/// the ones they type with shift
+const PLAIN_SYMBOLS: &str = "1234567890-=[];'`\\,./";
+const SHIFTED_SYMBOLS: &str = "!@#$%^&*()_+{}:\"~|<>?";
+const SYMBOL_KEYS: [Key; 21] = [
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::Num0,
+    Key::Minus,
+    Key::Equal,
+    Key::LeftBrace,
+    Key::RightBrace,
+    Key::Semicolon,
+    Key::Apostrophe,
+    Key::Grave,
+    Key::Backslash,
+    Key::Comma,
+    Key::Dot,
+    Key::Slash,
+];
+
+/// The `Keyboard` key of a button
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct KeyboardConfig {
+    /// The characters on the strip, in order
+    pub chars: Option<String>,
+}
+
+/// The key typing `c` on a US layout, and whether it takes shift
+fn key_for(c: char) -> Option<(Key, bool)> {
+    if c.is_ascii_alphabetic() {
+        let key = keycodes::parse_key(&c.to_ascii_uppercase().to_string())?;
+        return Some((key, c.is_ascii_uppercase()));
+    }
+    if c == ' ' {
+        return Some((Key::Space, false));
+    }
+    // all ASCII, so byte and char indices are the same
+    match (PLAIN_SYMBOLS.find(c), SHIFTED_SYMBOLS.find(c)) {
+        (Some(i), _) => Some((SYMBOL_KEYS[i], false)),
+        (_, Some(i)) => Some((SYMBOL_KEYS[i], true)),
+        _ => None,
+    }
+}
+
+pub struct Keyboard {
+    chars: Vec<char>,
+    /// The index of the character in the middle
+    cursor: usize,
+    shift: bool,
+    /// Where the finger went down, in cells, and the cursor then, while it
+    /// is on the strip
+    drag: Option<(f64, usize)>,
+    /// Where the finger was last, in cells
+    last: f64,
+    moved: bool,
+    changed: bool,
+}
+
+impl Keyboard {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Widget>> {
+        let cfg: KeyboardConfig = value.clone().try_into()?;
+        let chars: Vec<char> = cfg.chars.as_deref().unwrap_or(DEFAULT_CHARS).chars().collect();
+        if let Some(c) = chars.iter().find(|c| key_for(**c).is_none()) {
+            return Err(anyhow!("Keyboard cannot type {c:?}"));
+        }
+        if chars.is_empty() {
+            return Err(anyhow!("Keyboard has no characters"));
+        }
+        Ok(Box::new(Keyboard {
+            chars,
+            cursor: 0,
+            shift: false,
+            drag: None,
+            last: 0.0,
+            moved: false,
+            changed: true,
+        }))
+    }
+    /// The character in a cell of the strip, as it would be typed
+    fn char_at(&self, cell: usize) -> char {
+        let index = (self.cursor + cell + self.chars.len() * CELLS - MIDDLE) % self.chars.len();
+        let c = self.chars[index];
+        if self.shift { c.to_ascii_uppercase() } else { c }
+    }
+    fn type_char(&self, c: char, send: &mut dyn FnMut(Key, bool)) {
+        let Some((key, shift)) = key_for(c) else {
+            return;
+        };
+        if !ratelimit::allow_key() {
+            return;
+        }
+        if shift {
+            send(Key::LeftShift, true);
+        }
+        send(key, true);
+        send(key, false);
+        if shift {
+            send(Key::LeftShift, false);
+        }
+    }
+}
+
+impl Widget for Keyboard {
+    fn name(&self) -> &'static str {
+        "Keyboard"
+    }
+    fn update(&mut self, _power_supply_changed: bool) -> (bool, i32) {
+        (std::mem::take(&mut self.changed), i32::MAX)
+    }
+    fn draw(&self, canvas: &Canvas) {
+        let c = canvas.c;
+        let [r, g, b] = canvas.text_color;
+        let cell = canvas.width / CELLS as f64;
+        for i in 0..CELLS {
+            let (text, bright) = match i {
+                0 => ("⇧".to_string(), self.shift),
+                i => match self.char_at(i) {
+                    ' ' => ("␣".to_string(), i == MIDDLE),
+                    ch => (ch.to_string(), i == MIDDLE),
+                },
+            };
+            c.set_source_rgba(r, g, b, if bright { 1.0 } else { FADED_ALPHA });
+            let layout = text::layout(c, canvas.font, &text, canvas.text_direction);
+            let (width, height) = text::ink_size(&layout);
+            c.move_to(
+                canvas.left + cell * i as f64 + ((cell - width) / 2.0).round(),
+                canvas.y_shift + ((canvas.height + height) / 2.0).round(),
+            );
+            text::show(c, &layout);
+        }
+        // under the character a tap anywhere else would not type
+        c.set_source_rgb(r, g, b);
+        c.rectangle(
+            canvas.left + cell * (MIDDLE as f64 + 0.25),
+            canvas.y_shift + canvas.height * 0.8,
+            cell * 0.5,
+            2.0,
+        );
+        c.fill().unwrap();
+    }
+    fn preferred_size(&self) -> usize {
+        6
+    }
+    fn scrubs(&self) -> bool {
+        true
+    }
+    fn on_scrub(&mut self, scrub: Scrub, send: &mut dyn FnMut(Key, bool)) {
+        match scrub {
+            Scrub::Down(x) => {
+                let x = x * CELLS as f64;
+                self.last = x;
+                if x < 1.0 {
+                    self.shift = !self.shift;
+                    self.changed = true;
+                    return;
+                }
+                self.drag = Some((x, self.cursor));
+                self.moved = false;
+            }
+            Scrub::Move(x) => {
+                let x = x * CELLS as f64;
+                self.last = x;
+                let Some((start, cursor)) = self.drag else {
+                    return;
+                };
+                let delta = x - start;
+                self.moved |= delta.abs() > TAP_SLOP;
+                // the strip follows the finger, so moving right goes back
+                let len = self.chars.len() as isize;
+                let cursor = (cursor as isize - delta.round() as isize).rem_euclid(len) as usize;
+                if cursor != self.cursor {
+                    self.cursor = cursor;
+                    self.changed = true;
+                }
+            }
+            Scrub::Up => {
+                if self.drag.take().is_none() || self.moved {
+                    return;
+                }
+                let cell = (self.last as usize).clamp(1, CELLS - 1);
+                let c = self.char_at(cell);
+                self.cursor = (self.cursor + cell + self.chars.len() * CELLS - MIDDLE) % self.chars.len();
+                self.changed = true;
+                self.type_char(c, send);
+            }
+        }
+    }
+    fn keys(&self) -> Vec<Key> {
+        let mut keys: Vec<Key> = self
+            .chars
+            .iter()
+            .flat_map(|c| [key_for(*c), key_for(c.to_ascii_uppercase())])
+            .flatten()
+            .map(|(key, _)| key)
+            .collect();
+        keys.push(Key::LeftShift);
+        keys
+    }
+    fn state(&self, fields: &mut Vec<(String, Value)>) -> (&'static str, Value) {
+        fields.push(("shift".to_string(), self.shift.into()));
+        ("keyboard", self.char_at(MIDDLE).to_string().into())
+    }
+}
@@ -0,0 +1,94 @@
+use input_linux::{uinput::UInputHandle, EventKind, Key};
+use input_linux_sys::{input_id, uinput_setup};
+use libc::c_char;
+use serde::{
+    de::{self, value::StrDeserializer, IntoDeserializer},
+    Deserialize,
+};
+use std::{collections::HashSet, io, os::fd::AsRawFd};
+
+// The `Action` of a button names a key the way it always has, e.g.
+// `VolumeUp`, or as in linux/input-event-codes.h, e.g. `KEY_VOLUMEUP` or
+// `BTN_LEFT`, or gives its code as a number for keys without a name
+// anywhere. Codes above KEY_MAX cannot be sent through uinput. Names are
+// matched regardless of case, so `VoiceCommand` works as well as
+// `Voicecommand`.
+//
+// The virtual keyboard can only send keys it announced when it was created,
+// so it announces exactly the ones in the config, which keeps it from
+// looking like a full keyboard and lets less common consumer keys like
+// `EjectCD`, `DisplayToggle` or `ZoomIn` through. When a reload adds keys
+// it is created again with those as well.
+
+/// Finds a key by its name in linux/input-event-codes.h, which is the name
+/// in input-linux upper-cased, without underscores, and with a few prefixes
+fn from_kernel_name(name: &str) -> Option<Key> {
+    let normalized = if let Some(rest) = name.strip_prefix("KEY_") {
+        match rest.parse::<u8>() {
+            Ok(digit) if rest.len() == 1 => format!("NUM{digit}"),
+            _ => rest.replace('_', ""),
+        }
+    } else if let Some(rest) = name.strip_prefix("BTN_") {
+        format!("BUTTON{}", rest.replace('_', ""))
+    } else {
+        return None;
+    };
+    Key::iter().find(|key| format!("{key:?}").to_uppercase() == normalized)
+}
+
+pub fn parse_key(name: &str) -> Option<Key> {
+    let deserializer: StrDeserializer<de::value::Error> = name.into_deserializer();
+    Key::deserialize(deserializer)
+        .ok()
+        .or_else(|| from_kernel_name(name))
+        .or_else(|| Key::iter().find(|key| format!("{key:?}").eq_ignore_ascii_case(name)))
+}
+
+/// Finds a key by its code, `None` above KEY_MAX
+pub fn from_code(code: u64) -> Option<Key> {
+    u16::try_from(code).ok().and_then(|code| Key::from_code(code).ok())
+}
+
+/// Creates the virtual keyboard, able to send `keys`
+pub fn create_device<F: AsRawFd>(uinput: &UInputHandle<F>, keys: &HashSet<Key>) -> io::Result<()> {
+    uinput.set_evbit(EventKind::Key)?;
+    for key in keys {
+        uinput.set_keybit(*key)?;
+    }
+    let mut dev_name_c = [0 as c_char; 80];
+    let dev_name = "Dynamic Function Row Virtual Input Device".as_bytes();
+    for i in 0..dev_name.len() {
+        dev_name_c[i] = dev_name[i] as c_char;
+    }
+    uinput.dev_setup(&uinput_setup {
+        id: input_id {
+            bustype: 0x19,
+            vendor: 0x1209,
+            product: 0x316E,
+            version: 1,
+        },
+        ff_effects_max: 0,
+        name: dev_name_c,
+    })?;
+    uinput.dev_create()
+}
+
+/// Creates the virtual keyboard again if the config has keys it cannot
+/// send yet, keeping the ones it had. Must not be called with keys held.
+pub fn add_keys<F: AsRawFd>(
+    uinput: &UInputHandle<F>,
+    enabled: &mut HashSet<Key>,
+    actions: impl IntoIterator<Item = Key>,
+) {
+    let missing: Vec<Key> = actions.into_iter().filter(|key| !enabled.contains(key)).collect();
+    if missing.is_empty() {
+        return;
+    }
+    enabled.extend(missing);
+    let res = uinput
+        .dev_destroy()
+        .and_then(|_| create_device(uinput, enabled));
+    if let Err(e) = res {
+        eprintln!("Failed to recreate the virtual keyboard: {e}");
+    }
+}
@@ -0,0 +1,146 @@
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::source::{self, Source, Update};
+use anyhow::{anyhow, Result};
+use input_linux::{EventKind, EvdevHandle, LedKind};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver},
+    thread,
+};
+
+// Shows whether Caps Lock or Num Lock is on, since the Touch Bar took the
+// place of the keys with LEDs on them. The state comes from the keyboard's
+// own LED events, read from its evdev node next to libinput, so it follows
+// whatever sets it, including other keyboards the compositor syncs it with.
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum Lock {
+    CapsLock,
+    NumLock,
+}
+
+impl Lock {
+    fn led(self) -> LedKind {
+        match self {
+            Lock::CapsLock => LedKind::CapsLock,
+            Lock::NumLock => LedKind::NumLock,
+        }
+    }
+}
+
+/// The `LockIndicator` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LockIndicatorConfig {
+    pub key: Lock,
+    pub label: Option<String>,
+    pub device: Option<String>,
+    pub flash: Option<bool>,
+}
+
+/// Finds the keyboard with the LED, preferring `name` or else the
+/// internal keyboard
+fn find_keyboard(led: LedKind, name: Option<&str>) -> Result<EvdevHandle<File>> {
+    let mut fallback = None;
+    let mut paths: Vec<PathBuf> = fs::read_dir("/dev/input")?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.to_string_lossy().contains("/event"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let Ok(file) = File::open(&path) else {
+            continue;
+        };
+        let device = EvdevHandle::new(file);
+        if !device.led_bits().is_ok_and(|leds| leds.get(led)) {
+            continue;
+        }
+        let device_name = String::from_utf8_lossy(&device.device_name().unwrap_or_default()).into_owned();
+        let preferred = match name {
+            Some(name) => device_name.contains(name),
+            None => device_name.contains("Internal Keyboard"),
+        };
+        if preferred {
+            return Ok(device);
+        }
+        if name.is_none() && fallback.is_none() {
+            fallback = Some(device);
+        }
+    }
+    fallback.ok_or(anyhow!("no keyboard with that LED found"))
+}
+
+pub struct LockIndicator {
+    cfg: LockIndicatorConfig,
+    states: Option<Receiver<bool>>,
+    on: Option<bool>,
+}
+
+impl LockIndicator {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(LockIndicator::new(source::parse(value)?)))
+    }
+    pub fn new(cfg: LockIndicatorConfig) -> LockIndicator {
+        LockIndicator {
+            cfg,
+            states: None,
+            on: None,
+        }
+    }
+    fn start(&self, waker: &Waker) -> Result<Receiver<bool>> {
+        let led = self.cfg.key.led();
+        let device = find_keyboard(led, self.cfg.device.as_deref())?;
+        let mut leds = [0u8; 4];
+        device.led_state(&mut leds)?;
+        let index = led as usize;
+        let (tx, rx) = channel();
+        tx.send(leds[index / 8] & (1 << (index % 8)) != 0)?;
+        let waker = waker.clone();
+        thread::spawn(move || {
+            while let Ok(event) = device.read_input_event() {
+                if event.kind != EventKind::Led || event.code != led as u16 {
+                    continue;
+                }
+                if tx.send(event.value != 0).is_err() {
+                    break;
+                }
+                waker.wake();
+            }
+        });
+        Ok(rx)
+    }
+}
+
+impl Source for LockIndicator {
+    fn poll(&mut self, waker: &Waker) -> (Vec<Update>, i32) {
+        let mut updates = Vec::new();
+        if self.states.is_none() {
+            let label = self.cfg.label.clone().unwrap_or(match self.cfg.key {
+                Lock::CapsLock => "caps lock".to_string(),
+                Lock::NumLock => "num lock".to_string(),
+            });
+            updates.push(Update::Text(label));
+            match self.start(waker) {
+                Ok(states) => self.states = Some(states),
+                Err(e) => {
+                    eprintln!("Lock indicator is not available: {e:#}");
+                    // only try once, like scripts
+                    self.states = Some(channel().1);
+                }
+            }
+        }
+        if let Some(on) = self.states.as_ref().unwrap().try_iter().last() {
+            // the first state is only shown, not flashed
+            if self.on.is_some() && self.on != Some(on) && self.cfg.flash == Some(true) {
+                updates.push(Update::Flash(on));
+            }
+            self.on = Some(on);
+            updates.push(Update::Highlight(on));
+        }
+        (updates, i32::MAX)
+    }
+    fn touch(&mut self, _pressed: bool) {}
+}
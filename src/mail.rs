@@ -0,0 +1,110 @@
+use crate::config::ButtonConfig;
+use crate::http;
+use crate::runtime;
+use crate::source::{self, Async, AsyncJob, Periodic, Source, Update};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, time::Duration};
+
+// Counts unread mail, either by asking an IMAP server for the number of
+// unseen messages in a mailbox, or by looking at a local maildir.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 60 * 1000;
+
+/// The `Mail` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MailConfig {
+    pub maildir: Option<String>,
+    pub imap: Option<String>,
+    pub mailbox: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub password_command: Option<String>,
+}
+
+/// Messages in new/ have not been looked at yet, messages in cur/ are
+/// unread unless their info has the S (seen) flag.
+fn count_maildir(path: &Path) -> Result<usize> {
+    let mut count = fs::read_dir(path.join("new"))?.count();
+    for entry in fs::read_dir(path.join("cur"))? {
+        let name = entry?.file_name();
+        let seen = name
+            .to_string_lossy()
+            .rsplit_once(":2,")
+            .is_some_and(|(_, flags)| flags.contains('S'));
+        if !seen {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+pub struct Mail {
+    cfg: MailConfig,
+}
+
+impl Mail {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let mail = Mail::new(source::parse(value)?)?;
+        Ok(Box::new(Async::new(Periodic::new(mail, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS)))))
+    }
+    pub fn new(cfg: MailConfig) -> Result<Mail> {
+        if cfg.maildir.is_none() && cfg.imap.is_none() {
+            return Err(anyhow!("Mail needs either Maildir or Imap"));
+        }
+        Ok(Mail { cfg })
+    }
+    async fn password(&self) -> Result<Option<String>> {
+        let Some(command) = &self.cfg.password_command else {
+            return Ok(self.cfg.password.clone());
+        };
+        let password = runtime::shell(command, &[]).await?;
+        Ok(Some(password.trim_end_matches('\n').to_string()))
+    }
+    async fn count_imap(&self, server: &str) -> Result<usize> {
+        let mailbox = self.cfg.mailbox.as_deref().unwrap_or("INBOX");
+        let quoted = mailbox.replace('\\', "\\\\").replace('"', "\\\"");
+        let request = format!("STATUS \"{quoted}\" (UNSEEN)");
+        let user = match (&self.cfg.username, self.password().await?) {
+            (Some(username), Some(password)) => Some(format!("{username}:{password}")),
+            (Some(username), None) => Some(username.clone()),
+            (None, _) => None,
+        };
+        let url = format!("{}/", server.trim_end_matches('/'));
+        let mut options = vec![("url", url.as_str()), ("request", &request)];
+        if let Some(user) = &user {
+            options.push(("user", user));
+        }
+        // the answer looks like: * STATUS INBOX (UNSEEN 3)
+        let output = http::curl_async(&options).await?;
+        output
+            .split_once("UNSEEN ")
+            .and_then(|(_, rest)| rest.split(|c: char| !c.is_ascii_digit()).next())
+            .and_then(|count| count.parse().ok())
+            .ok_or(anyhow!("unexpected answer from {server}: {}", output.trim_end()))
+    }
+}
+
+impl AsyncJob for Mail {
+    const MIN_INTERVAL: Duration = Duration::from_millis(10 * 1000);
+
+    async fn refresh(&mut self) -> Vec<Update> {
+        let count = match (&self.cfg.maildir, &self.cfg.imap) {
+            (Some(maildir), _) => count_maildir(Path::new(maildir)),
+            (None, Some(server)) => self.count_imap(server).await,
+            (None, None) => unreachable!(),
+        };
+        match count {
+            Ok(0) => vec![Update::Badge(None)],
+            Ok(count) => vec![Update::Badge(Some(count.to_string()))],
+            Err(e) => {
+                eprintln!("Failed to count unread mail: {e:#}");
+                vec![Update::Badge(Some("?".to_string()))]
+            }
+        }
+    }
+    async fn touch(&mut self, _pressed: bool) -> bool {
+        false
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Result};
 use cairo::{Antialias, Context, Format, ImageSurface, Surface};
-use chrono::{Local, Locale, Timelike, format::{StrftimeItems, Item as ChronoItem}};
+use chrono::{Local, Timelike};
 use drm::control::ClipRect;
 use freedesktop_icons::lookup;
 use input::{
@@ -13,89 +13,229 @@ use input::{
     Device as InputDevice, Libinput, LibinputInterface,
 };
 use input_linux::{uinput::UInputHandle, EventKind, Key, SynchronizeKind};
-use input_linux_sys::{input_event, input_id, timeval, uinput_setup};
-use libc::{c_char, O_ACCMODE, O_RDONLY, O_RDWR, O_WRONLY};
+use input_linux_sys::{input_event, timeval};
+use libc::{O_ACCMODE, O_RDONLY, O_RDWR, O_WRONLY};
 use librsvg_rebind::{prelude::HandleExt, Handle, Rectangle};
 use nix::{
     errno::Errno,
     sys::{
         epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags},
         signal::{SigSet, Signal},
+        signalfd::{SfdFlags, SignalFd},
     },
+    unistd::{Gid, Group},
 };
+use pango::{AttrColor, AttrList, FontDescription};
 use privdrop::PrivDrop;
 use std::{
     cmp::min,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    f64::consts::PI,
     fs::{self, File, OpenOptions},
+    ops::Range,
     os::{
         fd::{AsFd, AsRawFd},
-        unix::{fs::OpenOptionsExt, io::OwnedFd},
+        unix::{
+            fs::{MetadataExt, OpenOptionsExt},
+            io::OwnedFd,
+        },
     },
     panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 use udev::MonitorBuilder;
 
+mod action;
+mod activeapp;
+mod audio;
+mod audit;
+mod auth;
 mod backlight;
+mod baroff;
+mod battery;
+mod bluetooth;
+mod caffeine;
+mod calendar;
+mod chargelimit;
+mod ci;
+mod clock;
 mod config;
+mod dbus;
+mod dial;
+mod edit;
+mod disk;
 mod display;
+mod docker;
+mod fnrow;
+mod focus;
 mod fonts;
+mod fullscreen;
+mod homeassistant;
+mod hotplug;
+mod http;
+mod i18n;
+mod inputmethod;
+mod jack;
+mod ipc;
+mod json;
+mod keyboard;
+mod keycodes;
+mod locks;
+mod mail;
+mod migrate;
+mod modifiers;
+mod mqtt;
+mod nightlight;
+mod obs;
+mod onbattery;
+mod paths;
 mod pixel_shift;
+mod plugin;
+mod postprocess;
+mod power;
+mod powermenu;
+mod powersource;
+mod privacy;
+mod ratelimit;
+mod rhaiscript;
+mod rss;
+mod runtime;
+mod screenshare;
+mod script;
+mod sensors;
+mod settings;
+mod sha256;
+mod shortcuts;
+mod socket;
+mod source;
+mod sparkline;
+mod statickeys;
+mod stocks;
+mod sun;
+mod text;
+mod toast;
+mod typing;
+mod usbtoast;
+mod tz;
+mod vumeter;
+mod webhook;
+mod widget;
+mod websocket;
+mod worldclock;
+mod writeback;
 
 use crate::config::ConfigManager;
 use backlight::BacklightManager;
-use config::{ButtonConfig, Config};
+use baroff::{Gesture, Passthrough};
+use action::{BuiltinAction, Dispatcher};
+use config::{
+    ButtonColors, ButtonConfig, Config, IconLayout, LayerStyle, SignalAction,
+};
 use display::DrmBackend;
+use edit::EditMode;
+use focus::{FocusMode, FocusSettings};
+use fullscreen::Fullscreen;
+use ipc::{Event as IpcEvent, IpcServer, Reply, Request, Waker};
+use json::Value;
 use pixel_shift::{PixelShiftManager, PIXEL_SHIFT_WIDTH_PX};
+use modifiers::Modifier;
+use hotplug::DisplayHotplug;
+use jack::Jack;
+use onbattery::{OnBattery, PowerSupply};
+use postprocess::PostProcess;
+use privacy::Privacy;
+use screenshare::ScreenShare;
+use shortcuts::HeldKeys;
+use power::PowerPolicy;
+use powermenu::PowerMenu;
+use source::{Segment, Source, Tint, Update};
+use sparkline::Sparkline;
+use text::TextDirection;
+use toast::{Toast, ToastQueue};
+use widget::{Canvas, Scrub, Widget};
 
 const BUTTON_SPACING_PX: i32 = 16;
 // Color constants are now configurable through the config system
 const ICON_SIZE: i32 = 48;
+const ICON_LABEL_SPACING_PX: f64 = 8.0;
+const PAGE_ARROW_LINE_PX: f64 = 4.0;
+const PAGE_DOT_RADIUS_PX: f64 = 3.0;
+const PAGE_DOT_SPACING_PX: f64 = 14.0;
 const TIMEOUT_MS: i32 = 10 * 1000;
-
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum BatteryState {
-    NotCharging,
-    Charging,
-    Low,
-}
-
-struct BatteryImages {
-    plain: Vec<Handle>,
-    charging: Vec<Handle>,
-    bolt: Handle,
-}
-
-#[derive(Eq, PartialEq, Copy, Clone)]
-enum BatteryIconMode {
-    Percentage,
-    Icon,
-    Both
-}
-
-impl BatteryIconMode {
-    fn should_draw_icon(self) -> bool {
-        self != BatteryIconMode::Percentage
-    }
-    fn should_draw_text(self) -> bool {
-        self != BatteryIconMode::Icon
-    }
-}
+const STATIC_IMAGE_BRIGHTNESS: u32 = 128;
+const FLASH_DURATION: Duration = Duration::from_millis(800);
+const ATTENTION_PULSE: Duration = Duration::from_millis(1000);
+const ATTENTION_FRAME_MS: i32 = 33;
+/// How long progress stays up without being updated
+const PROGRESS_TIMEOUT: Duration = Duration::from_secs(60);
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(5);
+const BADGE_RADIUS: f64 = 13.0;
+/// How opaque buttons disabled over IPC are drawn
+const DISABLED_ALPHA: f64 = 0.35;
+const BADGE_FONT_SIZE: f64 = 20.0;
+const GREEN: [f64; 3] = [0.1, 0.5, 0.1];
+const YELLOW: [f64; 3] = [0.6, 0.5, 0.0];
+const RED: [f64; 3] = [0.6, 0.1, 0.1];
+// brighter versions for text on the regular button background
+const TEXT_GREEN: [f64; 3] = [0.35, 0.85, 0.35];
+const TEXT_YELLOW: [f64; 3] = [0.95, 0.8, 0.2];
+const TEXT_RED: [f64; 3] = [1.0, 0.4, 0.4];
 
 enum ButtonImage {
     Text(String),
     Svg(Handle),
     Bitmap(ImageSurface),
-    Time(Vec<ChronoItem<'static>>, Locale),
-    Battery(String, BatteryIconMode, BatteryImages),
+    /// Drawn by the widget, which is initialized once it is true
+    Widget(Box<dyn Widget>, bool),
 }
 
 struct Button {
+    id: Option<String>,
     image: ButtonImage,
+    label: Option<String>,
+    layout: IconLayout,
+    spacing: f64,
     changed: bool,
     active: bool,
-    action: Key,
+    action: Option<Dispatcher>,
+    source: Option<Box<dyn Source>>,
+    /// Whether the last action succeeded, shown until the given time
+    flash: Option<(bool, Instant)>,
+    /// Pulsing color set over IPC to draw attention, with when it started and ends
+    attention: Option<([f64; 3], Instant, Instant)>,
+    /// Progress of a task set over IPC, with when it was last updated
+    progress: Option<(f64, Instant)>,
+    /// Set by the source to show the button as turned on
+    highlighted: bool,
+    /// Set by the source to show a status
+    tint: Option<Tint>,
+    badge: Option<String>,
+    /// Colored pieces making up the text, set by the source
+    segments: Option<Vec<Segment>>,
+    /// Recent samples from the source, drawn behind the contents
+    sparkline: Option<Sparkline>,
+    /// How far the button is filled up as a level meter, set by the source
+    level: Option<f64>,
+    text_direction: TextDirection,
+    /// Profiles cycled through by tapping the button
+    profiles: Option<Vec<String>>,
+    /// Pixels around the button where touches are still taken as on it
+    touch_padding: f64,
+    /// Asks before doing anything when tapped
+    confirm: bool,
+    /// Keeps the key down until the finger is lifted, even off the button
+    hold: bool,
+    /// Whether its contents should not be seen by others, e.g. mail counts
+    sensitive: bool,
+    /// Drawn with dots instead of its contents, in privacy mode
+    redacted: bool,
+    /// Set over IPC to draw the button dimmed and ignore touches
+    disabled: bool,
+    /// Only shown on AC or on battery
+    show_on: Option<PowerSupply>,
+    /// Left out, as the machine does not run on what `show_on` says
+    wrong_supply: bool,
 }
 
 fn try_load_svg(path: &str) -> Result<ButtonImage> {
@@ -160,17 +300,7 @@ fn try_load_image(name: impl AsRef<str>, theme: Option<impl AsRef<str>>) -> Resu
     let mut last_err = anyhow!("no suitable icon path was found"); // in case locations is empty
 
     for location in locations {
-        let result = match location.extension().and_then(|s| s.to_str()) {
-            Some("png") => try_load_png(&location),
-            Some("svg") => try_load_svg(
-                location
-                    .to_str()
-                    .ok_or(anyhow!("image path is not unicode"))?,
-            ),
-            _ => Err(anyhow!("invalid file extension")),
-        };
-
-        match result {
+        match try_load_path(&location) {
             Ok(image) => return Ok(image),
             Err(err) => {
                 last_err = err.context(format!("while loading path {}", location.display()));
@@ -182,158 +312,253 @@ fn try_load_image(name: impl AsRef<str>, theme: Option<impl AsRef<str>>) -> Resu
     Err(last_err.context(format!("failed loading all possible paths for icon {name}")))
 }
 
-fn find_battery_device() -> Option<String> {
-    let power_supply_path = "/sys/class/power_supply";
-    if let Ok(entries) = fs::read_dir(power_supply_path) {
-        for entry in entries.flatten() {
-            let dev_path = entry.path();
-            let type_path = dev_path.join("type");
-            if let Ok(typ) = fs::read_to_string(&type_path) {
-                if typ.trim() == "Battery" {
-                    if let Some(name) = dev_path.file_name().and_then(|n| n.to_str()) {
-                        return Some(name.to_string());
-                    }
-                }
-            }
-        }
+fn try_load_path(location: &Path) -> Result<ButtonImage> {
+    match location.extension().and_then(|s| s.to_str()) {
+        Some("png") => try_load_png(location),
+        Some("svg") => try_load_svg(
+            location
+                .to_str()
+                .ok_or(anyhow!("image path is not unicode"))?,
+        ),
+        _ => Err(anyhow!("invalid file extension")),
     }
-    None
 }
 
-fn get_battery_state(battery: &str) -> (u32, BatteryState) {
-    let status_path = format!("/sys/class/power_supply/{}/status", battery);
-    let status = fs::read_to_string(&status_path)
-        .unwrap_or_else(|_| "Unknown".to_string());
+/// Draws a red bubble with white text hanging into the top right corner
+/// of a button, whose right edge and top are given.
+fn render_badge(c: &Context, font: &FontDescription, badge: &str, right: f64, top: f64) {
+    c.save().unwrap();
+    c.set_font_size(BADGE_FONT_SIZE);
+    let layout = text::layout(c, font, badge, TextDirection::Auto);
+    let (width, height) = text::ink_size(&layout);
+    let half_width = (width / 2.0 + 4.0).max(BADGE_RADIUS) - BADGE_RADIUS;
+    let x = right - BADGE_RADIUS - half_width - 4.0;
+    let y = top + BADGE_RADIUS + 4.0;
+    c.set_source_rgb(RED[0], RED[1], RED[2]);
+    c.new_sub_path();
+    c.arc(x + half_width, y, BADGE_RADIUS, (-90.0f64).to_radians(), (90.0f64).to_radians());
+    c.arc(x - half_width, y, BADGE_RADIUS, (90.0f64).to_radians(), (270.0f64).to_radians());
+    c.close_path();
+    c.fill().unwrap();
+    c.set_source_rgb(1.0, 1.0, 1.0);
+    c.move_to(
+        (x - width / 2.0).round(),
+        (y - height / 2.0 + text::ascent(&layout)).round(),
+    );
+    text::show(c, &layout);
+    c.restore().unwrap();
+}
 
-    #[cfg(target_arch = "x86_64")]
-    let capacity = {
-        let charge_now_path = format!("/sys/class/power_supply/{}/charge_now", battery);
-        let charge_full_path = format!("/sys/class/power_supply/{}/charge_full", battery);
-        let charge_now = fs::read_to_string(&charge_now_path)
-            .ok()
-            .and_then(|s| s.trim().parse::<f64>().ok());
-        let charge_full = fs::read_to_string(&charge_full_path)
-            .ok()
-            .and_then(|s| s.trim().parse::<f64>().ok());
-        match (charge_now, charge_full) {
-            (Some(now), Some(full)) if full > 0.0 => ((now / full) * 100.0).round() as u32,
-            _ => 100,
-        }
-    };
+fn render_bitmap(c: &Context, surf: &ImageSurface, x: f64, y: f64, size: i32) {
+    c.save().unwrap();
+    c.translate(x, y);
+    if size != ICON_SIZE {
+        let scale = size as f64 / ICON_SIZE as f64;
+        c.scale(scale, scale);
+    }
+    c.set_source_surface(surf, 0.0, 0.0).unwrap();
+    c.rectangle(0.0, 0.0, ICON_SIZE as f64, ICON_SIZE as f64);
+    c.fill().unwrap();
+    c.restore().unwrap();
+}
 
-    #[cfg(target_arch = "aarch64")]
-    let capacity = {
-        let capacity_path = format!("/sys/class/power_supply/{}/capacity", battery);
-        fs::read_to_string(&capacity_path)
-            .ok()
-            .and_then(|s| s.trim().parse::<u32>().ok())
-            .unwrap_or(100)
-    };
+/// Draws an SVG in a single color, using it as a mask
+fn render_svg_masked(c: &Context, svg: &Handle, x: f64, y: f64, size: i32, color: [f64; 3]) {
+    // Save the current Cairo state
+    c.save().unwrap();
 
-    let status = match status.trim() {
-        "Charging" | "Full" => BatteryState::Charging,
-        "Discharging" if capacity < 10 => BatteryState::Low,
-        _ => BatteryState::NotCharging,
-    };
-    (capacity, status)
+    // Render it at the resolution of the display, which differs with Scale
+    let (dx, dy) = c.user_to_device_distance(1.0, 0.0).unwrap();
+    let device_scale = dx.hypot(dy);
+    let device_size = (size as f64 * device_scale).ceil() as i32;
+
+    // Create a temporary surface to render the SVG
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, device_size, device_size).unwrap();
+    let temp_context = cairo::Context::new(&surface).unwrap();
+    temp_context.scale(device_scale, device_scale);
+
+    // Render SVG to the temporary surface
+    svg.render_document(&temp_context, &Rectangle::new(0.0, 0.0, size as f64, size as f64))
+        .unwrap();
+
+    // Set our color as the source
+    c.set_source_rgba(color[0], color[1], color[2], 1.0);
+
+    // Use the SVG as a mask (this will apply our color to the SVG shape)
+    c.translate(x, y);
+    c.scale(1.0 / device_scale, 1.0 / device_scale);
+    let _ = c.mask_surface(&surface, 0.0, 0.0);
+
+    // Restore the Cairo state
+    c.restore().unwrap();
 }
 
 impl Button {
     fn with_config(cfg: ButtonConfig) -> Button {
-        if let Some(text) = cfg.text {
-            Button::new_text(text, cfg.action)
+        let text_direction = cfg.text_direction.unwrap_or_default();
+        let found = source::find(&cfg);
+        let sensitive = cfg
+            .sensitive
+            .unwrap_or(found.is_some_and(|(entry, _)| entry.sensitive));
+        let source = found.and_then(|(entry, value)| match (entry.build)(value, &cfg) {
+            Ok(source) => Some(source),
+            Err(e) => {
+                eprintln!("Invalid {} button: {e:#}", entry.key);
+                None
+            }
+        });
+        let invalid_source = found.is_some() && source.is_none();
+        let action = cfg.action.clone().map(Dispatcher::new);
+        let mut button = if let (Some(icon), Some(text)) = (&cfg.icon, &cfg.text) {
+            let mut button = Button::new_icon(icon, cfg.theme, action);
+            button.label = Some(text.clone());
+            button.layout = cfg.layout.unwrap_or_default();
+            button.spacing = match cfg.spacing {
+                Some(spacing) if spacing < 0.0 => {
+                    eprintln!("Spacing must not be negative, using {ICON_LABEL_SPACING_PX}");
+                    ICON_LABEL_SPACING_PX
+                }
+                spacing => spacing.unwrap_or(ICON_LABEL_SPACING_PX),
+            };
+            button
+        } else if let Some(text) = cfg.text {
+            Button::new_text(text, action)
         } else if let Some(icon) = cfg.icon {
-            Button::new_icon(&icon, cfg.theme, cfg.action)
-        } else if let Some(time) = cfg.time {
-            Button::new_time(cfg.action, &time, cfg.locale.as_deref())
-        } else if let Some(battery_mode) = cfg.battery {
-            if let Some(battery) = find_battery_device() {
-                Button::new_battery(cfg.action, battery, battery_mode, cfg.theme)
-            } else {
-                Button::new_text("Battery N/A".to_string(), cfg.action)
+            Button::new_icon(&icon, cfg.theme, action)
+        } else if let Some((entry, value)) = widget::find(&cfg) {
+            match (entry.build)(value, &cfg) {
+                Ok(widget) => Button::new_widget(widget, action),
+                Err(e) => {
+                    eprintln!("Invalid {} button: {e:#}", entry.key);
+                    Button::new_text(i18n::tr("N/A"), action)
+                }
             }
+        } else if let Some(source) = &source {
+            // the contents are filled in once the source is running
+            Button::new_text(source.label(), action)
+        } else if invalid_source {
+            Button::new_text(i18n::tr("N/A"), action)
         } else {
-            panic!("Invalid config, a button must have either Text, Icon or Time")
-        }
+            let keys: Vec<&str> = ["Text", "Icon"]
+                .into_iter()
+                .chain(widget::WIDGETS.iter().map(|entry| entry.key))
+                .chain(source::SOURCES.iter().map(|entry| entry.key))
+                .collect();
+            panic!("Invalid config, a button must have one of {}", keys.join(", "))
+        };
+        button.source = source;
+        button.id = cfg.id;
+        button.sparkline = cfg.sparkline.map(Sparkline::new);
+        button.text_direction = text_direction;
+        button.profiles = cfg.profiles;
+        button.touch_padding = cfg.touch_padding.unwrap_or(0.0).max(0.0);
+        button.confirm = cfg.confirm.unwrap_or(false);
+        button.show_on = cfg.show_on;
+        button.sensitive = sensitive;
+        // modifiers are only of use held down while other keys are pressed
+        button.hold = cfg
+            .hold
+            .unwrap_or(button.key().is_some_and(|key| Modifier::from_key(key as u16).is_some()));
+        button
     }
-    fn new_text(text: String, action: Key) -> Button {
+    fn new_text(text: String, action: Option<Dispatcher>) -> Button {
         Button {
             action,
             active: false,
             changed: false,
             image: ButtonImage::Text(text),
+            id: None,
+            source: None,
+            label: None,
+            layout: IconLayout::default(),
+            spacing: ICON_LABEL_SPACING_PX,
+            flash: None,
+            attention: None,
+            progress: None,
+            highlighted: false,
+            tint: None,
+            badge: None,
+            segments: None,
+            sparkline: None,
+            level: None,
+            text_direction: TextDirection::Auto,
+            profiles: None,
+            touch_padding: 0.0,
+            confirm: false,
+            hold: false,
+            sensitive: false,
+            redacted: false,
+            disabled: false,
+            show_on: None,
+            wrong_supply: false,
         }
     }
-    fn new_icon(path: impl AsRef<str>, theme: Option<impl AsRef<str>>, action: Key) -> Button {
+    fn new_icon(path: impl AsRef<str>, theme: Option<impl AsRef<str>>, action: Option<Dispatcher>) -> Button {
         let image = try_load_image(path, theme).expect("failed to load icon");
         Button {
             action,
             image,
             active: false,
             changed: false,
+            id: None,
+            source: None,
+            label: None,
+            layout: IconLayout::default(),
+            spacing: ICON_LABEL_SPACING_PX,
+            flash: None,
+            attention: None,
+            progress: None,
+            highlighted: false,
+            tint: None,
+            badge: None,
+            segments: None,
+            sparkline: None,
+            level: None,
+            text_direction: TextDirection::Auto,
+            profiles: None,
+            touch_padding: 0.0,
+            confirm: false,
+            hold: false,
+            sensitive: false,
+            redacted: false,
+            disabled: false,
+            show_on: None,
+            wrong_supply: false,
         }
     }
-    fn load_battery_image(icon: &str, theme: Option<impl AsRef<str>>) -> Handle {
-        if let ButtonImage::Svg(svg) = try_load_image(icon, theme).unwrap() {
-            return svg;
-        }
-        panic!("failed to load icon");
-    }
-    fn new_battery(action: Key, battery: String, battery_mode: String, theme: Option<impl AsRef<str>>) -> Button {
-        let bolt = Self::load_battery_image("bolt", theme.as_ref());
-        let mut plain = Vec::new();
-        let mut charging = Vec::new();
-        for icon in [
-            "battery_0_bar", "battery_1_bar", "battery_2_bar", "battery_3_bar",
-            "battery_4_bar", "battery_5_bar", "battery_6_bar", "battery_full",
-        ] {
-            plain.push(Self::load_battery_image(icon, theme.as_ref()));
-        }
-        for icon in [
-            "battery_charging_20", "battery_charging_30", "battery_charging_50",
-            "battery_charging_60", "battery_charging_80",
-            "battery_charging_90", "battery_charging_full",
-        ] {
-            charging.push(Self::load_battery_image(icon, theme.as_ref()));
-        }
-        let battery_mode = match battery_mode.as_str() {
-            "icon" => BatteryIconMode::Icon,
-            "percentage" => BatteryIconMode::Percentage,
-            "both" => BatteryIconMode::Both,
-            _ => panic!("invalid battery mode, accepted modes: icon, percentage, both"),
-        };
+    fn new_widget(widget: Box<dyn Widget>, action: Option<Dispatcher>) -> Button {
         Button {
             action,
             active: false,
             changed: false,
-            image: ButtonImage::Battery(battery, battery_mode, BatteryImages {
-                plain, bolt, charging
-            }),
-        }
-    }
-
-    fn new_time(action: Key, format: &str, locale_str: Option<&str>) -> Button {
-        let format_str = if format == "24hr" {
-            "%H:%M    %a %-e %b"
-        } else if format == "12hr" {
-            "%-l:%M %p    %a %-e %b"
-        } else {
-            format
-        };
-
-        let format_items = match StrftimeItems::new(format_str).parse_to_owned() {
-            Ok(s) => s,
-            Err(e) => panic!("Invalid time format, consult the configuration file for examples of correct ones: {e:?}"),
-        };
-
-        let locale = locale_str.and_then(|l| Locale::try_from(l).ok()).unwrap_or(Locale::POSIX);
-        Button {
-            action,
-            active: false,
-            changed: false,
-            image: ButtonImage::Time(format_items, locale),
+            image: ButtonImage::Widget(widget, false),
+            id: None,
+            source: None,
+            label: None,
+            layout: IconLayout::default(),
+            spacing: ICON_LABEL_SPACING_PX,
+            flash: None,
+            attention: None,
+            progress: None,
+            highlighted: false,
+            tint: None,
+            badge: None,
+            segments: None,
+            sparkline: None,
+            level: None,
+            text_direction: TextDirection::Auto,
+            profiles: None,
+            touch_padding: 0.0,
+            confirm: false,
+            hold: false,
+            sensitive: false,
+            redacted: false,
+            disabled: false,
+            show_on: None,
+            wrong_supply: false,
         }
     }
+    #[allow(clippy::too_many_arguments)]
     fn render(
         &self,
         c: &Context,
@@ -342,165 +567,445 @@ impl Button {
         button_width: u64,
         y_shift: f64,
         config: &crate::config::Config,
+        font: &FontDescription,
     ) {
+        if self.redacted {
+            self.set_text_color(c, config);
+            let layout = text::layout(c, font, privacy::REDACTED, self.text_direction);
+            let (width, text_height) = text::ink_size(&layout);
+            c.move_to(
+                button_left_edge + (button_width as f64 / 2.0 - width / 2.0).round(),
+                y_shift + (height as f64 / 2.0 + text_height / 2.0).round(),
+            );
+            text::show(c, &layout);
+            return;
+        }
         match &self.image {
             ButtonImage::Text(text) => {
                 self.set_text_color(c, config);
-                let extents = c.text_extents(text).unwrap();
+                let layout = text::layout(c, font, text, self.text_direction);
+                if let Some(segments) = &self.segments {
+                    // the text is the segments joined, so they are colored
+                    // by byte range, untinted ones keep the text color
+                    let attrs = AttrList::new();
+                    let mut start = 0;
+                    for segment in segments {
+                        let end = start + segment.text.len() as u32;
+                        if let Some(tint) = segment.tint {
+                            let color = match tint {
+                                Tint::Green => TEXT_GREEN,
+                                Tint::Yellow => TEXT_YELLOW,
+                                Tint::Red => TEXT_RED,
+                            };
+                            let [r, g, b] = color.map(|v| (v * u16::MAX as f64) as u16);
+                            let mut attr = AttrColor::new_foreground(r, g, b);
+                            attr.set_start_index(start);
+                            attr.set_end_index(end);
+                            attrs.insert(attr);
+                        }
+                        start = end;
+                    }
+                    layout.set_attributes(Some(&attrs));
+                }
+                let (width, text_height) = text::ink_size(&layout);
                 c.move_to(
-                    button_left_edge + (button_width as f64 / 2.0 - extents.width() / 2.0).round(),
-                    y_shift + (height as f64 / 2.0 + extents.height() / 2.0).round(),
+                    button_left_edge + (button_width as f64 / 2.0 - width / 2.0).round(),
+                    y_shift + (height as f64 / 2.0 + text_height / 2.0).round(),
                 );
-                c.show_text(text).unwrap();
+                text::show(c, &layout);
+            }
+            ButtonImage::Svg(_) | ButtonImage::Bitmap(_) if self.label.is_some() => {
+                self.render_icon_with_label(c, height, button_left_edge, button_width, y_shift, config, font);
             }
             ButtonImage::Svg(svg) => {
                 let x =
                     button_left_edge + (button_width as f64 / 2.0 - (ICON_SIZE / 2) as f64).round();
                 let y = y_shift + ((height as f64 - ICON_SIZE as f64) / 2.0).round();
 
-                self.render_svg_with_color(c, svg, x, y, config, false);
+                self.render_svg_with_color(c, svg, x, y, ICON_SIZE, config, false);
             }
             ButtonImage::Bitmap(surf) => {
                 let x =
                     button_left_edge + (button_width as f64 / 2.0 - (ICON_SIZE / 2) as f64).round();
                 let y = y_shift + ((height as f64 - ICON_SIZE as f64) / 2.0).round();
-                c.set_source_surface(surf, x, y).unwrap();
-                c.rectangle(x, y, ICON_SIZE as f64, ICON_SIZE as f64);
-                c.fill().unwrap();
+                render_bitmap(c, surf, x, y, ICON_SIZE);
             }
-            ButtonImage::Time(format, locale) => {
-                self.set_text_color(c, config);
-                let current_time = Local::now();
-                let formatted_time = current_time.format_localized_with_items(format.iter(), *locale).to_string();
-                let time_extents = c.text_extents(&formatted_time).unwrap();
-                c.move_to(
-                    button_left_edge + (button_width as f64 / 2.0 - time_extents.width() / 2.0).round(),
-                    y_shift + (height as f64 / 2.0 + time_extents.height() / 2.0).round()
-                );
-                c.show_text(&formatted_time).unwrap();
-            }
-            ButtonImage::Battery(battery, battery_mode, icons) => {
-                let (capacity, state) = get_battery_state(battery);
-                let icon = if battery_mode.should_draw_icon() {
-                    Some(match state {
-                        BatteryState::Charging => match capacity {
-                            0..=20 => &icons.charging[0],
-                            21..=30 => &icons.charging[1],
-                            31..=50 => &icons.charging[2],
-                            51..=60 => &icons.charging[3],
-                            61..=80 => &icons.charging[4],
-                            81..=99 => &icons.charging[5],
-                            _ => &icons.charging[6],
-                        },
-                        _ => match capacity {
-                            0 => &icons.plain[0],
-                            1..=20 => &icons.plain[1],
-                            21..=30 => &icons.plain[2],
-                            31..=50 => &icons.plain[3],
-                            51..=60 => &icons.plain[4],
-                            61..=80 => &icons.plain[5],
-                            81..=99 => &icons.plain[6],
-                            _ => &icons.plain[7],
-                        },
-                    })
-                } else if state == BatteryState::Charging {
-                    Some(&icons.bolt)
+            ButtonImage::Widget(widget, _) => {
+                let colors = self.colors(config);
+                widget.draw(&Canvas {
+                    c,
+                    left: button_left_edge,
+                    width: button_width as f64,
+                    height: height as f64,
+                    y_shift,
+                    text_color: colors.text,
+                    icon_color: colors.icon,
+                    text_direction: self.text_direction,
+                    font,
+                });
+            }
+        }
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn render_icon_with_label(
+        &self,
+        c: &Context,
+        height: i32,
+        button_left_edge: f64,
+        button_width: u64,
+        y_shift: f64,
+        config: &crate::config::Config,
+        font: &FontDescription,
+    ) {
+        let label = self.label.as_deref().unwrap_or_default();
+        c.save().unwrap();
+        let (label, icon_size, icon_x, icon_y, text_x, text_y) = match self.layout {
+            IconLayout::Left | IconLayout::Right => {
+                let label = text::layout(c, font, label, self.text_direction);
+                let (width, text_height) = text::ink_size(&label);
+                let total = ICON_SIZE as f64 + self.spacing + width;
+                let group_left = button_left_edge + (button_width as f64 / 2.0 - total / 2.0).round();
+                let (icon_x, text_x) = if self.layout == IconLayout::Left {
+                    (group_left, group_left + ICON_SIZE as f64 + self.spacing)
                 } else {
-                    None
+                    (group_left + width + self.spacing, group_left)
                 };
-                let percent_str = format!("{:.0}%", capacity);
-                let extents = c.text_extents(&percent_str).unwrap();
-                let mut width = extents.width();
-                let mut text_offset = 0;
-                if let Some(svg) = icon {
-                    if !battery_mode.should_draw_text() {
-                        width = ICON_SIZE as f64;
-                    } else {
-                        width += ICON_SIZE as f64;
-                    }
-                    text_offset = ICON_SIZE;
-                    let x =
-                        button_left_edge + (button_width as f64 / 2.0 - width / 2.0).round();
-                    let y = y_shift + ((height as f64 - ICON_SIZE as f64) / 2.0).round();
-
-                    self.render_svg_with_color(c, svg, x, y, config, false);
-                }
-                if battery_mode.should_draw_text() {
-                    self.set_text_color(c, config);
-                    c.move_to(
-                        button_left_edge + (button_width as f64 / 2.0 - width / 2.0 + text_offset as f64).round(),
-                        y_shift + (height as f64 / 2.0 + extents.height() / 2.0).round(),
-                    );
-                    c.show_text(&percent_str).unwrap();
-                }
+                (
+                    label,
+                    ICON_SIZE,
+                    icon_x,
+                    y_shift + ((height as f64 - ICON_SIZE as f64) / 2.0).round(),
+                    text_x,
+                    y_shift + (height as f64 / 2.0 + text_height / 2.0).round(),
+                )
+            }
+            IconLayout::Above => {
+                // The strip is not tall enough for a full size icon and label on top
+                // of each other, so shrink both to fit the button outline.
+                c.set_font_size(height as f64 * 0.2);
+                let label = text::layout(c, font, label, self.text_direction);
+                let (width, text_height) = text::ink_size(&label);
+                let available = height as f64 * 0.7;
+                // a large Spacing or a tall font leave no room, still a pixel
+                // for the icon surface
+                let icon_size = ((available - self.spacing - text_height) as i32).clamp(1, ICON_SIZE);
+                let group_top = y_shift
+                    + ((height as f64 - (icon_size as f64 + self.spacing + text_height)) / 2.0)
+                        .round();
+                (
+                    label,
+                    icon_size,
+                    button_left_edge + (button_width as f64 / 2.0 - icon_size as f64 / 2.0).round(),
+                    group_top,
+                    button_left_edge + (button_width as f64 / 2.0 - width / 2.0).round(),
+                    group_top + icon_size as f64 + self.spacing + text_height,
+                )
+            }
+        };
+        match &self.image {
+            ButtonImage::Svg(svg) => {
+                self.render_svg_with_color(c, svg, icon_x, icon_y, icon_size, config, false)
             }
+            ButtonImage::Bitmap(surf) => render_bitmap(c, surf, icon_x, icon_y, icon_size),
+            _ => {}
         }
+        self.set_text_color(c, config);
+        c.move_to(text_x, text_y.round());
+        text::show(c, &label);
+        c.restore().unwrap();
     }
-    fn render_svg_with_color(&self, c: &Context, svg: &Handle, x: f64, y: f64, config: &crate::config::Config, is_active: bool) {
-        // Save the current Cairo state
-        c.save().unwrap();
-        
+    #[allow(clippy::too_many_arguments)]
+    fn render_svg_with_color(&self, c: &Context, svg: &Handle, x: f64, y: f64, size: i32, config: &crate::config::Config, is_active: bool) {
         // Get button-specific colors
-        let (_, _, icon_color, icon_color_active, _) = config.colors.get_button_colors(&self.get_text());
+        let colors = self.colors(config);
         
         // Get the configured color
         let color = if is_active {
-            icon_color_active
+            colors.icon_active
         } else {
-            icon_color
+            colors.icon
         };
-        
-        // Create a temporary surface to render the SVG
-        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, ICON_SIZE, ICON_SIZE).unwrap();
-        let temp_context = cairo::Context::new(&surface).unwrap();
-        
-        // Render SVG to the temporary surface
-        svg.render_document(&temp_context, &Rectangle::new(0.0, 0.0, ICON_SIZE as f64, ICON_SIZE as f64))
-            .unwrap();
-        
-        // Set our color as the source
-        c.set_source_rgba(color[0], color[1], color[2], 1.0);
-        
-        // Use the SVG as a mask (this will apply our color to the SVG shape)
-        let _ = c.mask_surface(&surface, x, y);
-        
-        // Restore the Cairo state
-        c.restore().unwrap();
+        render_svg_masked(c, svg, x, y, size, color);
+    }
+    fn preferred_size(&self) -> usize {
+        match &self.image {
+            ButtonImage::Widget(widget, _) => widget.preferred_size(),
+            _ => 1,
+        }
+    }
+    /// Brings the widget up to date, if the button has one, marking the
+    /// button changed if it has to be drawn again. Returns the number of
+    /// milliseconds until it wants to be updated next.
+    fn update_widget(&mut self, power_supply_changed: bool) -> i32 {
+        let ButtonImage::Widget(widget, initialized) = &mut self.image else {
+            return i32::MAX;
+        };
+        if !*initialized {
+            widget.init();
+            *initialized = true;
+        }
+        let (changed, ms_left) = widget.update(power_supply_changed);
+        self.changed |= changed;
+        ms_left
+    }
+    fn state(&self) -> Value {
+        let mut fields = vec![
+            ("id".to_string(), self.id.clone().map_or(Value::Null, Value::from)),
+            (
+                "action".to_string(),
+                self.action.as_ref().map_or(Value::Null, |action| match action.action().key() {
+                    Some(_) => self.key_to_action_string().into(),
+                    None => action.action().kind().into(),
+                }),
+            ),
+            ("active".to_string(), self.active.into()),
+            ("enabled".to_string(), (!self.disabled).into()),
+        ];
+        let (kind, value) = match &self.image {
+            ButtonImage::Text(text) => ("text", text.clone().into()),
+            ButtonImage::Svg(_) | ButtonImage::Bitmap(_) => {
+                ("icon", self.label.clone().map_or(Value::Null, Value::from))
+            }
+            ButtonImage::Widget(widget, _) => widget.state(&mut fields),
+        };
+        fields.push(("kind".to_string(), kind.into()));
+        let value = if self.redacted { privacy::REDACTED.into() } else { value };
+        fields.push(("value".to_string(), value));
+        Value::Object(fields)
+    }
+    /// Polls the button's source, if it has one, applying whatever it asked
+    /// to show. Returns the number of milliseconds until it needs to be
+    /// polled again.
+    fn poll_source(&mut self, waker: &Waker) -> i32 {
+        let Some(source) = &mut self.source else {
+            return i32::MAX;
+        };
+        let (updates, mut ms_left) = source.poll(waker);
+        for update in updates {
+            let res = match update {
+                Update::Text(text) => self.set_text(text),
+                Update::Icon(icon) if !icon.contains('/') => try_load_image(&icon, None::<&str>)
+                    .map_err(|e| format!("{e:#}"))
+                    .and_then(|image| self.set_icon(image)),
+                Update::Icon(path) => try_load_path(Path::new(&path))
+                    .map_err(|e| format!("{e:#}"))
+                    .and_then(|image| self.set_icon(image)),
+                Update::Highlight(highlighted) => {
+                    self.changed |= self.highlighted != highlighted;
+                    self.highlighted = highlighted;
+                    Ok(())
+                }
+                Update::Segments(segments) => {
+                    let text = segments.iter().map(|s| s.text.as_str()).collect();
+                    self.set_text(text).map(|()| self.segments = Some(segments))
+                }
+                Update::Badge(badge) => {
+                    self.changed |= self.badge != badge;
+                    self.badge = badge;
+                    Ok(())
+                }
+                Update::Tint(tint) => {
+                    self.tint = tint;
+                    self.changed = true;
+                    Ok(())
+                }
+                Update::Sample(sample) => {
+                    if let Some(sparkline) = &mut self.sparkline {
+                        sparkline.push(sample);
+                        self.changed = true;
+                    }
+                    Ok(())
+                }
+                Update::Level(level) => {
+                    let level = level.map(|l| l.clamp(0.0, 1.0));
+                    self.changed |= self.level != level;
+                    self.level = level;
+                    Ok(())
+                }
+                Update::Flash(success) => {
+                    self.flash = Some((success, Instant::now() + FLASH_DURATION));
+                    self.changed = true;
+                    Ok(())
+                }
+            };
+            if let Err(e) = res {
+                eprintln!("Failed to apply button update: {e}");
+            }
+        }
+        if let Some((_, until)) = self.flash {
+            let now = Instant::now();
+            if now >= until {
+                self.flash = None;
+                self.changed = true;
+            } else {
+                ms_left = ms_left.min((until - now).as_millis() as i32 + 1);
+            }
+        }
+        ms_left
     }
-    fn set_active<F>(&mut self, uinput: &mut UInputHandle<F>, active: bool)
+    /// Keeps an attention pulse going and drops stale progress, returning
+    /// the number of milliseconds until it needs to be called again
+    fn animate(&mut self) -> i32 {
+        let now = Instant::now();
+        let mut ms_left = i32::MAX;
+        if let Some((_, updated)) = self.progress {
+            if now >= updated + PROGRESS_TIMEOUT {
+                self.progress = None;
+                self.changed = true;
+            } else {
+                ms_left = (updated + PROGRESS_TIMEOUT - now).as_millis() as i32 + 1;
+            }
+        }
+        let Some((_, _, until)) = self.attention else {
+            return ms_left;
+        };
+        self.changed = true;
+        if now >= until {
+            self.attention = None;
+            return ms_left;
+        }
+        ATTENTION_FRAME_MS
+    }
+    /// Shows how far a task got, finishing it with a green flash at 1.0
+    fn set_progress(&mut self, fraction: f64) {
+        let now = Instant::now();
+        if fraction >= 1.0 {
+            self.flash = Some((true, now + FLASH_DURATION));
+            self.progress = None;
+        } else {
+            // negative values clear it without the flash
+            self.progress = (fraction >= 0.0).then_some((fraction, now));
+        }
+        self.changed = true;
+    }
+    fn set_enabled(&mut self, enabled: bool) {
+        self.changed |= self.disabled == enabled;
+        self.disabled = !enabled;
+    }
+    /// Blends the background towards the attention color and back
+    fn attention_color(&self, background: (f64, f64, f64)) -> (f64, f64, f64) {
+        let Some((color, started, _)) = self.attention else {
+            return background;
+        };
+        let phase = started.elapsed().as_secs_f64() / ATTENTION_PULSE.as_secs_f64();
+        let t = (1.0 - (phase * 2.0 * PI).cos()) / 2.0;
+        let blend = |from: f64, to: f64| from + (to - from) * t;
+        let (r, g, b) = background;
+        (blend(r, color[0]), blend(g, color[1]), blend(b, color[2]))
+    }
+    fn set_text(&mut self, text: String) -> Result<(), String> {
+        self.segments = None;
+        match &mut self.image {
+            ButtonImage::Text(current) => *current = text,
+            ButtonImage::Svg(_) | ButtonImage::Bitmap(_) => self.label = Some(text),
+            _ => return Err("this button does not display text".to_string()),
+        }
+        self.changed = true;
+        Ok(())
+    }
+    fn set_icon(&mut self, image: ButtonImage) -> Result<(), String> {
+        match &self.image {
+            ButtonImage::Text(_) | ButtonImage::Svg(_) | ButtonImage::Bitmap(_) => {}
+            _ => return Err("this button does not display an icon".to_string()),
+        }
+        self.image = image;
+        self.changed = true;
+        Ok(())
+    }
+    fn set_active<F>(&mut self, uinput: &mut UInputHandle<F>, active: bool) -> bool
     where
         F: AsRawFd,
     {
-        if self.active != active {
+        // releases still go through, for a button disabled while touched
+        if self.active != active && !(active && self.disabled) {
             self.active = active;
             self.changed = true;
 
-            toggle_key(uinput, self.action, active as i32);
+            if let Some(action) = &mut self.action {
+                action.set_pressed(active, &mut |key, down| toggle_key(uinput, key, down as i32));
+            }
+            if let Some(source) = &mut self.source {
+                source.touch(active);
+            }
+            if let ButtonImage::Widget(widget, _) = &mut self.image {
+                widget.on_touch(active);
+            }
+            return true;
         }
+        false
+    }
+    /// Runs the Hold phase of the action once the button was held long
+    /// enough, returning the number of milliseconds until then
+    fn check_hold<F>(&mut self, uinput: &mut UInputHandle<F>) -> i32
+    where
+        F: AsRawFd,
+    {
+        match &mut self.action {
+            Some(action) if self.active => {
+                action.check_hold(&mut |key, down| toggle_key(uinput, key, down as i32))
+            }
+            _ => i32::MAX,
+        }
+    }
+    /// Every key the button may send, for the virtual keyboard to announce
+    fn keys(&self) -> Vec<Key> {
+        let mut keys = self.action.as_ref().map(|action| action.action().keys()).unwrap_or_default();
+        if let ButtonImage::Widget(widget, _) = &self.image {
+            keys.extend(widget.keys());
+        }
+        keys
+    }
+    /// Passes the finger on to the widget if it scrubs, returning whether it does
+    fn scrub<F>(&mut self, uinput: &mut UInputHandle<F>, scrub: Scrub) -> bool
+    where
+        F: AsRawFd,
+    {
+        match &mut self.image {
+            ButtonImage::Widget(widget, _) if widget.scrubs() => {
+                widget.on_scrub(scrub, &mut |key, down| toggle_key(uinput, key, down as i32));
+                true
+            }
+            _ => false,
+        }
+    }
+    /// The key the action sends, if it is a key
+    fn key(&self) -> Option<Key> {
+        self.action.as_ref()?.action().key()
+    }
+    /// What the action asks of the daemon itself, if that is what it does
+    fn builtin(&self) -> Option<BuiltinAction> {
+        self.action.as_ref()?.action().builtin()
     }
-
-
-
 
     fn set_text_color(&self, c: &Context, config: &crate::config::Config) {
         // Get button-specific text color from overrides
-        let (_, _, _, _, text_color) = config.colors.get_button_colors(&self.get_text());
+        let text_color = self.colors(config).text;
         c.set_source_rgb(text_color[0], text_color[1], text_color[2]);
     }
 
+    fn colors(&self, config: &crate::config::Config) -> ButtonColors {
+        config.colors.get_button_colors(self.id.as_deref(), &self.get_text())
+    }
+
     fn get_text(&self) -> String {
         match &self.image {
             ButtonImage::Text(text) => text.clone(),
-            ButtonImage::Time(_, _) => "Time".to_string(),
-            ButtonImage::Battery(_, _, _) => "Battery".to_string(),
+            ButtonImage::Widget(widget, _) => widget.name().to_string(),
+            ButtonImage::Svg(_) | ButtonImage::Bitmap(_) if self.label.is_some() => {
+                self.label.clone().unwrap()
+            }
             ButtonImage::Svg(_) => self.key_to_action_string(),
             ButtonImage::Bitmap(_) => self.key_to_action_string(),
         }
     }
 
     /// Convert Key enum back to action string for color override lookup
+    /// on buttons without an `Id`
     fn key_to_action_string(&self) -> String {
-        match self.action {
+        let Some(action) = self.key() else {
+            return String::new();
+        };
+        match action {
             // Function keys
             Key::F1 => "F1".to_string(),
             Key::F2 => "F2".to_string(),
@@ -531,45 +1036,177 @@ impl Button {
             Key::VolumeUp => "VolumeUp".to_string(),
             
             // Fallback for any other keys
-            _ => format!("{:?}", self.action),
+            _ => format!("{:?}", action),
         }
     }
 }
 
-#[derive(Default)]
 pub struct FunctionLayer {
-    displays_time: bool,
-    displays_battery: bool,
+    name: &'static str,
     buttons: Vec<(usize, Button)>,
     virtual_button_count: usize,
+    style: LayerStyle,
+    /// Number of buttons at the start that are not from the config
+    fixed: usize,
+    /// Where each button was when loaded, to save a new order
+    order: Vec<usize>,
+    /// The index of the first button of the second row, if the layer has
+    /// one, and how many virtual buttons that row is wide
+    second_row: Option<(usize, usize)>,
+    /// The index of the first button of each page, if there is more than one
+    pages: Vec<usize>,
+    /// The page shown
+    page: usize,
 }
 
 impl FunctionLayer {
-    fn with_config(cfg: Vec<ButtonConfig>) -> FunctionLayer {
+    fn with_config(
+        name: &'static str,
+        cfg: Vec<ButtonConfig>,
+        style: LayerStyle,
+        fixed: usize,
+    ) -> FunctionLayer {
         if cfg.is_empty() {
             panic!("Invalid configuration, layer has 0 buttons");
         }
 
-        let mut virtual_button_count = 0;
+        let order = (0..cfg.len()).collect();
+        let (buttons, virtual_button_count) = Self::lay_out(cfg);
         FunctionLayer {
-            displays_time: cfg.iter().any(|cfg| cfg.time.is_some()),
-            displays_battery: cfg.iter().any(|cfg| cfg.battery.is_some()),
-            buttons: cfg
-                .into_iter()
-                .scan(&mut virtual_button_count, |state, cfg| {
-                    let i = **state;
-                    let mut stretch = cfg.stretch.unwrap_or(1);
-                    if stretch < 1 {
-                        println!("Stretch value must be at least 1, setting to 1.");
-                        stretch = 1;
-                    }
-                    **state += stretch;
-                    Some((i, Button::with_config(cfg)))
-                })
-                .collect(),
+            name,
+            buttons,
             virtual_button_count,
+            style,
+            fixed,
+            order,
+            second_row: None,
+            pages: Vec::new(),
+            page: 0,
         }
     }
+    /// Makes the buttons of a row, and returns them with where they start
+    /// and how many virtual buttons the row is wide
+    fn lay_out(cfg: Vec<ButtonConfig>) -> (Vec<(usize, Button)>, usize) {
+        let mut virtual_button_count = 0;
+        let buttons = cfg
+            .into_iter()
+            .scan(&mut virtual_button_count, |state, cfg| {
+                let i = **state;
+                let stretch = cfg.stretch;
+                let button = Button::with_config(cfg);
+                let mut stretch = stretch.unwrap_or_else(|| button.preferred_size());
+                if stretch < 1 {
+                    println!("Stretch value must be at least 1, setting to 1.");
+                    stretch = 1;
+                }
+                **state += stretch;
+                Some((i, button))
+            })
+            .collect();
+        (buttons, virtual_button_count)
+    }
+    /// Stacks a second row of buttons under the first one, both of them
+    /// drawn at half the size
+    fn add_second_row(&mut self, cfg: Vec<ButtonConfig>) {
+        if cfg.is_empty() {
+            eprintln!("The second row of the {} layer has no buttons, ignoring it", self.name);
+            return;
+        }
+        let first = self.buttons.len();
+        let (buttons, virtual_button_count) = Self::lay_out(cfg);
+        self.order.extend(first..first + buttons.len());
+        self.buttons.extend(buttons);
+        self.second_row = Some((first, virtual_button_count));
+    }
+    /// How much smaller the buttons are drawn, so that the rows fit
+    fn row_scale(&self) -> f64 {
+        if self.second_row.is_some() {
+            0.5
+        } else {
+            1.0
+        }
+    }
+    /// Splits a single row of buttons over pages when they would be
+    /// narrower than `min_width` all on the bar, each page keeping a virtual
+    /// button on either side for the arrows going to the others
+    fn paginate(&mut self, width: u16, min_width: f64) {
+        self.pages.clear();
+        self.page = 0;
+        if self.second_row.is_some() || min_width <= 0.0 {
+            return;
+        }
+        let (inset_left, inset_right) = self.style.insets;
+        let usable_width = width as f64 / self.style.scale - inset_left - inset_right;
+        let spacing = BUTTON_SPACING_PX as f64;
+        let fits = ((usable_width + spacing) / (min_width + spacing)) as usize;
+        if self.virtual_button_count <= fits {
+            return;
+        }
+        let per_page = fits.saturating_sub(2).max(1);
+        let mut origin = 0;
+        self.pages.push(0);
+        for i in 1..self.buttons.len() {
+            let end = self.buttons.get(i + 1).map_or(self.virtual_button_count, |(start, _)| *start);
+            if end - origin > per_page {
+                self.pages.push(i);
+                origin = self.buttons[i].0;
+            }
+        }
+    }
+    fn paged(&self) -> bool {
+        self.pages.len() > 1
+    }
+    /// Shows another page
+    fn turn_page(&mut self, page: usize) {
+        self.page = page;
+        for (_, button) in &mut self.buttons {
+            button.changed = true;
+        }
+    }
+    /// Whether the order of the buttons can be changed with a long press
+    fn editable(&self) -> bool {
+        self.second_row.is_none() && !self.paged()
+    }
+    /// The indices of the buttons of a row, on the page shown, the virtual
+    /// button the first one starts at and how many virtual buttons the row
+    /// is wide
+    fn row(&self, row: usize) -> (Range<usize>, usize, usize) {
+        match (self.second_row, row) {
+            (Some((first, count)), 1) => (first..self.buttons.len(), 0, count),
+            (Some((first, _)), _) => (0..first, 0, self.virtual_button_count),
+            (None, _) if self.paged() => {
+                let first = self.pages[self.page];
+                let end = self.pages.get(self.page + 1).copied().unwrap_or(self.buttons.len());
+                let origin = self.buttons[first].0;
+                let last = self.buttons.get(end).map_or(self.virtual_button_count, |(start, _)| *start);
+                // and the arrows
+                (first..end, origin, last - origin + 2)
+            }
+            (None, _) => (0..self.buttons.len(), 0, self.virtual_button_count),
+        }
+    }
+    /// The indices of the buttons on the bar, the ones of other pages left out
+    fn shown(&self) -> Range<usize> {
+        match self.second_row {
+            Some(_) => 0..self.buttons.len(),
+            None => self.row(0).0,
+        }
+    }
+    /// The row of a button, where it starts and ends in virtual buttons of
+    /// its row and how many of them the row is wide
+    fn span(&self, i: usize) -> (usize, usize, usize, usize) {
+        let row = self.second_row.is_some_and(|(first, _)| i >= first) as usize;
+        let (buttons, origin, count) = self.row(row);
+        let arrow = self.paged() as usize;
+        let start = self.buttons[i].0 + arrow - origin;
+        let end = if i + 1 < buttons.end {
+            self.buttons[i + 1].0 + arrow - origin
+        } else {
+            count - arrow
+        };
+        (row, start, end, count)
+    }
+    #[allow(clippy::too_many_arguments)]
     fn draw(
         &mut self,
         config: &Config,
@@ -578,6 +1215,7 @@ impl FunctionLayer {
         surface: &Surface,
         pixel_shift: (f64, f64),
         complete_redraw: bool,
+        focus: Option<&FocusSettings>,
     ) -> Vec<ClipRect> {
         let c = Context::new(surface).unwrap();
         let mut modified_regions = if complete_redraw {
@@ -587,15 +1225,23 @@ impl FunctionLayer {
         };
         c.translate(height as f64, 0.0);
         c.rotate((90.0f64).to_radians());
+        // laid out in scaled pixels from here on
+        let scale = self.style.scale;
+        c.scale(scale, scale);
+        let (width, panel_height) = ((width as f64 / scale) as i32, height as f64 / scale);
+        // buttons only take the band of the height that is used
+        let (band_top, height) = self.style.vertical.band(panel_height, scale);
+        c.translate(0.0, band_top);
+        let height = height as i32;
         let pixel_shift_width = if config.enable_pixel_shift {
             PIXEL_SHIFT_WIDTH_PX
         } else {
             0
         };
-        let virtual_button_width = ((width - pixel_shift_width as i32)
-            - (BUTTON_SPACING_PX * (self.virtual_button_count - 1) as i32))
-            as f64
-            / self.virtual_button_count as f64;
+        // each row is laid out in its own units, smaller by k with two rows
+        let k = self.row_scale();
+        let (inset_left, inset_right) = (self.style.insets.0 / k, self.style.insets.1 / k);
+        let row_width = (width - pixel_shift_width as i32) as f64 / k - inset_left - inset_right;
         let radius = 8.0f64;
         let bot = (height as f64) * 0.15;
         let top = (height as f64) * 0.85;
@@ -605,42 +1251,61 @@ impl FunctionLayer {
             c.set_source_rgb(0.0, 0.0, 0.0);
             c.paint().unwrap();
         }
-        c.set_font_face(&config.font_face);
+        c.set_font_face(&self.style.font_face);
+        c.set_font_options(&config.font_options);
         c.set_font_size(32.0);
 
-        for i in 0..self.buttons.len() {
-            let end = if i + 1 < self.buttons.len() {
-                self.buttons[i + 1].0
-            } else {
-                self.virtual_button_count
-            };
-            let (start, button) = &mut self.buttons[i];
-            let start = *start;
+        for i in self.shown() {
+            let (row, start, end, count) = self.span(i);
+            let button = &mut self.buttons[i].1;
 
             if !button.changed && !complete_redraw {
                 continue;
             };
+            // left black, focus mode is only turned on or off with a complete redraw
+            if Self::hides(button, focus) {
+                button.changed = false;
+                continue;
+            }
 
+            let virtual_button_width =
+                (row_width - (BUTTON_SPACING_PX * (count - 1) as i32) as f64) / count as f64;
             let left_edge = (start as f64 * (virtual_button_width + BUTTON_SPACING_PX as f64))
                 .floor()
-                + pixel_shift_x
-                + (pixel_shift_width / 2) as f64;
+                + (pixel_shift_x + (pixel_shift_width / 2) as f64) / k
+                + inset_left;
 
             let button_width = virtual_button_width
                 + ((end - start - 1) as f64 * (virtual_button_width + BUTTON_SPACING_PX as f64))
                     .floor();
 
             // Get button-specific colors
-            let (bg_inactive, bg_active, _, _, _) = 
-                config.colors.get_button_colors(&button.get_text());
+            let colors = button.colors(config);
+            let (bg_inactive, bg_active) = (colors.background_inactive, colors.background_active);
             
-            let (r, g, b) = if button.active {
+            let tint = match button.flash {
+                Some((success, _)) => Some(if success { Tint::Green } else { Tint::Red }),
+                None => button.tint,
+            };
+            let (r, g, b) = if let (Some(tint), false) = (tint, button.active) {
+                let color = match tint {
+                    Tint::Green => GREEN,
+                    Tint::Yellow => YELLOW,
+                    Tint::Red => RED,
+                };
+                (color[0], color[1], color[2])
+            } else if button.active || button.highlighted {
                 (bg_active[0], bg_active[1], bg_active[2])
-            } else if config.show_button_outlines {
+            } else if self.style.show_button_outlines {
                 (bg_inactive[0], bg_inactive[1], bg_inactive[2])
             } else {
                 (0.0, 0.0, 0.0)
             };
+            let (r, g, b) = button.attention_color((r, g, b));
+            let row_top = row as f64 * height as f64 * k;
+            c.save().unwrap();
+            c.translate(0.0, row_top);
+            c.scale(k, k);
             if !complete_redraw {
                 c.set_source_rgb(0.0, 0.0, 0.0);
                 c.rectangle(
@@ -651,6 +1316,11 @@ impl FunctionLayer {
                 );
                 c.fill().unwrap();
             }
+            let alpha = self.style.alpha * if button.disabled { DISABLED_ALPHA } else { 1.0 };
+            if alpha < 1.0 {
+                // everything on the button is faded together below
+                c.push_group();
+            }
             // Set the button background color
             c.set_source_rgb(r, g, b);
             
@@ -689,6 +1359,42 @@ impl FunctionLayer {
             c.close_path();
 
             c.fill().unwrap();
+            if let Some((progress, _)) = button.progress {
+                c.set_source_rgb(bg_active[0], bg_active[1], bg_active[2]);
+                c.rectangle(
+                    left_edge + radius,
+                    bot,
+                    ((button_width.ceil() - radius * 2.0) * progress).round(),
+                    top - bot,
+                );
+                c.fill().unwrap();
+            }
+            if let Some(level) = button.level {
+                // green, then yellow and red close to clipping
+                let color = match level {
+                    l if l >= 0.9 => RED,
+                    l if l >= 0.7 => YELLOW,
+                    _ => GREEN,
+                };
+                c.set_source_rgb(color[0], color[1], color[2]);
+                c.rectangle(
+                    left_edge + radius,
+                    bot,
+                    ((button_width.ceil() - radius * 2.0) * level).round(),
+                    top - bot,
+                );
+                c.fill().unwrap();
+            }
+            if let (Some(sparkline), false) = (&button.sparkline, button.redacted) {
+                sparkline.draw(
+                    &c,
+                    left_edge + radius,
+                    bot,
+                    button_width.ceil() - radius * 2.0,
+                    top - bot,
+                    colors.text,
+                );
+            }
             button.render(
                 &c,
                 height,
@@ -696,62 +1402,293 @@ impl FunctionLayer {
                 button_width.ceil() as u64,
                 pixel_shift_y,
                 config,
+                &self.style.font,
             );
+            if let (Some(badge), false) = (&button.badge, button.redacted) {
+                render_badge(&c, &self.style.font, badge, left_edge + button_width.ceil(), bot - radius);
+            }
+            if alpha < 1.0 {
+                c.pop_group_to_source().unwrap();
+                c.paint_with_alpha(alpha).unwrap();
+            }
+            c.restore().unwrap();
 
             button.changed = false;
 
             if !complete_redraw {
                 modified_regions.push(ClipRect::new(
-                    height as u16 - top as u16 - radius as u16,
-                    left_edge as u16,
-                    height as u16 - bot as u16 + radius as u16,
-                    left_edge as u16 + button_width as u16,
+                    ((panel_height - band_top - row_top - (top + radius) * k) * scale) as u16,
+                    (left_edge * k * scale) as u16,
+                    ((panel_height - band_top - row_top - (bot - radius) * k) * scale).ceil() as u16,
+                    ((left_edge + button_width) * k * scale).ceil() as u16,
                 ));
             }
         }
+        if complete_redraw && self.paged() {
+            let shift = pixel_shift_x + (pixel_shift_width / 2) as f64;
+            self.draw_paging(&c, config, (width - pixel_shift_width as i32) as f64, height as f64, shift);
+        }
 
         modified_regions
     }
 
-    fn hit(&self, width: u16, height: u16, x: f64, y: f64, i: Option<usize>) -> Option<usize> {
-        let virtual_button_width =
-            (width as i32 - (BUTTON_SPACING_PX * (self.virtual_button_count - 1) as i32)) as f64
-                / self.virtual_button_count as f64;
+    /// Draws the arrows to the other pages, and a dot for each page under
+    /// the buttons
+    fn draw_paging(&self, c: &Context, config: &Config, width: f64, height: f64, shift: f64) {
+        let text = config.colors.text_color;
+        c.set_source_rgb(text[0], text[1], text[2]);
+        c.set_line_width(PAGE_ARROW_LINE_PX);
+        let (_, _, count) = self.row(0);
+        let size = height * 0.15;
+        let arrows = [(0, -1.0, self.page > 0), (count - 1, 1.0, self.page + 1 < self.pages.len())];
+        for (unit, direction, shown) in arrows {
+            if !shown {
+                continue;
+            }
+            let (left, right) = self.unit_edges(width, unit, unit + 1, count);
+            let x = shift + (left + right) / 2.0;
+            c.move_to(x - direction * size / 2.0, height / 2.0 - size);
+            c.line_to(x + direction * size / 2.0, height / 2.0);
+            c.line_to(x - direction * size / 2.0, height / 2.0 + size);
+            c.stroke().unwrap();
+        }
+        let y = height * 0.925;
+        let dots_width = (self.pages.len() - 1) as f64 * PAGE_DOT_SPACING_PX;
+        for page in 0..self.pages.len() {
+            let alpha = if page == self.page { 1.0 } else { 0.4 };
+            c.set_source_rgba(text[0], text[1], text[2], alpha);
+            let x = shift + (width - dots_width) / 2.0 + page as f64 * PAGE_DOT_SPACING_PX;
+            c.arc(x, y, PAGE_DOT_RADIUS_PX, 0.0, 2.0 * PI);
+            c.fill().unwrap();
+        }
+    }
+
+    fn state(&self) -> Value {
+        Value::Object(vec![
+            ("name".to_string(), self.name.into()),
+            (
+                "buttons".to_string(),
+                Value::Array(self.buttons.iter().map(|(_, b)| b.state()).collect()),
+            ),
+        ])
+    }
 
-        let i = i.unwrap_or_else(|| {
-            let virtual_i = (x / (width as f64 / self.virtual_button_count as f64)) as usize;
-            self.buttons
+    fn set_active<F>(&mut self, btn: usize, uinput: &mut UInputHandle<F>, active: bool, ipc: &IpcServer)
+    where
+        F: AsRawFd,
+    {
+        let button = &mut self.buttons[btn].1;
+        if button.set_active(uinput, active) {
+            let layer = self.name.to_string();
+            let id = button.id.clone().unwrap_or_else(|| button.get_text());
+            ipc.publish(if active {
+                IpcEvent::ButtonPressed { layer, id }
+            } else {
+                IpcEvent::ButtonReleased { layer, id }
+            });
+        }
+    }
+
+    /// Whether focus mode, when it is on, or the power supply hides the button
+    fn hides(button: &Button, focus: Option<&FocusSettings>) -> bool {
+        button.wrong_supply
+            || focus.is_some_and(|focus| {
+                button.builtin() != Some(BuiltinAction::FocusToggle {})
+                    && !focus.keeps(button.key(), button.id.as_deref())
+            })
+    }
+
+    fn buttons_with_id<'a>(&'a mut self, id: &'a str) -> impl Iterator<Item = &'a mut Button> {
+        self.buttons
+            .iter_mut()
+            .map(|(_, button)| button)
+            .filter(move |button| button.id.as_deref() == Some(id))
+    }
+
+    /// The index of the button of the row at `x`, wherever it is across the bar
+    fn position_at(&self, width: u16, x: f64, row: usize) -> usize {
+        let (inset_left, inset_right) = self.style.insets;
+        let (width, x) = (width as f64 / self.style.scale, x / self.style.scale);
+        let usable_width = width - inset_left - inset_right;
+        let (buttons, origin, count) = self.row(row);
+        let arrow = self.paged() as usize;
+        let virtual_i = ((x - inset_left).max(0.0) / (usable_width / count as f64)) as usize;
+        buttons.start
+            + self.buttons[buttons.clone()]
                 .iter()
-                .position(|(start, _)| *start > virtual_i)
-                .unwrap_or(self.buttons.len())
-                - 1
-        });
-        if i >= self.buttons.len() {
+                .position(|(start, _)| start + arrow - origin > virtual_i)
+                .unwrap_or(buttons.len())
+                .saturating_sub(1)
+    }
+
+    /// Where `x` is across a button, from 0 at its left edge to 1 at its right one
+    fn fraction(&self, width: u16, i: usize, x: f64) -> f64 {
+        let scale = self.style.scale;
+        let (left, right) = self.edges(width as f64 / scale, i);
+        (x / scale / self.row_scale() - left) / (right - left)
+    }
+
+    /// The page an arrow at the touch goes to, if it is on one
+    fn page_arrow(&self, width: u16, height: u16, x: f64, y: f64) -> Option<usize> {
+        if !self.paged() {
             return None;
         }
-
-        let start = self.buttons[i].0;
-        let end = if i + 1 < self.buttons.len() {
-            self.buttons[i + 1].0
+        let scale = self.style.scale;
+        let (band_top, height) = self.style.vertical.band(height as f64 / scale, scale);
+        let (width, x, y) = (width as f64 / scale, x / scale, y / scale - band_top);
+        if y < 0.0 || y > height {
+            return None;
+        }
+        let shown = self.shown();
+        if self.page > 0 && x < self.edges(width, shown.start).0 {
+            Some(self.page - 1)
+        } else if self.page + 1 < self.pages.len() && x > self.edges(width, shown.end - 1).1 {
+            Some(self.page + 1)
         } else {
-            self.virtual_button_count
+            None
+        }
+    }
+
+    /// Moves a button to another place, the ones in between shift over
+    fn move_button(&mut self, from: usize, to: usize) {
+        let ends = self.buttons.iter().skip(1).map(|(start, _)| *start);
+        let stretches: Vec<usize> = self
+            .buttons
+            .iter()
+            .zip(ends.chain([self.virtual_button_count]))
+            .map(|((start, _), end)| end - start)
+            .collect();
+        let mut buttons: Vec<_> = self.buttons.drain(..).map(|(_, b)| b).zip(stretches).collect();
+        let button = buttons.remove(from);
+        buttons.insert(to, button);
+        let index = self.order.remove(from);
+        self.order.insert(to, index);
+        let mut start = 0;
+        for (mut button, stretch) in buttons {
+            button.changed = true;
+            self.buttons.push((start, button));
+            start += stretch;
+        }
+    }
+
+    /// The order of the buttons as indices into the layer's keys in the config
+    fn config_order(&self) -> Vec<usize> {
+        self.order[self.fixed..].iter().map(|i| i - self.fixed).collect()
+    }
+
+    fn hit(
+        &self,
+        width: u16,
+        height: u16,
+        x: f64,
+        y: f64,
+        i: Option<usize>,
+        focus: Option<&FocusSettings>,
+    ) -> Option<usize> {
+        let scale = self.style.scale;
+        let (band_top, height) = self.style.vertical.band(height as f64 / scale, scale);
+        let k = self.row_scale();
+        // the second row is the lower half of the band
+        let row = (self.second_row.is_some() && y / scale - band_top >= height * k) as usize;
+        let nearest = self.position_at(width, x, row);
+        let (width, x, y) = (width as f64 / scale, x / scale, y / scale - band_top);
+        // how far outside of a button the touch is, if it is taken as on it,
+        // in the units of the button's row
+        let distance = |i: usize| {
+            let button = &self.buttons[i].1;
+            if Self::hides(button, focus) {
+                return None;
+            }
+            let (row, ..) = self.span(i);
+            let (x, y) = (x / k, (y - row as f64 * height * k) / k);
+            let (left_edge, right_edge) = self.edges(width, i);
+            let dx = (left_edge - x).max(x - right_edge).max(0.0);
+            let dy = (0.1 * height - y).max(y - 0.9 * height).max(0.0);
+            (dx <= button.touch_padding && dy <= button.touch_padding).then_some(dx.max(dy))
         };
+        if let Some(i) = i {
+            // on another page, it is as good as gone
+            return self.shown().contains(&i).then(|| distance(i)).flatten().map(|_| i);
+        }
+        // padding reaches over the spacing, so the neighbours are tried too
+        let (buttons, ..) = self.row(row);
+        (nearest.saturating_sub(1)..=nearest + 1)
+            .filter(|i| buttons.contains(i))
+            .filter_map(|i| Some((distance(i)?, i)))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, i)| i)
+    }
+
+    /// Where the button starts and ends, in the units of its row
+    fn edges(&self, width: f64, i: usize) -> (f64, f64) {
+        let (_, start, end, count) = self.span(i);
+        self.unit_edges(width, start, end, count)
+    }
 
-        let left_edge = (start as f64 * (virtual_button_width + BUTTON_SPACING_PX as f64)).floor();
+    /// Where virtual buttons `start` to `end` of a row `count` of them wide
+    /// start and end, in the units of the row
+    fn unit_edges(&self, width: f64, start: usize, end: usize, count: usize) -> (f64, f64) {
+        let k = self.row_scale();
+        let (inset_left, inset_right) = (self.style.insets.0 / k, self.style.insets.1 / k);
+        let virtual_button_width = (width / k
+            - inset_left
+            - inset_right
+            - (BUTTON_SPACING_PX * (count - 1) as i32) as f64)
+            / count as f64;
+
+        let left_edge =
+            (start as f64 * (virtual_button_width + BUTTON_SPACING_PX as f64)).floor() + inset_left;
 
         let button_width = virtual_button_width
             + ((end - start - 1) as f64 * (virtual_button_width + BUTTON_SPACING_PX as f64))
                 .floor();
 
-        if x < left_edge
-            || x > (left_edge + button_width)
-            || y < 0.1 * height as f64
-            || y > 0.9 * height as f64
-        {
-            return None;
-        }
+        (left_edge, left_edge + button_width)
+    }
+}
+
+/// Asks whether to go ahead with the action of a button with `Confirm`
+/// set, shown over the layer until answered or timed out
+struct Confirm {
+    layer: FunctionLayer,
+    /// The layer and index of the button waiting for an answer
+    target: (usize, usize),
+    until: Instant,
+}
 
-        Some(i)
+impl Confirm {
+    const YES: usize = 1;
+    const NO: usize = 2;
+
+    fn new(config: &Config, target: (usize, usize)) -> Confirm {
+        let texts = [(0, "Really?"), (2, "✓"), (3, "✗")];
+        let layer = FunctionLayer {
+            name: "confirm",
+            buttons: texts
+                .into_iter()
+                .map(|(start, text)| (start, Button::new_text(i18n::tr(text), None)))
+                .collect(),
+            virtual_button_count: 4,
+            style: LayerStyle {
+                font_face: config.font_face.clone(),
+                font: config.font.clone(),
+                show_button_outlines: true,
+                insets: config.safe_area_insets,
+                scale: config.scale,
+                alpha: 1.0,
+                vertical: config.vertical,
+            },
+            fixed: 0,
+            order: (0..texts.len()).collect(),
+            second_row: None,
+            pages: Vec::new(),
+            page: 0,
+        };
+        Confirm {
+            layer,
+            target,
+            until: Instant::now() + CONFIRM_TIMEOUT,
+        }
     }
 }
 
@@ -791,6 +1728,80 @@ where
         .unwrap();
 }
 
+/// A layer shown instead of the default one for a while
+#[derive(Clone, Copy, PartialEq)]
+enum StandIn {
+    LowBattery,
+    Fullscreen,
+    ScreenShare,
+    OnBattery,
+    Jack,
+    DisplayHotplug,
+    PowerMenu,
+}
+
+impl StandIn {
+    fn get(self, cfg: &mut Config) -> Option<&mut FunctionLayer> {
+        match self {
+            StandIn::LowBattery => cfg.low_battery_layer.as_mut(),
+            StandIn::Fullscreen => cfg.fullscreen_layer.as_mut(),
+            StandIn::ScreenShare => cfg.screen_share_layer.as_mut(),
+            StandIn::OnBattery => cfg.on_battery_layer.as_mut(),
+            StandIn::Jack => cfg.jack_layer.as_mut(),
+            StandIn::DisplayHotplug => cfg.display_hotplug_layer.as_mut(),
+            StandIn::PowerMenu => cfg.power_menu_layer.as_mut(),
+        }
+    }
+}
+
+/// The regular layers and those that may stand in for them
+fn all_layers<'a>(layers: &'a mut [FunctionLayer], cfg: &'a mut Config) -> impl Iterator<Item = &'a mut FunctionLayer> {
+    let stand_ins = [
+        &mut cfg.low_battery_layer,
+        &mut cfg.fullscreen_layer,
+        &mut cfg.screen_share_layer,
+        &mut cfg.on_battery_layer,
+        &mut cfg.jack_layer,
+        &mut cfg.display_hotplug_layer,
+        &mut cfg.power_menu_layer,
+    ];
+    layers.iter_mut().chain(stand_ins.into_iter().flatten())
+}
+
+/// Every key the buttons in the config send, and touches while the bar is off
+fn config_actions<'a>(layers: &'a [FunctionLayer], cfg: &'a Config) -> impl Iterator<Item = Key> + 'a {
+    layers
+        .iter()
+        .chain(&cfg.low_battery_layer)
+        .chain(&cfg.fullscreen_layer)
+        .chain(&cfg.screen_share_layer)
+        .chain(&cfg.on_battery_layer)
+        .chain(&cfg.jack_layer)
+        .chain(&cfg.display_hotplug_layer)
+        .chain(&cfg.power_menu_layer)
+        .flat_map(|layer| &layer.buttons)
+        .flat_map(|(_, button)| button.keys())
+        .chain(cfg.bar_off.passthrough_keys())
+}
+
+/// Lets go of any of the keys that are down, e.g. from buttons a reload took
+/// away while they were touched. The kernel drops releases of keys that are
+/// not down.
+fn release_keys<F>(uinput: &mut UInputHandle<F>, keys: &HashSet<Key>)
+where
+    F: AsRawFd,
+{
+    for key in keys {
+        emit(uinput, EventKind::Key, *key as u16, 0);
+    }
+    emit(
+        uinput,
+        EventKind::Synchronize,
+        SynchronizeKind::Report as u16,
+        0,
+    );
+}
+
 fn toggle_key<F>(uinput: &mut UInputHandle<F>, code: Key, value: i32)
 where
     F: AsRawFd,
@@ -804,10 +1815,221 @@ where
     );
 }
 
+/// Updates the buttons with `id` on every layer, including stand-ins that are
+/// not shown and the regular layer one is showing in place of
+fn update_buttons(
+    layers: &mut [FunctionLayer],
+    cfg: &mut Config,
+    id: &str,
+    mut update: impl FnMut(&mut Button) -> Result<(), String>,
+) -> Reply {
+    let mut found = false;
+    for layer in all_layers(layers, cfg) {
+        for button in layer.buttons_with_id(id) {
+            found = true;
+            update(button)?;
+        }
+    }
+    if !found {
+        return Err(format!("no button with id {id}"));
+    }
+    Ok(Value::Null)
+}
+
+/// Parses a color given over IPC, either a tint name or #rrggbb
+fn parse_color(color: &str) -> Result<[f64; 3], String> {
+    match color.to_lowercase().as_str() {
+        "green" => return Ok(GREEN),
+        "yellow" => return Ok(YELLOW),
+        "red" => return Ok(RED),
+        _ => {}
+    }
+    let invalid = || format!("invalid color {color}, expected green, yellow, red or #rrggbb");
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6).ok_or_else(invalid)?;
+    let channel = |i: usize| {
+        u8::from_str_radix(hex.get(i..i + 2).ok_or_else(invalid)?, 16)
+            .map(|c| c as f64 / 255.0)
+            .map_err(|_| invalid())
+    };
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Paints the given text or icon in the middle of the bar, over nothing
+fn paint_message(
+    surface: &ImageSurface,
+    cfg: &Config,
+    (height, width): (u16, u16),
+    text: Option<&str>,
+    icon: Option<&str>,
+) {
+    let mut button = Button::new_text(text.unwrap_or_default().to_string(), None);
+    if let Some(icon) = icon {
+        let image = if icon.starts_with('/') {
+            try_load_path(Path::new(icon))
+        } else {
+            try_load_image(icon, None::<&str>)
+        };
+        match image {
+            Ok(image) => {
+                button.image = image;
+                button.label = text.map(str::to_string);
+            }
+            Err(e) => eprintln!("Failed to load icon {icon}: {e:#}"),
+        }
+    }
+    let c = Context::new(surface).unwrap();
+    c.set_source_rgb(0.0, 0.0, 0.0);
+    c.paint().unwrap();
+    c.translate(height as f64, 0.0);
+    c.rotate((90.0f64).to_radians());
+    c.scale(cfg.scale, cfg.scale);
+    c.set_font_face(&cfg.font_face);
+    c.set_font_options(&cfg.font_options);
+    c.set_font_size(32.0);
+    let (height, width) = (height as f64 / cfg.scale, width as f64 / cfg.scale);
+    let (band_top, height) = cfg.vertical.band(height, cfg.scale);
+    c.translate(0.0, band_top);
+    button.render(&c, height as i32, 0.0, width as u64, 0.0, cfg, &cfg.font);
+}
+
+/// Shows nothing but the given text or icon in the middle of the bar,
+/// while starting up and after being asked to stop.
+fn show_splash(
+    drm: &mut DrmBackend,
+    surface: &mut ImageSurface,
+    cfg: &Config,
+    text: Option<&str>,
+    icon: Option<&str>,
+) {
+    let (height, width) = drm.mode().size();
+    paint_message(surface, cfg, (height, width), text, icon);
+    let post = PostProcess {
+        rotate_180: cfg.rotate_180,
+        ..Default::default()
+    };
+    let stride = surface.stride() as usize;
+    let data = surface.data().unwrap();
+    post.apply(&data, drm.map().unwrap().as_mut(), stride);
+    drm.dirty(&[ClipRect::new(0, 0, height, width)]).unwrap();
+}
+
+/// Paints a png over the whole bar, scaled to fit, and keeps it up until
+/// stopped. Framebuffers go away with the process that created them, so
+/// this has to keep running, but it does nothing else.
+fn show_static_image(drm: &mut DrmBackend, path: &str, sigset: &SigSet) -> Result<()> {
+    let (height, width) = drm.mode().size();
+    let (db_width, db_height) = drm.fb_info()?.size();
+    let image = ImageSurface::create_from_png(&mut File::open(path)?)?;
+    let mut surface = ImageSurface::create(Format::ARgb32, db_width as i32, db_height as i32)?;
+    {
+        let c = Context::new(&surface)?;
+        c.set_source_rgb(0.0, 0.0, 0.0);
+        c.paint()?;
+        c.translate(height as f64, 0.0);
+        c.rotate((90.0f64).to_radians());
+        let scale = f64::min(
+            width as f64 / image.width() as f64,
+            height as f64 / image.height() as f64,
+        );
+        c.translate(
+            ((width as f64 - image.width() as f64 * scale) / 2.0).round(),
+            ((height as f64 - image.height() as f64 * scale) / 2.0).round(),
+        );
+        c.scale(scale, scale);
+        c.set_source_surface(&image, 0.0, 0.0)?;
+        c.paint()?;
+    }
+    let data = surface.data()?;
+    drm.map()?.as_mut()[..data.len()].copy_from_slice(&data);
+    drm.dirty(&[ClipRect::new(0, 0, height, width)])?;
+    BacklightManager::new().set_brightness(STATIC_IMAGE_BRIGHTNESS);
+    sigset.wait()?;
+    Ok(())
+}
+
+const USAGE: &str = "Usage:
+    tiny-dfr [--config <path>] [--system-config <path>]
+    tiny-dfr [--config <path>] [--system-config <path>] --dump-config [profile]
+    tiny-dfr --set-static-image <path to png>";
+
+enum Mode {
+    Daemon,
+    DumpConfig(Option<String>),
+    StaticImage(String),
+}
+
+struct Args {
+    mode: Mode,
+    /// Replace /etc/tiny-dfr/config.toml and /usr/share/tiny-dfr/config.toml
+    config: Option<PathBuf>,
+    system_config: Option<PathBuf>,
+}
+
+fn parse_args() -> Option<Args> {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut parsed = Args {
+        mode: Mode::Daemon,
+        config: None,
+        system_config: None,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => parsed.config = Some(args.next()?.into()),
+            "--system-config" => parsed.system_config = Some(args.next()?.into()),
+            "--dump-config" => {
+                parsed.mode = Mode::DumpConfig(args.next_if(|arg| !arg.starts_with("--")))
+            }
+            "--set-static-image" => parsed.mode = Mode::StaticImage(args.next()?),
+            _ => return None,
+        }
+    }
+    Some(parsed)
+}
+
 fn main() {
+    let Some(args) = parse_args() else {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    };
+    let static_image = match &args.mode {
+        Mode::Daemon => None,
+        Mode::DumpConfig(profile) => {
+            let mut cfg_mgr = ConfigManager::new(args.config.clone(), args.system_config.clone());
+            if let Err(e) = cfg_mgr.set_profile(profile.clone()) {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+            match cfg_mgr.dump_config() {
+                Ok(dump) => print!("{dump}"),
+                Err(e) => {
+                    eprintln!("Failed to dump the config: {e:#}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Mode::StaticImage(path) => Some(path.clone()),
+    };
     let mut drm = DrmBackend::open_card().unwrap();
     let (height, width) = drm.mode().size();
-    let _ = panic::catch_unwind(AssertUnwindSafe(|| real_main(&mut drm)));
+    // handled by the main loop, or waited for below after crashing;
+    // blocked before any threads are started so that they inherit it
+    let mut sigset = SigSet::empty();
+    sigset.add(Signal::SIGTERM);
+    sigset.add(Signal::SIGINT);
+    sigset.add(Signal::SIGUSR1);
+    sigset.add(Signal::SIGUSR2);
+    sigset.thread_block().unwrap();
+    if let Some(path) = static_image {
+        if let Err(e) = show_static_image(&mut drm, &path, &sigset) {
+            eprintln!("Failed to show {path}: {e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if panic::catch_unwind(AssertUnwindSafe(|| real_main(&mut drm, &args))).is_ok() {
+        return;
+    }
     let crash_bitmap = include_bytes!("crash_bitmap.raw");
     let mut map = drm.map().unwrap();
     let data = map.as_mut();
@@ -825,23 +2047,60 @@ fn main() {
     }
     drop(map);
     drm.dirty(&[ClipRect::new(0, 0, height, width)]).unwrap();
-    let mut sigset = SigSet::empty();
-    sigset.add(Signal::SIGTERM);
     sigset.wait().unwrap();
 }
 
-fn real_main(drm: &mut DrmBackend) {
+fn real_main(drm: &mut DrmBackend, args: &Args) {
     let (height, width) = drm.mode().size();
     let (db_width, db_height) = drm.fb_info().unwrap().size();
     let mut uinput = UInputHandle::new(OpenOptions::new().write(true).open("/dev/uinput").unwrap());
     let mut backlight = BacklightManager::new();
-    let mut last_redraw_minute = Local::now().minute();
-    let mut cfg_mgr = ConfigManager::new();
+    let mut cfg_mgr = ConfigManager::new(args.config.clone(), args.system_config.clone());
+    // forks, so it goes first
+    if let Err(e) = cfg_mgr.start_writer() {
+        eprintln!("Changes made on the bar cannot be saved: {e:#}");
+    }
     let (mut cfg, mut layers) = cfg_mgr.load_config(width);
+    let mut surface =
+        ImageSurface::create(Format::ARgb32, db_width as i32, db_height as i32).unwrap();
+    let splash_shown = Instant::now();
+    show_splash(
+        drm,
+        &mut surface,
+        &cfg,
+        cfg.splash.text.as_deref(),
+        cfg.splash.icon.as_deref(),
+    );
     let mut pixel_shift = PixelShiftManager::new();
+    let mut power = PowerPolicy::new();
+    let mut stand_in: Option<StandIn> = None;
+    let mut power_supply_changed = false;
+    let (ipc, ipc_client) = IpcServer::new();
+    if let Err(e) = dbus::start(ipc_client.clone(), &cfg.ipc) {
+        eprintln!("D-Bus control interface is not available: {e}");
+    }
+    if let Err(e) = socket::start(ipc_client, &cfg.ipc) {
+        eprintln!("Control socket {} is not available: {e}", paths::SOCKET_PATH);
+    }
+
+    chargelimit::open();
+    fnrow::start(&cfg.fn_row);
 
     // drop privileges to input and video group
-    let groups = ["input", "video"];
+    let mut groups = vec!["input".to_string(), "video".to_string()];
+    // and whatever group gives access to the Docker socket, if KeepSocketGroup asks for it
+    for socket in &cfg.docker_sockets {
+        let group = fs::metadata(socket)
+            .ok()
+            .and_then(|meta| Group::from_gid(Gid::from_raw(meta.gid())).ok().flatten());
+        match group {
+            Some(group) if group.gid.as_raw() != 0 => {
+                eprintln!("Keeping group {} for {socket}, which gives control over the host", group.name);
+                groups.push(group.name);
+            }
+            _ => eprintln!("Not giving access to {socket}, it is not owned by a group other than root"),
+        }
+    }
 
     PrivDrop::default()
         .user("nobody")
@@ -849,10 +2108,9 @@ fn real_main(drm: &mut DrmBackend) {
         .apply()
         .unwrap_or_else(|e| panic!("Failed to drop privileges: {}", e));
 
-    let mut surface =
-        ImageSurface::create(Format::ARgb32, db_width as i32, db_height as i32).unwrap();
     let mut active_layer = 0;
     let mut needs_complete_redraw = true;
+    let mut post = PostProcess::default();
 
     let mut input_tb = Libinput::new_with_udev(Interface);
     let mut input_main = Libinput::new_with_udev(Interface);
@@ -862,6 +2120,10 @@ fn real_main(drm: &mut DrmBackend) {
         .unwrap()
         .match_subsystem("power_supply")
         .unwrap()
+        .match_subsystem("drm")
+        .unwrap()
+        .match_subsystem_devtype("usb", "usb_device")
+        .unwrap()
         .listen()
         .unwrap();
     let epoll = Epoll::new(EpollCreateFlags::empty()).unwrap();
@@ -877,42 +2139,221 @@ fn real_main(drm: &mut DrmBackend) {
     epoll
         .add(&udev_monitor, EpollEvent::new(EpollFlags::EPOLLIN, 3))
         .unwrap();
-    uinput.set_evbit(EventKind::Key).unwrap();
-    for layer in &layers {
-        for button in &layer.buttons {
-            uinput.set_keybit(button.1.action).unwrap();
-        }
-    }
-    let mut dev_name_c = [0 as c_char; 80];
-    let dev_name = "Dynamic Function Row Virtual Input Device".as_bytes();
-    for i in 0..dev_name.len() {
-        dev_name_c[i] = dev_name[i] as c_char;
-    }
-    uinput
-        .dev_setup(&uinput_setup {
-            id: input_id {
-                bustype: 0x19,
-                vendor: 0x1209,
-                product: 0x316E,
-                version: 1,
-            },
-            ff_effects_max: 0,
-            name: dev_name_c,
-        })
+    epoll
+        .add(ipc.fd(), EpollEvent::new(EpollFlags::EPOLLIN, 4))
+        .unwrap();
+    let mut sigset = SigSet::empty();
+    sigset.add(Signal::SIGTERM);
+    sigset.add(Signal::SIGINT);
+    sigset.add(Signal::SIGUSR1);
+    sigset.add(Signal::SIGUSR2);
+    let signals = SignalFd::with_flags(&sigset, SfdFlags::SFD_NONBLOCK).unwrap();
+    epoll
+        .add(&signals, EpollEvent::new(EpollFlags::EPOLLIN, 5))
         .unwrap();
-    uinput.dev_create().unwrap();
+    // sysfs attributes are always readable, changes are signalled as priority data
+    epoll
+        .add(backlight.display_fd(), EpollEvent::new(EpollFlags::EPOLLPRI, 6))
+        .unwrap();
+    let mut enabled_keys: HashSet<Key> = config_actions(&layers, &cfg).collect();
+    keycodes::create_device(&uinput, &enabled_keys).unwrap();
 
     let mut digitizer: Option<InputDevice> = None;
-    let mut touches = HashMap::new();
+    let mut touches: HashMap<u32, (usize, usize)> = HashMap::new();
+    let mut published_layer = layers[active_layer].name;
+    let waker = ipc.waker();
+    let mut fullscreen = Fullscreen::start(&cfg.fullscreen, &waker);
+    let mut screen_share = ScreenShare::start(&cfg.screen_share, &waker);
+    let mut on_battery = OnBattery::start(cfg.follows_power_supply, &waker);
+    let mut jack = Jack::start(&cfg.jack, &waker);
+    let mut display_hotplug = DisplayHotplug::new(&cfg.display_hotplug);
+    let mut displays_changed = false;
+    let mut power_menu = PowerMenu::new(&cfg.power_menu);
+    // what the buttons with ShowOn were last sorted out for
+    let mut sorted_for_battery = None;
+    let mut privacy = Privacy::start(&cfg.privacy, &waker);
+    let mut redacted = false;
+    let min_splash = Duration::from_millis(cfg.splash.min_duration_ms.unwrap_or(0));
+    std::thread::sleep(min_splash.saturating_sub(splash_shown.elapsed()));
+    let mut reload_requested = false;
+    let mut focus = FocusMode::default();
+    let mut focus_shown = false;
+    let mut toasts = ToastQueue::default();
+    let mut confirm: Option<Confirm> = None;
+    let mut edit: Option<EditMode> = None;
+    // a finger held next to the buttons, which enters edit mode after a while
+    let mut long_press: Option<(u32, Instant)> = None;
+    let mut held_keys = HeldKeys::default();
+    // kept from switching with Fn by a shortcut
+    let mut layer_locked = false;
+    // the layer LayerTimeoutSec is counting for, and since when it is untouched
+    let mut timed_layer = 0;
+    let mut layer_touched = Instant::now();
+    let mut bar_gesture = Gesture::default();
+    let mut passthrough = Passthrough::default();
+    let mut bar_was_off = false;
     loop {
-        if cfg_mgr.update_config(&mut cfg, &mut layers, width) {
+        let mut reloaded = cfg_mgr.update_config(&mut cfg, &mut layers, width);
+        if reload_requested {
+            cfg_mgr.reload(&mut cfg, &mut layers, width);
+            reload_requested = false;
+            reloaded = true;
+        }
+        if reloaded {
+            // the buttons being touched may be gone, with their keys still down
+            touches.clear();
+            release_keys(&mut uinput, &enabled_keys);
+            confirm = None;
+            // unsaved, the layers it was editing are gone
+            edit = None;
+            long_press = None;
             active_layer = 0;
             needs_complete_redraw = true;
+            stand_in = None;
+            fullscreen = Fullscreen::start(&cfg.fullscreen, &waker);
+            screen_share = ScreenShare::start(&cfg.screen_share, &waker);
+            on_battery = OnBattery::start(cfg.follows_power_supply, &waker);
+            jack = Jack::start(&cfg.jack, &waker);
+            display_hotplug = DisplayHotplug::new(&cfg.display_hotplug);
+            power_menu = PowerMenu::new(&cfg.power_menu);
+            sorted_for_battery = None;
+            privacy = privacy.restart(&cfg.privacy, &waker);
+            redacted = false;
+            power.recheck();
+            keycodes::add_keys(&uinput, &mut enabled_keys, config_actions(&layers, &cfg));
+            ipc.publish(IpcEvent::ConfigReloaded);
+        }
+        let fullscreen_on = fullscreen.update();
+        power.pause_animations(fullscreen_on && cfg.fullscreen.pause_animations != Some(false));
+        power.update(&cfg);
+        let sharing = screen_share.update();
+        let unplugged = on_battery.update();
+        let (selecting_output, selector_ms_left) = jack.update();
+        let (arranging_displays, arrange_ms_left) = display_hotplug.update(displays_changed);
+        displays_changed = false;
+        let (in_power_menu, power_menu_ms_left) = power_menu.update();
+        let (toggle_bar, gesture_ms_left) = bar_gesture.check(&cfg.bar_off);
+        if toggle_bar {
+            backlight.toggle_off();
+        }
+        if backlight.is_off() != bar_was_off {
+            bar_was_off = backlight.is_off();
+            // nothing stays down across turning the bar off or on
+            for (_, (layer, btn)) in touches.drain() {
+                layers[layer].set_active(btn, &mut uinput, false, &ipc);
+            }
+            for key in passthrough.release_all() {
+                toggle_key(&mut uinput, key, 0);
+            }
+            confirm = None;
+            long_press = None;
+            needs_complete_redraw = true;
+        }
+        let wanted = [
+            (in_power_menu, StandIn::PowerMenu),
+            (sharing, StandIn::ScreenShare),
+            (arranging_displays, StandIn::DisplayHotplug),
+            (selecting_output, StandIn::Jack),
+            (fullscreen_on, StandIn::Fullscreen),
+            (power.low_battery(), StandIn::LowBattery),
+            (unplugged, StandIn::OnBattery),
+        ]
+        .into_iter()
+        .find(|(on, layer)| *on && layer.get(&mut cfg).is_some())
+        .map(|(_, layer)| layer);
+        if wanted != stand_in {
+            if let Some(e) = edit.take() {
+                let layer = e.layer;
+                if let Err(e) = e.finish(&mut layers[layer], &mut cfg_mgr) {
+                    eprintln!("Failed to save the new order: {e:#}");
+                }
+            }
+            // the fingers on the layer going away let go of its buttons, as
+            // their indices mean nothing on the other one
+            for (_, (layer, btn)) in touches.drain() {
+                layers[layer].set_active(btn, &mut uinput, false, &ipc);
+            }
+            long_press = None;
+            // swapping again puts the regular layer back
+            for layer in [stand_in, wanted].into_iter().flatten() {
+                std::mem::swap(&mut layers[0], layer.get(&mut cfg).unwrap());
+            }
+            stand_in = wanted;
+            confirm = None;
+            needs_complete_redraw = true;
+        }
+        if sorted_for_battery != Some(unplugged) {
+            sorted_for_battery = Some(unplugged);
+            for layer in all_layers(&mut layers, &mut cfg) {
+                for (_, button) in &mut layer.buttons {
+                    button.wrong_supply = button.show_on.is_some_and(|on| !on.matches(unplugged));
+                }
+            }
+            needs_complete_redraw = true;
+        }
+        let shared = sharing && cfg.screen_share.blank != Some(false);
+        let private = privacy.update(shared);
+        if private != redacted {
+            redacted = private;
+            for layer in all_layers(&mut layers, &mut cfg) {
+                for (_, button) in &mut layer.buttons {
+                    button.redacted = private && button.sensitive;
+                }
+            }
+            needs_complete_redraw = true;
+        }
+        if timed_layer != active_layer {
+            timed_layer = active_layer;
+            layer_touched = Instant::now();
+        }
+        let mut layer_ms_left = i32::MAX;
+        // not while a finger is still on it, letting go counts as a touch
+        if let (true, false, true, Some(timeout)) = (
+            active_layer != 0,
+            layer_locked,
+            touches.is_empty(),
+            cfg.layer_timeouts[active_layer],
+        ) {
+            let untouched = layer_touched.elapsed();
+            if untouched >= timeout {
+                active_layer = 0;
+                timed_layer = 0;
+                needs_complete_redraw = true;
+            } else {
+                layer_ms_left = (timeout - untouched).as_millis() as i32 + 1;
+            }
+        }
+        if layers[active_layer].name != published_layer {
+            published_layer = layers[active_layer].name;
+            ipc.publish(IpcEvent::LayerChanged {
+                layer: published_layer.to_string(),
+            });
         }
 
         let now = Local::now();
+        // the schedule is checked at least once a minute, as the time is redrawn
+        focus.update(&cfg.focus_mode, now.time());
+        if focus.is_on() != focus_shown {
+            focus_shown = focus.is_on();
+            for layer in layers.iter_mut() {
+                for (_, button) in &mut layer.buttons {
+                    if button.builtin() == Some(BuiltinAction::FocusToggle {}) {
+                        button.highlighted = focus_shown;
+                    }
+                }
+            }
+            needs_complete_redraw = true;
+        }
+        // the static keys never go away
+        let focus_filter = Some(&cfg.focus_mode).filter(|_| focus_shown && !cfg.static_fn_keys.is_on());
         let ms_left = ((60 - now.second()) * 1000) as i32;
         let mut next_timeout_ms = min(ms_left, TIMEOUT_MS);
+        // the selector, display layer and power menu go away by themselves
+        next_timeout_ms = min(next_timeout_ms, selector_ms_left);
+        next_timeout_ms = min(next_timeout_ms, arrange_ms_left);
+        next_timeout_ms = min(next_timeout_ms, power_menu_ms_left);
+        next_timeout_ms = min(next_timeout_ms, layer_ms_left);
+        next_timeout_ms = min(next_timeout_ms, gesture_ms_left);
 
         if cfg.enable_pixel_shift {
             let (pixel_shift_needs_redraw, pixel_shift_next_timeout_ms) = pixel_shift.update();
@@ -922,36 +2363,93 @@ fn real_main(drm: &mut DrmBackend) {
             next_timeout_ms = min(next_timeout_ms, pixel_shift_next_timeout_ms);
         }
 
-        let current_minute = now.minute();
-        if layers[active_layer].displays_time && (current_minute != last_redraw_minute) {
-            needs_complete_redraw = true;
-            last_redraw_minute = current_minute;
+        for layer in layers.iter_mut() {
+            for (_, button) in &mut layer.buttons {
+                next_timeout_ms = min(next_timeout_ms, button.poll_source(&waker));
+                next_timeout_ms = min(next_timeout_ms, button.animate());
+            }
         }
-        if layers[active_layer].displays_battery {
-            for button in &mut layers[active_layer].buttons {
-                if let ButtonImage::Battery(_, _, _) = button.1.image {
-                    button.1.changed = true;
+        next_timeout_ms = min(next_timeout_ms, backlight.ms_until_step());
+
+        for (_, button) in &mut layers[active_layer].buttons {
+            next_timeout_ms = min(next_timeout_ms, button.update_widget(power_supply_changed));
+            next_timeout_ms = min(next_timeout_ms, button.check_hold(&mut uinput));
+        }
+        power_supply_changed = false;
+
+        if let Some((_, since)) = long_press {
+            if since.elapsed() >= edit::LONG_PRESS {
+                long_press = None;
+                // a layer standing in for another one, or the static keys, are not saved
+                // nor are two rows or pages, whose buttons cannot move between them
+                if edit.is_none()
+                    && stand_in.is_none()
+                    && !cfg.static_fn_keys.is_on()
+                    && layers[active_layer].editable()
+                {
+                    edit = Some(EditMode::start(&mut layers[active_layer], active_layer));
+                    needs_complete_redraw = true;
                 }
+            } else {
+                let ms_left = (since + edit::LONG_PRESS - Instant::now()).as_millis() as i32 + 1;
+                next_timeout_ms = min(next_timeout_ms, ms_left);
             }
         }
-
-        if needs_complete_redraw || layers[active_layer].buttons.iter().any(|b| b.1.changed) {
+        if edit.as_ref().is_some_and(|e| e.layer != active_layer) {
+            let e = edit.take().unwrap();
+            let layer = e.layer;
+            if let Err(e) = e.finish(&mut layers[layer], &mut cfg_mgr) {
+                eprintln!("Failed to save the new order: {e:#}");
+            }
+        }
+        if confirm.as_ref().is_some_and(|c| Instant::now() >= c.until) {
+            confirm = None;
+            needs_complete_redraw = true;
+        }
+        if let Some(c) = &confirm {
+            next_timeout_ms = min(next_timeout_ms, (c.until - Instant::now()).as_millis() as i32 + 1);
+        }
+        // the question and buttons being moved are never hidden by focus mode
+        let (shown, shown_filter) = match &mut confirm {
+            Some(c) => (&mut c.layer, None),
+            None if edit.is_some() => (&mut layers[active_layer], None),
+            None => (&mut layers[active_layer], focus_filter),
+        };
+        let (toast_changed, toast_ms_left) = toasts.update();
+        next_timeout_ms = min(next_timeout_ms, toast_ms_left);
+        // the layer is redrawn completely once the last toast is gone
+        needs_complete_redraw |= toast_changed;
+        if let Some(toast) = toasts.current() {
+            if needs_complete_redraw {
+                let icon = toast.icon.as_deref();
+                paint_message(&surface, &cfg, (height, width), Some(&toast.text), icon);
+                let stride = surface.stride() as usize;
+                let data = surface.data().unwrap();
+                post.apply(&data, drm.map().unwrap().as_mut(), stride);
+                drm.dirty(&[ClipRect::new(0, 0, height, width)]).unwrap();
+                needs_complete_redraw = false;
+            }
+        } else if needs_complete_redraw
+            || shown.buttons.iter().any(|b| b.1.changed)
+        {
             let shift = if cfg.enable_pixel_shift {
                 pixel_shift.get()
             } else {
                 (0.0, 0.0)
             };
-            let clips = layers[active_layer].draw(
+            let clips = shown.draw(
                 &cfg,
                 width as i32,
                 height as i32,
                 &surface,
                 shift,
                 needs_complete_redraw,
+                shown_filter,
             );
+            let stride = surface.stride() as usize;
             let data = surface.data().unwrap();
-            drm.map().unwrap().as_mut()[..data.len()].copy_from_slice(&data);
-            drm.dirty(&clips).unwrap();
+            post.apply(&data, drm.map().unwrap().as_mut(), stride);
+            drm.dirty(&post.clips(clips, (height, width))).unwrap();
             needs_complete_redraw = false;
         }
 
@@ -963,7 +2461,40 @@ fn real_main(drm: &mut DrmBackend) {
             e => e.unwrap(),
         };
 
-        _ = udev_monitor.iter().last();
+        let signal = signals
+            .read_signal()
+            .ok()
+            .flatten()
+            .and_then(|info| Signal::try_from(info.ssi_signo as i32).ok());
+        // from signals and keyboard shortcuts
+        let mut actions: Vec<SignalAction> = match signal {
+            Some(Signal::SIGUSR1) => cfg.signals.usr1,
+            Some(Signal::SIGUSR2) => cfg.signals.usr2,
+            _ => None,
+        }
+        .into_iter()
+        .collect();
+        if matches!(signal, Some(Signal::SIGTERM | Signal::SIGINT)) {
+            let goodbye = cfg.splash.goodbye_text.as_deref();
+            show_splash(drm, &mut surface, &cfg, goodbye, None);
+            if goodbye.is_some() {
+                // leave it up for a moment before the display is closed
+                std::thread::sleep(min_splash);
+            }
+            return;
+        }
+        for event in udev_monitor.iter() {
+            match event.subsystem().and_then(|s| s.to_str()) {
+                Some("drm") => displays_changed = true,
+                Some("usb") => {
+                    if let Some(toast) = usbtoast::toast_for(&cfg.usb_toast, &event) {
+                        // only fails with a long queue, this one can go
+                        _ = toasts.push(toast);
+                    }
+                }
+                _ => power_supply_changed = true,
+            }
+        }
 
         input_tb.dispatch().unwrap();
         input_main.dispatch().unwrap();
@@ -977,30 +2508,129 @@ fn real_main(drm: &mut DrmBackend) {
                     }
                 }
                 Event::Keyboard(KeyboardEvent::Key(key)) => {
-                    if key.key() == Key::Fn as u32 {
-                        let new_layer = match key.key_state() {
-                            KeyState::Pressed => 1,
-                            KeyState::Released => 0,
-                        };
-                        if active_layer != new_layer {
-                            active_layer = new_layer;
-                            needs_complete_redraw = true;
-                        }
+                    let pressed = key.key_state() == KeyState::Pressed;
+                    actions.extend(held_keys.update(key.key(), pressed, &cfg.shortcuts));
+                    if key.key() != Key::Fn as u32 || layer_locked {
+                        continue;
+                    }
+                    let new_layer = pressed as usize;
+                    if active_layer != new_layer {
+                        active_layer = new_layer;
+                        needs_complete_redraw = true;
                     }
                 }
                 Event::Touch(te) => {
-                    if Some(te.device()) != digitizer || backlight.current_bl() == 0 {
+                    // a touch waking up the bar is not taken as pressing a button,
+                    // fingers already down are still let go of
+                    let waking = backlight.current_bl() == 0
+                        || (cfg.wake_consumes_touch
+                            && backlight.is_idle()
+                            && matches!(te, TouchEvent::Down(_)));
+                    if Some(te.device()) != digitizer {
                         continue;
                     }
+                    match &te {
+                        TouchEvent::Down(dn) => bar_gesture.touch(&cfg.bar_off, dn.seat_slot(), true),
+                        TouchEvent::Up(up) => bar_gesture.touch(&cfg.bar_off, up.seat_slot(), false),
+                        _ => {}
+                    }
+                    // turned off, touches only press the keys at fixed places, if any
+                    if backlight.is_off() {
+                        match &te {
+                            TouchEvent::Down(dn) => {
+                                let x = post.touch(width, dn.x_transformed(width as u32)) / width as f64;
+                                let keys = cfg.bar_off.passthrough_keys();
+                                if let Some(key) = passthrough.press(dn.seat_slot(), x, &keys) {
+                                    toggle_key(&mut uinput, key, 1);
+                                }
+                            }
+                            TouchEvent::Up(up) => {
+                                if let Some(key) = passthrough.release(up.seat_slot()) {
+                                    toggle_key(&mut uinput, key, 0);
+                                }
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+                    if waking {
+                        continue;
+                    }
+                    layer_touched = Instant::now();
                     match te {
+                        // tapping a toast only takes it down
+                        TouchEvent::Down(_) if toasts.current().is_some() => toasts.dismiss(),
+                        // while asking, nothing but the answer is taken
+                        TouchEvent::Down(dn) if confirm.is_some() => {
+                            let x = post.touch(width, dn.x_transformed(width as u32));
+                            let y = post.touch(height, dn.y_transformed(height as u32));
+                            let c = confirm.as_ref().unwrap();
+                            let answer = c.layer.hit(width, height, x, y, None, None);
+                            if answer != Some(Confirm::YES) && answer != Some(Confirm::NO) {
+                                continue;
+                            }
+                            let (layer, btn) = c.target;
+                            confirm = None;
+                            needs_complete_redraw = true;
+                            if answer == Some(Confirm::YES) {
+                                // a whole tap, the finger is already gone from the button
+                                layers[layer].set_active(btn, &mut uinput, true, &ipc);
+                                layers[layer].set_active(btn, &mut uinput, false, &ipc);
+                                if stand_in == Some(StandIn::PowerMenu) {
+                                    power_menu.dismiss();
+                                }
+                            }
+                        }
+                        TouchEvent::Down(dn) if edit.is_some() => {
+                            let x = post.touch(width, dn.x_transformed(width as u32));
+                            let y = post.touch(height, dn.y_transformed(height as u32));
+                            let layer = &mut layers[active_layer];
+                            match layer.hit(width, height, x, y, None, None) {
+                                Some(btn) => edit.as_mut().unwrap().touch_down(layer, dn.seat_slot(), btn),
+                                None => {
+                                    let res = edit.take().unwrap().finish(layer, &mut cfg_mgr);
+                                    if let Err(e) = res {
+                                        eprintln!("Failed to save the new order: {e:#}");
+                                    }
+                                }
+                            }
+                            needs_complete_redraw = true;
+                        }
+                        TouchEvent::Motion(mtn) if edit.is_some() => {
+                            let x = post.touch(width, mtn.x_transformed(width as u32));
+                            let layer = &mut layers[active_layer];
+                            needs_complete_redraw |= edit.as_mut().unwrap().touch_motion(layer, mtn.seat_slot(), width, x);
+                        }
+                        TouchEvent::Up(up) if edit.is_some() => {
+                            edit.as_mut().unwrap().touch_up(&mut layers[active_layer], up.seat_slot());
+                        }
                         TouchEvent::Down(dn) => {
-                            let x = dn.x_transformed(width as u32);
-                            let y = dn.y_transformed(height as u32);
-                            if let Some(btn) = layers[active_layer].hit(width, height, x, y, None) {
+                            let x = post.touch(width, dn.x_transformed(width as u32));
+                            let y = post.touch(height, dn.y_transformed(height as u32));
+                            if let Some(page) = layers[active_layer].page_arrow(width, height, x, y) {
+                                layers[active_layer].turn_page(page);
+                                needs_complete_redraw = true;
+                                continue;
+                            }
+                            let hit = layers[active_layer].hit(width, height, x, y, None, focus_filter);
+                            if hit.is_none() {
+                                long_press = Some((dn.seat_slot(), Instant::now()));
+                            }
+                            if let Some(btn) = hit {
+                                let button = &layers[active_layer].buttons[btn].1;
+                                if button.confirm && !button.disabled {
+                                    // the menu stays up for as long as it asks
+                                    if stand_in == Some(StandIn::PowerMenu) {
+                                        power_menu.open();
+                                    }
+                                    confirm = Some(Confirm::new(&cfg, (active_layer, btn)));
+                                    needs_complete_redraw = true;
+                                    continue;
+                                }
                                 touches.insert(dn.seat_slot(), (active_layer, btn));
-                                layers[active_layer].buttons[btn]
-                                    .1
-                                    .set_active(&mut uinput, true);
+                                layers[active_layer].set_active(btn, &mut uinput, true, &ipc);
+                                let x = layers[active_layer].fraction(width, btn, x);
+                                layers[active_layer].buttons[btn].1.scrub(&mut uinput, Scrub::Down(x));
                             }
                         }
                         TouchEvent::Motion(mtn) => {
@@ -1008,20 +2638,60 @@ fn real_main(drm: &mut DrmBackend) {
                                 continue;
                             }
 
-                            let x = mtn.x_transformed(width as u32);
-                            let y = mtn.y_transformed(height as u32);
+                            let x = post.touch(width, mtn.x_transformed(width as u32));
+                            let y = post.touch(height, mtn.y_transformed(height as u32));
                             let (layer, btn) = *touches.get(&mtn.seat_slot()).unwrap();
-                            let hit = layers[active_layer]
-                                .hit(width, height, x, y, Some(btn))
+                            if layers[layer].buttons[btn].1.hold {
+                                continue;
+                            }
+                            // held down wherever it goes
+                            let fraction = layers[layer].fraction(width, btn, x);
+                            if layers[layer].buttons[btn].1.scrub(&mut uinput, Scrub::Move(fraction)) {
+                                continue;
+                            }
+                            let hit = layers[layer]
+                                .hit(width, height, x, y, Some(btn), focus_filter)
                                 .is_some();
-                            layers[layer].buttons[btn].1.set_active(&mut uinput, hit);
+                            layers[layer].set_active(btn, &mut uinput, hit, &ipc);
                         }
                         TouchEvent::Up(up) => {
+                            if long_press.is_some_and(|(slot, _)| slot == up.seat_slot()) {
+                                long_press = None;
+                            }
                             if !touches.contains_key(&up.seat_slot()) {
                                 continue;
                             }
                             let (layer, btn) = *touches.get(&up.seat_slot()).unwrap();
-                            layers[layer].buttons[btn].1.set_active(&mut uinput, false);
+                            let button = &layers[layer].buttons[btn].1;
+                            // only when released on the button, like a key
+                            if let (true, Some(profiles)) = (button.active, &button.profiles) {
+                                let next = profiles
+                                    .iter()
+                                    .position(|p| Some(p.as_str()) == cfg_mgr.profile())
+                                    .map_or(0, |i| (i + 1) % profiles.len());
+                                if let Some(profile) = profiles.get(next) {
+                                    match cfg_mgr.switch_profile(Some(profile.clone())) {
+                                        Ok(()) => reload_requested = true,
+                                        Err(e) => eprintln!("Cannot switch profile: {e}"),
+                                    }
+                                }
+                            }
+                            if button.active && button.builtin() == Some(BuiltinAction::FocusToggle {}) {
+                                focus.toggle();
+                            }
+                            // a choice was made, the displays are arranged
+                            if button.active && stand_in == Some(StandIn::DisplayHotplug) {
+                                display_hotplug.dismiss();
+                            }
+                            // going back, or away with esc
+                            if button.active && stand_in == Some(StandIn::PowerMenu) {
+                                power_menu.dismiss();
+                            }
+                            if button.active && button.builtin() == Some(BuiltinAction::PowerMenu {}) {
+                                power_menu.open();
+                            }
+                            layers[layer].buttons[btn].1.scrub(&mut uinput, Scrub::Up);
+                            layers[layer].set_active(btn, &mut uinput, false, &ipc);
                         }
                         _ => {}
                     }
@@ -1029,6 +2699,155 @@ fn real_main(drm: &mut DrmBackend) {
                 _ => {}
             }
         }
-        backlight.update_backlight(&cfg);
+        for action in actions {
+            match action {
+                SignalAction::ToggleLayer => {
+                    active_layer = (active_layer + 1) % layers.len();
+                    needs_complete_redraw = true;
+                }
+                SignalAction::ReloadConfig => reload_requested = true,
+                SignalAction::ToggleBar => backlight.toggle_off(),
+                SignalAction::ToggleFocus => focus.toggle(),
+                SignalAction::LockLayer => layer_locked = !layer_locked,
+                SignalAction::TogglePrivacy => privacy.toggle(shared),
+            }
+        }
+        ipc.dispatch(|request| match request {
+            Request::SetLayer { layer } => {
+                let Some(idx) = layers.iter().position(|l| l.name == layer) else {
+                    return Err(format!("unknown layer {layer}, expected primary or media"));
+                };
+                if active_layer != idx {
+                    active_layer = idx;
+                    needs_complete_redraw = true;
+                }
+                Ok(Value::Null)
+            }
+            Request::SetBrightness { brightness } => {
+                backlight.set_brightness_override(brightness);
+                Ok(Value::Null)
+            }
+            Request::SwitchProfile { name } => {
+                cfg_mgr.set_profile(name.clone())?;
+                reload_requested = true;
+                cfg_mgr
+                    .save_profile(name)
+                    .map_err(|e| format!("switched, but cannot save the profile: {e:#}"))?;
+                Ok(Value::Null)
+            }
+            Request::SetSetting { key, value } => {
+                cfg_mgr
+                    .set_setting(&key, &value)
+                    .map_err(|e| format!("cannot set {key}: {e:#}"))?;
+                reload_requested = true;
+                Ok(Value::Null)
+            }
+            Request::SetEditMode { enabled: true } => {
+                if stand_in.is_some() {
+                    return Err("the layer standing in for the default one cannot be edited".to_string());
+                }
+                if cfg.static_fn_keys.is_on() {
+                    return Err("the static keys cannot be edited".to_string());
+                }
+                if !layers[active_layer].editable() {
+                    return Err("a layer with two rows or pages cannot be edited".to_string());
+                }
+                if edit.is_none() {
+                    edit = Some(EditMode::start(&mut layers[active_layer], active_layer));
+                    needs_complete_redraw = true;
+                }
+                Ok(Value::Null)
+            }
+            Request::SetEditMode { enabled: false } => {
+                let Some(e) = edit.take() else {
+                    return Ok(Value::Null);
+                };
+                needs_complete_redraw = true;
+                let layer = e.layer;
+                e.finish(&mut layers[layer], &mut cfg_mgr)
+                    .map_err(|e| format!("failed to save the new order: {e:#}"))?;
+                Ok(Value::Null)
+            }
+            Request::SetFocusMode { enabled } => {
+                focus.set(enabled);
+                Ok(Value::Null)
+            }
+            Request::SetPrivacyMode { enabled } => {
+                privacy.set(enabled);
+                Ok(Value::Null)
+            }
+            Request::SetBarEnabled { enabled } => {
+                backlight.set_off(!enabled);
+                Ok(Value::Null)
+            }
+            Request::SetChargeLimit { limit } => {
+                chargelimit::set(limit).map_err(|e| format!("cannot set the charge limit: {e}"))?;
+                Ok(Value::Null)
+            }
+            Request::GetLayer => Ok(layers[active_layer].name.into()),
+            Request::GetBrightness => Ok(backlight.current_bl().into()),
+            Request::GetAllSettings => cfg_mgr
+                .settings()
+                .map_err(|e| format!("cannot read the settings: {e:#}")),
+            Request::GetState => Ok(Value::Object(vec![
+                ("layer".to_string(), layers[active_layer].name.into()),
+                ("brightness".to_string(), backlight.current_bl().into()),
+                (
+                    "profile".to_string(),
+                    cfg_mgr.profile().map_or(Value::Null, Value::from),
+                ),
+                ("focus".to_string(), focus.is_on().into()),
+                ("privacy".to_string(), redacted.into()),
+                ("enabled".to_string(), (!backlight.is_off()).into()),
+                (
+                    "layers".to_string(),
+                    Value::Array(layers.iter().map(FunctionLayer::state).collect()),
+                ),
+            ])),
+            Request::SetButtonText { id, text } => {
+                update_buttons(&mut layers, &mut cfg, &id, |button| button.set_text(text.clone()))
+            }
+            Request::FlashButton { id, color, duration_ms } => {
+                let color = parse_color(&color)?;
+                let now = Instant::now();
+                update_buttons(&mut layers, &mut cfg, &id, |button| {
+                    button.attention = Some((color, now, now + Duration::from_millis(duration_ms)));
+                    button.changed = true;
+                    Ok(())
+                })
+            }
+            Request::ShowToast { text, icon, timeout_ms } => {
+                toasts.push(Toast::new(text, icon, timeout_ms))?;
+                Ok(Value::Null)
+            }
+            Request::SetButtonEnabled { id, enabled } => update_buttons(&mut layers, &mut cfg, &id, |button| {
+                button.set_enabled(enabled);
+                Ok(())
+            }),
+            Request::SetProgress { id, fraction } => update_buttons(&mut layers, &mut cfg, &id, |button| {
+                button.set_progress(fraction);
+                Ok(())
+            }),
+            Request::SetButtonIcon { id, path } => update_buttons(&mut layers, &mut cfg, &id, |button| {
+                let image = try_load_path(Path::new(&path)).map_err(|e| format!("{e:#}"))?;
+                button.set_icon(image)
+            }),
+        });
+        let previous_bl = backlight.current_bl();
+        backlight.update_backlight(&cfg, power.max_brightness(&cfg));
+        if backlight.current_bl() != previous_bl {
+            ipc.publish(IpcEvent::BrightnessChanged {
+                brightness: backlight.current_bl(),
+            });
+        }
+        let new_post = PostProcess {
+            dimming: backlight.content_dimming(),
+            mode: cfg.render_mode,
+            rotate_180: cfg.rotate_180,
+        };
+        if new_post != post {
+            post = new_post;
+            needs_complete_redraw = true;
+        }
     }
 }
@@ -0,0 +1,155 @@
+use std::path::Path;
+use toml::{Table, Value};
+
+// Configs carry a ConfigVersion so that settings can change shape between
+// releases without breaking existing files. Older configs are upgraded here,
+// before they are deserialized, with a warning for every change so that
+// users can update their files at their own pace.
+
+pub const CONFIG_VERSION: i64 = 2;
+
+/// Settings that moved from buttons into the table of the widget they are for
+struct MovedIntoWidget {
+    widget: &'static str,
+    /// The field a string value of the widget is taken as
+    short: Option<&'static str>,
+    /// The old and new names of each setting
+    moved: &'static [(&'static str, &'static str)],
+}
+
+const MOVED_INTO_WIDGETS: &[MovedIntoWidget] = &[
+    MovedIntoWidget {
+        widget: "Time",
+        short: Some("Format"),
+        moved: &[("Locale", "Locale"), ("Calendar", "Calendar")],
+    },
+    MovedIntoWidget {
+        widget: "Battery",
+        short: Some("Mode"),
+        moved: &[("Locale", "Locale"), ("BatteryReadouts", "Readouts"), ("BatterySource", "Source")],
+    },
+    MovedIntoWidget {
+        widget: "Plugin",
+        short: Some("Name"),
+        moved: &[("PluginConfig", "Config")],
+    },
+    MovedIntoWidget { widget: "Disk", short: None, moved: &[("Locale", "Locale")] },
+    MovedIntoWidget { widget: "Stocks", short: None, moved: &[("Locale", "Locale")] },
+];
+
+/// The buttons of the regular layers, their second rows, and the layers
+/// of sections like `[LowBattery]`
+fn layer_keys(table: &mut Table) -> Vec<&mut Table> {
+    let mut layers = Vec::new();
+    for (key, value) in table.iter_mut() {
+        match (key.as_str(), value) {
+            (
+                "PrimaryLayerKeys" | "MediaLayerKeys" | "PrimaryLayerSecondRow" | "MediaLayerSecondRow",
+                Value::Array(keys),
+            ) => layers.push(keys),
+            (_, Value::Table(section)) => {
+                if let Some(Value::Array(keys)) = section.get_mut("LayerKeys") {
+                    layers.push(keys);
+                }
+            }
+            _ => {}
+        }
+    }
+    layers
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_table_mut)
+        .collect()
+}
+
+/// Version 1 dropped the `Svg` alias of `Icon`, and looks up
+/// `[Colors.ButtonOverrides]` by button `Id` rather than by label
+fn to_v1(table: &mut Table, path: &Path) -> bool {
+    let mut changed = false;
+    let overrides: Vec<String> = table
+        .get("Colors")
+        .and_then(|colors| colors.get("ButtonOverrides"))
+        .and_then(Value::as_table)
+        .map(|overrides| overrides.keys().cloned().collect())
+        .unwrap_or_default();
+    for button in layer_keys(table) {
+        if let Some(svg) = button.remove("Svg") {
+            eprintln!("{}: Svg is now called Icon", path.display());
+            button.entry("Icon").or_insert(svg);
+            changed = true;
+        }
+        if button.contains_key("Id") {
+            continue;
+        }
+        let label = button.get("Text").and_then(Value::as_str).map(str::to_string);
+        if let Some(label) = label.filter(|label| overrides.contains(label)) {
+            eprintln!(
+                "{}: colors of the {label} button are overridden by its label, giving it Id = \"{label}\"",
+                path.display()
+            );
+            button.insert("Id".to_string(), Value::String(label));
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Version 2 moved the settings of widgets from their buttons into their own
+/// tables, e.g. `{ Time = "12hr", Locale = "de_DE" }` is now
+/// `{ Time = { Format = "12hr", Locale = "de_DE" } }`
+fn to_v2(table: &mut Table, path: &Path) -> bool {
+    let mut changed = false;
+    for button in layer_keys(table) {
+        for MovedIntoWidget { widget: key, short, moved } in MOVED_INTO_WIDGETS {
+            if !moved.iter().any(|(old, _)| button.contains_key(*old)) {
+                continue;
+            }
+            let mut widget = match (button.get(*key), short) {
+                (Some(Value::Table(widget)), _) => widget.clone(),
+                (Some(value @ Value::String(_)), Some(short)) => Table::from_iter([(short.to_string(), value.clone())]),
+                _ => continue,
+            };
+            for (old, new) in *moved {
+                if let Some(value) = button.remove(*old) {
+                    eprintln!("{}: {old} of {key} buttons is now {key} = {{ {new} = ... }}", path.display());
+                    widget.entry(*new).or_insert(value);
+                }
+            }
+            button.insert(key.to_string(), Value::Table(widget));
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Upgrades `table` read from `path` to the current config version
+pub fn migrate(table: &mut Table, path: &Path) {
+    let version = match table.get("ConfigVersion") {
+        None => 0,
+        Some(Value::Integer(version)) => *version,
+        Some(_) => {
+            eprintln!("{}: ConfigVersion must be a number", path.display());
+            return;
+        }
+    };
+    if version > CONFIG_VERSION {
+        eprintln!(
+            "{}: ConfigVersion {version} is newer than this tiny-dfr supports ({CONFIG_VERSION})",
+            path.display()
+        );
+        return;
+    }
+    let mut changed = false;
+    if version < 1 {
+        changed |= to_v1(table, path);
+    }
+    if version < 2 {
+        changed |= to_v2(table, path);
+    }
+    if changed {
+        eprintln!(
+            "{}: upgraded from ConfigVersion {version}, set ConfigVersion = {CONFIG_VERSION} once the file is updated",
+            path.display()
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::source::{self, Segment, Source, Tint, Update};
+use anyhow::Result;
+use input_linux::{EventKind, EvdevHandle, Key};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    sync::mpsc::{channel, Receiver},
+    thread,
+};
+
+// Shows which modifiers are held down, for screencasts or to keep track of
+// sticky keys. Key events are read from every keyboard's evdev node next to
+// libinput; only modifier keys are looked at, everything else is dropped
+// right away.
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Cmd,
+}
+
+const ALL: [Modifier; 4] = [Modifier::Ctrl, Modifier::Alt, Modifier::Shift, Modifier::Cmd];
+
+impl Modifier {
+    pub fn from_key(code: u16) -> Option<Modifier> {
+        Some(match code {
+            c if c == Key::LeftCtrl as u16 || c == Key::RightCtrl as u16 => Modifier::Ctrl,
+            c if c == Key::LeftAlt as u16 || c == Key::RightAlt as u16 => Modifier::Alt,
+            c if c == Key::LeftShift as u16 || c == Key::RightShift as u16 => Modifier::Shift,
+            c if c == Key::LeftMeta as u16 || c == Key::RightMeta as u16 => Modifier::Cmd,
+            _ => return None,
+        })
+    }
+    fn symbol(self) -> &'static str {
+        match self {
+            Modifier::Ctrl => "⌃",
+            Modifier::Alt => "⌥",
+            Modifier::Shift => "⇧",
+            Modifier::Cmd => "⌘",
+        }
+    }
+}
+
+/// The `Modifiers` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ModifiersConfig {
+    pub show: Option<Vec<Modifier>>,
+}
+
+/// Opens every keyboard, as modifiers may be held on an external one
+pub fn open_keyboards() -> Vec<EvdevHandle<File>> {
+    let Ok(entries) = fs::read_dir("/dev/input") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.to_string_lossy().contains("/event"))
+        .filter_map(|path| File::open(path).ok())
+        .map(EvdevHandle::new)
+        .filter(|device| {
+            device
+                .key_bits()
+                .is_ok_and(|keys| keys.get(Key::LeftShift) && keys.get(Key::A))
+        })
+        .collect()
+}
+
+pub struct Modifiers {
+    show: Vec<Modifier>,
+    /// Key codes held down per keyboard, so that left and right, or the same
+    /// key on two keyboards, do not release each other
+    held: HashSet<(usize, u16)>,
+    events: Option<Receiver<(usize, u16, bool)>>,
+}
+
+impl Modifiers {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(Modifiers::new(source::parse(value)?)))
+    }
+    pub fn new(cfg: ModifiersConfig) -> Modifiers {
+        Modifiers {
+            show: cfg.show.filter(|show| !show.is_empty()).unwrap_or(ALL.to_vec()),
+            held: HashSet::new(),
+            events: None,
+        }
+    }
+    fn start(&mut self, waker: &Waker) {
+        let (tx, rx) = channel();
+        let keyboards = open_keyboards();
+        if keyboards.is_empty() {
+            eprintln!("Modifiers: no keyboards found");
+        }
+        for (i, device) in keyboards.into_iter().enumerate() {
+            let tx = tx.clone();
+            let waker = waker.clone();
+            thread::spawn(move || {
+                while let Ok(event) = device.read_input_event() {
+                    if event.kind != EventKind::Key || Modifier::from_key(event.code).is_none() {
+                        continue;
+                    }
+                    // 2 is a repeat, which means it is still held
+                    if tx.send((i, event.code, event.value != 0)).is_err() {
+                        break;
+                    }
+                    waker.wake();
+                }
+            });
+        }
+        self.events = Some(rx);
+    }
+    fn is_held(&self, modifier: Modifier) -> bool {
+        self.held
+            .iter()
+            .any(|(_, code)| Modifier::from_key(*code) == Some(modifier))
+    }
+}
+
+impl Source for Modifiers {
+    fn poll(&mut self, waker: &Waker) -> (Vec<Update>, i32) {
+        let first = self.events.is_none();
+        if first {
+            self.start(waker);
+        }
+        let events: Vec<_> = self.events.as_ref().unwrap().try_iter().collect();
+        if events.is_empty() && !first {
+            return (Vec::new(), i32::MAX);
+        }
+        for (device, code, pressed) in events {
+            if pressed {
+                self.held.insert((device, code));
+            } else {
+                self.held.remove(&(device, code));
+            }
+        }
+        let segments = self
+            .show
+            .iter()
+            .enumerate()
+            .map(|(i, &modifier)| Segment {
+                text: if i > 0 {
+                    format!(" {}", modifier.symbol())
+                } else {
+                    modifier.symbol().to_string()
+                },
+                tint: self.is_held(modifier).then_some(Tint::Green),
+            })
+            .collect();
+        let any_held = self.show.iter().any(|&modifier| self.is_held(modifier));
+        (
+            vec![Update::Segments(segments), Update::Highlight(any_held)],
+            i32::MAX,
+        )
+    }
+    fn touch(&mut self, _pressed: bool) {}
+}
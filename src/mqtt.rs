@@ -0,0 +1,197 @@
+use crate::audit;
+use crate::config::ButtonConfig;
+use crate::ratelimit;
+use crate::runtime;
+use crate::source::{self, Async, AsyncSource, Source, Update};
+use anyhow::Result;
+use gio::{prelude::*, DataInputStream, Subprocess, SubprocessFlags};
+use serde::{Deserialize, Serialize};
+use std::{ffi::OsStr, time::Duration};
+
+// MQTT is spoken through the mosquitto command line clients, which already
+// handle TLS, authentication and reconnecting. mosquitto_sub runs for as long
+// as the button exists and prints one line per message, while mosquitto_pub
+// is run once per tap. Both are waited on by the runtime.
+
+const RESTART_DELAY: Duration = Duration::from_secs(10);
+
+/// The `[Mqtt]` section, shared by all MQTT buttons
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct MqttServer {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Use TLS, verifying the broker against the system certificates
+    /// unless CaFile is set
+    pub tls: Option<bool>,
+    pub ca_file: Option<String>,
+    pub cert_file: Option<String>,
+    pub key_file: Option<String>,
+}
+
+impl MqttServer {
+    /// Fills in the settings not set on a button from the `[Mqtt]` section.
+    pub fn merge(&mut self, defaults: &MqttServer) {
+        self.host = self.host.take().or(defaults.host.clone());
+        self.port = self.port.or(defaults.port);
+        self.username = self.username.take().or(defaults.username.clone());
+        self.password = self.password.take().or(defaults.password.clone());
+        self.tls = self.tls.or(defaults.tls);
+        self.ca_file = self.ca_file.take().or(defaults.ca_file.clone());
+        self.cert_file = self.cert_file.take().or(defaults.cert_file.clone());
+        self.key_file = self.key_file.take().or(defaults.key_file.clone());
+    }
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let options = [
+            ("-h", &self.host),
+            ("-u", &self.username),
+            ("-P", &self.password),
+            ("--cafile", &self.ca_file),
+            ("--cert", &self.cert_file),
+            ("--key", &self.key_file),
+        ];
+        for (flag, value) in options {
+            if let Some(value) = value {
+                args.extend([flag.to_string(), value.clone()]);
+            }
+        }
+        if let Some(port) = self.port {
+            args.extend(["-p".to_string(), port.to_string()]);
+        }
+        if self.tls == Some(true) && self.ca_file.is_none() {
+            args.push("--tls-use-os-certs".to_string());
+        }
+        args
+    }
+}
+
+/// The `Mqtt` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MqttConfig {
+    pub subscribe: Option<String>,
+    pub format: Option<String>,
+    pub publish: Option<String>,
+    pub payload: Option<String>,
+    pub qos: Option<u8>,
+    pub retain: Option<bool>,
+    #[serde(flatten)]
+    pub server: MqttServer,
+}
+
+/// Runs mosquitto_sub on the runtime, reading its messages as they come
+pub struct Subscriber {
+    args: Vec<String>,
+    topic: Option<String>,
+    process: Option<(Subprocess, DataInputStream)>,
+}
+
+impl Subscriber {
+    fn start(&self, topic: &str) -> Result<(Subprocess, DataInputStream), glib::Error> {
+        let mut argv: Vec<&OsStr> = vec![OsStr::new("mosquitto_sub")];
+        argv.extend(self.args.iter().map(OsStr::new));
+        argv.extend([OsStr::new("-t"), OsStr::new(topic)]);
+        let process = Subprocess::newv(&argv, SubprocessFlags::STDOUT_PIPE)?;
+        let lines = DataInputStream::new(&process.stdout_pipe().unwrap());
+        Ok((process, lines))
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        if let Some((process, _)) = &self.process {
+            process.force_exit();
+        }
+    }
+}
+
+pub struct Mqtt {
+    cfg: MqttConfig,
+}
+
+impl Mqtt {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(Async::new(Mqtt::new(source::parse(value)?))))
+    }
+    pub fn new(cfg: MqttConfig) -> Mqtt {
+        Mqtt { cfg }
+    }
+    fn publish(&self, topic: &str) {
+        if !ratelimit::allow_command() {
+            return;
+        }
+        audit::record("Published MQTT message", Some(topic));
+        let mut args = self.cfg.server.args();
+        args.extend(["-q".to_string(), self.cfg.qos.unwrap_or(0).to_string()]);
+        args.extend(["-t".to_string(), topic.to_string()]);
+        args.extend(["-m".to_string(), self.cfg.payload.clone().unwrap_or_default()]);
+        if self.cfg.retain == Some(true) {
+            args.push("-r".to_string());
+        }
+        // reaped on the runtime, so that taps never wait on the broker
+        runtime::spawn_detached(move || async move {
+            let mut argv: Vec<&OsStr> = vec![OsStr::new("mosquitto_pub")];
+            argv.extend(args.iter().map(OsStr::new));
+            match Subprocess::newv(&argv, SubprocessFlags::NONE) {
+                Ok(process) => _ = process.wait_future().await,
+                Err(e) => eprintln!("Failed to run mosquitto_pub: {e}"),
+            }
+        });
+    }
+}
+
+impl AsyncSource for Mqtt {
+    type Data = String;
+    type Poller = Subscriber;
+
+    fn poller(&mut self) -> impl FnOnce() -> Subscriber + Send + 'static {
+        let mut args = self.cfg.server.args();
+        args.extend(["-q".to_string(), self.cfg.qos.unwrap_or(0).to_string()]);
+        let topic = self.cfg.subscribe.clone();
+        move || Subscriber {
+            args,
+            topic,
+            process: None,
+        }
+    }
+    async fn poll_update(subscriber: &mut Subscriber) -> Option<String> {
+        let topic = subscriber.topic.clone()?;
+        loop {
+            if subscriber.process.is_none() {
+                match subscriber.start(&topic) {
+                    Ok(process) => subscriber.process = Some(process),
+                    Err(e) => {
+                        eprintln!("Failed to run mosquitto_sub: {e}");
+                        glib::timeout_future(RESTART_DELAY).await;
+                        continue;
+                    }
+                }
+            }
+            let (process, lines) = subscriber.process.as_ref().unwrap();
+            match lines.read_line_utf8_future(glib::Priority::DEFAULT).await {
+                Ok(Some(line)) => return Some(line.to_string()),
+                Ok(None) | Err(_) => {
+                    process.force_exit();
+                    _ = process.wait_future().await;
+                    eprintln!("mosquitto_sub for {topic} stopped, restarting");
+                    subscriber.process = None;
+                    glib::timeout_future(RESTART_DELAY).await;
+                }
+            }
+        }
+    }
+    fn render_data(&mut self, payload: String) -> Vec<Update> {
+        vec![Update::Text(match &self.cfg.format {
+            Some(format) => format.replace("{}", &payload),
+            None => payload,
+        })]
+    }
+    fn touch(&mut self, pressed: bool) {
+        if let (true, Some(topic)) = (pressed, &self.cfg.publish) {
+            self.publish(topic);
+        }
+    }
+}
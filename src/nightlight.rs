@@ -0,0 +1,142 @@
+use crate::action;
+use crate::audit;
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::ratelimit;
+use crate::runtime;
+use crate::source::{self, Source, Update};
+use anyhow::Result;
+use glib::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+// The color temperature is shared by all night light buttons, so that
+// buttons making it warmer or cooler and ones showing it stay in sync.
+// Gamma is controlled from inside the user's session, by the compositor,
+// gammastep, redshift and the like, so the new temperature is passed on
+// with a D-Bus call on the session bus, reached by its address like the
+// DBus actions of buttons, to whatever applies it there.
+
+const DEFAULT_TEMPERATURE: u32 = 6500;
+const DEFAULT_MIN: u32 = 1000;
+const DEFAULT_MAX: u32 = 10000;
+
+static TEMPERATURE: Mutex<Option<u32>> = Mutex::new(None);
+
+/// The method the temperature is passed to, in Kelvin as its only argument
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct NightLightCall {
+    /// The address of the user's session bus, or "session" or "system"
+    pub d_bus: String,
+    pub destination: String,
+    pub path: String,
+    pub interface: String,
+    pub method: String,
+}
+
+/// The `[NightLight]` section, shared by all night light buttons
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct NightLightSettings {
+    pub call: Option<NightLightCall>,
+    pub default: Option<u32>,
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+}
+
+/// The `NightLight` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct NightLightConfig {
+    pub step: Option<i32>,
+    pub format: Option<String>,
+    #[serde(flatten)]
+    pub settings: NightLightSettings,
+}
+
+pub struct NightLight {
+    cfg: NightLightConfig,
+    shown: Option<u32>,
+}
+
+impl NightLight {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(NightLight::new(source::parse(value)?)))
+    }
+    pub fn new(cfg: NightLightConfig) -> NightLight {
+        NightLight { cfg, shown: None }
+    }
+    fn current(&self) -> u32 {
+        let default = self.cfg.settings.default.unwrap_or(DEFAULT_TEMPERATURE);
+        *TEMPERATURE.lock().unwrap().get_or_insert(default)
+    }
+    fn apply(&self, temperature: u32) {
+        let Some(call) = self.cfg.settings.call.clone() else {
+            return;
+        };
+        if !ratelimit::allow_command() {
+            return;
+        }
+        audit::record(
+            "Called night light D-Bus method",
+            Some(&format!("{}.{}({temperature}) on {}", call.interface, call.method, call.destination)),
+        );
+        runtime::spawn_detached(move || async move {
+            let args = (temperature,).to_variant();
+            let res = action::call_method(
+                &call.d_bus,
+                &call.destination,
+                &call.path,
+                &call.interface,
+                &call.method,
+                &args,
+            )
+            .await;
+            if let Err(e) = res {
+                eprintln!("Failed to pass the night light on to {}.{}: {e}", call.interface, call.method);
+            }
+        });
+    }
+}
+
+impl Source for NightLight {
+    fn label(&self) -> String {
+        match self.cfg.step {
+            Some(step) if step != 0 => format!("{step:+}K"),
+            _ => String::new(),
+        }
+    }
+    fn poll(&mut self, _waker: &Waker) -> (Vec<Update>, i32) {
+        // buttons that change the temperature keep their own label
+        if self.cfg.step.unwrap_or(0) != 0 {
+            return (Vec::new(), i32::MAX);
+        }
+        let temperature = self.current();
+        if self.shown == Some(temperature) {
+            return (Vec::new(), i32::MAX);
+        }
+        self.shown = Some(temperature);
+        let format = self.cfg.format.as_deref().unwrap_or("{}K");
+        (
+            vec![Update::Text(format.replace("{}", &temperature.to_string()))],
+            i32::MAX,
+        )
+    }
+    fn touch(&mut self, pressed: bool) {
+        if !pressed {
+            return;
+        }
+        let settings = &self.cfg.settings;
+        let temperature = match self.cfg.step.unwrap_or(0) {
+            // tapping the readout goes back to the default
+            0 => settings.default.unwrap_or(DEFAULT_TEMPERATURE),
+            step => self.current().saturating_add_signed(step).clamp(
+                settings.min.unwrap_or(DEFAULT_MIN),
+                settings.max.unwrap_or(DEFAULT_MAX),
+            ),
+        };
+        *TEMPERATURE.lock().unwrap() = Some(temperature);
+        self.apply(temperature);
+    }
+}
@@ -0,0 +1,376 @@
+use crate::config::ButtonConfig;
+use crate::json::Value;
+use crate::sha256::{base64, sha256};
+use crate::source::{self, Async, AsyncSource, Source, Update};
+use crate::websocket::{self, Message, WsReader, WsWriter};
+use anyhow::{anyhow, Result};
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures_util::{
+    future::{select, Either},
+    StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+// Talks to OBS Studio through obs-websocket (protocol version 5), see
+// https://github.com/obsproject/obs-websocket/blob/master/docs/generated/protocol.md
+// Every OBS button keeps its own connection on the runtime, which is
+// re-established whenever OBS is restarted.
+
+pub const DEFAULT_URL: &str = "ws://localhost:4455";
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+const OP_HELLO: u64 = 0;
+const OP_IDENTIFY: u64 = 1;
+const OP_IDENTIFIED: u64 = 2;
+const OP_EVENT: u64 = 5;
+const OP_REQUEST: u64 = 6;
+const OP_REQUEST_RESPONSE: u64 = 7;
+
+const EVENT_SUBSCRIPTION_SCENES: u32 = 1 << 2;
+const EVENT_SUBSCRIPTION_OUTPUTS: u32 = 1 << 6;
+
+/// The `[Obs]` section, shared by all OBS buttons
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct ObsServer {
+    pub url: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum ObsOutput {
+    Record,
+    Stream,
+}
+
+/// The `Obs` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ObsConfig {
+    pub scene: Option<String>,
+    pub toggle: Option<ObsOutput>,
+    pub indicator: Option<bool>,
+    #[serde(flatten)]
+    pub server: ObsServer,
+}
+
+impl ObsConfig {
+    fn kind(&self) -> Result<Kind> {
+        Ok(match (&self.scene, self.toggle, self.indicator) {
+            (Some(scene), _, _) => Kind::Scene(scene.clone()),
+            (None, Some(output), _) => Kind::Toggle(output),
+            (None, None, Some(true)) => Kind::Indicator,
+            _ => return Err(anyhow!("Obs needs one of Scene, Toggle or Indicator")),
+        })
+    }
+}
+
+#[derive(Clone)]
+enum Kind {
+    Scene(String),
+    Toggle(ObsOutput),
+    Indicator,
+}
+
+fn object(fields: Vec<(&str, Value)>) -> Value {
+    Value::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn message(op: u64, d: Value) -> String {
+    object(vec![("op", Value::Number(op as f64)), ("d", d)]).to_string()
+}
+
+fn request(request_type: &str, data: Option<Value>) -> String {
+    let mut d = vec![
+        ("requestType", request_type.into()),
+        ("requestId", request_type.into()),
+    ];
+    if let Some(data) = data {
+        d.push(("requestData", data));
+    }
+    message(OP_REQUEST, object(d))
+}
+
+fn identify(hello: &Value, password: Option<&str>) -> Result<String> {
+    let mut d = vec![
+        ("rpcVersion", Value::Number(1.0)),
+        (
+            "eventSubscriptions",
+            (EVENT_SUBSCRIPTION_SCENES | EVENT_SUBSCRIPTION_OUTPUTS).into(),
+        ),
+    ];
+    if let Some(auth) = hello.get("d").and_then(|d| d.get("authentication")) {
+        let password = password.ok_or(anyhow!("OBS requires a password"))?;
+        let challenge = auth.get("challenge").and_then(Value::as_str).unwrap_or("");
+        let salt = auth.get("salt").and_then(Value::as_str).unwrap_or("");
+        let secret = base64(&sha256(format!("{password}{salt}").as_bytes()));
+        let response = base64(&sha256(format!("{secret}{challenge}").as_bytes()));
+        d.push(("authentication", response.into()));
+    }
+    Ok(message(OP_IDENTIFY, object(d)))
+}
+
+fn op(message: &Value) -> Option<u64> {
+    message.get("op").and_then(Value::as_u64)
+}
+
+#[derive(Default)]
+struct ObsState {
+    scene: Option<String>,
+    recording: bool,
+    streaming: bool,
+}
+
+impl ObsState {
+    /// Applies an event or response, returning whether it should be
+    /// flashed as failed.
+    fn apply(&mut self, message: &Value) -> bool {
+        let d = message.get("d");
+        let field = |outer: &str, name: &str| d.and_then(|d| d.get(outer)).and_then(|o| o.get(name));
+        match op(message) {
+            Some(OP_EVENT) => {
+                let event_type = d.and_then(|d| d.get("eventType")).and_then(Value::as_str);
+                match event_type {
+                    Some("CurrentProgramSceneChanged") => {
+                        self.scene = field("eventData", "sceneName")
+                            .and_then(Value::as_str)
+                            .map(str::to_string);
+                    }
+                    Some("RecordStateChanged") => {
+                        self.recording = field("eventData", "outputActive") == Some(&Value::Bool(true));
+                    }
+                    Some("StreamStateChanged") => {
+                        self.streaming = field("eventData", "outputActive") == Some(&Value::Bool(true));
+                    }
+                    _ => {}
+                }
+                false
+            }
+            Some(OP_REQUEST_RESPONSE) => {
+                let request_type = d.and_then(|d| d.get("requestType")).and_then(Value::as_str);
+                let active = field("responseData", "outputActive") == Some(&Value::Bool(true));
+                match request_type {
+                    Some("GetCurrentProgramScene") => {
+                        self.scene = field("responseData", "currentProgramSceneName")
+                            .and_then(Value::as_str)
+                            .map(str::to_string);
+                    }
+                    Some("GetRecordStatus") => self.recording = active,
+                    Some("GetStreamStatus") => self.streaming = active,
+                    _ => {}
+                }
+                field("requestStatus", "result") == Some(&Value::Bool(false))
+            }
+            _ => false,
+        }
+    }
+    fn highlighted(&self, kind: &Kind) -> bool {
+        match kind {
+            Kind::Scene(scene) => self.scene.as_ref() == Some(scene),
+            Kind::Toggle(ObsOutput::Record) => self.recording,
+            Kind::Toggle(ObsOutput::Stream) => self.streaming,
+            Kind::Indicator => self.recording || self.streaming,
+        }
+    }
+}
+
+type Reading = Pin<Box<dyn Future<Output = (WsReader, Result<Message>)>>>;
+
+/// Starts reading the next message. The read is kept across taps rather
+/// than started again, so that a tap never cuts off a message halfway.
+fn read(reader: WsReader) -> Reading {
+    Box::pin(async move {
+        let message = reader.read().await;
+        (reader, message)
+    })
+}
+
+/// Reads the next message during the handshake, answering pings
+async fn read_json(reader: &WsReader, writer: &WsWriter) -> Result<Value> {
+    loop {
+        match reader.read().await? {
+            Message::Text(text) => return Value::parse(&text),
+            Message::Ping(payload) => writer.pong(&payload).await?,
+        }
+    }
+}
+
+async fn send_tap(writer: &WsWriter, kind: &Kind) -> Result<()> {
+    let text = match kind {
+        Kind::Scene(scene) => request(
+            "SetCurrentProgramScene",
+            Some(object(vec![("sceneName", scene.as_str().into())])),
+        ),
+        Kind::Toggle(ObsOutput::Record) => request("ToggleRecord", None),
+        Kind::Toggle(ObsOutput::Stream) => request("ToggleStream", None),
+        Kind::Indicator => return Ok(()),
+    };
+    writer.send_text(&text).await
+}
+
+struct Session {
+    writer: WsWriter,
+    reading: Reading,
+    state: ObsState,
+}
+
+async fn connect(kind: &Kind, server: &ObsServer) -> Result<Session> {
+    let url = server.url.as_deref().unwrap_or(DEFAULT_URL);
+    let (reader, writer) = websocket::connect(url, "obswebsocket.json").await?;
+    let hello = read_json(&reader, &writer).await?;
+    if op(&hello) != Some(OP_HELLO) {
+        return Err(anyhow!("unexpected message from OBS"));
+    }
+    writer
+        .send_text(&identify(&hello, server.password.as_deref())?)
+        .await?;
+    if op(&read_json(&reader, &writer).await?) != Some(OP_IDENTIFIED) {
+        return Err(anyhow!("OBS did not accept the connection"));
+    }
+    let initial: &[&str] = match kind {
+        Kind::Scene(_) => &["GetCurrentProgramScene"],
+        Kind::Toggle(ObsOutput::Record) => &["GetRecordStatus"],
+        Kind::Toggle(ObsOutput::Stream) => &["GetStreamStatus"],
+        Kind::Indicator => &["GetRecordStatus", "GetStreamStatus"],
+    };
+    for request_type in initial {
+        writer.send_text(&request(request_type, None)).await?;
+    }
+    Ok(Session {
+        writer,
+        reading: read(reader),
+        state: ObsState::default(),
+    })
+}
+
+/// Serves taps and reads events until there is something to show, or
+/// until the button went away, which returns `None`.
+async fn serve(
+    session: &mut Session,
+    kind: &Kind,
+    touches: &mut UnboundedReceiver<()>,
+) -> Result<Option<Vec<Update>>> {
+    loop {
+        match select(&mut session.reading, touches.next()).await {
+            Either::Left(((reader, message), _)) => {
+                session.reading = read(reader);
+                match message? {
+                    Message::Ping(payload) => session.writer.pong(&payload).await?,
+                    Message::Text(text) => {
+                        let failed = session.state.apply(&Value::parse(&text)?);
+                        let mut updates = vec![Update::Highlight(session.state.highlighted(kind))];
+                        if failed {
+                            updates.push(Update::Flash(false));
+                        }
+                        return Ok(Some(updates));
+                    }
+                }
+            }
+            Either::Right((Some(()), _)) => send_tap(&session.writer, kind).await?,
+            Either::Right((None, _)) => {
+                session.writer.close().await;
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// The half of an OBS button on the runtime, which keeps its connection
+pub struct Connection {
+    kind: Kind,
+    server: ObsServer,
+    touches: UnboundedReceiver<()>,
+    session: Option<Session>,
+    /// When to connect again after the connection failed
+    retry: Option<Instant>,
+}
+
+pub struct Obs {
+    kind: Kind,
+    server: ObsServer,
+    touches: Option<UnboundedSender<()>>,
+}
+
+impl Obs {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(Async::new(Obs::new(source::parse(value)?)?)))
+    }
+    pub fn new(cfg: ObsConfig) -> Result<Obs> {
+        Ok(Obs {
+            kind: cfg.kind()?,
+            server: cfg.server,
+            touches: None,
+        })
+    }
+}
+
+impl AsyncSource for Obs {
+    type Data = Vec<Update>;
+    type Poller = Connection;
+
+    fn poller(&mut self) -> impl FnOnce() -> Connection + Send + 'static {
+        let (touches_tx, touches) = unbounded();
+        self.touches = Some(touches_tx);
+        let (kind, server) = (self.kind.clone(), self.server.clone());
+        move || Connection {
+            kind,
+            server,
+            touches,
+            session: None,
+            retry: None,
+        }
+    }
+    async fn poll_update(conn: &mut Connection) -> Option<Vec<Update>> {
+        loop {
+            if let Some(retry) = conn.retry {
+                let timeout = retry.saturating_duration_since(Instant::now());
+                match glib::future_with_timeout(timeout, conn.touches.next()).await {
+                    // tapped while OBS is not reachable
+                    Ok(Some(())) => return Some(vec![Update::Flash(false)]),
+                    Ok(None) => return None,
+                    Err(_) => conn.retry = None,
+                }
+            }
+            let result = match &mut conn.session {
+                Some(session) => serve(session, &conn.kind, &mut conn.touches).await,
+                None => match connect(&conn.kind, &conn.server).await {
+                    Ok(session) => {
+                        conn.session = Some(session);
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+            match result {
+                Ok(updates) => return updates,
+                Err(e) => {
+                    eprintln!("OBS connection failed: {e:#}");
+                    conn.session = None;
+                    conn.retry = Some(Instant::now() + RETRY_DELAY);
+                    return Some(vec![Update::Highlight(false)]);
+                }
+            }
+        }
+    }
+    fn render_data(&mut self, updates: Vec<Update>) -> Vec<Update> {
+        updates
+    }
+    fn touch(&mut self, pressed: bool) {
+        if let (true, Some(touches)) = (pressed, &self.touches) {
+            _ = touches.unbounded_send(());
+        }
+    }
+    fn label(&self) -> String {
+        match &self.kind {
+            Kind::Scene(scene) => scene.clone(),
+            Kind::Toggle(ObsOutput::Record) => "REC".to_string(),
+            Kind::Toggle(ObsOutput::Stream) => "LIVE".to_string(),
+            Kind::Indicator => "ON AIR".to_string(),
+        }
+    }
+}
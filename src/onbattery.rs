@@ -0,0 +1,154 @@
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::runtime::{self, Task};
+use gio::{prelude::*, BusType, DBusCallFlags, DBusConnection, DBusSignalFlags, SignalSubscriptionId};
+use glib::{Variant, VariantTy};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    future,
+    sync::mpsc::{channel, Receiver, Sender},
+    time::Duration,
+};
+
+// Follows whether the machine runs on its battery or is plugged in, as
+// UPower tells with its OnBattery property, so that buttons and layers can
+// be swapped for ones that suit it: buttons with ShowOn are only shown on
+// AC or on battery, and the LayerKeys of [OnBattery] stand in for the
+// default layer while unplugged, e.g. without power-hungry widgets.
+//
+// UPower signals every change, so nothing is polled. Without UPower the
+// machine is taken as plugged in.
+
+const UPOWER: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const DBUS_TIMEOUT_MS: i32 = 5000;
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// The `[OnBattery]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct OnBatteryConfig {
+    pub layer_keys: Option<Vec<ButtonConfig>>,
+}
+
+/// The `ShowOn` setting of a button
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum PowerSupply {
+    #[serde(rename = "AC")]
+    Ac,
+    Battery,
+}
+
+impl PowerSupply {
+    pub fn matches(self, on_battery: bool) -> bool {
+        (self == PowerSupply::Battery) == on_battery
+    }
+}
+
+async fn read_on_battery(connection: &DBusConnection) -> Result<bool, glib::Error> {
+    let reply = connection
+        .call_future(
+            Some(UPOWER),
+            UPOWER_PATH,
+            "org.freedesktop.DBus.Properties",
+            "Get",
+            Some(&(UPOWER, "OnBattery").to_variant()),
+            Some(VariantTy::new("(v)").unwrap()),
+            DBusCallFlags::NONE,
+            DBUS_TIMEOUT_MS,
+        )
+        .await?;
+    Ok(reply.child_value(0).as_variant().and_then(|v| v.get::<bool>()).unwrap_or(false))
+}
+
+/// Unsubscribes from the signal when the future holding it is stopped
+struct Subscription(DBusConnection, Option<SignalSubscriptionId>);
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(id) = self.1.take() {
+            self.0.signal_unsubscribe(id);
+        }
+    }
+}
+
+async fn follow(states: Sender<bool>, waker: Waker) {
+    loop {
+        let connection = match gio::bus_get_future(BusType::System).await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("OnBattery: cannot connect to the system bus: {e}");
+                glib::timeout_future(RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let (tx, waker_tx) = (states.clone(), waker.clone());
+        // delivered on the runtime thread, whose context is the default here
+        let id = connection.signal_subscribe(
+            Some(UPOWER),
+            Some("org.freedesktop.DBus.Properties"),
+            Some("PropertiesChanged"),
+            Some(UPOWER_PATH),
+            Some(UPOWER),
+            DBusSignalFlags::NONE,
+            move |_, _, _, _, _, params| {
+                let Some((_, changed, _)) = params.get::<(String, HashMap<String, Variant>, Vec<String>)>() else {
+                    return;
+                };
+                if let Some(on_battery) = changed.get("OnBattery").and_then(Variant::get::<bool>) {
+                    if tx.send(on_battery).is_ok() {
+                        waker_tx.wake();
+                    }
+                }
+            },
+        );
+        let _subscription = Subscription(connection.clone(), Some(id));
+        match read_on_battery(&connection).await {
+            Ok(on_battery) => {
+                if states.send(on_battery).is_err() {
+                    return;
+                }
+                waker.wake();
+                // changes come in through the subscription from here on
+                future::pending::<()>().await;
+            }
+            Err(e) => {
+                eprintln!("OnBattery: cannot ask UPower: {e}");
+                glib::timeout_future(RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Whether the machine runs on battery, as far as UPower knows
+#[derive(Default)]
+pub struct OnBattery {
+    states: Option<Receiver<bool>>,
+    _task: Option<Task>,
+    on: bool,
+}
+
+impl OnBattery {
+    /// Starts following UPower if anything in the config depends on it,
+    /// stopping the last one when it is replaced
+    pub fn start(needed: bool, waker: &Waker) -> OnBattery {
+        if !needed {
+            return OnBattery::default();
+        }
+        let (tx, rx) = channel();
+        let waker = waker.clone();
+        let task = runtime::spawn(move || follow(tx, waker));
+        OnBattery {
+            states: Some(rx),
+            _task: Some(task),
+            on: false,
+        }
+    }
+    pub fn update(&mut self) -> bool {
+        if let Some(on) = self.states.as_ref().and_then(|rx| rx.try_iter().last()) {
+            self.on = on;
+        }
+        self.on
+    }
+}
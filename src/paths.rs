@@ -0,0 +1,5 @@
+// Paths shared by the daemon and tiny-dfrctl, which includes this file
+// with #[path] so that the two cannot disagree.
+
+/// The JSON-RPC control socket, see src/socket.rs
+pub const SOCKET_PATH: &str = "/run/tiny-dfr/control.sock";
@@ -0,0 +1,193 @@
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::power;
+use crate::script::DEFAULT_REFRESH_INTERVAL_MS;
+use crate::source::{self, Source, Update};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+// Plugins are shared libraries exporting a single function,
+// `tiny_dfr_plugin_entry`, which returns a table of callbacks.
+// The layout of that table is defined in include/tiny-dfr-plugin.h and must
+// be kept in sync with `PluginVTable` below. Any incompatible change to it
+// has to bump PLUGIN_ABI_VERSION, so that old plugins are refused
+// instead of crashing the daemon.
+
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+const ENTRY_SYMBOL: &CStr = c"tiny_dfr_plugin_entry";
+const MIN_REFRESH_INTERVAL_MS: u64 = 50;
+const RENDER_BUF_LEN: usize = 256;
+
+#[repr(C)]
+struct RenderData {
+    text: [c_char; RENDER_BUF_LEN],
+    icon: [c_char; RENDER_BUF_LEN],
+}
+
+#[repr(C)]
+struct PluginVTable {
+    abi_version: u32,
+    create: Option<unsafe extern "C" fn(config: *const c_char) -> *mut c_void>,
+    destroy: Option<unsafe extern "C" fn(instance: *mut c_void)>,
+    render_data: Option<unsafe extern "C" fn(instance: *mut c_void, data: *mut RenderData) -> c_int>,
+    touch: Option<unsafe extern "C" fn(instance: *mut c_void, pressed: c_int)>,
+}
+
+type EntryFn = unsafe extern "C" fn() -> *const PluginVTable;
+
+struct Instance {
+    handle: *mut c_void,
+    vtable: &'static PluginVTable,
+    state: *mut c_void,
+}
+
+impl Instance {
+    unsafe fn load(path: &Path, config: &CStr) -> Result<Instance, String> {
+        let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+        let handle = libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            return Err(CStr::from_ptr(libc::dlerror()).to_string_lossy().into_owned());
+        }
+        let entry = libc::dlsym(handle, ENTRY_SYMBOL.as_ptr());
+        if entry.is_null() {
+            libc::dlclose(handle);
+            return Err(format!("missing {} symbol", ENTRY_SYMBOL.to_string_lossy()));
+        }
+        let entry: EntryFn = std::mem::transmute(entry);
+        let Some(vtable) = entry().as_ref() else {
+            libc::dlclose(handle);
+            return Err("plugin entry returned NULL".into());
+        };
+        if vtable.abi_version != PLUGIN_ABI_VERSION {
+            libc::dlclose(handle);
+            return Err(format!(
+                "plugin ABI version {} is not supported, expected {PLUGIN_ABI_VERSION}",
+                vtable.abi_version
+            ));
+        }
+        let state = match vtable.create {
+            Some(create) => create(config.as_ptr()),
+            None => std::ptr::null_mut(),
+        };
+        Ok(Instance {
+            handle,
+            vtable,
+            state,
+        })
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(destroy) = self.vtable.destroy {
+                destroy(self.state);
+            }
+            libc::dlclose(self.handle);
+        }
+    }
+}
+
+fn buf_to_string(buf: &[c_char]) -> Option<String> {
+    let bytes: Vec<u8> = buf.iter().take_while(|c| **c != 0).map(|c| *c as u8).collect();
+    if bytes.is_empty() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The `Plugin` key of a button, or just its name
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PluginConfig {
+    /// Made into the path of the library when the config is read
+    pub name: String,
+    /// Passed to the plugin as is
+    pub config: Option<String>,
+}
+
+pub struct Plugin {
+    path: PathBuf,
+    config: CString,
+    interval: Duration,
+    instance: Option<Instance>,
+    next_update: Instant,
+    failed: bool,
+}
+
+impl Plugin {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let plugin: PluginConfig = source::parse_short(value, "Name")?;
+        let config = plugin.config.unwrap_or_default();
+        Ok(Box::new(Plugin::new(plugin.name, config, cfg.refresh_interval_ms)))
+    }
+    pub fn new(path: impl Into<PathBuf>, config: String, interval_ms: Option<u64>) -> Plugin {
+        let interval_ms = interval_ms
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL_MS)
+            .max(MIN_REFRESH_INTERVAL_MS);
+        Plugin {
+            path: path.into(),
+            config: CString::new(config.replace('\0', "")).unwrap(),
+            interval: Duration::from_millis(interval_ms),
+            instance: None,
+            next_update: Instant::now(),
+            failed: false,
+        }
+    }
+}
+
+impl Source for Plugin {
+    fn touch(&mut self, pressed: bool) {
+        if let Some(instance) = &self.instance {
+            if let Some(touch) = instance.vtable.touch {
+                unsafe { touch(instance.state, pressed as c_int) };
+            }
+            // let the plugin show the result of the touch right away
+            self.next_update = Instant::now();
+        }
+    }
+
+    /// Loads the plugin if needed and asks it for new render data when due.
+    /// Like scripts, plugins are loaded lazily from the main loop so that
+    /// their code only ever runs after privileges have been dropped.
+    fn poll(&mut self, _waker: &Waker) -> (Vec<Update>, i32) {
+        if self.instance.is_none() && !self.failed {
+            match unsafe { Instance::load(&self.path, &self.config) } {
+                Ok(instance) => self.instance = Some(instance),
+                Err(e) => {
+                    eprintln!("Failed to load plugin {}: {e}", self.path.display());
+                    self.failed = true;
+                }
+            }
+        }
+        let Some(instance) = &self.instance else {
+            return (Vec::new(), i32::MAX);
+        };
+        let mut updates = Vec::new();
+        let now = Instant::now();
+        if now >= self.next_update {
+            if let Some(render_data) = instance.vtable.render_data {
+                let mut data = RenderData {
+                    text: [0; RENDER_BUF_LEN],
+                    icon: [0; RENDER_BUF_LEN],
+                };
+                if unsafe { render_data(instance.state, &mut data) } != 0 {
+                    // make sure a plugin that filled the whole buffer is still terminated
+                    data.text[RENDER_BUF_LEN - 1] = 0;
+                    data.icon[RENDER_BUF_LEN - 1] = 0;
+                    updates.extend(buf_to_string(&data.text).map(Update::Text));
+                    updates.extend(buf_to_string(&data.icon).map(Update::Icon));
+                }
+            }
+            self.next_update = source::next_refresh(power::scale(self.interval));
+        }
+        let ms_left = (self.next_update - now).as_millis() as i32;
+        (updates, ms_left)
+    }
+}
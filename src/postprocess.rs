@@ -0,0 +1,127 @@
+use drm::control::ClipRect;
+use serde::{Deserialize, Serialize};
+
+// Steps applied to the rendered bar on its way to the display, leaving the
+// surface itself as drawn so that partial redraws can keep building on it.
+//
+// Dimming the content lets the bar go darker than the lowest backlight
+// levels allow without the visible banding of those: the backlight stays
+// at a level where it is even, and the pixels are scaled down instead. The
+// scaling is done in linear light and dithered, so that dark gradients and
+// antialiased edges do not turn into steps either.
+//
+// The render mode takes the color out of everything, or most of it, for
+// fewer distractions and less wear on the panel.
+//
+// The picture is turned around here too for panels mounted upside down, so
+// that drawing never needs to know about it. Touches are turned around to
+// match with `PostProcess::touch`.
+
+/// An ordered dithering threshold map, in sixteenths
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+/// The gamma pixel values are encoded with
+const GAMMA: f64 = 2.2;
+/// How much of the saturation the reduced mode keeps
+const REDUCED_SATURATION: f64 = 0.3;
+
+/// The `RenderMode` setting
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderMode {
+    #[default]
+    Color,
+    Grayscale,
+    Reduced,
+}
+
+impl RenderMode {
+    fn saturation(self) -> f64 {
+        match self {
+            RenderMode::Color => 1.0,
+            RenderMode::Grayscale => 0.0,
+            RenderMode::Reduced => REDUCED_SATURATION,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct PostProcess {
+    /// How bright the content is shown in linear light, 1.0 for as drawn
+    pub dimming: f64,
+    pub mode: RenderMode,
+    /// Whether the panel is upside down
+    pub rotate_180: bool,
+}
+
+impl Default for PostProcess {
+    fn default() -> PostProcess {
+        PostProcess {
+            dimming: 1.0,
+            mode: RenderMode::Color,
+            rotate_180: false,
+        }
+    }
+}
+
+impl PostProcess {
+    /// Copies the ARGB32 pixels from `src` to `dst`, which have rows of
+    /// `stride` bytes, processing them on the way
+    pub fn apply(&self, src: &[u8], dst: &mut [u8], stride: usize) {
+        let dst = &mut dst[..src.len()];
+        if self.dimming >= 1.0 && self.mode == RenderMode::Color && !self.rotate_180 {
+            dst.copy_from_slice(src);
+            return;
+        }
+        // scaling linear light by the factor is scaling encoded values by
+        // its gamma-encoded counterpart
+        let scale = self.dimming.clamp(0.0, 1.0).powf(1.0 / GAMMA);
+        let saturation = self.mode.saturation();
+        let rows = src.len() / stride;
+        for (y, dst_row) in dst.chunks_mut(stride).enumerate() {
+            let src_y = if self.rotate_180 { rows - 1 - y } else { y };
+            let src_row = &src[src_y * stride..(src_y + 1) * stride];
+            let thresholds = &BAYER[y % 4];
+            let columns = stride / 4;
+            for (x, dst_px) in dst_row.chunks_exact_mut(4).enumerate() {
+                let src_x = if self.rotate_180 { columns - 1 - x } else { x };
+                let src_px = &src_row[src_x * 4..src_x * 4 + 4];
+                let threshold = (thresholds[x % 4] as f64 + 0.5) / 16.0;
+                // blue, green and red, weighted by how bright they look
+                let [b, g, r] = [src_px[0], src_px[1], src_px[2]].map(f64::from);
+                let luma = 0.0722 * b + 0.7152 * g + 0.2126 * r;
+                for (c, value) in [b, g, r].into_iter().enumerate() {
+                    let value = luma + (value - luma) * saturation;
+                    dst_px[c] = (value * scale + threshold).clamp(0.0, 255.0) as u8;
+                }
+                // alpha as is
+                dst_px[3] = src_px[3];
+            }
+        }
+    }
+    /// Where the clip rectangles of the drawn surface, of the given size,
+    /// end up on the display
+    pub fn clips(&self, clips: Vec<ClipRect>, (width, height): (u16, u16)) -> Vec<ClipRect> {
+        if !self.rotate_180 {
+            return clips;
+        }
+        clips
+            .into_iter()
+            .map(|c| {
+                ClipRect::new(
+                    width - c.x2(),
+                    height - c.y2(),
+                    width - c.x1(),
+                    height - c.y1(),
+                )
+            })
+            .collect()
+    }
+    /// Where a touch at `pos` along an axis of `size` is on the bar as drawn
+    pub fn touch(&self, size: u16, pos: f64) -> f64 {
+        if self.rotate_180 {
+            size as f64 - pos
+        } else {
+            pos
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::config::{ButtonConfig, Config};
+use crate::sensors;
+use crate::battery::{find_battery_device, get_battery_state};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+// Decides how much the bar is allowed to cost. While the battery is low
+// the brightness is capped, widgets are refreshed less often and
+// animations stop, until the machine is plugged in again. While the
+// machine is hot, widgets and animations are slowed down until it has
+// cooled down a bit below where that started, so that it doesn't flip
+// back and forth. The main loop updates the policy, widgets running on
+// other threads read it through `slowdown` and `animations_enabled`.
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_BRIGHTNESS: u32 = 32;
+const DEFAULT_REFRESH_MULTIPLIER: u32 = 4;
+const DEFAULT_HOT: f64 = 85.0;
+const DEFAULT_COOL: f64 = 75.0;
+const DEFAULT_THERMAL_REFRESH_MULTIPLIER: u32 = 2;
+
+static SLOWDOWN: AtomicU32 = AtomicU32::new(1);
+static ANIMATIONS: AtomicBool = AtomicBool::new(true);
+
+/// How many times longer widgets should wait between refreshes, and
+/// animations between frames
+pub fn slowdown() -> u32 {
+    SLOWDOWN.load(Ordering::Relaxed)
+}
+
+/// Scales a refresh interval by the current slowdown
+pub fn scale(interval: Duration) -> Duration {
+    interval * slowdown()
+}
+
+/// Whether widgets may animate, e.g. redraw many times a second
+pub fn animations_enabled() -> bool {
+    ANIMATIONS.load(Ordering::Relaxed)
+}
+
+/// Whether the machine has a power supply for mains power that is offline.
+/// Machines without one, like desktops, never run on battery.
+pub fn on_battery() -> bool {
+    let Ok(supplies) = fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    let mut has_mains = false;
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        let kind = fs::read_to_string(path.join("type")).unwrap_or_default();
+        if kind.trim() != "Mains" {
+            continue;
+        }
+        has_mains = true;
+        if fs::read_to_string(path.join("online")).is_ok_and(|online| online.trim() == "1") {
+            return false;
+        }
+    }
+    has_mains
+}
+
+/// The `[LowBattery]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct LowBatteryConfig {
+    pub threshold: Option<u32>,
+    pub brightness: Option<u32>,
+    pub refresh_multiplier: Option<u32>,
+    pub disable_animations: Option<bool>,
+    pub layer_keys: Option<Vec<ButtonConfig>>,
+}
+
+/// The `[Thermal]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct ThermalConfig {
+    pub enable: Option<bool>,
+    pub hot: Option<f64>,
+    pub cool: Option<f64>,
+    pub refresh_multiplier: Option<u32>,
+}
+
+pub struct PowerPolicy {
+    battery: Option<String>,
+    low_battery: bool,
+    hot: bool,
+    /// Whether animations are stopped for a fullscreen window
+    paused: bool,
+    next_check: Instant,
+}
+
+impl PowerPolicy {
+    pub fn new() -> PowerPolicy {
+        PowerPolicy {
+            battery: find_battery_device(),
+            low_battery: false,
+            hot: false,
+            paused: false,
+            next_check: Instant::now(),
+        }
+    }
+    /// Checks the battery now and then
+    pub fn update(&mut self, cfg: &Config) {
+        let now = Instant::now();
+        if now < self.next_check {
+            return;
+        }
+        self.next_check = now + CHECK_INTERVAL;
+        let threshold = cfg.low_battery.threshold.unwrap_or(0);
+        let low_battery = match &self.battery {
+            Some(battery) if threshold > 0 => {
+                get_battery_state(battery).0 < threshold && on_battery()
+            }
+            _ => false,
+        };
+        let thermal = &cfg.thermal;
+        self.hot = match (thermal.enable != Some(false), sensors::hottest()) {
+            (true, Some(temp)) if self.hot => temp > thermal.cool.unwrap_or(DEFAULT_COOL),
+            (true, Some(temp)) => temp >= thermal.hot.unwrap_or(DEFAULT_HOT),
+            _ => false,
+        };
+        let mut slowdown = 1;
+        if low_battery {
+            slowdown = cfg.low_battery.refresh_multiplier.unwrap_or(DEFAULT_REFRESH_MULTIPLIER);
+        }
+        if self.hot {
+            let multiplier = thermal
+                .refresh_multiplier
+                .unwrap_or(DEFAULT_THERMAL_REFRESH_MULTIPLIER);
+            slowdown = slowdown.max(multiplier);
+        }
+        let animations = (!low_battery || cfg.low_battery.disable_animations == Some(false)) && !self.paused;
+        SLOWDOWN.store(slowdown.max(1), Ordering::Relaxed);
+        ANIMATIONS.store(animations, Ordering::Relaxed);
+        self.low_battery = low_battery;
+    }
+    /// Makes the next update check the battery right away, e.g. after
+    /// the config changed
+    pub fn recheck(&mut self) {
+        self.next_check = Instant::now();
+    }
+    /// Stops animations, or lets them go again, right away
+    pub fn pause_animations(&mut self, paused: bool) {
+        if self.paused != paused {
+            self.paused = paused;
+            self.recheck();
+        }
+    }
+    pub fn low_battery(&self) -> bool {
+        self.low_battery
+    }
+    /// The highest brightness the bar may currently use
+    pub fn max_brightness(&self, cfg: &Config) -> Option<u32> {
+        self.low_battery
+            .then(|| cfg.low_battery.brightness.unwrap_or(DEFAULT_BRIGHTNESS))
+    }
+}
@@ -0,0 +1,130 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+// A small power menu, for kiosks and setups without a desktop offering one:
+// tapping a button with Action = { PowerMenu = {} } brings up a layer in
+// place of the default one, with lock, suspend, reboot and power off buttons
+// and one to go back. Each of them asks first, then calls logind, and the
+// layer goes away once one of them is tapped or after TimeoutMs.
+//
+// Locking asks logind to lock every session, which their screen lockers act
+// on. Like with the other calls, polkit decides whether the daemon's user
+// may make them. That is nobody, without a session or anyone to ask for a
+// password, so it takes a rule, see share/polkit-1/rules.d.
+
+const DEFAULT_TIMEOUT_MS: u64 = 15 * 1000;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum PowerChoice {
+    Lock,
+    Suspend,
+    Reboot,
+    PowerOff,
+}
+
+impl PowerChoice {
+    const ALL: [PowerChoice; 4] = [PowerChoice::Lock, PowerChoice::Suspend, PowerChoice::Reboot, PowerChoice::PowerOff];
+
+    fn text(self) -> &'static str {
+        match self {
+            PowerChoice::Lock => "lock",
+            PowerChoice::Suspend => "suspend",
+            PowerChoice::Reboot => "reboot",
+            PowerChoice::PowerOff => "power off",
+        }
+    }
+    /// The method of org.freedesktop.login1.Manager, and whether it takes
+    /// the interactive flag
+    fn method(self) -> (&'static str, bool) {
+        match self {
+            PowerChoice::Lock => ("LockSessions", false),
+            PowerChoice::Suspend => ("Suspend", true),
+            PowerChoice::Reboot => ("Reboot", true),
+            PowerChoice::PowerOff => ("PowerOff", true),
+        }
+    }
+}
+
+/// The `[PowerMenu]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct PowerMenuConfig {
+    /// The buttons shown, in order, by default all of them
+    pub choices: Option<Vec<PowerChoice>>,
+    pub timeout_ms: Option<u64>,
+}
+
+impl PowerMenuConfig {
+    /// The buttons of the menu, and the one going back
+    pub fn layer_keys(&self) -> Vec<ButtonConfig> {
+        let choices = self.choices.clone().unwrap_or(PowerChoice::ALL.to_vec());
+        let mut keys: Vec<ButtonConfig> = choices
+            .into_iter()
+            .filter_map(|choice| {
+                let (method, interactive) = choice.method();
+                let mut action = toml::Table::new();
+                action.insert("DBus".into(), "system".into());
+                action.insert("Destination".into(), "org.freedesktop.login1".into());
+                action.insert("Path".into(), "/org/freedesktop/login1".into());
+                action.insert("Interface".into(), "org.freedesktop.login1.Manager".into());
+                action.insert("Method".into(), method.into());
+                if interactive {
+                    // polkit is not asked interactively, there is nobody to answer
+                    action.insert("Args".into(), vec![toml::Value::Boolean(false)].into());
+                }
+                let mut button = toml::Table::new();
+                button.insert("Text".into(), i18n::tr(choice.text()).into());
+                button.insert("Action".into(), action.into());
+                button.insert("Confirm".into(), true.into());
+                button
+                    .try_into()
+                    .map_err(|e| eprintln!("PowerMenu: cannot make the {} button: {e}", choice.text()))
+                    .ok()
+            })
+            .collect();
+        let mut back = toml::Table::new();
+        back.insert("Text".into(), i18n::tr("back").into());
+        match back.try_into() {
+            Ok(back) => keys.push(back),
+            Err(e) => eprintln!("PowerMenu: cannot make the back button: {e}"),
+        }
+        keys
+    }
+}
+
+/// Whether the menu is shown, after a button opened it
+pub struct PowerMenu {
+    timeout: Duration,
+    /// Until when the menu is shown
+    until: Option<Instant>,
+}
+
+impl PowerMenu {
+    pub fn new(cfg: &PowerMenuConfig) -> PowerMenu {
+        PowerMenu {
+            timeout: Duration::from_millis(cfg.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS)),
+            until: None,
+        }
+    }
+    pub fn open(&mut self) {
+        self.until = Some(Instant::now() + self.timeout);
+    }
+    /// Returns whether the menu is shown, and the number of milliseconds
+    /// until that changes
+    pub fn update(&mut self) -> (bool, i32) {
+        let now = Instant::now();
+        match self.until {
+            Some(until) if until > now => (true, (until - now).as_millis() as i32 + 1),
+            _ => {
+                self.until = None;
+                (false, i32::MAX)
+            }
+        }
+    }
+    /// Takes the menu down, once a choice was made
+    pub fn dismiss(&mut self) {
+        self.until = None;
+    }
+}
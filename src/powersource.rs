@@ -0,0 +1,242 @@
+use crate::battery::{find_battery_device, get_battery_power, get_battery_state, BatteryState};
+use anyhow::{anyhow, Result};
+use gio::{BusType, Cancellable, DBusCallFlags, DBusConnection};
+use glib::{Variant, VariantTy};
+use std::{
+    collections::HashMap,
+    path::Path,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::Duration,
+};
+
+// Where a battery button gets its readings from. That is the machine's own
+// battery in /sys/class/power_supply unless BatterySource names another one:
+//
+// - any other supply there by name, like the battery of a mouse or tablet
+// - "ups" or "phone", for the first UPS or phone UPower knows of, e.g. a UPS
+//   on USB or a phone paired over KDE Connect
+// - "nut:<ups>@<host>", for a UPS behind Network UPS Tools, read with upsc
+//
+// UPower and NUT are asked on a thread of their own, as they may take a
+// while to answer, and the button shows the last reading.
+
+const DBUS_TIMEOUT_MS: i32 = 1000;
+const UPOWER_UPS: u32 = 3;
+const UPOWER_PHONE: u32 = 8;
+
+#[derive(Clone, Copy)]
+struct Reading {
+    capacity: u32,
+    state: BatteryState,
+    watts: Option<f64>,
+    time: Option<u64>,
+}
+
+#[derive(Clone)]
+enum Remote {
+    /// A UPower device of the given type
+    UPower(u32),
+    Nut(String),
+}
+
+/// A battery read on a thread, with the last reading
+pub struct RemoteSource {
+    remote: Remote,
+    interval: Duration,
+    reading: Arc<Mutex<Option<Reading>>>,
+    started: bool,
+}
+
+impl RemoteSource {
+    fn reading(&self) -> Option<Reading> {
+        *self.reading.lock().unwrap()
+    }
+}
+
+pub enum PowerSource {
+    Sysfs(String),
+    Remote(RemoteSource),
+}
+
+fn upower_device(connection: &DBusConnection, kind: u32) -> Result<Option<Reading>> {
+    let call = |path: &str, interface: &str, method: &str, args: Option<Variant>, reply: &str| {
+        connection.call_sync(
+            Some("org.freedesktop.UPower"),
+            path,
+            interface,
+            method,
+            args.as_ref(),
+            Some(VariantTy::new(reply).unwrap()),
+            DBusCallFlags::NONE,
+            DBUS_TIMEOUT_MS,
+            Cancellable::NONE,
+        )
+    };
+    let devices = call(
+        "/org/freedesktop/UPower",
+        "org.freedesktop.UPower",
+        "EnumerateDevices",
+        None,
+        "(ao)",
+    )?;
+    for device in devices.child_value(0).iter() {
+        let Some(path) = device.str() else {
+            continue;
+        };
+        let (props,) = call(
+            path,
+            "org.freedesktop.DBus.Properties",
+            "GetAll",
+            Some(("org.freedesktop.UPower.Device",).into()),
+            "(a{sv})",
+        )?
+        .get::<(HashMap<String, Variant>,)>()
+        .ok_or(anyhow!("unexpected reply from UPower"))?;
+        let prop = |name: &str| props.get(name);
+        let present = prop("IsPresent").and_then(Variant::get::<bool>).unwrap_or(true);
+        if prop("Type").and_then(Variant::get::<u32>) != Some(kind) || !present {
+            continue;
+        }
+        let capacity = prop("Percentage").and_then(Variant::get::<f64>).unwrap_or(0.0).round() as u32;
+        // charging, discharging, empty, fully charged, pending charge and discharge
+        let upower_state = prop("State").and_then(Variant::get::<u32>);
+        let charging = matches!(upower_state, Some(1 | 4));
+        let state = match upower_state {
+            Some(1 | 4) => BatteryState::Charging,
+            Some(2 | 3) if capacity < 10 => BatteryState::Low,
+            _ => BatteryState::NotCharging,
+        };
+        let time = prop(if charging { "TimeToFull" } else { "TimeToEmpty" })
+            .and_then(Variant::get::<i64>)
+            .filter(|secs| *secs > 0)
+            .map(|secs| secs as u64);
+        let watts = prop("EnergyRate").and_then(Variant::get::<f64>).filter(|w| *w > 0.0);
+        return Ok(Some(Reading { capacity, state, watts, time }));
+    }
+    Ok(None)
+}
+
+/// Reads a UPS with upsc, where watts are what the load draws
+fn nut_ups(ups: &str) -> Result<Reading> {
+    let output = Command::new("upsc")
+        .arg(ups)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!("upsc exited with {}", output.status));
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    let vars: HashMap<&str, &str> = output
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .collect();
+    let number = |name: &str| vars.get(name)?.trim().parse::<f64>().ok();
+    let capacity = number("battery.charge").ok_or(anyhow!("{ups} reports no battery.charge"))?;
+    let status: Vec<&str> = vars.get("ups.status").map_or(vec![], |s| s.split(' ').collect());
+    let on_battery = status.contains(&"OB");
+    let state = if status.contains(&"CHRG") || status.contains(&"OL") {
+        BatteryState::Charging
+    } else if on_battery && (status.contains(&"LB") || capacity < 10.0) {
+        BatteryState::Low
+    } else {
+        BatteryState::NotCharging
+    };
+    let watts = number("ups.realpower")
+        .or_else(|| Some(number("ups.realpower.nominal")? * number("ups.load")? / 100.0))
+        .filter(|w| *w > 0.0);
+    let time = number("battery.runtime").filter(|_| on_battery).map(|secs| secs as u64);
+    Ok(Reading {
+        capacity: capacity.round() as u32,
+        state,
+        watts,
+        time,
+    })
+}
+
+/// Reads `remote` every `interval` for as long as the button is around
+fn watch(remote: Remote, interval: Duration, reading: Weak<Mutex<Option<Reading>>>) {
+    let mut connection = None;
+    loop {
+        let res = match &remote {
+            Remote::UPower(kind) => {
+                if connection.is_none() {
+                    connection = gio::bus_get_sync(BusType::System, Cancellable::NONE).ok();
+                }
+                match &connection {
+                    Some(connection) => upower_device(connection, *kind),
+                    None => Err(anyhow!("cannot connect to the system bus")),
+                }
+            }
+            Remote::Nut(ups) => nut_ups(ups).map(Some),
+        };
+        let res = res.unwrap_or_else(|e| {
+            eprintln!("Failed to read the battery: {e:#}");
+            // connect again next time, in case the bus was restarted
+            connection = None;
+            None
+        });
+        let Some(reading) = reading.upgrade() else {
+            return;
+        };
+        *reading.lock().unwrap() = res;
+        drop(reading);
+        thread::sleep(interval);
+    }
+}
+
+impl PowerSource {
+    /// The source named by BatterySource, read every `interval` if it is
+    /// not in sysfs. `None` if there is no such battery.
+    pub fn new(name: Option<&str>, interval: Duration) -> Option<PowerSource> {
+        let remote = |remote| {
+            PowerSource::Remote(RemoteSource {
+                remote,
+                interval,
+                reading: Arc::new(Mutex::new(None)),
+                started: false,
+            })
+        };
+        match name {
+            None | Some("internal") => find_battery_device().map(PowerSource::Sysfs),
+            Some("ups") => Some(remote(Remote::UPower(UPOWER_UPS))),
+            Some("phone") => Some(remote(Remote::UPower(UPOWER_PHONE))),
+            Some(name) => match name.strip_prefix("nut:") {
+                Some(ups) => Some(remote(Remote::Nut(ups.to_string()))),
+                None => Path::new("/sys/class/power_supply")
+                    .join(name)
+                    .exists()
+                    .then(|| PowerSource::Sysfs(name.to_string())),
+            },
+        }
+    }
+    /// Starts reading it on a thread, if it is not in sysfs. Called once
+    /// privileges were dropped.
+    pub fn start(&mut self) {
+        let PowerSource::Remote(remote) = self else {
+            return;
+        };
+        if !remote.started {
+            remote.started = true;
+            let (kind, interval) = (remote.remote.clone(), remote.interval);
+            let reading = Arc::downgrade(&remote.reading);
+            thread::spawn(move || watch(kind, interval, reading));
+        }
+    }
+    /// The charge in percent and the state, `None` while it is not known
+    pub fn state(&self) -> Option<(u32, BatteryState)> {
+        match self {
+            PowerSource::Sysfs(battery) => Some(get_battery_state(battery)),
+            PowerSource::Remote(remote) => remote.reading().map(|r| (r.capacity, r.state)),
+        }
+    }
+    /// The watts and the seconds until full or empty, like `get_battery_power`
+    pub fn power(&self) -> (Option<f64>, Option<u64>) {
+        match self {
+            PowerSource::Sysfs(battery) => get_battery_power(battery),
+            PowerSource::Remote(remote) => remote.reading().map_or((None, None), |r| (r.watts, r.time)),
+        }
+    }
+}
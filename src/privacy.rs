@@ -0,0 +1,116 @@
+use crate::ipc::Waker;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    process::{Command, Stdio},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+// Privacy mode shows the contents of buttons marked Sensitive as dots, and
+// leaves them out of the state given to IPC clients. It follows the screen
+// being shared and the session being locked, either of which turns it on,
+// or is set by hand over IPC, a signal or a shortcut, which holds until it
+// is set back to following them.
+//
+// Whether the session is locked comes from logind, which anyone may ask,
+// so unlike screen sharing this needs nothing from the user's session.
+
+pub const REDACTED: &str = "•••";
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// The `[Privacy]` section
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "PascalCase")]
+pub struct PrivacyConfig {
+    pub on_lock: Option<bool>,
+}
+
+fn loginctl(args: &[&str]) -> Result<String> {
+    let output = Command::new("loginctl")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!("loginctl exited with {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn follow_lock(states: &Sender<bool>, waker: &Waker) -> Result<()> {
+    let mut last = None;
+    loop {
+        // nobody logged in counts as locked
+        let session = loginctl(&["show-seat", "seat0", "--property=ActiveSession", "--value"])?;
+        let locked = session.is_empty()
+            || loginctl(&["show-session", &session, "--property=LockedHint", "--value"])? == "yes";
+        if last != Some(locked) {
+            last = Some(locked);
+            states.send(locked)?;
+            waker.wake();
+        }
+        thread::sleep(LOCK_POLL_INTERVAL);
+    }
+}
+
+#[derive(Default)]
+pub struct Privacy {
+    locks: Option<Receiver<bool>>,
+    locked: bool,
+    /// Set by hand, `None` when following the screen and lock
+    forced: Option<bool>,
+}
+
+impl Privacy {
+    /// Starts following the session lock, if enabled. The thread of the
+    /// last one stops once it notices it is no longer listened to.
+    pub fn start(cfg: &PrivacyConfig, waker: &Waker) -> Privacy {
+        if cfg.on_lock != Some(true) {
+            return Privacy::default();
+        }
+        let (tx, rx) = channel();
+        let waker = waker.clone();
+        thread::spawn(move || loop {
+            let res = follow_lock(&tx, &waker);
+            if tx.send(false).is_err() {
+                break;
+            }
+            waker.wake();
+            if let Err(e) = res {
+                eprintln!("Privacy: cannot tell whether the session is locked: {e:#}");
+            }
+            thread::sleep(RETRY_DELAY);
+        });
+        Privacy {
+            locks: Some(rx),
+            locked: false,
+            forced: None,
+        }
+    }
+    /// Keeps the setting made by hand, e.g. across reloads
+    pub fn restart(self, cfg: &PrivacyConfig, waker: &Waker) -> Privacy {
+        Privacy {
+            forced: self.forced,
+            ..Privacy::start(cfg, waker)
+        }
+    }
+    pub fn set(&mut self, enabled: Option<bool>) {
+        self.forced = enabled;
+    }
+    pub fn toggle(&mut self, sharing: bool) {
+        self.forced = Some(!self.is_on(sharing));
+    }
+    /// Whether privacy mode is on, given whether the screen is shared
+    pub fn is_on(&self, sharing: bool) -> bool {
+        self.forced.unwrap_or(sharing || self.locked)
+    }
+    pub fn update(&mut self, sharing: bool) -> bool {
+        if let Some(locked) = self.locks.as_ref().and_then(|rx| rx.try_iter().last()) {
+            self.locked = locked;
+        }
+        self.is_on(sharing)
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// A safety valve against floods of synthetic key presses and commands,
+// e.g. from a touch that keeps coming and going or a widget gone wrong.
+// Both are let through in bursts, then at the configured rate, with what
+// goes over it dropped. Key releases are never held back, so keys cannot
+// get stuck down.
+
+const DEFAULT_KEYS_PER_SECOND: u32 = 30;
+const DEFAULT_COMMANDS_PER_MINUTE: u32 = 60;
+/// How many of the allowed per second or minute may come at once
+const BURST_FRACTION: f64 = 0.5;
+
+/// The `[RateLimit]` section, 0 turns a limit off
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "PascalCase")]
+pub struct RateLimitConfig {
+    pub keys_per_second: Option<u32>,
+    pub commands_per_minute: Option<u32>,
+}
+
+/// A token bucket
+struct Bucket {
+    per_second: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+    /// Whether dropping was already warned about since the last one let through
+    warned: bool,
+}
+
+impl Bucket {
+    fn new(count: u32, period: Duration) -> Option<Bucket> {
+        if count == 0 {
+            return None;
+        }
+        let burst = (count as f64 * BURST_FRACTION).max(1.0);
+        Some(Bucket {
+            per_second: count as f64 / period.as_secs_f64(),
+            burst,
+            tokens: burst,
+            last: Instant::now(),
+            warned: false,
+        })
+    }
+    fn take(&mut self) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.per_second;
+        self.tokens = (self.tokens + refill).min(self.burst);
+        self.last = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        self.warned = false;
+        true
+    }
+}
+
+static KEYS: Mutex<Option<Bucket>> = Mutex::new(None);
+static COMMANDS: Mutex<Option<Bucket>> = Mutex::new(None);
+
+pub fn configure(cfg: &RateLimitConfig) {
+    let keys = cfg.keys_per_second.unwrap_or(DEFAULT_KEYS_PER_SECOND);
+    let commands = cfg.commands_per_minute.unwrap_or(DEFAULT_COMMANDS_PER_MINUTE);
+    *KEYS.lock().unwrap() = Bucket::new(keys, Duration::from_secs(1));
+    *COMMANDS.lock().unwrap() = Bucket::new(commands, Duration::from_secs(60));
+}
+
+fn allow(bucket: &Mutex<Option<Bucket>>, what: &str) -> bool {
+    let mut bucket = bucket.lock().unwrap();
+    let Some(bucket) = bucket.as_mut() else {
+        return true;
+    };
+    if bucket.take() {
+        return true;
+    }
+    if !bucket.warned {
+        bucket.warned = true;
+        eprintln!("Too many {what}, dropping them until they slow down");
+    }
+    false
+}
+
+/// Whether a synthetic key press may be sent now
+pub fn allow_key() -> bool {
+    allow(&KEYS, "key presses")
+}
+
+/// Whether a command, script or request may be started now
+pub fn allow_command() -> bool {
+    allow(&COMMANDS, "commands")
+}
@@ -0,0 +1,228 @@
+use crate::action;
+use crate::audit;
+use crate::ipc::Waker;
+use crate::power;
+use crate::ratelimit;
+use crate::script::DEFAULT_REFRESH_INTERVAL_MS;
+use crate::source::{self, Source, Tint, Update};
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::{
+    fs::read_to_string,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+// Script widgets ending in .rhai run in an interpreter embedded in the
+// daemon rather than as a co-process, see src/script.rs for the others.
+// The script defines functions the bar calls, all optional:
+//
+//     init()      once after loading, returns the state, `#{}` if left out
+//     update()    every RefreshIntervalMs, and once after init
+//     tap()       when the button is pressed, the action of the button
+//     draw()      after each of the above, returns what the button shows
+//
+// Each is called with the state as `this`, so it lives on between calls.
+// draw returns a string for a label, or a map of "text", "icon", "badge",
+// "tint", "level", "highlight" and "sample", of which only what changed is
+// applied, and keys left out keep showing what they showed.
+//
+// Scripts run on the main loop, so they are cut off after a fixed number of
+// operations rather than being able to hang the bar. Besides what Rhai comes
+// with, they can call read_file(path), run(command) and open_url(url).
+
+const MIN_REFRESH_INTERVAL_MS: u64 = 100;
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_COLLECTION_SIZE: usize = 4096;
+
+fn engine(path: &str) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE);
+    let print_path = path.to_string();
+    engine.on_print(move |s| eprintln!("{print_path}: {s}"));
+    let debug_path = path.to_string();
+    engine.on_debug(move |s, _, pos| eprintln!("{debug_path}:{pos}: {s}"));
+    engine.register_fn("read_file", |path: &str| read_to_string(path).unwrap_or_default());
+    engine.register_fn("run", |command: &str| {
+        if ratelimit::allow_command() {
+            action::run_command(command);
+        }
+    });
+    engine.register_fn("open_url", |url: &str| source::open_url(None, url));
+    engine
+}
+
+fn number(value: &Dynamic) -> Option<f64> {
+    value.as_float().ok().or_else(|| value.as_int().ok().map(|i| i as f64))
+}
+
+fn parse_key(key: &str, value: &Dynamic) -> Option<Update> {
+    let text = || value.clone().into_string().ok();
+    match key {
+        "text" => Some(Update::Text(value.to_string())),
+        "icon" => text().map(Update::Icon),
+        "badge" if value.is_unit() => Some(Update::Badge(None)),
+        "badge" => Some(Update::Badge(Some(value.to_string()))),
+        "level" if value.is_unit() => Some(Update::Level(None)),
+        "level" => number(value).map(|level| Update::Level(Some(level))),
+        "highlight" => value.as_bool().ok().map(Update::Highlight),
+        "tint" if value.is_unit() => Some(Update::Tint(None)),
+        "tint" => match text()?.as_str() {
+            "green" => Some(Update::Tint(Some(Tint::Green))),
+            "yellow" => Some(Update::Tint(Some(Tint::Yellow))),
+            "red" => Some(Update::Tint(Some(Tint::Red))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+struct Loaded {
+    engine: Engine,
+    ast: AST,
+    state: Dynamic,
+}
+
+pub struct RhaiScript {
+    path: PathBuf,
+    interval: Duration,
+    loaded: Option<Loaded>,
+    /// What draw returned last, to only apply what changed
+    drawn: Map,
+    next_update: Instant,
+    failed: bool,
+    updates: Vec<Update>,
+}
+
+impl RhaiScript {
+    pub fn new(path: impl Into<PathBuf>, interval_ms: Option<u64>) -> RhaiScript {
+        let interval_ms = interval_ms
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL_MS)
+            .max(MIN_REFRESH_INTERVAL_MS);
+        RhaiScript {
+            path: path.into(),
+            interval: Duration::from_millis(interval_ms),
+            loaded: None,
+            drawn: Map::new(),
+            next_update: Instant::now(),
+            failed: false,
+            updates: Vec::new(),
+        }
+    }
+
+    fn load(&self) -> Result<Loaded, String> {
+        audit::record("Loaded script", Some(&self.path.to_string_lossy()));
+        let engine = engine(&self.path.display().to_string());
+        let ast = engine.compile_file(self.path.clone()).map_err(|e| e.to_string())?;
+        let mut loaded = Loaded {
+            engine,
+            ast,
+            state: Dynamic::from_map(Map::new()),
+        };
+        if let Some(state) = self.call(&mut loaded, "init") {
+            loaded.state = state;
+        }
+        Ok(loaded)
+    }
+
+    /// Calls `name` with the state as `this`, if the script defines it
+    fn call(&self, loaded: &mut Loaded, name: &str) -> Option<Dynamic> {
+        if !loaded.ast.iter_functions().any(|f| f.name == name && f.params.is_empty()) {
+            return None;
+        }
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut loaded.state);
+        match loaded.engine.call_fn_with_options(options, &mut Scope::new(), &loaded.ast, name, ()) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                eprintln!("{}: {name}: {e}", self.path.display());
+                None
+            }
+        }
+    }
+
+    /// Runs `callback` and then draw, queueing what changed
+    fn run(&mut self, callback: &str) {
+        let Some(mut loaded) = self.loaded.take() else {
+            return;
+        };
+        self.call(&mut loaded, callback);
+        if let Some(value) = self.call(&mut loaded, "draw") {
+            self.apply(value, callback == "update");
+        }
+        self.loaded = Some(loaded);
+    }
+
+    fn apply(&mut self, value: Dynamic, sample: bool) {
+        let map = if value.is_map() {
+            value.cast::<Map>()
+        } else if value.is_unit() {
+            Map::new()
+        } else {
+            Map::from([("text".into(), value)])
+        };
+        for (key, value) in &map {
+            // samples add up, so they are taken once per update rather
+            // than when they changed
+            if key == "sample" {
+                match number(value) {
+                    Some(value) if value.is_finite() => {
+                        if sample {
+                            self.updates.push(Update::Sample(value));
+                        }
+                    }
+                    _ => eprintln!("{}: sample has to be a number", self.path.display()),
+                }
+                continue;
+            }
+            if self.drawn.get(key).map(|v| v.to_string()) == Some(value.to_string()) {
+                continue;
+            }
+            match parse_key(key, value) {
+                Some(update) => self.updates.push(update),
+                None => eprintln!("{}: cannot show {key} = {value}", self.path.display()),
+            }
+            self.drawn.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+impl Source for RhaiScript {
+    fn touch(&mut self, pressed: bool) {
+        if pressed {
+            self.run("tap");
+        }
+    }
+
+    /// Loads the script if needed and calls update when it is due.
+    /// Scripts are loaded lazily from the main loop so that they are read
+    /// after privileges have been dropped.
+    fn poll(&mut self, _waker: &Waker) -> (Vec<Update>, i32) {
+        if self.loaded.is_none() && !self.failed {
+            match self.load() {
+                Ok(loaded) => self.loaded = Some(loaded),
+                Err(e) => {
+                    eprintln!("Failed to load script {}: {e}", self.path.display());
+                    self.failed = true;
+                }
+            }
+        }
+        if self.loaded.is_none() {
+            return (Vec::new(), i32::MAX);
+        }
+        let now = Instant::now();
+        if now >= self.next_update {
+            self.run("update");
+            self.next_update = source::next_refresh(power::scale(self.interval));
+        }
+        let ms_left = (self.next_update - now).as_millis() as i32;
+        (std::mem::take(&mut self.updates), ms_left)
+    }
+}
@@ -0,0 +1,232 @@
+use crate::config::ButtonConfig;
+use crate::http;
+use crate::i18n;
+use crate::source::{self, Async, AsyncJob, Periodic, Source, Update};
+use crate::text::truncate;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+// Cycles through the headlines of RSS and Atom feeds. Feeds are only
+// picked apart as far as needed to find the titles and links of their
+// items, anything else in them is ignored.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 15 * 60 * 1000;
+pub const DEFAULT_ROTATE_INTERVAL_MS: u64 = 5000;
+const MIN_FETCH_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_LENGTH: usize = 60;
+
+/// The `Rss` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RssConfig {
+    pub feeds: Vec<String>,
+    pub rotate_interval_ms: Option<u64>,
+    pub max_length: Option<usize>,
+    pub open_command: Option<String>,
+}
+
+struct Headline {
+    title: String,
+    link: Option<String>,
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';').filter(|end| *end <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let decoded = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            entity => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn strip_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Finds the next `<name ...>` or `<name .../>` tag in `xml`, returning its
+/// attributes, its contents and what comes after it.
+fn find_element<'a>(xml: &'a str, name: &str) -> Option<(&'a str, &'a str, &'a str)> {
+    let mut search = xml;
+    loop {
+        let start = search.find(&format!("<{name}"))? + name.len() + 1;
+        let after = &search[start..];
+        // make sure this is not a longer tag name, like <itemList>
+        if !after.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+            search = after;
+            continue;
+        }
+        let tag_end = after.find('>')?;
+        let attributes = &after[..tag_end];
+        let after_tag = &after[tag_end + 1..];
+        if attributes.ends_with('/') {
+            return Some((attributes, "", after_tag));
+        }
+        let close = format!("</{name}>");
+        let end = after_tag.find(&close)?;
+        return Some((attributes, &after_tag[..end], &after_tag[end + close.len()..]));
+    }
+}
+
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    for quote in ['"', '\''] {
+        let key = format!("{name}={quote}");
+        if let Some(start) = attributes.find(&key) {
+            let value = &attributes[start + key.len()..];
+            return value.find(quote).map(|end| &value[..end]);
+        }
+    }
+    None
+}
+
+fn text_content(content: &str) -> String {
+    let content = content.trim();
+    let text = match content
+        .strip_prefix("<![CDATA[")
+        .and_then(|c| c.strip_suffix("]]>"))
+    {
+        Some(cdata) => cdata.to_string(),
+        None => decode_entities(content),
+    };
+    // titles of type="html" can have markup in them
+    strip_tags(&text).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn parse_feed(xml: &str) -> Vec<Headline> {
+    let mut headlines = Vec::new();
+    for item_tag in ["item", "entry"] {
+        let mut rest = xml;
+        while let Some((_, item, after)) = find_element(rest, item_tag) {
+            rest = after;
+            let Some((_, title, _)) = find_element(item, "title") else {
+                continue;
+            };
+            let link = find_element(item, "link").and_then(|(attributes, content, _)| {
+                // Atom has the URL in href, RSS as the contents
+                attribute(attributes, "href")
+                    .map(decode_entities)
+                    .or_else(|| Some(text_content(content)).filter(|l| !l.is_empty()))
+            });
+            headlines.push(Headline {
+                title: text_content(title),
+                link,
+            });
+        }
+    }
+    headlines
+}
+
+pub struct Rss {
+    cfg: RssConfig,
+    fetch_interval: Duration,
+    next_fetch: Instant,
+    headlines: Vec<Headline>,
+    current: usize,
+}
+
+impl Rss {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let rss: RssConfig = source::parse(value)?;
+        let rotate_interval = rss.rotate_interval_ms.unwrap_or(DEFAULT_ROTATE_INTERVAL_MS);
+        let rss = Rss::new(rss, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS))?;
+        Ok(Box::new(Async::new(Periodic::new(rss, Duration::from_millis(rotate_interval)))))
+    }
+    pub fn new(cfg: RssConfig, fetch_interval: Duration) -> Result<Rss> {
+        if cfg.feeds.is_empty() {
+            return Err(anyhow!("Rss needs at least one feed in Feeds"));
+        }
+        Ok(Rss {
+            cfg,
+            fetch_interval: fetch_interval.max(MIN_FETCH_INTERVAL),
+            next_fetch: Instant::now(),
+            headlines: Vec::new(),
+            current: 0,
+        })
+    }
+    async fn fetch(&mut self) {
+        let mut headlines = Vec::new();
+        for url in &self.cfg.feeds {
+            match http::request_async("GET", url, &[("User-Agent", "tiny-dfr")], None).await {
+                Ok(response) if response.is_success() => {
+                    headlines.extend(parse_feed(&response.body))
+                }
+                Ok(response) => eprintln!("Failed to fetch {url}: HTTP {}", response.status),
+                Err(e) => eprintln!("Failed to fetch {url}: {e:#}"),
+            }
+        }
+        // keep showing the old headlines if all feeds are unreachable
+        if !headlines.is_empty() {
+            self.headlines = headlines;
+            self.current = 0;
+        }
+    }
+}
+
+impl AsyncJob for Rss {
+    const MIN_INTERVAL: Duration = Duration::from_millis(1000);
+
+    /// Called every RotateIntervalMs to show the next headline, fetching
+    /// the feeds again when RefreshIntervalMs has passed.
+    async fn refresh(&mut self) -> Vec<Update> {
+        let now = Instant::now();
+        if now >= self.next_fetch {
+            self.fetch().await;
+            self.next_fetch = now + self.fetch_interval;
+        } else if !self.headlines.is_empty() {
+            self.current = (self.current + 1) % self.headlines.len();
+        }
+        let Some(headline) = self.headlines.get(self.current) else {
+            return vec![Update::Text(i18n::tr("No headlines"))];
+        };
+        let max_length = self.cfg.max_length.unwrap_or(DEFAULT_MAX_LENGTH);
+        vec![Update::Text(truncate(&headline.title, max_length))]
+    }
+    async fn touch(&mut self, pressed: bool) -> bool {
+        let link = self.headlines.get(self.current).and_then(|h| h.link.as_ref());
+        if let (true, Some(link)) = (pressed, link) {
+            source::open_url(self.cfg.open_command.as_deref(), link);
+        }
+        false
+    }
+}
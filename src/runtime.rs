@@ -0,0 +1,117 @@
+use anyhow::{anyhow, Result};
+use gio::{Subprocess, SubprocessFlags};
+use glib::{MainContext, MainLoop, SourceId};
+use std::{
+    ffi::OsStr,
+    future::Future,
+    mem,
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+};
+
+// A single thread running a glib main context, on which sources that wait
+// on the network or on other processes run as futures, instead of each
+// having a thread of their own. gio does the waiting, with its async
+// sockets, streams and subprocesses, and the futures hand what they got to
+// the main loop through its waker, like threads do.
+//
+// Futures are made on the runtime thread, so they may hold on to gio
+// objects, which cannot be sent between threads, only what makes them has
+// to be. The thread is started by the first spawn, which happens after
+// privileges were dropped.
+
+static CONTEXT: OnceLock<MainContext> = OnceLock::new();
+
+fn context() -> &'static MainContext {
+    CONTEXT.get_or_init(|| {
+        let ctx = MainContext::new();
+        let thread_ctx = ctx.clone();
+        thread::spawn(move || {
+            let main_loop = MainLoop::new(Some(&thread_ctx), false);
+            thread_ctx
+                .with_thread_default(|| main_loop.run())
+                .expect("the runtime context is only used by its thread");
+        });
+        ctx
+    })
+}
+
+enum State {
+    /// Not started on the runtime thread yet
+    Pending,
+    Running(SourceId),
+    Stopped,
+}
+
+/// Stops the future it was returned for when dropped
+pub struct Task(Arc<Mutex<State>>);
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().unwrap();
+        if let State::Running(id) = mem::replace(&mut *state, State::Stopped) {
+            context().invoke(move || {
+                if let Some(source) = context().find_source_by_id(&id) {
+                    source.destroy();
+                }
+            });
+        }
+    }
+}
+
+/// Runs the future made by `make` on the runtime thread until it is done or
+/// the returned `Task` is dropped
+pub fn spawn<F, Fut>(make: F) -> Task
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    let state = Arc::new(Mutex::new(State::Pending));
+    let task_state = state.clone();
+    context().invoke(move || {
+        let mut state = task_state.lock().unwrap();
+        if matches!(*state, State::Stopped) {
+            return;
+        }
+        let done_state = task_state.clone();
+        let future = make();
+        let handle = context().spawn_local(async move {
+            future.await;
+            // so that the source id, which may be reused, is not destroyed later
+            *done_state.lock().unwrap() = State::Stopped;
+        });
+        if let Ok(id) = handle.into_source_id() {
+            *state = State::Running(id);
+        }
+    });
+    Task(state)
+}
+
+/// Runs the future made by `make` on the runtime thread until it is done
+pub fn spawn_detached<F, Fut>(make: F)
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    context().invoke(move || {
+        context().spawn_local(make());
+    });
+}
+
+/// Runs `sh -c command` with `args` as its arguments and waits for it,
+/// returning what it printed, or an error if it failed. What it prints to
+/// stderr goes to ours.
+pub async fn shell(command: &str, args: &[&str]) -> Result<String> {
+    let mut argv: Vec<&OsStr> = ["sh", "-c", command, "sh"].map(OsStr::new).to_vec();
+    argv.extend(args.iter().map(OsStr::new));
+    let process = Subprocess::newv(&argv, SubprocessFlags::STDOUT_PIPE)
+        .map_err(|e| anyhow!("failed to run {command}: {e}"))?;
+    let (stdout, _) = process.communicate_future(None).await?;
+    if process.has_signaled() {
+        return Err(anyhow!("{command} was killed by signal {}", process.term_sig()));
+    }
+    if !process.is_successful() {
+        return Err(anyhow!("{command} exited with {}", process.exit_status()));
+    }
+    Ok(String::from_utf8_lossy(stdout.as_deref().unwrap_or_default()).into_owned())
+}
@@ -0,0 +1,127 @@
+use crate::config::ButtonConfig;
+use crate::fullscreen::follow_command;
+use crate::ipc::Waker;
+use crate::json::Value;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    process::{Command, Stdio},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+// Follows whether the screen is being shared, so that the bar does not show
+// what it should not to whoever is watching: buttons with sensitive
+// contents, like mail counts or window titles, are blanked, and LayerKeys
+// stands in for the default layer.
+//
+// Screencasts through the desktop portal are PipeWire video sources that
+// no device is behind, unlike cameras, and are being read from while
+// shared. PipeWire runs inside the user's session, so like for AudioOutput
+// the daemon needs to be pointed at a socket it may use, through Remote.
+// Command covers anything else, with a program that prints a line with 1
+// or 0 whenever sharing starts or stops.
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub enum Backend {
+    PipeWire,
+    Command,
+}
+
+/// The `[ScreenShare]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct ScreenShareConfig {
+    pub backend: Option<Backend>,
+    pub remote: Option<String>,
+    pub command: Option<String>,
+    /// Whether buttons with sensitive contents are left empty while sharing
+    pub blank: Option<bool>,
+    pub layer_keys: Option<Vec<ButtonConfig>>,
+}
+
+fn is_screencast(object: &Value) -> bool {
+    if object.get("type").and_then(Value::as_str) != Some("PipeWire:Interface:Node") {
+        return false;
+    }
+    let info = object.get("info");
+    let props = info.and_then(|info| info.get("props"));
+    let prop = |key| props.and_then(|p| p.get(key));
+    prop("media.class").and_then(Value::as_str) == Some("Video/Source")
+        && prop("device.api").is_none()
+        && info.and_then(|info| info.get("state")).and_then(Value::as_str) == Some("running")
+}
+
+fn follow_pipewire(remote: Option<&str>, states: &Sender<bool>, waker: &Waker) -> Result<()> {
+    let mut last = None;
+    loop {
+        let mut cmd = Command::new("pw-dump");
+        if let Some(remote) = remote {
+            cmd.env("PIPEWIRE_REMOTE", remote);
+        }
+        let output = cmd.stdin(Stdio::null()).stderr(Stdio::null()).output()?;
+        if !output.status.success() {
+            return Err(anyhow!("pw-dump exited with {}", output.status));
+        }
+        let sharing = match Value::parse(&String::from_utf8_lossy(&output.stdout))? {
+            Value::Array(objects) => objects.iter().any(is_screencast),
+            _ => false,
+        };
+        if last != Some(sharing) {
+            last = Some(sharing);
+            states.send(sharing)?;
+            waker.wake();
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Whether the screen is being shared, as far as the backend knows
+#[derive(Default)]
+pub struct ScreenShare {
+    states: Option<Receiver<bool>>,
+    on: bool,
+}
+
+impl ScreenShare {
+    /// Starts following the backend, if one is set. The thread of the last
+    /// one stops once it notices it is no longer listened to.
+    pub fn start(cfg: &ScreenShareConfig, waker: &Waker) -> ScreenShare {
+        let Some(backend) = cfg.backend else {
+            return ScreenShare::default();
+        };
+        let (tx, rx) = channel();
+        let (remote, command) = (cfg.remote.clone(), cfg.command.clone());
+        let waker = waker.clone();
+        thread::spawn(move || loop {
+            let res = match (backend, &command) {
+                (Backend::PipeWire, _) => follow_pipewire(remote.as_deref(), &tx, &waker),
+                (Backend::Command, Some(command)) => follow_command(command, &tx, &waker),
+                (Backend::Command, None) => Err(anyhow!("Command is not set")),
+            };
+            // without knowing, the screen is taken as not shared
+            if tx.send(false).is_err() {
+                break;
+            }
+            waker.wake();
+            if let Err(e) = res {
+                eprintln!("ScreenShare: cannot tell whether the screen is shared: {e:#}");
+            }
+            thread::sleep(RETRY_DELAY);
+        });
+        ScreenShare {
+            states: Some(rx),
+            on: false,
+        }
+    }
+    pub fn update(&mut self) -> bool {
+        if let Some(on) = self.states.as_ref().and_then(|rx| rx.try_iter().last()) {
+            self.on = on;
+        }
+        self.on
+    }
+}
@@ -0,0 +1,171 @@
+use crate::audit;
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::power;
+use crate::rhaiscript::RhaiScript;
+use crate::source::{self, Source, Update};
+use anyhow::{anyhow, Result};
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::fd::AsRawFd,
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{channel, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Script widgets run an executable (usually a script with a shebang line,
+// e.g. `#!/usr/bin/env lua`) as a co-process for as long as the button
+// exists. The daemon writes one callback name per line to its stdin:
+//
+//     update    sent every RefreshIntervalMs, and once after starting
+//     tap       sent when the button is pressed
+//
+// and the script answers on stdout, at any time, with one command per line:
+//
+//     text <label>    show <label> on the button
+//     icon <path>     show the svg or png at <path>
+//     sample <value>  add <value> to the button's sparkline
+//
+// Keeping the process alive lets scripts keep state between callbacks.
+// Scripts ending in .rhai are run by the embedded interpreter instead,
+// see src/rhaiscript.rs.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 1000;
+const MIN_REFRESH_INTERVAL_MS: u64 = 100;
+
+fn parse_command(line: &str) -> Option<Update> {
+    let (cmd, arg) = line.split_once(' ').unwrap_or((line, ""));
+    match cmd {
+        "text" => Some(Update::Text(arg.to_string())),
+        "icon" => Some(Update::Icon(arg.to_string())),
+        "sample" => arg.trim().parse().ok().map(Update::Sample),
+        _ => None,
+    }
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    commands: Receiver<Update>,
+}
+
+pub struct Script {
+    path: PathBuf,
+    interval: Duration,
+    process: Option<Process>,
+    next_update: Instant,
+    failed: bool,
+}
+
+impl Script {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let path = value.as_str().ok_or(anyhow!("Script must be a path"))?;
+        if path.ends_with(".rhai") {
+            return Ok(Box::new(RhaiScript::new(path, cfg.refresh_interval_ms)));
+        }
+        Ok(Box::new(Script::new(path, cfg.refresh_interval_ms)))
+    }
+    pub fn new(path: impl Into<PathBuf>, interval_ms: Option<u64>) -> Script {
+        let interval_ms = interval_ms
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL_MS)
+            .max(MIN_REFRESH_INTERVAL_MS);
+        Script {
+            path: path.into(),
+            interval: Duration::from_millis(interval_ms),
+            process: None,
+            next_update: Instant::now(),
+            failed: false,
+        }
+    }
+
+    fn spawn(&self, waker: &Waker) -> std::io::Result<Process> {
+        audit::record("Started script", Some(&self.path.to_string_lossy()));
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        // A script that stops reading must not be able to block the main loop
+        unsafe {
+            let fd = stdin.as_raw_fd();
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+        let stdout = child.stdout.take().unwrap();
+        let (tx, commands) = channel();
+        let waker = waker.clone();
+        let path = self.path.display().to_string();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                match parse_command(&line) {
+                    Some(cmd) => {
+                        if tx.send(cmd).is_err() {
+                            break;
+                        }
+                        waker.wake();
+                    }
+                    None => eprintln!("{path}: unknown script command: {line}"),
+                }
+            }
+        });
+        Ok(Process {
+            child,
+            stdin,
+            commands,
+        })
+    }
+
+    fn send(&mut self, callback: &str) {
+        if let Some(process) = &mut self.process {
+            _ = writeln!(process.stdin, "{callback}");
+        }
+    }
+}
+
+impl Source for Script {
+    fn touch(&mut self, pressed: bool) {
+        if pressed {
+            self.send("tap");
+        }
+    }
+
+    /// Starts the script if needed and sends `update` when it is due.
+    /// Scripts are started lazily from the main loop so that they run
+    /// after privileges have been dropped.
+    fn poll(&mut self, waker: &Waker) -> (Vec<Update>, i32) {
+        if self.process.is_none() && !self.failed {
+            match self.spawn(waker) {
+                Ok(process) => self.process = Some(process),
+                Err(e) => {
+                    eprintln!("Failed to start script {}: {e}", self.path.display());
+                    self.failed = true;
+                }
+            }
+        }
+        let Some(process) = &self.process else {
+            return (Vec::new(), i32::MAX);
+        };
+        let commands = process.commands.try_iter().collect();
+        let now = Instant::now();
+        if now >= self.next_update {
+            self.send("update");
+            self.next_update = source::next_refresh(power::scale(self.interval));
+        }
+        let ms_left = (self.next_update - now).as_millis() as i32;
+        (commands, ms_left)
+    }
+}
+
+impl Drop for Script {
+    fn drop(&mut self) {
+        if let Some(process) = &mut self.process {
+            _ = process.child.kill();
+            _ = process.child.wait();
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::audit;
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::ratelimit;
+use crate::runtime;
+use crate::source::{self, Async, AsyncJob, Periodic, Source, Tint, Update};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// Shows temperatures and fan speeds read from hwmon, e.g. from the SMC
+// through applesmc on Intel Macs or macsmc_hwmon on Apple Silicon.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 2000;
+const DEFAULT_CHIPS: &[&str] = &["applesmc", "macsmc_hwmon", "coretemp", "k10temp"];
+const DEFAULT_FORMAT: &str = "{temp}°";
+const DEFAULT_WARNING: f64 = 70.0;
+const DEFAULT_CRITICAL: f64 = 90.0;
+
+/// The `Sensors` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SensorsConfig {
+    pub chip: Option<String>,
+    pub temperature: Option<String>,
+    pub fan: Option<String>,
+    pub format: Option<String>,
+    pub warning: Option<f64>,
+    pub critical: Option<f64>,
+    pub command: Option<String>,
+}
+
+fn find_chip(name: Option<&str>) -> Result<PathBuf> {
+    let mut chips = Vec::new();
+    for entry in fs::read_dir("/sys/class/hwmon")? {
+        let path = entry?.path();
+        if let Ok(chip) = fs::read_to_string(path.join("name")) {
+            chips.push((chip.trim().to_string(), path));
+        }
+    }
+    let wanted: Vec<&str> = match name {
+        Some(name) => vec![name],
+        None => DEFAULT_CHIPS.to_vec(),
+    };
+    wanted
+        .iter()
+        .find_map(|w| chips.iter().find(|(chip, _)| chip == w))
+        .map(|(_, path)| path.clone())
+        .ok_or(anyhow!("no hwmon chip named {}", wanted.join(" or ")))
+}
+
+/// Reads every `<kind><n>_input` of the chip, optionally only the one whose
+/// label or name (like `temp1`) matches `sensor`.
+fn read_inputs(chip: &Path, kind: &str, sensor: Option<&str>) -> Result<Vec<f64>> {
+    let mut values = Vec::new();
+    for entry in fs::read_dir(chip)? {
+        let file_name = entry?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let Some(name) = file_name.strip_suffix("_input") else {
+            continue;
+        };
+        if !name.strip_prefix(kind).is_some_and(|n| n.bytes().all(|b| b.is_ascii_digit())) {
+            continue;
+        }
+        if let Some(sensor) = sensor {
+            let label = fs::read_to_string(chip.join(format!("{name}_label"))).unwrap_or_default();
+            if label.trim() != sensor && name != sensor {
+                continue;
+            }
+        }
+        if let Some(value) = fs::read_to_string(chip.join(&*file_name))
+            .ok()
+            .and_then(|v| v.trim().parse::<f64>().ok())
+        {
+            values.push(value);
+        }
+    }
+    Ok(values)
+}
+
+/// Returns the hottest temperature of the default chips in °C
+pub fn hottest() -> Option<f64> {
+    let chip = find_chip(None).ok()?;
+    read_inputs(&chip, "temp", None)
+        .ok()?
+        .into_iter()
+        .map(|t| t / 1000.0)
+        .reduce(f64::max)
+}
+
+pub struct Sensors {
+    cfg: SensorsConfig,
+}
+
+impl Sensors {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let sensors = Sensors::new(source::parse(value)?);
+        Ok(Box::new(Async::new(Periodic::new(sensors, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS)))))
+    }
+    pub fn new(cfg: SensorsConfig) -> Sensors {
+        Sensors { cfg }
+    }
+    /// Returns the hottest matching temperature in °C and the fastest fan in RPM
+    fn read(&self) -> Result<(Option<f64>, Option<f64>)> {
+        let chip = find_chip(self.cfg.chip.as_deref())?;
+        let temp = read_inputs(&chip, "temp", self.cfg.temperature.as_deref())?
+            .into_iter()
+            .map(|t| t / 1000.0)
+            .reduce(f64::max);
+        let fan = read_inputs(&chip, "fan", self.cfg.fan.as_deref())?
+            .into_iter()
+            .reduce(f64::max);
+        Ok((temp, fan))
+    }
+}
+
+fn show(value: Option<f64>) -> String {
+    match value {
+        Some(value) => format!("{value:.0}"),
+        None => "?".to_string(),
+    }
+}
+
+impl AsyncJob for Sensors {
+    const MIN_INTERVAL: Duration = Duration::from_millis(500);
+
+    async fn refresh(&mut self) -> Vec<Update> {
+        let (temp, fan) = match self.read() {
+            Ok(values) => values,
+            Err(e) => {
+                eprintln!("Failed to read sensors: {e:#}");
+                return vec![Update::Text(i18n::tr("N/A")), Update::Tint(None)];
+            }
+        };
+        let format = self.cfg.format.as_deref().unwrap_or(DEFAULT_FORMAT);
+        let text = format.replace("{temp}", &show(temp)).replace("{fan}", &show(fan));
+        let tint = temp.and_then(|temp| {
+            if temp >= self.cfg.critical.unwrap_or(DEFAULT_CRITICAL) {
+                Some(Tint::Red)
+            } else if temp >= self.cfg.warning.unwrap_or(DEFAULT_WARNING) {
+                Some(Tint::Yellow)
+            } else {
+                None
+            }
+        });
+        let mut updates = vec![Update::Text(text), Update::Tint(tint)];
+        updates.extend(temp.map(Update::Sample));
+        updates
+    }
+    async fn touch(&mut self, pressed: bool) -> bool {
+        let Some(command) = self.cfg.command.as_deref().filter(|_| pressed) else {
+            return false;
+        };
+        if !ratelimit::allow_command() {
+            return false;
+        }
+        audit::record("Ran command", Some(command));
+        if let Err(e) = runtime::shell(command, &[]).await {
+            eprintln!("Sensors tap command failed: {e:#}");
+        }
+        true
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::{MAX_SCALE, MIN_SCALE};
+use crate::json::Value;
+use chrono::NaiveTime;
+
+// The settings that can be changed at runtime, over IPC or from a settings
+// app through D-Bus properties, with what values they take. Only safe single
+// values are listed: buttons and colors are left to the config file, and so
+// are tokens and passwords, as anyone on the bus can read properties. So are
+// commands, sockets and the hosts tokens are sent to, as whoever sets them
+// would get to run commands or talk to services as the daemon.
+
+pub enum Kind {
+    Bool,
+    Int { min: i64, max: i64 },
+    Float { min: f64, max: f64 },
+    String,
+    /// An HH:MM time of day
+    Time,
+    Choice(&'static [&'static str]),
+}
+
+pub struct Setting {
+    /// Where it is in the config, e.g. `Splash.Text`
+    pub key: &'static str,
+    pub kind: Kind,
+}
+
+const SIGNAL_ACTIONS: &[&str] = &["ToggleLayer", "ReloadConfig", "ToggleBar", "ToggleFocus", "LockLayer", "TogglePrivacy"];
+const BRIGHTNESS: Kind = Kind::Int { min: 0, max: 255 };
+const MULTIPLIER: Kind = Kind::Int { min: 1, max: 100 };
+const MS: Kind = Kind::Int { min: 0, max: i32::MAX as i64 };
+const CELSIUS: Kind = Kind::Float { min: 0.0, max: 150.0 };
+
+pub const SETTINGS: &[Setting] = &[
+    Setting { key: "Locale", kind: Kind::String },
+    Setting { key: "AuditLog", kind: Kind::Bool },
+    Setting { key: "MediaLayerDefault", kind: Kind::Bool },
+    Setting { key: "ShowButtonOutlines", kind: Kind::Bool },
+    Setting { key: "EnablePixelShift", kind: Kind::Bool },
+    Setting { key: "Rotate180", kind: Kind::Bool },
+    Setting { key: "Scale", kind: Kind::Float { min: MIN_SCALE, max: MAX_SCALE } },
+    Setting { key: "SafeAreaInsetLeft", kind: Kind::Int { min: 0, max: 200 } },
+    Setting { key: "SafeAreaInsetRight", kind: Kind::Int { min: 0, max: 200 } },
+    Setting { key: "FontTemplate", kind: Kind::String },
+    Setting { key: "TextAntialias", kind: Kind::Choice(&["none", "gray", "subpixel"]) },
+    Setting { key: "SubpixelOrder", kind: Kind::Choice(&["rgb", "bgr", "vrgb", "vbgr"]) },
+    Setting { key: "TextHinting", kind: Kind::Choice(&["none", "slight", "medium", "full"]) },
+    Setting { key: "RenderMode", kind: Kind::Choice(&["color", "grayscale", "reduced"]) },
+    Setting { key: "AdaptiveBrightness", kind: Kind::Bool },
+    Setting { key: "ActiveBrightness", kind: BRIGHTNESS },
+    Setting { key: "BrightnessFadeMs", kind: MS },
+    Setting { key: "DimBrightness", kind: BRIGHTNESS },
+    Setting { key: "SoftwareDimmingBelow", kind: BRIGHTNESS },
+    Setting { key: "WakeConsumesTouch", kind: Kind::Bool },
+    Setting { key: "MinBrightness", kind: BRIGHTNESS },
+    Setting { key: "MaxBrightness", kind: BRIGHTNESS },
+    Setting { key: "NightBrightness.From", kind: Kind::Time },
+    Setting { key: "NightBrightness.To", kind: Kind::Time },
+    Setting { key: "NightBrightness.MinBrightness", kind: BRIGHTNESS },
+    Setting { key: "NightBrightness.MaxBrightness", kind: BRIGHTNESS },
+    Setting { key: "BrightnessSync.Enable", kind: Kind::Bool },
+    Setting { key: "BrightnessSync.Factor", kind: Kind::Float { min: 0.0, max: 10.0 } },
+    Setting { key: "PrimaryLayer.FontTemplate", kind: Kind::String },
+    Setting { key: "PrimaryLayer.ShowButtonOutlines", kind: Kind::Bool },
+    Setting { key: "MediaLayer.FontTemplate", kind: Kind::String },
+    Setting { key: "MediaLayer.ShowButtonOutlines", kind: Kind::Bool },
+    Setting { key: "Splash.Text", kind: Kind::String },
+    Setting { key: "Splash.Icon", kind: Kind::String },
+    Setting { key: "Splash.MinDurationMs", kind: MS },
+    Setting { key: "Splash.GoodbyeText", kind: Kind::String },
+    Setting { key: "Signals.Usr1", kind: Kind::Choice(SIGNAL_ACTIONS) },
+    Setting { key: "Signals.Usr2", kind: Kind::Choice(SIGNAL_ACTIONS) },
+    Setting { key: "FocusMode.From", kind: Kind::Time },
+    Setting { key: "FocusMode.To", kind: Kind::Time },
+    Setting { key: "LowBattery.Threshold", kind: Kind::Int { min: 0, max: 100 } },
+    Setting { key: "LowBattery.Brightness", kind: BRIGHTNESS },
+    Setting { key: "LowBattery.RefreshMultiplier", kind: MULTIPLIER },
+    Setting { key: "LowBattery.DisableAnimations", kind: Kind::Bool },
+    Setting { key: "Thermal.Enable", kind: Kind::Bool },
+    Setting { key: "Thermal.Hot", kind: CELSIUS },
+    Setting { key: "Thermal.Cool", kind: CELSIUS },
+    Setting { key: "Thermal.RefreshMultiplier", kind: MULTIPLIER },
+    Setting { key: "Fullscreen.Backend", kind: Kind::Choice(&["Sway", "Hyprland", "X11", "Command"]) },
+    Setting { key: "Fullscreen.PauseAnimations", kind: Kind::Bool },
+    Setting { key: "ScreenShare.Backend", kind: Kind::Choice(&["PipeWire", "Command"]) },
+    Setting { key: "ScreenShare.Blank", kind: Kind::Bool },
+    Setting { key: "Privacy.OnLock", kind: Kind::Bool },
+    Setting { key: "NightLight.Default", kind: Kind::Int { min: 1000, max: 10000 } },
+    Setting { key: "NightLight.Min", kind: Kind::Int { min: 1000, max: 10000 } },
+    Setting { key: "NightLight.Max", kind: Kind::Int { min: 1000, max: 10000 } },
+];
+
+pub fn find(key: &str) -> Result<&'static Setting, String> {
+    SETTINGS
+        .iter()
+        .find(|setting| setting.key == key)
+        .ok_or(format!("unknown setting {key}"))
+}
+
+/// Finds a setting by its D-Bus property name, the key without dots
+pub fn find_property(name: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|setting| setting.property() == name)
+}
+
+impl Setting {
+    pub fn property(&self) -> String {
+        self.key.replace('.', "")
+    }
+    /// The D-Bus type of its property
+    pub fn signature(&self) -> &'static str {
+        match self.kind {
+            Kind::Bool => "b",
+            Kind::Int { .. } => "x",
+            Kind::Float { .. } => "d",
+            Kind::String | Kind::Time | Kind::Choice(_) => "s",
+        }
+    }
+    /// Checks a value given as text, returning it as it goes in the config
+    pub fn parse(&self, value: &str) -> Result<toml_edit::Value, String> {
+        let invalid = |expected: &str| format!("{} must be {expected}", self.key);
+        Ok(match self.kind {
+            Kind::Bool => value.parse::<bool>().map_err(|_| invalid("true or false"))?.into(),
+            Kind::Int { min, max } => match value.parse::<i64>() {
+                Ok(n) if (min..=max).contains(&n) => n.into(),
+                _ => return Err(invalid(&format!("a whole number from {min} to {max}"))),
+            },
+            Kind::Float { min, max } => match value.parse::<f64>() {
+                // NaN is never in range
+                Ok(n) if (min..=max).contains(&n) => n.into(),
+                _ => return Err(invalid(&format!("a number from {min} to {max}"))),
+            },
+            Kind::String => value.into(),
+            Kind::Time => {
+                NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| invalid("a time as HH:MM"))?;
+                value.into()
+            }
+            Kind::Choice(choices) => {
+                if !choices.contains(&value) {
+                    return Err(invalid(&format!("one of {}", choices.join(", "))));
+                }
+                value.into()
+            }
+        })
+    }
+}
+
+/// Turns a value from the config into the same kind of JSON value
+pub fn to_json(value: &toml::Value) -> Value {
+    match value {
+        toml::Value::Boolean(b) => Value::Bool(*b),
+        toml::Value::Integer(n) => Value::Number(*n as f64),
+        toml::Value::Float(n) => Value::Number(*n),
+        toml::Value::String(s) => s.clone().into(),
+        other => other.to_string().into(),
+    }
+}
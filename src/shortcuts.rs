@@ -0,0 +1,59 @@
+use crate::config::SignalAction;
+use crate::keycodes;
+use std::collections::{HashMap, HashSet};
+
+// Chords on the physical keyboards, like Fn+Esc, that do to the bar what
+// the signals can do, without reaching for a terminal. Keys are read
+// through libinput next to the compositor, which still gets them too, so
+// chords are best made of keys that do nothing on their own, e.g. with Fn.
+//
+// A chord fires when its last key goes down while exactly its keys are
+// held, so Fn+Ctrl+Esc does not fire Fn+Esc as well.
+
+pub struct Shortcut {
+    keys: Vec<u32>,
+    action: SignalAction,
+}
+
+/// Reads the `[Shortcuts]` section, which maps chords written like
+/// "Fn+Esc" to actions, leaving out chords with unknown keys
+pub fn parse(shortcuts: HashMap<String, SignalAction>) -> Vec<Shortcut> {
+    shortcuts
+        .into_iter()
+        .filter_map(|(chord, action)| {
+            let keys: Option<Vec<u32>> = chord
+                .split('+')
+                .map(|name| keycodes::parse_key(name.trim()).map(|key| key as u32))
+                .collect();
+            if keys.is_none() {
+                eprintln!("Shortcut {chord} has an unknown key, it is ignored");
+            }
+            Some(Shortcut { keys: keys?, action })
+        })
+        .collect()
+}
+
+/// Keys held down on any keyboard
+#[derive(Default)]
+pub struct HeldKeys(HashSet<u32>);
+
+impl HeldKeys {
+    /// Takes a key going down or up, returning the action of the shortcut
+    /// it completes
+    pub fn update(&mut self, key: u32, pressed: bool, shortcuts: &[Shortcut]) -> Option<SignalAction> {
+        if !pressed {
+            self.0.remove(&key);
+            return None;
+        }
+        // already held, e.g. on another keyboard
+        if !self.0.insert(key) {
+            return None;
+        }
+        shortcuts
+            .iter()
+            .find(|shortcut| {
+                shortcut.keys.len() == self.0.len() && shortcut.keys.iter().all(|key| self.0.contains(key))
+            })
+            .map(|shortcut| shortcut.action)
+    }
+}
@@ -0,0 +1,304 @@
+use crate::auth::{self, IpcConfig};
+use crate::ipc::{Access, IpcClient, Request};
+use crate::json::Value;
+use crate::paths::SOCKET_PATH;
+use anyhow::Result;
+use std::{
+    fs::{self, Permissions},
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+// Limits on what clients may hold on to, as other users may connect
+const MAX_CONNECTIONS: usize = 16;
+const MAX_LINE_BYTES: u64 = 64 * 1024;
+/// How long a reply or event may wait for a client that stopped reading
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const REQUEST_FAILED: i32 = -32000;
+
+fn str_param(params: &Value, name: &str) -> Result<String, (i32, String)> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or((INVALID_PARAMS, format!("missing string parameter {name}")))
+}
+
+fn parse_request(method: &str, params: &Value) -> Result<Request, (i32, String)> {
+    Ok(match method {
+        "SetButtonText" => Request::SetButtonText {
+            id: str_param(params, "id")?,
+            text: str_param(params, "text")?,
+        },
+        "SetButtonIcon" => Request::SetButtonIcon {
+            id: str_param(params, "id")?,
+            path: str_param(params, "path")?,
+        },
+        "FlashButton" => Request::FlashButton {
+            id: str_param(params, "id")?,
+            color: str_param(params, "color")?,
+            duration_ms: params
+                .get("duration_ms")
+                .and_then(Value::as_u64)
+                .ok_or((INVALID_PARAMS, "missing number parameter duration_ms".to_string()))?,
+        },
+        "SetProgress" => Request::SetProgress {
+            id: str_param(params, "id")?,
+            fraction: match params.get("fraction") {
+                Some(Value::Number(fraction)) => *fraction,
+                _ => return Err((INVALID_PARAMS, "missing number parameter fraction".to_string())),
+            },
+        },
+        "SetButtonEnabled" => Request::SetButtonEnabled {
+            id: str_param(params, "id")?,
+            enabled: match params.get("enabled") {
+                Some(Value::Bool(enabled)) => *enabled,
+                _ => return Err((INVALID_PARAMS, "missing boolean parameter enabled".to_string())),
+            },
+        },
+        "ShowToast" => Request::ShowToast {
+            text: str_param(params, "text")?,
+            icon: match params.get("icon") {
+                None | Some(Value::Null) => None,
+                Some(_) => Some(str_param(params, "icon")?),
+            },
+            timeout_ms: match params.get("timeout_ms") {
+                None | Some(Value::Null) => 0,
+                Some(value) => value.as_u64().ok_or((
+                    INVALID_PARAMS,
+                    "timeout_ms must be a number".to_string(),
+                ))?,
+            },
+        },
+        "SetLayer" => Request::SetLayer {
+            layer: str_param(params, "layer")?,
+        },
+        "SetBrightness" => Request::SetBrightness {
+            brightness: match params.get("brightness") {
+                None | Some(Value::Null) => None,
+                Some(value) => Some(value.as_u64().filter(|b| *b <= 255).ok_or((
+                    INVALID_PARAMS,
+                    "brightness must be between 0 and 255".to_string(),
+                ))? as u32),
+            },
+        },
+        "SwitchProfile" => Request::SwitchProfile {
+            name: match params.get("name") {
+                None | Some(Value::Null) => None,
+                Some(_) => Some(str_param(params, "name")?),
+            },
+        },
+        "SetSetting" => Request::SetSetting {
+            key: str_param(params, "key")?,
+            value: str_param(params, "value")?,
+        },
+        "SetEditMode" => Request::SetEditMode {
+            enabled: match params.get("enabled") {
+                Some(Value::Bool(enabled)) => *enabled,
+                _ => return Err((INVALID_PARAMS, "missing boolean parameter enabled".to_string())),
+            },
+        },
+        "SetFocusMode" => Request::SetFocusMode {
+            enabled: match params.get("enabled") {
+                None | Some(Value::Null) => None,
+                Some(Value::Bool(enabled)) => Some(*enabled),
+                Some(_) => {
+                    return Err((INVALID_PARAMS, "enabled must be a boolean or null".to_string()))
+                }
+            },
+        },
+        "SetPrivacyMode" => Request::SetPrivacyMode {
+            enabled: match params.get("enabled") {
+                None | Some(Value::Null) => None,
+                Some(Value::Bool(enabled)) => Some(*enabled),
+                Some(_) => {
+                    return Err((INVALID_PARAMS, "enabled must be a boolean or null".to_string()))
+                }
+            },
+        },
+        "SetBarEnabled" => Request::SetBarEnabled {
+            enabled: match params.get("enabled") {
+                Some(Value::Bool(enabled)) => *enabled,
+                _ => return Err((INVALID_PARAMS, "missing boolean parameter enabled".to_string())),
+            },
+        },
+        "SetChargeLimit" => Request::SetChargeLimit {
+            limit: params
+                .get("limit")
+                .and_then(Value::as_u64)
+                .filter(|limit| (1..=100).contains(limit))
+                .ok_or((INVALID_PARAMS, "limit must be between 1 and 100".to_string()))?
+                as u32,
+        },
+        "GetLayer" => Request::GetLayer,
+        "GetBrightness" => Request::GetBrightness,
+        "GetState" => Request::GetState,
+        "GetAllSettings" => Request::GetAllSettings,
+        _ => return Err((METHOD_NOT_FOUND, format!("unknown method {method}"))),
+    })
+}
+
+fn response(id: Value, result: Result<Value, (i32, String)>) -> Value {
+    let mut fields = vec![("jsonrpc".to_string(), "2.0".into()), ("id".to_string(), id)];
+    match result {
+        Ok(result) => fields.push(("result".to_string(), result)),
+        Err((code, message)) => fields.push((
+            "error".to_string(),
+            Value::Object(vec![
+                ("code".to_string(), Value::Number(code as f64)),
+                ("message".to_string(), message.into()),
+            ]),
+        )),
+    }
+    Value::Object(fields)
+}
+
+/// Carries out a request from `uid`, returning the reply, or nothing for a
+/// notification, which is a request without an id
+fn handle_line(line: &str, client: &IpcClient, uid: u32) -> Option<Value> {
+    let message = match Value::parse(line) {
+        Ok(message) => message,
+        Err(e) => return Some(response(Value::Null, Err((PARSE_ERROR, e.to_string())))),
+    };
+    let id = message.get("id").cloned();
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        let id = id.unwrap_or(Value::Null);
+        return Some(response(id, Err((INVALID_REQUEST, "missing method".to_string()))));
+    };
+    let params = message
+        .get("params")
+        .cloned()
+        .unwrap_or(Value::Object(Vec::new()));
+    let result = parse_request(method, &params).and_then(|request| {
+        if request.access() == Access::ChargeLimit && uid != 0 {
+            return Err((REQUEST_FAILED, "only root may change the charge limit".to_string()));
+        }
+        client.call(request).map_err(|e| (REQUEST_FAILED, e))
+    });
+    Some(response(id?, result))
+}
+
+/// The id of a `Subscribe` request, if the line is one, `None` inside for a
+/// notification
+fn is_subscribe(line: &str) -> Option<Option<Value>> {
+    let message = Value::parse(line).ok()?;
+    if message.get("method").and_then(Value::as_str) != Some("Subscribe") {
+        return None;
+    }
+    Some(message.get("id").cloned())
+}
+
+/// After a `Subscribe` request the connection only carries events,
+/// sent as `Event` notifications until the client disconnects.
+fn stream_events(mut writer: UnixStream, id: Option<Value>, client: &IpcClient) {
+    let events = client.subscribe();
+    if let Some(id) = id {
+        if writeln!(writer, "{}", response(id, Ok(Value::Null))).is_err() {
+            return;
+        }
+    }
+    for event in events {
+        let notification = Value::Object(vec![
+            ("jsonrpc".to_string(), "2.0".into()),
+            ("method".to_string(), "Event".into()),
+            ("params".to_string(), event.to_json()),
+        ]);
+        if writeln!(writer, "{notification}").is_err() {
+            return;
+        }
+    }
+}
+
+fn serve(stream: UnixStream, client: IpcClient, uids: &[u32]) {
+    let uid = match auth::socket_peer_uid(&stream) {
+        Ok(uid) if uid == 0 || uids.contains(&uid) => uid,
+        Ok(uid) => {
+            eprintln!("Refusing control socket connection from uid {uid}");
+            return;
+        }
+        Err(e) => {
+            eprintln!("Cannot tell who connected to the control socket: {e}");
+            return;
+        }
+    };
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    _ = writer.set_write_timeout(Some(WRITE_TIMEOUT));
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.by_ref().take(MAX_LINE_BYTES).read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) if !line.ends_with('\n') && line.len() as u64 == MAX_LINE_BYTES => {
+                eprintln!("Closing control socket connection that sent a line over {MAX_LINE_BYTES} bytes");
+                break;
+            }
+            Ok(_) => {}
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(id) = is_subscribe(&line) {
+            stream_events(writer, id, &client);
+            return;
+        }
+        let Some(reply) = handle_line(&line, &client, uid) else {
+            continue;
+        };
+        if writeln!(writer, "{reply}").is_err() {
+            break;
+        }
+    }
+}
+
+/// Creates the control socket and accepts connections on a separate thread,
+/// serving up to MAX_CONNECTIONS at once on a thread each. The socket is
+/// only accessible by root and the SocketUsers, and has to be created before
+/// dropping privileges.
+pub fn start(client: IpcClient, cfg: &IpcConfig) -> Result<()> {
+    let path = Path::new(SOCKET_PATH);
+    fs::create_dir_all(path.parent().unwrap())?;
+    _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    let uids: Arc<[u32]> = cfg.socket_uids().into();
+    // others are let in by their credentials rather than by the file mode
+    let mode = if uids.is_empty() { 0o600 } else { 0o666 };
+    fs::set_permissions(path, Permissions::from_mode(mode))?;
+    if !uids.is_empty() {
+        fs::set_permissions(path.parent().unwrap(), Permissions::from_mode(0o755))?;
+    }
+    let connections = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                eprintln!("Refusing control socket connection, {MAX_CONNECTIONS} are open already");
+                continue;
+            }
+            let (client, uids, connections) = (client.clone(), uids.clone(), connections.clone());
+            thread::spawn(move || {
+                serve(stream, client, &uids);
+                connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    Ok(())
+}
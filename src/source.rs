@@ -0,0 +1,459 @@
+use crate::activeapp::ActiveApp;
+use crate::audio::AudioOutput;
+use crate::audit;
+use crate::bluetooth::BluetoothBattery;
+use crate::caffeine::Caffeine;
+use crate::chargelimit::ChargeLimit;
+use crate::ci::Ci;
+use crate::config::ButtonConfig;
+use crate::disk::Disk;
+use crate::docker::Docker;
+use crate::homeassistant::HomeAssistant;
+use crate::inputmethod::InputMethod;
+use crate::ipc::Waker;
+use crate::locks::LockIndicator;
+use crate::mail::Mail;
+use crate::modifiers::Modifiers;
+use crate::mqtt::Mqtt;
+use crate::nightlight::NightLight;
+use crate::obs::Obs;
+use crate::plugin::Plugin;
+use crate::power;
+use crate::ratelimit;
+use crate::rss::Rss;
+use crate::runtime::{self, Task};
+use crate::script::Script;
+use crate::sensors::Sensors;
+use crate::stocks::Stocks;
+use crate::sun::Sun;
+use crate::typing::Typing;
+use crate::vumeter::VuMeter;
+use crate::webhook::Webhook;
+use crate::worldclock::WorldClock;
+use anyhow::Result;
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use std::{
+    future::Future,
+    process::Command,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const MAX_TICK: Duration = Duration::from_millis(250);
+
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Returns when something refreshing every `interval` is due next. Rather
+/// than exactly one interval from now, that is rounded up to a tick of
+/// at most a quarter of the interval, counted from a shared starting point,
+/// so that widgets due around the same time wake the main loop together.
+pub fn next_refresh(interval: Duration) -> Instant {
+    let epoch = *EPOCH.get_or_init(Instant::now);
+    let tick = (interval / 4).clamp(Duration::from_millis(1), MAX_TICK).as_millis();
+    let due = (Instant::now() + interval).duration_since(epoch).as_millis();
+    let aligned = due.div_ceil(tick) * tick;
+    epoch + Duration::from_millis(aligned as u64)
+}
+
+/// Something outside the daemon that decides what a button shows,
+/// like a script or a plugin.
+pub trait Source {
+    /// Returns the updates received since the last call, along with the
+    /// number of milliseconds until the source wants to be polled again.
+    /// Called from the main loop, after privileges have been dropped.
+    fn poll(&mut self, waker: &Waker) -> (Vec<Update>, i32);
+    fn touch(&mut self, pressed: bool);
+    /// What the button shows until the source sends something, when
+    /// neither Text nor Icon is set
+    fn label(&self) -> String {
+        String::new()
+    }
+}
+
+pub struct SourceEntry {
+    /// The key of a button's config that makes the source
+    pub key: &'static str,
+    /// Makes it from the value of the key and the rest of the button's config
+    pub build: fn(&toml::Value, &ButtonConfig) -> Result<Box<dyn Source>>,
+    /// Whether its buttons are hidden in privacy mode unless Sensitive is set
+    pub sensitive: bool,
+}
+
+const fn entry(key: &'static str, build: fn(&toml::Value, &ButtonConfig) -> Result<Box<dyn Source>>) -> SourceEntry {
+    SourceEntry { key, build, sensitive: false }
+}
+
+pub const SOURCES: &[SourceEntry] = &[
+    entry("Script", Script::build),
+    entry("Plugin", Plugin::build),
+    entry("HomeAssistant", HomeAssistant::build),
+    entry("Mqtt", Mqtt::build),
+    entry("Http", Webhook::build),
+    entry("Obs", Obs::build),
+    entry("Ci", Ci::build),
+    SourceEntry { key: "Mail", build: Mail::build, sensitive: true },
+    entry("Rss", Rss::build),
+    entry("Stocks", Stocks::build),
+    entry("WorldClock", WorldClock::build),
+    entry("Sun", Sun::build),
+    entry("NightLight", NightLight::build),
+    entry("Sensors", Sensors::build),
+    entry("Disk", Disk::build),
+    entry("AudioOutput", AudioOutput::build),
+    entry("VuMeter", VuMeter::build),
+    entry("ChargeLimit", ChargeLimit::build),
+    SourceEntry { key: "ActiveApp", build: ActiveApp::build, sensitive: true },
+    entry("Typing", Typing::build),
+    entry("Modifiers", Modifiers::build),
+    entry("LockIndicator", LockIndicator::build),
+    entry("InputMethod", InputMethod::build),
+    entry("BluetoothBattery", BluetoothBattery::build),
+    entry("Caffeine", Caffeine::build),
+    entry("Docker", Docker::build),
+];
+
+/// The first entry of SOURCES whose key `cfg` has, with its value
+pub fn find(cfg: &ButtonConfig) -> Option<(&'static SourceEntry, &toml::Value)> {
+    SOURCES
+        .iter()
+        .find_map(|entry| Some((entry, cfg.widgets.get(entry.key)?)))
+}
+
+/// Reads the config of a source from the value of its key
+pub fn parse<T: DeserializeOwned>(value: &toml::Value) -> Result<T> {
+    Ok(value.clone().try_into()?)
+}
+
+/// Like `parse`, for keys that may also be given just a string, taken as
+/// the `short` field of the table, e.g. `Time = "12hr"` for
+/// `Time = { Format = "12hr" }`
+pub fn parse_short<T: DeserializeOwned>(value: &toml::Value, short: &str) -> Result<T> {
+    match value {
+        toml::Value::String(_) => parse(&toml::Table::from_iter([(short.to_string(), value.clone())]).into()),
+        value => parse(value),
+    }
+}
+
+/// How often a source refreshes, RefreshIntervalMs or `default_ms`
+pub fn interval(cfg: &ButtonConfig, default_ms: u64) -> Duration {
+    Duration::from_millis(cfg.refresh_interval_ms.unwrap_or(default_ms))
+}
+
+pub enum Update {
+    Text(String),
+    /// An icon name, looked up like the Icon of a button, or a path
+    Icon(String),
+    /// Briefly highlights the button green when true and red when false,
+    /// to show the outcome of an action
+    Flash(bool),
+    /// Shows the button as turned on, e.g. while a toggle is active
+    Highlight(bool),
+    /// Colors the button to show a status, `None` goes back to normal
+    Tint(Option<Tint>),
+    /// Shows a small label in the corner of the button, e.g. a count
+    Badge(Option<String>),
+    /// Replaces the text with pieces of text in different colors
+    Segments(Vec<Segment>),
+    /// Adds a value to the button's sparkline, if it has one
+    Sample(f64),
+    /// Fills the button up to the given fraction like a level meter,
+    /// `None` removes it
+    Level(Option<f64>),
+}
+
+pub struct Segment {
+    pub text: String,
+    /// Colors the text, `None` uses the normal text color
+    pub tint: Option<Tint>,
+}
+
+#[derive(Clone, Copy)]
+pub enum Tint {
+    Green,
+    Yellow,
+    Red,
+}
+
+/// Work for a source that has to wait on the network or on other
+/// processes, and so has to run on its own thread.
+pub trait Job: Send + 'static {
+    /// The shortest interval the job may be refreshed at, to keep
+    /// configs from hammering servers or the machine
+    const MIN_INTERVAL: Duration = Duration::from_millis(100);
+    fn refresh(&mut self) -> Vec<Update>;
+    /// Returns whether the source should be refreshed right away.
+    fn touch(&mut self, pressed: bool) -> bool;
+    /// See `Source::label`
+    fn label(&self) -> String {
+        String::new()
+    }
+}
+
+/// Runs a `Job` on a background thread, refreshing it every `interval`,
+/// or less often while the power policy asks for it, but never more often
+/// than its `MIN_INTERVAL`.
+/// The thread is started on the first poll, after privileges were dropped,
+/// and stops once the button goes away.
+pub struct Background<J: Job> {
+    job: Option<J>,
+    interval: Duration,
+    touches: Option<Sender<bool>>,
+    updates: Option<Receiver<Update>>,
+}
+
+impl<J: Job> Background<J> {
+    pub fn new(job: J, interval: Duration) -> Background<J> {
+        Background {
+            job: Some(job),
+            interval: interval.max(J::MIN_INTERVAL),
+            touches: None,
+            updates: None,
+        }
+    }
+}
+
+fn run_job(
+    mut job: impl Job,
+    interval: Duration,
+    touches: Receiver<bool>,
+    updates: Sender<Update>,
+    waker: Waker,
+) {
+    loop {
+        for update in job.refresh() {
+            if updates.send(update).is_err() {
+                return;
+            }
+        }
+        waker.wake();
+        let deadline = next_refresh(power::scale(interval));
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match touches.recv_timeout(timeout) {
+                Ok(pressed) => {
+                    if job.touch(pressed) {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+impl<J: Job> Source for Background<J> {
+    fn poll(&mut self, waker: &Waker) -> (Vec<Update>, i32) {
+        if let Some(job) = self.job.take() {
+            let (touches_tx, touches_rx) = channel();
+            let (updates_tx, updates_rx) = channel();
+            let interval = self.interval;
+            let waker = waker.clone();
+            thread::spawn(move || run_job(job, interval, touches_rx, updates_tx, waker));
+            self.touches = Some(touches_tx);
+            self.updates = Some(updates_rx);
+        }
+        let updates = match &self.updates {
+            Some(updates) => updates.try_iter().collect(),
+            None => Vec::new(),
+        };
+        (updates, i32::MAX)
+    }
+    fn touch(&mut self, pressed: bool) {
+        if let Some(touches) = &self.touches {
+            _ = touches.send(pressed);
+        }
+    }
+    fn label(&self) -> String {
+        self.job.as_ref().map(J::label).unwrap_or_default()
+    }
+}
+
+/// A source that waits for what it shows as a future on the shared
+/// runtime, rather than on a thread of its own. It is split in two: the
+/// source itself stays on the main thread, where it turns data into updates
+/// and takes touches, while its poller lives on the runtime and waits for
+/// the data.
+pub trait AsyncSource: 'static {
+    /// What the poller hands over to the main thread
+    type Data: Send + 'static;
+    /// The half on the runtime thread, which may hold on to gio objects
+    type Poller: 'static;
+    /// Returns what sets up the poller on the runtime thread. Called on the
+    /// first poll, after privileges were dropped.
+    fn poller(&mut self) -> impl FnOnce() -> Self::Poller + Send + 'static;
+    /// Waits for the next data, `None` once there will be no more
+    fn poll_update(poller: &mut Self::Poller) -> impl Future<Output = Option<Self::Data>>;
+    /// Turns data into what the button shows
+    fn render_data(&mut self, data: Self::Data) -> Vec<Update>;
+    fn touch(&mut self, pressed: bool);
+    /// See `Source::label`
+    fn label(&self) -> String {
+        String::new()
+    }
+}
+
+/// Runs the poller of an `AsyncSource` on the runtime until the button
+/// goes away.
+pub struct Async<S: AsyncSource> {
+    source: S,
+    task: Option<Task>,
+    data: Option<Receiver<S::Data>>,
+}
+
+impl<S: AsyncSource> Async<S> {
+    pub fn new(source: S) -> Async<S> {
+        Async {
+            source,
+            task: None,
+            data: None,
+        }
+    }
+}
+
+impl<S: AsyncSource> Source for Async<S> {
+    fn poll(&mut self, waker: &Waker) -> (Vec<Update>, i32) {
+        if self.task.is_none() {
+            let (tx, rx) = channel();
+            let setup = self.source.poller();
+            let waker = waker.clone();
+            self.task = Some(runtime::spawn(move || async move {
+                let mut poller = setup();
+                while let Some(data) = S::poll_update(&mut poller).await {
+                    if tx.send(data).is_err() {
+                        return;
+                    }
+                    waker.wake();
+                }
+            }));
+            self.data = Some(rx);
+        }
+        let mut updates = Vec::new();
+        if let Some(data) = &self.data {
+            for data in data.try_iter() {
+                updates.extend(self.source.render_data(data));
+            }
+        }
+        (updates, i32::MAX)
+    }
+    fn touch(&mut self, pressed: bool) {
+        self.source.touch(pressed);
+    }
+    fn label(&self) -> String {
+        self.source.label()
+    }
+}
+
+/// Like a `Job`, but waits for the network or other processes as futures
+/// on the runtime, rather than on a thread of its own.
+pub trait AsyncJob: Send + 'static {
+    /// See `Job::MIN_INTERVAL`
+    const MIN_INTERVAL: Duration = Duration::from_millis(100);
+    fn refresh(&mut self) -> impl Future<Output = Vec<Update>>;
+    /// Returns whether the source should be refreshed right away.
+    fn touch(&mut self, pressed: bool) -> impl Future<Output = bool>;
+    /// See `Source::label`
+    fn label(&self) -> String {
+        String::new()
+    }
+}
+
+/// Runs an `AsyncJob` on the runtime, refreshing it like `Background` does
+/// a `Job`. The job moves to the runtime on the first poll, and touches are
+/// handed to it there.
+pub struct Periodic<J: AsyncJob> {
+    job: Option<J>,
+    label: String,
+    interval: Duration,
+    touches: Option<UnboundedSender<bool>>,
+}
+
+impl<J: AsyncJob> Periodic<J> {
+    pub fn new(job: J, interval: Duration) -> Periodic<J> {
+        Periodic {
+            label: job.label(),
+            job: Some(job),
+            interval: interval.max(J::MIN_INTERVAL),
+            touches: None,
+        }
+    }
+}
+
+/// The half of a `Periodic` on the runtime
+pub struct Schedule<J: AsyncJob> {
+    job: J,
+    interval: Duration,
+    touches: UnboundedReceiver<bool>,
+    /// When the job is refreshed next, right away at first
+    due: Instant,
+}
+
+impl<J: AsyncJob> AsyncSource for Periodic<J> {
+    type Data = Vec<Update>;
+    type Poller = Schedule<J>;
+
+    fn poller(&mut self) -> impl FnOnce() -> Schedule<J> + Send + 'static {
+        let (touches_tx, touches) = unbounded();
+        self.touches = Some(touches_tx);
+        let job = self.job.take().expect("the poller is only made once");
+        let interval = self.interval;
+        move || Schedule {
+            job,
+            interval,
+            touches,
+            due: Instant::now(),
+        }
+    }
+    async fn poll_update(schedule: &mut Schedule<J>) -> Option<Vec<Update>> {
+        loop {
+            let timeout = schedule.due.saturating_duration_since(Instant::now());
+            match glib::future_with_timeout(timeout, schedule.touches.next()).await {
+                Ok(Some(pressed)) => {
+                    if !schedule.job.touch(pressed).await {
+                        continue;
+                    }
+                }
+                Ok(None) => return None,
+                Err(_) => {}
+            }
+            let updates = schedule.job.refresh().await;
+            schedule.due = next_refresh(power::scale(schedule.interval));
+            return Some(updates);
+        }
+    }
+    fn render_data(&mut self, updates: Vec<Update>) -> Vec<Update> {
+        updates
+    }
+    fn touch(&mut self, pressed: bool) {
+        if let Some(touches) = &self.touches {
+            _ = touches.unbounded_send(pressed);
+        }
+    }
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+}
+
+const DEFAULT_OPEN_COMMAND: &str = "xdg-open";
+
+/// Runs `open_command`, or xdg-open, with the given URL without waiting for it.
+pub fn open_url(open_command: Option<&str>, url: &str) {
+    let open = open_command.unwrap_or(DEFAULT_OPEN_COMMAND);
+    if !ratelimit::allow_command() {
+        return;
+    }
+    // links come from what buttons show
+    audit::record("Opened a link", None);
+    match Command::new("sh").args(["-c", &format!("{open} \"$1\""), "sh", url]).spawn() {
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => eprintln!("Failed to run {open}: {e}"),
+    }
+}
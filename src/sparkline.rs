@@ -0,0 +1,110 @@
+use cairo::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// A small graph of the most recent samples of a value, drawn behind the
+// contents of a button. Sources feed it with `Update::Sample`, the button
+// keeps the history so that sources don't have to.
+
+const DEFAULT_LENGTH: usize = 30;
+const LINE_WIDTH: f64 = 2.0;
+const ALPHA: f64 = 0.4;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SparklineStyle {
+    #[default]
+    Line,
+    Filled,
+    Bars,
+}
+
+/// The `Sparkline` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SparklineConfig {
+    pub length: Option<usize>,
+    pub style: Option<SparklineStyle>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+pub struct Sparkline {
+    samples: VecDeque<f64>,
+    length: usize,
+    style: SparklineStyle,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Sparkline {
+    pub fn new(cfg: SparklineConfig) -> Sparkline {
+        let length = cfg.length.unwrap_or(DEFAULT_LENGTH).max(2);
+        Sparkline {
+            samples: VecDeque::with_capacity(length),
+            length,
+            style: cfg.style.unwrap_or_default(),
+            min: cfg.min,
+            max: cfg.max,
+        }
+    }
+    pub fn push(&mut self, sample: f64) {
+        if !sample.is_finite() {
+            return;
+        }
+        if self.samples.len() == self.length {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+    /// Returns the lowest and highest value of the graph. Unless fixed in
+    /// the config they follow the samples, starting from zero.
+    fn range(&self) -> (f64, f64) {
+        let lowest = self.samples.iter().copied().fold(0.0, f64::min);
+        let highest = self.samples.iter().copied().fold(0.0, f64::max);
+        let min = self.min.unwrap_or(lowest);
+        let max = self.max.unwrap_or(highest);
+        if max > min {
+            (min, max)
+        } else {
+            (min, min + 1.0)
+        }
+    }
+    /// Draws the graph in the given rectangle, newest sample on the right
+    pub fn draw(&self, c: &Context, left: f64, top: f64, width: f64, height: f64, color: [f64; 3]) {
+        if self.samples.is_empty() {
+            return;
+        }
+        let (min, max) = self.range();
+        let bottom = top + height;
+        let y = |sample: f64| bottom - (sample.clamp(min, max) - min) / (max - min) * height;
+        let step = width / (self.length - 1) as f64;
+        let x = |i: usize| left + width - (self.samples.len() - 1 - i) as f64 * step;
+        c.save().unwrap();
+        c.set_source_rgba(color[0], color[1], color[2], ALPHA);
+        match self.style {
+            SparklineStyle::Line | SparklineStyle::Filled => {
+                for (i, sample) in self.samples.iter().enumerate() {
+                    c.line_to(x(i), y(*sample));
+                }
+                if self.style == SparklineStyle::Filled {
+                    c.line_to(x(self.samples.len() - 1), bottom);
+                    c.line_to(x(0), bottom);
+                    c.close_path();
+                    c.fill().unwrap();
+                } else {
+                    c.set_line_width(LINE_WIDTH);
+                    c.stroke().unwrap();
+                }
+            }
+            SparklineStyle::Bars => {
+                let bar_width = (width / self.length as f64 - 1.0).max(1.0);
+                for (i, sample) in self.samples.iter().enumerate() {
+                    let top = y(*sample);
+                    c.rectangle(x(i) - bar_width, top, bar_width, bottom - top);
+                }
+                c.fill().unwrap();
+            }
+        }
+        c.restore().unwrap();
+    }
+}
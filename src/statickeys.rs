@@ -0,0 +1,58 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use serde::{Deserialize, Serialize};
+
+// A fallback where the bar is a plain row of esc and F1 to F12, laid out
+// like the keys of a keyboard without a Touch Bar, with esc half again as
+// wide. Whatever the layers in the config say, touches press the key of the
+// zone they land in, the zones never move and nothing on the bar changes:
+// it shows nothing at all, or only faint labels. Both layers are the same,
+// and nothing stands in for them, so that the keys are always where the
+// fingers remember them.
+
+const LABEL_ALPHA: f64 = 0.3;
+const KEYS: [&str; 13] = [
+    "Esc", "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+];
+
+/// The `StaticFnKeys` setting
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+pub enum StaticFnKeys {
+    #[default]
+    Off,
+    Blank,
+    Labels,
+}
+
+impl StaticFnKeys {
+    pub fn is_on(self) -> bool {
+        self != StaticFnKeys::Off
+    }
+    /// How strongly the buttons are drawn
+    pub fn alpha(self) -> f64 {
+        match self {
+            StaticFnKeys::Off => 1.0,
+            StaticFnKeys::Blank => 0.0,
+            StaticFnKeys::Labels => LABEL_ALPHA,
+        }
+    }
+}
+
+/// The keys of both layers
+pub fn layer_keys() -> Vec<ButtonConfig> {
+    KEYS.iter()
+        .filter_map(|key| {
+            let mut button = toml::Table::new();
+            let label = if *key == "Esc" { i18n::tr("esc") } else { key.to_string() };
+            button.insert("Text".into(), label.into());
+            button.insert("Action".into(), (*key).into());
+            // in halves of a key, for the wider esc
+            let stretch = if *key == "Esc" { 3 } else { 2 };
+            button.insert("Stretch".into(), stretch.into());
+            button
+                .try_into()
+                .map_err(|e| eprintln!("StaticFnKeys: cannot make the {key} key: {e}"))
+                .ok()
+        })
+        .collect()
+}
@@ -0,0 +1,172 @@
+use crate::config::ButtonConfig;
+use crate::http;
+use crate::i18n;
+use crate::json::Value;
+use crate::runtime;
+use crate::source::{self, Async, AsyncJob, Periodic, Segment, Source, Tint, Update};
+use anyhow::{anyhow, Result};
+use chrono::Locale;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+
+// Shows prices for a list of symbols. There is no free quote API worth
+// depending on, so prices come from an endpoint of the user's choosing,
+// picked out of its JSON answer, or from a command.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 60 * 1000;
+
+/// The `Stocks` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StocksConfig {
+    pub symbols: Vec<String>,
+    pub url: Option<String>,
+    pub headers: Option<HashMap<String, String>>,
+    pub price: Option<String>,
+    pub change: Option<String>,
+    pub command: Option<String>,
+    pub locale: Option<String>,
+}
+
+/// Looks up a dot separated path like `quote.0.price` in a JSON value,
+/// accepting numbers given as strings, as many APIs do.
+fn lookup(value: &Value, path: &str) -> Option<f64> {
+    let mut value = value;
+    for key in path.split('.').filter(|key| !key.is_empty()) {
+        value = match (value, key.parse::<usize>()) {
+            (Value::Array(items), Ok(i)) => items.get(i)?,
+            (value, _) => value.get(key)?,
+        };
+    }
+    match value {
+        Value::Number(n) => Some(*n),
+        Value::String(s) => s.trim().trim_end_matches('%').parse().ok(),
+        _ => None,
+    }
+}
+
+fn format_price(price: f64, locale: Locale) -> String {
+    let decimals = if price >= 1000.0 {
+        0
+    } else if price >= 1.0 {
+        2
+    } else {
+        4
+    };
+    i18n::decimal(price, decimals, locale)
+}
+
+struct Quote {
+    price: f64,
+    /// Change in percent, if the source knows it
+    change: Option<f64>,
+}
+
+pub struct Stocks {
+    cfg: StocksConfig,
+    last_prices: HashMap<String, f64>,
+    locale: Locale,
+}
+
+impl Stocks {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        let stocks = Stocks::new(source::parse(value)?)?;
+        Ok(Box::new(Async::new(Periodic::new(stocks, source::interval(cfg, DEFAULT_REFRESH_INTERVAL_MS)))))
+    }
+    pub fn new(cfg: StocksConfig) -> Result<Stocks> {
+        if cfg.url.is_none() && cfg.command.is_none() {
+            return Err(anyhow!("Stocks needs either Url or Command"));
+        }
+        if cfg.url.is_some() && cfg.price.is_none() {
+            return Err(anyhow!("Stocks needs Price to find the price in the answer from Url"));
+        }
+        Ok(Stocks {
+            locale: i18n::resolve(cfg.locale.as_deref()),
+            cfg,
+            last_prices: HashMap::new(),
+        })
+    }
+    async fn quote(&self, symbol: &str) -> Result<Quote> {
+        if let Some(url) = &self.cfg.url {
+            let url = url.replace("{symbol}", &http::encode(symbol));
+            let headers: Vec<(&str, &str)> = self
+                .cfg
+                .headers
+                .iter()
+                .flatten()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let response = http::request_async("GET", &url, &headers, None).await?;
+            if !response.is_success() {
+                return Err(anyhow!("HTTP {}", response.status));
+            }
+            let answer = Value::parse(&response.body)?;
+            let price_path = self.cfg.price.as_deref().unwrap();
+            return Ok(Quote {
+                price: lookup(&answer, price_path)
+                    .ok_or(anyhow!("no price at {price_path} in the answer"))?,
+                change: self.cfg.change.as_deref().and_then(|path| lookup(&answer, path)),
+            });
+        }
+        // the command prints the price, optionally followed by the change in percent
+        let command = self.cfg.command.as_deref().unwrap();
+        let stdout = runtime::shell(command, &[symbol]).await?;
+        let mut words = stdout.split_whitespace();
+        Ok(Quote {
+            price: words
+                .next()
+                .and_then(|price| price.parse().ok())
+                .ok_or(anyhow!("{command} did not print a price"))?,
+            change: words
+                .next()
+                .and_then(|change| change.trim_end_matches('%').parse().ok()),
+        })
+    }
+}
+
+impl AsyncJob for Stocks {
+    const MIN_INTERVAL: Duration = Duration::from_millis(5000);
+
+    async fn refresh(&mut self) -> Vec<Update> {
+        let mut segments = Vec::new();
+        for symbol in &self.cfg.symbols {
+            if !segments.is_empty() {
+                segments.push(Segment {
+                    text: "  ".to_string(),
+                    tint: None,
+                });
+            }
+            let (text, tint) = match self.quote(symbol).await {
+                Ok(quote) => {
+                    // without a change from the source, compare to the last refresh
+                    let change = quote.change.or_else(|| {
+                        let last = self.last_prices.get(symbol)?;
+                        Some((quote.price - last) / last * 100.0)
+                    });
+                    self.last_prices.insert(symbol.clone(), quote.price);
+                    let price = format_price(quote.price, self.locale);
+                    match change {
+                        Some(change) if change > 0.0 => {
+                            let change = i18n::decimal(change, 1, self.locale);
+                            (format!("{symbol} {price} ▲{change}%"), Some(Tint::Green))
+                        }
+                        Some(change) if change < 0.0 => {
+                            let change = i18n::decimal(-change, 1, self.locale);
+                            (format!("{symbol} {price} ▼{change}%"), Some(Tint::Red))
+                        }
+                        _ => (format!("{symbol} {price}"), None),
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to get the price of {symbol}: {e:#}");
+                    (format!("{symbol} ?"), None)
+                }
+            };
+            segments.push(Segment { text, tint });
+        }
+        vec![Update::Segments(segments)]
+    }
+    async fn touch(&mut self, pressed: bool) -> bool {
+        pressed
+    }
+}
@@ -0,0 +1,283 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::ipc::Waker;
+use crate::source::{self, Source, Update};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+// Counts down to the next sunrise, sunset or prayer time at the configured
+// place, worked out from where the sun is, with the formulas and angles
+// used by praytimes.org. Times are good to a minute or so, prayer times
+// follow the calculation method, which differ on how dark it is at Fajr and
+// Isha. Events that do not happen, like sunset in polar summer, are skipped.
+
+const DEFAULT_FORMAT: &str = "{event} {in}";
+/// How far below the horizon the sun's upper edge is at sunrise and sunset,
+/// with refraction
+const HORIZON: f64 = 0.833;
+const CIVIL_TWILIGHT: f64 = 6.0;
+const JD_OF_FIXED: f64 = 1721424.5;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub enum SunEvent {
+    Dawn,
+    Sunrise,
+    Noon,
+    Sunset,
+    Dusk,
+    Fajr,
+    Dhuhr,
+    Asr,
+    Maghrib,
+    Isha,
+}
+
+/// Who decides on the Fajr and Isha angles
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+pub enum PrayerMethod {
+    /// Muslim World League
+    #[default]
+    Mwl,
+    /// Islamic Society of North America
+    Isna,
+    /// Egyptian General Authority of Survey
+    Egypt,
+    /// Umm al-Qura University, Makkah
+    Makkah,
+    /// University of Islamic Sciences, Karachi
+    Karachi,
+}
+
+/// How long the shadow is at Asr
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+pub enum AsrShadow {
+    /// As long as the object, the Shafiʻi, Maliki and Hanbali schools
+    #[default]
+    Standard,
+    /// Twice as long
+    Hanafi,
+}
+
+/// The `Sun` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SunConfig {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// What to count down to, sunrise and sunset by default
+    pub events: Option<Vec<SunEvent>>,
+    pub method: Option<PrayerMethod>,
+    pub asr: Option<AsrShadow>,
+    /// With {event}, {time} and {in} for what comes next, when and how
+    /// long until then
+    pub format: Option<String>,
+}
+
+impl SunEvent {
+    fn label(self) -> String {
+        i18n::tr(match self {
+            SunEvent::Dawn => "Dawn",
+            SunEvent::Sunrise => "Sunrise",
+            SunEvent::Noon => "Noon",
+            SunEvent::Sunset => "Sunset",
+            SunEvent::Dusk => "Dusk",
+            SunEvent::Fajr => "Fajr",
+            SunEvent::Dhuhr => "Dhuhr",
+            SunEvent::Asr => "Asr",
+            SunEvent::Maghrib => "Maghrib",
+            SunEvent::Isha => "Isha",
+        })
+    }
+}
+
+/// Isha is either an angle or, for Umm al-Qura, minutes after Maghrib
+enum Isha {
+    Angle(f64),
+    Minutes(f64),
+}
+
+impl PrayerMethod {
+    fn angles(self) -> (f64, Isha) {
+        match self {
+            PrayerMethod::Mwl => (18.0, Isha::Angle(17.0)),
+            PrayerMethod::Isna => (15.0, Isha::Angle(15.0)),
+            PrayerMethod::Egypt => (19.5, Isha::Angle(17.5)),
+            PrayerMethod::Makkah => (18.5, Isha::Minutes(90.0)),
+            PrayerMethod::Karachi => (18.0, Isha::Angle(18.0)),
+        }
+    }
+}
+
+fn sin_deg(degrees: f64) -> f64 {
+    (degrees * PI / 180.0).sin()
+}
+
+fn cos_deg(degrees: f64) -> f64 {
+    (degrees * PI / 180.0).cos()
+}
+
+fn tan_deg(degrees: f64) -> f64 {
+    (degrees * PI / 180.0).tan()
+}
+
+/// The sun's declination in degrees and the equation of time in hours
+fn sun_position(jd: f64) -> (f64, f64) {
+    let d = jd - 2451545.0;
+    let g = 357.529 + 0.98560028 * d;
+    let q = 280.459 + 0.98564736 * d;
+    let l = q + 1.915 * sin_deg(g) + 0.020 * sin_deg(2.0 * g);
+    let e = 23.439 - 0.00000036 * d;
+    let ra = (cos_deg(e) * sin_deg(l)).atan2(cos_deg(l)).to_degrees() / 15.0;
+    let declination = (sin_deg(e) * sin_deg(l)).asin().to_degrees();
+    let equation = q / 15.0 - ra.rem_euclid(24.0);
+    (declination, (equation + 12.0).rem_euclid(24.0) - 12.0)
+}
+
+struct Place {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl Place {
+    /// Hours after midnight UTC that the sun crosses the meridian
+    fn noon(&self, jd: f64, hours: f64) -> f64 {
+        let (_, equation) = sun_position(jd + hours / 24.0);
+        12.0 - equation - self.longitude / 15.0
+    }
+    /// Hours after midnight UTC that the sun is at `altitude`, before noon
+    /// if `morning`, refined once with the sun where it is by then
+    fn at_altitude(&self, jd: f64, altitude: impl Fn(f64) -> f64, morning: bool) -> Option<f64> {
+        let mut hours = if morning { 6.0 } else { 18.0 } - self.longitude / 15.0;
+        for _ in 0..2 {
+            let (declination, _) = sun_position(jd + hours / 24.0);
+            let cos_hour_angle = (sin_deg(altitude(declination)) - sin_deg(declination) * sin_deg(self.latitude))
+                / (cos_deg(declination) * cos_deg(self.latitude));
+            if !(-1.0..=1.0).contains(&cos_hour_angle) {
+                return None;
+            }
+            let hour_angle = cos_hour_angle.acos().to_degrees() / 15.0;
+            let noon = self.noon(jd, hours);
+            hours = if morning { noon - hour_angle } else { noon + hour_angle };
+        }
+        Some(hours)
+    }
+    fn below_horizon(&self, jd: f64, depression: f64, morning: bool) -> Option<f64> {
+        self.at_altitude(jd, |_| -depression, morning)
+    }
+    /// Hours after midnight UTC of `event` on the day starting at `jd`
+    fn event(&self, jd: f64, event: SunEvent, method: PrayerMethod, asr: AsrShadow) -> Option<f64> {
+        let (fajr, isha) = method.angles();
+        match event {
+            SunEvent::Dawn => self.below_horizon(jd, CIVIL_TWILIGHT, true),
+            SunEvent::Sunrise => self.below_horizon(jd, HORIZON, true),
+            SunEvent::Noon => Some(self.noon(jd, 12.0 - self.longitude / 15.0)),
+            // a little after noon, once the sun is past the meridian
+            SunEvent::Dhuhr => Some(self.noon(jd, 12.0 - self.longitude / 15.0) + 1.0 / 60.0),
+            SunEvent::Sunset | SunEvent::Maghrib => self.below_horizon(jd, HORIZON, false),
+            SunEvent::Dusk => self.below_horizon(jd, CIVIL_TWILIGHT, false),
+            SunEvent::Fajr => self.below_horizon(jd, fajr, true),
+            SunEvent::Asr => {
+                let factor = match asr {
+                    AsrShadow::Standard => 1.0,
+                    AsrShadow::Hanafi => 2.0,
+                };
+                let altitude = |declination: f64| {
+                    (1.0 / (factor + tan_deg((self.latitude - declination).abs())))
+                        .atan()
+                        .to_degrees()
+                };
+                self.at_altitude(jd, altitude, false)
+            }
+            SunEvent::Isha => match isha {
+                Isha::Angle(angle) => self.below_horizon(jd, angle, false),
+                Isha::Minutes(minutes) => Some(self.below_horizon(jd, HORIZON, false)? + minutes / 60.0),
+            },
+        }
+    }
+}
+
+pub struct Sun {
+    place: Place,
+    events: Vec<SunEvent>,
+    method: PrayerMethod,
+    asr: AsrShadow,
+    format: String,
+    shown: String,
+}
+
+impl Sun {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(Sun::new(source::parse(value)?)?))
+    }
+    pub fn new(cfg: SunConfig) -> Result<Sun> {
+        if !(-90.0..=90.0).contains(&cfg.latitude) || !(-180.0..=180.0).contains(&cfg.longitude) {
+            return Err(anyhow!("Latitude and Longitude must be in degrees, north and east"));
+        }
+        let events = cfg.events.unwrap_or(vec![SunEvent::Sunrise, SunEvent::Sunset]);
+        if events.is_empty() {
+            return Err(anyhow!("Sun needs at least one event in Events"));
+        }
+        Ok(Sun {
+            place: Place {
+                latitude: cfg.latitude,
+                longitude: cfg.longitude,
+            },
+            events,
+            method: cfg.method.unwrap_or_default(),
+            asr: cfg.asr.unwrap_or_default(),
+            format: cfg.format.unwrap_or(DEFAULT_FORMAT.to_string()),
+            shown: String::new(),
+        })
+    }
+    /// The first of the events after `now`, looking up to a few days
+    /// ahead, for when the sun stays up or down
+    fn next_event(&self, now: DateTime<Utc>) -> Option<(SunEvent, DateTime<Utc>)> {
+        let today = now.with_timezone(&Local).date_naive();
+        (0..3)
+            .filter_map(|days| today.checked_add_signed(ChronoDuration::days(days)))
+            .find_map(|date: NaiveDate| {
+                let midnight = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?);
+                let jd = date.num_days_from_ce() as f64 + JD_OF_FIXED;
+                self.events
+                    .iter()
+                    .filter_map(|&event| {
+                        let hours = self.place.event(jd, event, self.method, self.asr)?;
+                        let at = midnight + ChronoDuration::seconds((hours * 3600.0) as i64);
+                        (at > now).then_some((event, at))
+                    })
+                    .min_by_key(|(_, at)| *at)
+            })
+    }
+}
+
+impl Source for Sun {
+    fn poll(&mut self, _waker: &Waker) -> (Vec<Update>, i32) {
+        let now = Utc::now();
+        let ms_to_minute = 60_000 - (now.timestamp_millis() % 60_000) as i32;
+        let (text, next_poll) = match self.next_event(now) {
+            Some((event, at)) => {
+                // counted to the minute, rounding up, so it changes when a
+                // whole minute less is left rather than on the clock's minute
+                let ms_left = (at - now).num_milliseconds();
+                let minutes = (ms_left + 59_999) / 60_000;
+                let text = self
+                    .format
+                    .replace("{event}", &event.label())
+                    .replace("{time}", &at.with_timezone(&Local).format("%H:%M").to_string())
+                    .replace("{in}", &format!("{}:{:02}", minutes / 60, minutes % 60));
+                let ms_to_change = ((ms_left - 1).rem_euclid(60_000) + 1) as i32;
+                (text, ms_to_change.min(ms_to_minute))
+            }
+            None => (i18n::tr("N/A"), ms_to_minute),
+        };
+        let mut updates = Vec::new();
+        if text != self.shown {
+            self.shown = text.clone();
+            updates.push(Update::Text(text));
+        }
+        (updates, next_poll)
+    }
+    fn touch(&mut self, _pressed: bool) {}
+}
@@ -0,0 +1,79 @@
+use cairo::Context;
+use pango::{Direction, FontDescription, Layout};
+use serde::{Deserialize, Serialize};
+
+// cairo's toy text API draws codepoints one after another in logical order,
+// so right-to-left labels would come out backwards and Arabic letters in
+// their isolated forms. Labels are laid out with pango instead, which shapes
+// them with HarfBuzz and reorders runs following the Unicode bidi algorithm.
+// The font size and options are taken from the cairo context.
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TextDirection {
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+/// Lays out `text` in `font` at the size of `c`'s current font. `direction`
+/// sets the paragraph direction, `Auto` picks it from the first strongly
+/// directional character.
+pub fn layout(c: &Context, font: &FontDescription, text: &str, direction: TextDirection) -> Layout {
+    let layout = pangocairo::functions::create_layout(c);
+    let context = layout.context();
+    if let Ok(options) = c.font_options() {
+        pangocairo::functions::context_set_font_options(&context, Some(&options));
+    }
+    match direction {
+        TextDirection::Auto => {}
+        TextDirection::Ltr => context.set_base_dir(Direction::Ltr),
+        TextDirection::Rtl => context.set_base_dir(Direction::Rtl),
+    }
+    layout.set_auto_dir(direction == TextDirection::Auto);
+    layout.context_changed();
+    let mut font = font.clone();
+    font.set_absolute_size(c.font_matrix().xx() * pango::SCALE as f64);
+    layout.set_font_description(Some(&font));
+    layout.set_text(text);
+    layout
+}
+
+/// The width and height of what `layout` draws, like `TextExtents`
+pub fn ink_size(layout: &Layout) -> (f64, f64) {
+    let (ink, _) = layout.extents();
+    (
+        ink.width() as f64 / pango::SCALE as f64,
+        ink.height() as f64 / pango::SCALE as f64,
+    )
+}
+
+/// How far what `layout` draws reaches above its baseline, like the
+/// negated `y_bearing` of `TextExtents`
+pub fn ascent(layout: &Layout) -> f64 {
+    let (ink, _) = layout.extents();
+    (layout.baseline() - ink.y()) as f64 / pango::SCALE as f64
+}
+
+/// Draws `layout` like `show_text`, with what it draws starting at the
+/// current point and its baseline on it
+pub fn show(c: &Context, layout: &Layout) {
+    let (ink, _) = layout.extents();
+    let (x, y) = c.current_point().unwrap();
+    c.move_to(
+        x - ink.x() as f64 / pango::SCALE as f64,
+        y - layout.baseline() as f64 / pango::SCALE as f64,
+    );
+    pangocairo::functions::show_layout(c, layout);
+}
+
+/// Shortens text to at most `max_length` characters, ending it with an ellipsis
+pub fn truncate(text: &str, max_length: usize) -> String {
+    if text.chars().count() <= max_length {
+        return text.to_string();
+    }
+    let mut out: String = text.chars().take(max_length.saturating_sub(1)).collect();
+    out.truncate(out.trim_end().len());
+    out.push('…');
+    out
+}
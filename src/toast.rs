@@ -0,0 +1,78 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Short messages shown across the whole bar over IPC, e.g. "Build finished",
+// covering the layer until they time out or are tapped away. Toasts sent
+// while one is up wait their turn instead of replacing it.
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(3000);
+const MAX_QUEUED: usize = 16;
+
+pub struct Toast {
+    pub text: String,
+    /// Icon name or path, shown next to the text
+    pub icon: Option<String>,
+    pub timeout: Duration,
+}
+
+impl Toast {
+    /// A timeout of 0 uses the default
+    pub fn new(text: String, icon: Option<String>, timeout_ms: u64) -> Toast {
+        let timeout = match timeout_ms {
+            0 => DEFAULT_TIMEOUT,
+            ms => Duration::from_millis(ms),
+        };
+        Toast { text, icon, timeout }
+    }
+}
+
+#[derive(Default)]
+pub struct ToastQueue {
+    queue: VecDeque<Toast>,
+    /// The toast being shown and until when
+    shown: Option<(Toast, Instant)>,
+}
+
+impl ToastQueue {
+    pub fn push(&mut self, toast: Toast) -> Result<(), String> {
+        if self.queue.len() >= MAX_QUEUED {
+            return Err("too many toasts queued".to_string());
+        }
+        self.queue.push_back(toast);
+        Ok(())
+    }
+    /// Moves on to the next toast once the shown one timed out. Returns
+    /// whether what is shown changed, and the number of milliseconds until
+    /// it needs to be called again.
+    pub fn update(&mut self) -> (bool, i32) {
+        let now = Instant::now();
+        let mut changed = false;
+        if self.shown.as_ref().is_some_and(|(_, until)| now >= *until) {
+            self.shown = None;
+            changed = true;
+        }
+        if self.shown.is_none() {
+            if let Some(toast) = self.queue.pop_front() {
+                let until = now + toast.timeout;
+                self.shown = Some((toast, until));
+                changed = true;
+            }
+        }
+        let ms_left = match &self.shown {
+            Some((_, until)) => (*until - now).as_millis() as i32 + 1,
+            None => i32::MAX,
+        };
+        (changed, ms_left)
+    }
+    pub fn current(&self) -> Option<&Toast> {
+        self.shown.as_ref().map(|(toast, _)| toast)
+    }
+    /// Takes the shown toast down early, the next one follows on the next update
+    pub fn dismiss(&mut self) {
+        if let Some((_, until)) = &mut self.shown {
+            *until = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::config::ButtonConfig;
+use crate::i18n;
+use crate::ipc::Waker;
+use crate::modifiers::{open_keyboards, Modifier};
+use crate::power;
+use crate::source::{self, Source, Update};
+use anyhow::Result;
+use input_linux::EventKind;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{channel, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Shows how fast the user is typing, in words per minute over the last
+// half minute, and feeds it to the button's sparkline if it has one. Only
+// the time of each key press is kept, never which key it was, and nothing
+// outlives the daemon. Tapping the button stops counting until tapped again.
+
+pub const DEFAULT_REFRESH_INTERVAL_MS: u64 = 1000;
+const MIN_REFRESH_INTERVAL_MS: u64 = 250;
+const WINDOW: Duration = Duration::from_secs(30);
+/// The usual definition of a word for typing speed
+const CHARS_PER_WORD: f64 = 5.0;
+const DEFAULT_DEVICE: &str = "Internal Keyboard";
+
+/// The `Typing` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TypingConfig {
+    pub device: Option<String>,
+    pub format: Option<String>,
+}
+
+pub struct Typing {
+    cfg: TypingConfig,
+    interval: Duration,
+    presses: Option<Receiver<Instant>>,
+    recent: VecDeque<Instant>,
+    paused: bool,
+    next_update: Instant,
+}
+
+impl Typing {
+    pub fn build(value: &toml::Value, cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(Typing::new(source::parse(value)?, cfg.refresh_interval_ms)))
+    }
+    pub fn new(cfg: TypingConfig, interval_ms: Option<u64>) -> Typing {
+        let interval_ms = interval_ms
+            .unwrap_or(DEFAULT_REFRESH_INTERVAL_MS)
+            .max(MIN_REFRESH_INTERVAL_MS);
+        Typing {
+            cfg,
+            interval: Duration::from_millis(interval_ms),
+            presses: None,
+            recent: VecDeque::new(),
+            paused: false,
+            next_update: Instant::now(),
+        }
+    }
+    fn start(&mut self) {
+        let name = self.cfg.device.as_deref().unwrap_or(DEFAULT_DEVICE);
+        let keyboards = open_keyboards();
+        let named: Vec<_> = keyboards
+            .iter()
+            .map(|device| String::from_utf8_lossy(&device.device_name().unwrap_or_default()).contains(name))
+            .collect();
+        // without the keyboard asked for, count them all
+        let any_named = named.contains(&true);
+        let (tx, rx) = channel();
+        for (device, named) in keyboards.into_iter().zip(named) {
+            if any_named && !named {
+                continue;
+            }
+            let tx = tx.clone();
+            // the count is shown on the next refresh, no need to wake up for every key
+            thread::spawn(move || {
+                while let Ok(event) = device.read_input_event() {
+                    if event.kind != EventKind::Key
+                        || event.value != 1
+                        || Modifier::from_key(event.code).is_some()
+                    {
+                        continue;
+                    }
+                    if tx.send(Instant::now()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        self.presses = Some(rx);
+    }
+}
+
+impl Source for Typing {
+    fn poll(&mut self, _waker: &Waker) -> (Vec<Update>, i32) {
+        if self.presses.is_none() {
+            self.start();
+        }
+        let now = Instant::now();
+        // drained even while paused, so that nothing typed then is counted later
+        let presses = self.presses.as_ref().unwrap().try_iter();
+        if self.paused {
+            presses.for_each(drop);
+        } else {
+            self.recent.extend(presses);
+        }
+        if now < self.next_update {
+            return (Vec::new(), (self.next_update - now).as_millis() as i32);
+        }
+        self.next_update = source::next_refresh(power::scale(self.interval));
+        let ms_left = (self.next_update - now).as_millis() as i32;
+        if self.paused {
+            return (vec![Update::Text(i18n::tr("paused"))], ms_left);
+        }
+        while self.recent.front().is_some_and(|t| now - *t > WINDOW) {
+            self.recent.pop_front();
+        }
+        let wpm = self.recent.len() as f64 / CHARS_PER_WORD * (60.0 / WINDOW.as_secs_f64());
+        let format = self.cfg.format.as_deref().unwrap_or("{} wpm");
+        let text = format.replace("{}", &format!("{wpm:.0}"));
+        (vec![Update::Text(text), Update::Sample(wpm)], ms_left)
+    }
+    fn touch(&mut self, pressed: bool) {
+        if pressed {
+            self.paused = !self.paused;
+            self.recent.clear();
+            self.next_update = Instant::now();
+        }
+    }
+}
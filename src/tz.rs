@@ -0,0 +1,280 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, NaiveDate};
+use std::fs;
+
+// Reads time zones from the system's zoneinfo database (TZif files, see
+// tzfile(5)), as chrono only knows about the local zone. Times after the
+// last transition in a file are covered by the POSIX TZ rule at its end,
+// which is all recent "slim" zoneinfo files have.
+
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+/// When daylight saving time starts or ends, see tzset(3)
+#[derive(Debug, Clone, Copy)]
+enum Date {
+    /// Jn: day 1 to 365, February 29th is never counted
+    Julian(u32),
+    /// n: day 0 to 365, counting February 29th in leap years
+    ZeroBased(u32),
+    /// Mm.w.d: day d (0 is Sunday) of week w (5 is the last) of month m
+    MonthWeekDay(u32, u32, u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rule {
+    start: (Date, i64),
+    end: (Date, i64),
+}
+
+/// A POSIX TZ string such as `CET-1CEST,M3.5.0,M10.5.0/3`.
+/// Offsets are stored as seconds east of UTC.
+#[derive(Debug, Clone, Copy)]
+struct PosixTz {
+    std_offset: i64,
+    dst: Option<(i64, Rule)>,
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn name(&mut self) -> Option<()> {
+        if self.eat(b'<') {
+            while !self.eat(b'>') {
+                self.peek()?;
+                self.pos += 1;
+            }
+        } else {
+            let start = self.pos;
+            while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                self.pos += 1;
+            }
+            if self.pos - start < 3 {
+                return None;
+            }
+        }
+        Some(())
+    }
+    fn number(&mut self) -> Option<i64> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.pos]).ok()?.parse().ok()
+    }
+    /// [+-]hh[:mm[:ss]] in seconds
+    fn time(&mut self) -> Option<i64> {
+        let sign = if self.eat(b'-') {
+            -1
+        } else {
+            self.eat(b'+');
+            1
+        };
+        let mut seconds = self.number()? * 3600;
+        if self.eat(b':') {
+            seconds += self.number()? * 60;
+            if self.eat(b':') {
+                seconds += self.number()?;
+            }
+        }
+        Some(sign * seconds)
+    }
+    fn date(&mut self) -> Option<(Date, i64)> {
+        let date = if self.eat(b'J') {
+            Date::Julian(self.number()? as u32)
+        } else if self.eat(b'M') {
+            let month = self.number()? as u32;
+            self.eat(b'.').then_some(())?;
+            let week = self.number()? as u32;
+            self.eat(b'.').then_some(())?;
+            Date::MonthWeekDay(month, week, self.number()? as u32)
+        } else {
+            Date::ZeroBased(self.number()? as u32)
+        };
+        let time = if self.eat(b'/') { self.time()? } else { 2 * 3600 };
+        Some((date, time))
+    }
+}
+
+impl PosixTz {
+    fn parse(s: &str) -> Option<PosixTz> {
+        let mut p = Parser {
+            s: s.as_bytes(),
+            pos: 0,
+        };
+        p.name()?;
+        // POSIX offsets are west of UTC
+        let std_offset = -p.time()?;
+        if p.peek().is_none() {
+            return Some(PosixTz {
+                std_offset,
+                dst: None,
+            });
+        }
+        p.name()?;
+        let dst_offset = match p.peek() {
+            Some(b',') => std_offset + 3600,
+            _ => -p.time()?,
+        };
+        p.eat(b',').then_some(())?;
+        let start = p.date()?;
+        p.eat(b',').then_some(())?;
+        let end = p.date()?;
+        Some(PosixTz {
+            std_offset,
+            dst: Some((dst_offset, Rule { start, end })),
+        })
+    }
+    fn offset_at(&self, timestamp: i64) -> i64 {
+        let Some((dst_offset, rule)) = self.dst else {
+            return self.std_offset;
+        };
+        let Some(year) = DateTime::from_timestamp(timestamp + self.std_offset, 0).map(|t| t.year())
+        else {
+            return self.std_offset;
+        };
+        // the start is given in standard time, the end in daylight saving time
+        let start = transition(year, rule.start) - self.std_offset;
+        let end = transition(year, rule.end) - dst_offset;
+        let in_dst = if start < end {
+            start <= timestamp && timestamp < end
+        } else {
+            // southern hemisphere, DST spans the new year
+            !(end <= timestamp && timestamp < start)
+        };
+        if in_dst {
+            dst_offset
+        } else {
+            self.std_offset
+        }
+    }
+}
+
+/// Local seconds since the epoch at which a rule takes effect in `year`
+fn transition(year: i32, (date, time): (Date, i64)) -> i64 {
+    let jan1 = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+    let day = match date {
+        Date::Julian(n) => {
+            let leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+            let n = n.clamp(1, 365);
+            jan1 + chrono::Days::new((n - 1 + (leap && n >= 60) as u32) as u64)
+        }
+        Date::ZeroBased(n) => jan1 + chrono::Days::new(n.min(365) as u64),
+        Date::MonthWeekDay(month, week, weekday) => {
+            let first = NaiveDate::from_ymd_opt(year, month.clamp(1, 12), 1).unwrap();
+            let first_weekday = first.weekday().num_days_from_sunday();
+            let mut day = 1 + (weekday + 7 - first_weekday) % 7 + (week.clamp(1, 5) - 1) * 7;
+            while NaiveDate::from_ymd_opt(year, month, day).is_none() {
+                day -= 7;
+            }
+            NaiveDate::from_ymd_opt(year, month, day).unwrap()
+        }
+    };
+    day.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() + time
+}
+
+pub struct Zone {
+    /// Transition times and the offset in effect from then on
+    transitions: Vec<(i64, i64)>,
+    /// Offset before the first transition
+    initial_offset: i64,
+    rule: Option<PosixTz>,
+}
+
+fn be_u32(data: &[u8], at: usize) -> Result<u32> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .ok_or(anyhow!("truncated zoneinfo file"))
+}
+
+impl Zone {
+    /// Loads a zone by its name, e.g. `America/New_York`
+    pub fn load(name: &str) -> Result<Zone> {
+        if name.split('/').any(|part| part == ".." || part.is_empty()) {
+            return Err(anyhow!("invalid time zone {name}"));
+        }
+        let data = fs::read(format!("{ZONEINFO_DIR}/{name}"))
+            .map_err(|e| anyhow!("unknown time zone {name}: {e}"))?;
+        Zone::parse(&data).map_err(|e| anyhow!("{name}: {e}"))
+    }
+    fn parse(data: &[u8]) -> Result<Zone> {
+        if !data.starts_with(b"TZif") {
+            return Err(anyhow!("not a zoneinfo file"));
+        }
+        let counts = |at: usize| -> Result<[usize; 6]> {
+            let mut counts = [0; 6];
+            for (i, count) in counts.iter_mut().enumerate() {
+                *count = be_u32(data, at + 20 + i * 4)? as usize;
+            }
+            Ok(counts)
+        };
+        // isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt
+        let [isut, isstd, leap, time, types, chars] = counts(0)?;
+        let mut header = 0;
+        let mut time_size = 4;
+        let mut v1_size = time * 5 + types * 6 + chars + leap * 8 + isstd + isut;
+        if data[4] >= b'2' {
+            // skip the 32-bit data, the 64-bit version follows it
+            header = 44 + v1_size;
+            time_size = 8;
+        }
+        let [isut, isstd, leap, time, types, chars] = counts(header)?;
+        let body = header + 44;
+        let read_time = |i: usize| -> Result<i64> {
+            let at = body + i * time_size;
+            let bytes = data.get(at..at + time_size).ok_or(anyhow!("truncated zoneinfo file"))?;
+            Ok(match time_size {
+                8 => i64::from_be_bytes(bytes.try_into().unwrap()),
+                _ => i32::from_be_bytes(bytes.try_into().unwrap()) as i64,
+            })
+        };
+        let type_indices = body + time * time_size;
+        let ttinfos = type_indices + time;
+        let offset_of = |index: usize| -> Result<i64> {
+            if index >= types {
+                return Err(anyhow!("invalid local time type"));
+            }
+            Ok(be_u32(data, ttinfos + index * 6)? as i32 as i64)
+        };
+        let mut transitions = Vec::with_capacity(time);
+        for i in 0..time {
+            let index = *data.get(type_indices + i).ok_or(anyhow!("truncated zoneinfo file"))?;
+            transitions.push((read_time(i)?, offset_of(index as usize)?));
+        }
+        let initial_offset = if types > 0 { offset_of(0)? } else { 0 };
+        v1_size = time * (time_size + 1) + types * 6 + chars + leap * (time_size + 4) + isstd + isut;
+        let footer = data.get(body + v1_size..).unwrap_or_default();
+        let rule = std::str::from_utf8(footer)
+            .ok()
+            .map(|s| s.trim_matches('\n'))
+            .filter(|s| !s.is_empty())
+            .and_then(PosixTz::parse);
+        Ok(Zone {
+            transitions,
+            initial_offset,
+            rule,
+        })
+    }
+    /// The offset from UTC in seconds at the given time
+    pub fn offset_at(&self, timestamp: i64) -> i64 {
+        match self.transitions.iter().rposition(|(time, _)| *time <= timestamp) {
+            Some(i) if i + 1 < self.transitions.len() || self.rule.is_none() => {
+                self.transitions[i].1
+            }
+            None if !self.transitions.is_empty() || self.rule.is_none() => self.initial_offset,
+            _ => self.rule.unwrap().offset_at(timestamp),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::i18n;
+use crate::toast::Toast;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use udev::{Event, EventType};
+
+// Shows the name of a USB device as a toast when it is plugged in, as a
+// confirmation that it was seen, since on machines whose ports are all the
+// same USB-C it is otherwise hard to tell. Names come from the device, or
+// from the hardware database udev fills in when the device has none.
+//
+// Hubs are left out, including those inside docks and displays, which
+// would otherwise show up alongside what is plugged into them.
+
+const USB_CLASS_HUB: &str = "09";
+
+/// The `[UsbToast]` section
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct UsbToastConfig {
+    pub enable: Option<bool>,
+    pub timeout_ms: Option<u64>,
+    pub icon: Option<String>,
+    /// Devices not to show, as "vendor:product" ids like "05ac:8302"
+    pub ignore: Option<Vec<String>>,
+}
+
+fn value(value: Option<&OsStr>) -> Option<String> {
+    let value = value?.to_string_lossy().trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+/// The toast for a USB device that was just plugged in, if it is shown
+pub fn toast_for(cfg: &UsbToastConfig, event: &Event) -> Option<Toast> {
+    if cfg.enable != Some(true)
+        || event.event_type() != EventType::Add
+        || event.devtype() != Some(OsStr::new("usb_device"))
+        || value(event.attribute_value("bDeviceClass")).as_deref() == Some(USB_CLASS_HUB)
+    {
+        return None;
+    }
+    let vendor_id = value(event.attribute_value("idVendor")).unwrap_or_default();
+    let product_id = value(event.attribute_value("idProduct")).unwrap_or_default();
+    let id = format!("{vendor_id}:{product_id}");
+    if cfg.ignore.iter().flatten().any(|ignored| ignored.eq_ignore_ascii_case(&id)) {
+        return None;
+    }
+    let product = value(event.attribute_value("product"))
+        .or_else(|| value(event.property_value("ID_MODEL_FROM_DATABASE")));
+    let vendor = value(event.attribute_value("manufacturer"))
+        .or_else(|| value(event.property_value("ID_VENDOR_FROM_DATABASE")));
+    let name = match (vendor, product) {
+        // product names often start with the vendor already
+        (Some(vendor), Some(product)) if !product.starts_with(&vendor) => format!("{vendor} {product}"),
+        (_, Some(product)) => product,
+        (Some(vendor), None) => vendor,
+        (None, None) => format!("{} {id}", i18n::tr("USB device")),
+    };
+    Some(Toast::new(name, cfg.icon.clone(), cfg.timeout_ms.unwrap_or(0)))
+}
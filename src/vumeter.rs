@@ -0,0 +1,179 @@
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::power;
+use crate::source::{self, Source, Update};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Read,
+    process::{Child, Command, Stdio},
+    sync::mpsc::{channel, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Shows how loud the audio being played is, by recording the monitor of
+// the default output through parec (which works with pipewire-pulse) at a
+// low sample rate and only looking at the peak of every frame. The level
+// falls off slowly like on a real VU meter, and only changes worth
+// redrawing wake up the main loop, so an idle meter costs nothing.
+
+const SAMPLE_RATE: u32 = 8000;
+const DEFAULT_FPS: u32 = 20;
+const MAX_FPS: u32 = 30;
+const DECAY: f64 = 0.8;
+const MIN_CHANGE: f64 = 0.01;
+const RESTART_DELAY: Duration = Duration::from_secs(10);
+const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The `VuMeter` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct VuMeterConfig {
+    pub server: Option<String>,
+    pub fps: Option<u32>,
+    pub pause_on_battery: Option<bool>,
+}
+
+struct Capture {
+    child: Child,
+    levels: Receiver<f64>,
+}
+
+pub struct VuMeter {
+    cfg: VuMeterConfig,
+    capture: Option<Capture>,
+    next_start: Instant,
+    /// Turned off by tapping the button
+    stopped: bool,
+    on_battery: bool,
+    next_power_check: Instant,
+}
+
+impl VuMeter {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(VuMeter::new(source::parse(value)?)))
+    }
+    pub fn new(cfg: VuMeterConfig) -> VuMeter {
+        VuMeter {
+            cfg,
+            capture: None,
+            next_start: Instant::now(),
+            stopped: false,
+            on_battery: false,
+            next_power_check: Instant::now(),
+        }
+    }
+    fn start(&self, waker: &Waker) -> std::io::Result<Capture> {
+        let fps = self.cfg.fps.unwrap_or(DEFAULT_FPS).clamp(1, MAX_FPS);
+        let mut cmd = Command::new("parec");
+        cmd.args([
+            "--device=@DEFAULT_MONITOR@",
+            "--format=s16le",
+            "--channels=1",
+            &format!("--rate={SAMPLE_RATE}"),
+            &format!("--latency-msec={}", 1000 / fps),
+            "--raw",
+        ]);
+        if let Some(server) = &self.cfg.server {
+            cmd.env("PULSE_SERVER", server);
+        }
+        let mut child = cmd.stdin(Stdio::null()).stdout(Stdio::piped()).spawn()?;
+        let mut stdout = child.stdout.take().unwrap();
+        let (tx, levels) = channel();
+        let waker = waker.clone();
+        thread::spawn(move || {
+            let mut frame = vec![0u8; (SAMPLE_RATE / fps * 2) as usize];
+            let mut level = 0.0;
+            let mut shown = 0.0;
+            let mut skipped = 0;
+            while stdout.read_exact(&mut frame).is_ok() {
+                let peak = frame
+                    .chunks_exact(2)
+                    .map(|s| i16::from_le_bytes([s[0], s[1]]).unsigned_abs())
+                    .max()
+                    .unwrap_or(0);
+                level = (peak as f64 / 32768.0).max(level * DECAY);
+                // while slowed down by the power policy, only every
+                // few frames are shown
+                skipped += 1;
+                if skipped < power::slowdown() || f64::abs(level - shown) < MIN_CHANGE {
+                    continue;
+                }
+                skipped = 0;
+                shown = level;
+                if tx.send(level).is_err() {
+                    break;
+                }
+                waker.wake();
+            }
+        });
+        Ok(Capture { child, levels })
+    }
+    fn stop(&mut self) {
+        if let Some(mut capture) = self.capture.take() {
+            _ = capture.child.kill();
+            _ = capture.child.wait();
+        }
+    }
+}
+
+impl Source for VuMeter {
+    fn poll(&mut self, waker: &Waker) -> (Vec<Update>, i32) {
+        let now = Instant::now();
+        // check back now and then to follow the power policy
+        let mut ms_left = POWER_CHECK_INTERVAL.as_millis() as i32;
+        if self.cfg.pause_on_battery == Some(true) {
+            if now >= self.next_power_check {
+                self.on_battery = power::on_battery();
+                self.next_power_check = now + POWER_CHECK_INTERVAL;
+            }
+            ms_left = (self.next_power_check - now).as_millis() as i32;
+        }
+        if self.stopped || self.on_battery || !power::animations_enabled() {
+            if self.capture.is_some() {
+                self.stop();
+                return (vec![Update::Level(None)], ms_left);
+            }
+            return (Vec::new(), ms_left);
+        }
+        if let Some(capture) = &mut self.capture {
+            if let Ok(Some(status)) = capture.child.try_wait() {
+                eprintln!("parec exited with {status}, restarting");
+                self.capture = None;
+                self.next_start = now + RESTART_DELAY;
+            }
+        }
+        if self.capture.is_none() {
+            if now < self.next_start {
+                let until_start = (self.next_start - now).as_millis() as i32;
+                return (vec![Update::Level(None)], ms_left.min(until_start));
+            }
+            match self.start(waker) {
+                Ok(capture) => self.capture = Some(capture),
+                Err(e) => {
+                    eprintln!("Failed to run parec: {e}");
+                    self.next_start = now + RESTART_DELAY;
+                    return (Vec::new(), ms_left);
+                }
+            }
+        }
+        let capture = self.capture.as_ref().unwrap();
+        let updates = match capture.levels.try_iter().last() {
+            Some(level) => vec![Update::Level(Some(level))],
+            None => Vec::new(),
+        };
+        (updates, ms_left)
+    }
+    fn touch(&mut self, pressed: bool) {
+        if pressed {
+            self.stopped = !self.stopped;
+        }
+    }
+}
+
+impl Drop for VuMeter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,96 @@
+use crate::audit;
+use crate::config::ButtonConfig;
+use crate::http;
+use crate::ipc::Waker;
+use crate::ratelimit;
+use crate::source::{self, Source, Update};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+/// The `Http` key of a button
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct HttpConfig {
+    pub url: String,
+    pub method: Option<String>,
+    pub body: Option<String>,
+    pub headers: Option<HashMap<String, String>>,
+}
+
+/// Sends a request every time the button is pressed, flashing the button
+/// to show whether it succeeded once the response comes in.
+pub struct Webhook {
+    cfg: HttpConfig,
+    results: Receiver<bool>,
+    results_tx: Sender<bool>,
+    waker: Option<Waker>,
+}
+
+impl Webhook {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(Webhook::new(source::parse(value)?)))
+    }
+    pub fn new(cfg: HttpConfig) -> Webhook {
+        let (results_tx, results) = channel();
+        Webhook {
+            cfg,
+            results,
+            results_tx,
+            waker: None,
+        }
+    }
+}
+
+fn send(cfg: &HttpConfig) -> bool {
+    let method = cfg.method.as_deref().unwrap_or(match cfg.body {
+        Some(_) => "POST",
+        None => "GET",
+    });
+    let headers: Vec<(&str, &str)> = cfg
+        .headers
+        .iter()
+        .flatten()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    audit::record("Sent web request", Some(&format!("{method} {}", cfg.url)));
+    match http::request(method, &cfg.url, &headers, cfg.body.as_deref()) {
+        Ok(response) if response.is_success() => true,
+        Ok(response) => {
+            eprintln!("{method} {} failed with HTTP {}", cfg.url, response.status);
+            false
+        }
+        Err(e) => {
+            eprintln!("{method} {} failed: {e:#}", cfg.url);
+            false
+        }
+    }
+}
+
+impl Source for Webhook {
+    fn poll(&mut self, waker: &Waker) -> (Vec<Update>, i32) {
+        self.waker.get_or_insert_with(|| waker.clone());
+        let updates = self.results.try_iter().map(Update::Flash).collect();
+        (updates, i32::MAX)
+    }
+    fn touch(&mut self, pressed: bool) {
+        // touches can only happen after the first poll, so the waker is set
+        let (true, Some(waker)) = (pressed, &self.waker) else {
+            return;
+        };
+        if !ratelimit::allow_command() {
+            return;
+        }
+        let cfg = self.cfg.clone();
+        let results = self.results_tx.clone();
+        let waker = waker.clone();
+        thread::spawn(move || {
+            _ = results.send(send(&cfg));
+            waker.wake();
+        });
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use gio::{prelude::*, DataInputStream, OutputStream, SocketClient, SocketConnection};
+use glib::Priority;
+use std::{fs::File, io::Read};
+
+// A small websocket client (RFC 6455), enough to talk to local services
+// such as obs-websocket. Only plain ws:// URLs are supported. It runs on
+// the runtime, with gio doing the waiting on the socket.
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut bytes))
+        .expect("failed to read /dev/urandom");
+    bytes
+}
+
+/// What the other end sent
+pub enum Message {
+    Text(String),
+    /// Has to be answered with `WsWriter::pong`. The reader leaves that to
+    /// the writer, so that writes never overlap.
+    Ping(Vec<u8>),
+}
+
+/// The sending half of a connection, which also keeps it open.
+pub struct WsWriter {
+    connection: SocketConnection,
+    stream: OutputStream,
+}
+
+impl WsWriter {
+    async fn send_frame(&self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len @ 0..=125 => frame.push(0x80 | len as u8),
+            len @ 126..=0xFFFF => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        // clients have to mask everything they send
+        let mask: [u8; 4] = random_bytes();
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        write_all(&self.stream, frame).await
+    }
+    pub async fn send_text(&self, text: &str) -> Result<()> {
+        self.send_frame(OP_TEXT, text.as_bytes()).await
+    }
+    pub async fn pong(&self, payload: &[u8]) -> Result<()> {
+        self.send_frame(OP_PONG, payload).await
+    }
+    /// Closes the connection, which also ends a pending `WsReader::read`.
+    pub async fn close(&self) {
+        _ = self.send_frame(OP_CLOSE, &[]).await;
+        _ = self.connection.close_future(Priority::DEFAULT).await;
+    }
+}
+
+/// The receiving half of a connection.
+pub struct WsReader {
+    stream: DataInputStream,
+}
+
+impl WsReader {
+    async fn read_exact<const N: usize>(&self) -> Result<[u8; N]> {
+        let bytes = self.read_vec(N).await?;
+        Ok(bytes.try_into().unwrap())
+    }
+    async fn read_vec(&self, len: usize) -> Result<Vec<u8>> {
+        let (bytes, read, error) = self
+            .stream
+            .read_all_future(vec![0u8; len], Priority::DEFAULT)
+            .await
+            .map_err(|(_, e)| e)?;
+        if let Some(e) = error {
+            return Err(e.into());
+        }
+        if read < len {
+            return Err(anyhow!("connection closed"));
+        }
+        Ok(bytes)
+    }
+    async fn read_frame(&self) -> Result<(bool, u8, Vec<u8>)> {
+        let header: [u8; 2] = self.read_exact().await?;
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0F;
+        let masked = header[1] & 0x80 != 0;
+        let len = match header[1] & 0x7F {
+            126 => u16::from_be_bytes(self.read_exact().await?) as u64,
+            127 => u64::from_be_bytes(self.read_exact().await?),
+            len => len as u64,
+        };
+        let mask: [u8; 4] = if masked { self.read_exact().await? } else { [0; 4] };
+        let mut payload = self.read_vec(len as usize).await?;
+        if masked {
+            for (i, b) in payload.iter_mut().enumerate() {
+                *b ^= mask[i % 4];
+            }
+        }
+        Ok((fin, opcode, payload))
+    }
+    /// Waits for the next text message or ping.
+    pub async fn read(&self) -> Result<Message> {
+        let mut message = Vec::new();
+        loop {
+            let (fin, opcode, payload) = self.read_frame().await?;
+            match opcode {
+                OP_TEXT | OP_CONTINUATION => {
+                    message.extend_from_slice(&payload);
+                    if fin {
+                        return Ok(Message::Text(String::from_utf8(message)?));
+                    }
+                }
+                OP_PING => return Ok(Message::Ping(payload)),
+                OP_CLOSE => return Err(anyhow!("connection closed by server")),
+                _ => {}
+            }
+        }
+    }
+}
+
+async fn write_all(stream: &OutputStream, bytes: Vec<u8>) -> Result<()> {
+    let (_, _, error) = stream
+        .write_all_future(bytes, Priority::DEFAULT)
+        .await
+        .map_err(|(_, e)| e)?;
+    match error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+async fn read_line(stream: &DataInputStream) -> Result<String> {
+    let line = stream.read_line_utf8_future(Priority::DEFAULT).await?;
+    Ok(line.map(String::from).unwrap_or_default())
+}
+
+/// Connects to a ws://host:port/path URL, asking for the given subprotocol.
+pub async fn connect(url: &str, protocol: &str) -> Result<(WsReader, WsWriter)> {
+    let rest = url
+        .strip_prefix("ws://")
+        .ok_or(anyhow!("unsupported websocket URL {url}, only ws:// is supported"))?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let connection = SocketClient::new().connect_to_host_future(host, 80).await?;
+    let writer = WsWriter {
+        stream: connection.output_stream(),
+        connection: connection.clone(),
+    };
+    let key = crate::sha256::base64(&random_bytes::<16>());
+    let request = format!(
+        "GET {path} HTTP/1.1\r\nHost: {host}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: {protocol}\r\n\r\n"
+    );
+    write_all(&writer.stream, request.into_bytes()).await?;
+    let reader = WsReader {
+        stream: DataInputStream::new(&connection.input_stream()),
+    };
+    let status = read_line(&reader.stream).await?;
+    if status.split_whitespace().nth(1) != Some("101") {
+        return Err(anyhow!("websocket handshake failed: {}", status.trim_end()));
+    }
+    // lines keep their \r, the headers end with an empty one
+    while !read_line(&reader.stream).await?.trim_end().is_empty() {}
+    Ok((reader, writer))
+}
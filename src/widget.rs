@@ -0,0 +1,121 @@
+use crate::battery::Battery;
+use crate::clock::Clock;
+use crate::config::ButtonConfig;
+use crate::dial::Dial;
+use crate::json::Value;
+use crate::keyboard::Keyboard;
+use crate::render_svg_masked;
+use crate::text::{self, TextDirection};
+use crate::ICON_SIZE;
+use anyhow::Result;
+use cairo::Context;
+use input_linux::Key;
+use librsvg_rebind::Handle;
+use pango::FontDescription;
+
+// Widgets draw what a button shows by themselves, like the clock or the
+// battery, rather than showing text or an icon they were given. Each one
+// is made from its own key of a button's config, looked up in WIDGETS, so
+// that adding one does not take more than an entry there.
+//
+// Widgets that scrub are told where the finger is for as long as it is down,
+// for strips and dials dragged along, and the button stays pressed wherever
+// the finger goes until it is lifted.
+//
+// Sources, which fill in text and icons from outside, are the other way of
+// making buttons show something. They are made the same way, from the
+// entries of `source::SOURCES`.
+
+/// Where a widget draws, in the colors of its button
+pub struct Canvas<'a> {
+    pub c: &'a Context,
+    pub left: f64,
+    pub width: f64,
+    pub height: f64,
+    pub y_shift: f64,
+    pub text_color: [f64; 3],
+    pub icon_color: [f64; 3],
+    pub text_direction: TextDirection,
+    pub font: &'a FontDescription,
+}
+
+impl Canvas<'_> {
+    pub fn set_text_color(&self) {
+        let [r, g, b] = self.text_color;
+        self.c.set_source_rgb(r, g, b);
+    }
+    /// Draws `text` in the middle of the button
+    pub fn centered_text(&self, text: &str) {
+        self.set_text_color();
+        let layout = text::layout(self.c, self.font, text, self.text_direction);
+        let (width, height) = text::ink_size(&layout);
+        self.c.move_to(
+            self.left + (self.width / 2.0 - width / 2.0).round(),
+            self.y_shift + (self.height / 2.0 + height / 2.0).round(),
+        );
+        text::show(self.c, &layout);
+    }
+    /// Draws an icon at `x`, `y` in the icon color
+    pub fn icon(&self, svg: &Handle, x: f64, y: f64) {
+        render_svg_masked(self.c, svg, x, y, ICON_SIZE, self.icon_color);
+    }
+}
+
+/// A finger on a widget that scrubs, at `x` from 0 at the left edge of the
+/// widget to 1 at its right one, which it may go past
+#[derive(Clone, Copy)]
+pub enum Scrub {
+    Down(f64),
+    Move(f64),
+    Up,
+}
+
+pub trait Widget {
+    /// What the widget is called for colors and the edit mode, e.g. "Time"
+    fn name(&self) -> &'static str;
+    /// Called once before the first update, after privileges were dropped
+    fn init(&mut self) {}
+    /// Returns whether the widget has to be drawn again, and the number of
+    /// milliseconds until it wants to be updated next. Only called while
+    /// the widget is shown.
+    fn update(&mut self, power_supply_changed: bool) -> (bool, i32);
+    fn draw(&self, canvas: &Canvas);
+    fn on_touch(&mut self, _pressed: bool) {}
+    fn scrubs(&self) -> bool {
+        false
+    }
+    /// Follows the finger of a widget that scrubs, sending keys through `send`
+    fn on_scrub(&mut self, _scrub: Scrub, _send: &mut dyn FnMut(Key, bool)) {}
+    /// Every key it may send, for the virtual keyboard to announce
+    fn keys(&self) -> Vec<Key> {
+        Vec::new()
+    }
+    /// How many buttons wide it would like to be when Stretch is not set
+    fn preferred_size(&self) -> usize {
+        1
+    }
+    /// The kind and value of the button for the state sent over IPC,
+    /// adding any fields of its own to `fields`
+    fn state(&self, fields: &mut Vec<(String, Value)>) -> (&'static str, Value);
+}
+
+pub struct WidgetEntry {
+    /// The key of a button's config that makes the widget
+    pub key: &'static str,
+    /// Makes it from the value of the key and the rest of the button's config
+    pub build: fn(&toml::Value, &ButtonConfig) -> Result<Box<dyn Widget>>,
+}
+
+pub const WIDGETS: &[WidgetEntry] = &[
+    WidgetEntry { key: "Time", build: Clock::build },
+    WidgetEntry { key: "Battery", build: Battery::build },
+    WidgetEntry { key: "Keyboard", build: Keyboard::build },
+    WidgetEntry { key: "Dial", build: Dial::build },
+];
+
+/// The first entry of WIDGETS whose key `cfg` has, with its value
+pub fn find(cfg: &ButtonConfig) -> Option<(&'static WidgetEntry, &toml::Value)> {
+    WIDGETS
+        .iter()
+        .find_map(|entry| Some((entry, cfg.widgets.get(entry.key)?)))
+}
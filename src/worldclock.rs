@@ -0,0 +1,92 @@
+use crate::config::ButtonConfig;
+use crate::ipc::Waker;
+use crate::source::{self, Source, Update};
+use crate::tz::Zone;
+use anyhow::{anyhow, Result};
+use chrono::{FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_ROTATE_INTERVAL_MS: u64 = 5000;
+const DEFAULT_FORMAT: &str = "%H:%M";
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ClockZone {
+    pub label: String,
+    pub zone: String,
+}
+
+/// The `WorldClock` key of a button
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct WorldClockConfig {
+    pub zones: Vec<ClockZone>,
+    pub format: Option<String>,
+    pub rotate_interval_ms: Option<u64>,
+}
+
+/// Cycles through the time in several zones, moving on every rotate
+/// interval or when tapped.
+pub struct WorldClock {
+    zones: Vec<(String, Zone)>,
+    format: String,
+    interval: Duration,
+    current: usize,
+    next_rotate: Instant,
+    shown: String,
+}
+
+impl WorldClock {
+    pub fn build(value: &toml::Value, _cfg: &ButtonConfig) -> Result<Box<dyn Source>> {
+        Ok(Box::new(WorldClock::new(source::parse(value)?)?))
+    }
+    pub fn new(cfg: WorldClockConfig) -> Result<WorldClock> {
+        if cfg.zones.is_empty() {
+            return Err(anyhow!("WorldClock needs at least one zone in Zones"));
+        }
+        let zones = cfg
+            .zones
+            .into_iter()
+            .map(|zone| Ok((zone.label, Zone::load(&zone.zone)?)))
+            .collect::<Result<_>>()?;
+        let interval_ms = cfg.rotate_interval_ms.unwrap_or(DEFAULT_ROTATE_INTERVAL_MS);
+        Ok(WorldClock {
+            zones,
+            format: cfg.format.unwrap_or(DEFAULT_FORMAT.to_string()),
+            interval: Duration::from_millis(interval_ms.max(100)),
+            current: 0,
+            next_rotate: Instant::now() + Duration::from_millis(interval_ms),
+            shown: String::new(),
+        })
+    }
+}
+
+impl Source for WorldClock {
+    fn poll(&mut self, _waker: &Waker) -> (Vec<Update>, i32) {
+        let now = Instant::now();
+        if now >= self.next_rotate {
+            self.current = (self.current + 1) % self.zones.len();
+            self.next_rotate = now + self.interval;
+        }
+        let (label, zone) = &self.zones[self.current];
+        let utc = Utc::now();
+        let offset = FixedOffset::east_opt(zone.offset_at(utc.timestamp()) as i32)
+            .unwrap_or(FixedOffset::east_opt(0).unwrap());
+        let text = format!("{label} {}", utc.with_timezone(&offset).format(&self.format));
+        let mut updates = Vec::new();
+        if text != self.shown {
+            self.shown = text.clone();
+            updates.push(Update::Text(text));
+        }
+        // come back when the next zone is due, or the minute changes
+        let ms_to_minute = 60_000 - (utc.timestamp_millis() % 60_000) as i32;
+        let ms_to_rotate = (self.next_rotate - now).as_millis() as i32;
+        (updates, ms_to_minute.min(ms_to_rotate))
+    }
+    fn touch(&mut self, pressed: bool) {
+        if pressed {
+            self.next_rotate = Instant::now();
+        }
+    }
+}
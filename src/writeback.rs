@@ -0,0 +1,131 @@
+use crate::json::Value;
+use anyhow::{anyhow, Result};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    os::unix::{fs::OpenOptionsExt, net::UnixStream},
+    path::{Path, PathBuf},
+};
+use toml_edit::DocumentMut;
+
+// Changes made on the bar itself, like reordering buttons, are saved to the
+// config files. Those belong to root while the daemon runs as nobody, so
+// the writing is left to a small helper process forked off before dropping
+// privileges. It only writes to the user config and profiles, and replaces
+// them atomically so that the watcher never sees half a file.
+
+/// Asks the helper to write config files
+pub struct ConfigWriter {
+    stream: UnixStream,
+    allowed: Vec<PathBuf>,
+}
+
+/// Starts the helper, which may write to `user_path` and any file directly
+/// in `profiles_dir`. Needs to be called while still root, and before any
+/// threads are started, as it forks.
+pub fn start(user_path: &Path, profiles_dir: &Path) -> Result<ConfigWriter> {
+    let allowed = vec![user_path.to_path_buf(), profiles_dir.to_path_buf()];
+    let (parent, child) = UnixStream::pair()?;
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error().into()),
+        0 => {
+            drop(parent);
+            serve(child, &allowed);
+            // gone without running anything the daemon set up
+            std::process::exit(0);
+        }
+        _ => Ok(ConfigWriter {
+            stream: parent,
+            allowed,
+        }),
+    }
+}
+
+fn is_allowed(path: &Path, allowed: &[PathBuf]) -> bool {
+    let [user_path, profiles_dir] = allowed else {
+        return false;
+    };
+    path == user_path || path.parent() == Some(profiles_dir.as_path())
+}
+
+/// Replaces the file with a temporary one next to it, so that it is
+/// either the old or the new contents
+fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let name = path.file_name().ok_or(anyhow!("not a file"))?;
+    let tmp = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o644)
+        .open(&tmp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    // make the rename itself durable
+    File::open(path.parent().ok_or(anyhow!("no directory"))?)?.sync_all()?;
+    Ok(())
+}
+
+fn handle(line: &str, allowed: &[PathBuf]) -> Result<()> {
+    let request = Value::parse(line)?;
+    let path = request.get("path").and_then(Value::as_str).ok_or(anyhow!("missing path"))?;
+    let contents = request
+        .get("contents")
+        .and_then(Value::as_str)
+        .ok_or(anyhow!("missing contents"))?;
+    let path = Path::new(path);
+    if !is_allowed(path, allowed) {
+        return Err(anyhow!("not allowed to write {}", path.display()));
+    }
+    write_atomically(path, contents)
+}
+
+fn serve(stream: UnixStream, allowed: &[PathBuf]) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    // ends when the daemon goes away
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let reply = match handle(&line, allowed) {
+            Ok(()) => Value::Null,
+            Err(e) => format!("{e:#}").into(),
+        };
+        if writeln!(writer, "{reply}").is_err() {
+            break;
+        }
+    }
+}
+
+impl ConfigWriter {
+    /// Reads a config file for changing it, keeping its comments and
+    /// formatting. A missing file is an empty one.
+    pub fn read(&self, path: &Path) -> Result<DocumentMut> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(contents.parse()?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DocumentMut::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    pub fn write(&self, path: &Path, doc: &DocumentMut) -> Result<()> {
+        if !is_allowed(path, &self.allowed) {
+            return Err(anyhow!("not allowed to write {}", path.display()));
+        }
+        let request = Value::Object(vec![
+            ("path".to_string(), path.to_string_lossy().into_owned().into()),
+            ("contents".to_string(), doc.to_string().into()),
+        ]);
+        let mut stream = &self.stream;
+        writeln!(stream, "{request}")?;
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        match Value::parse(&reply)? {
+            Value::Null => Ok(()),
+            Value::String(e) => Err(anyhow!("{e}")),
+            _ => Err(anyhow!("unexpected reply from the config writer")),
+        }
+    }
+}